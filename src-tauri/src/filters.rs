@@ -1,14 +1,8 @@
+use log::info;
 use serde::{Deserialize, Serialize};
 use std::fs;
 use std::path::PathBuf;
 
-/// Log a message to stdout for debugging
-macro_rules! log {
-    ($($arg:tt)*) => {
-        println!("[InboxCleanup] {}", format!($($arg)*));
-    };
-}
-
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct FilterConfig {
     pub patterns: Vec<FilterPattern>,
@@ -23,7 +17,51 @@ pub struct FilterPattern {
     pub field: FilterField,
     #[serde(default)]
     pub is_regex: bool,
+    #[serde(default)]
+    pub case_sensitive: bool,
     pub enabled: bool,
+    /// Position in the filter list; lower values come first and win when an
+    /// email is categorized under a single filter.
+    #[serde(default)]
+    pub priority: i64,
+    /// Applied to newly synced emails that match this filter.
+    #[serde(default)]
+    pub action: FilterAction,
+    /// Report what `action` would do during sync without touching the mailbox.
+    #[serde(default)]
+    pub action_dry_run: bool,
+    /// How a non-regex pattern is matched; ignored for regex filters.
+    #[serde(default)]
+    pub match_mode: MatchMode,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MatchMode {
+    #[default]
+    Substring,
+    /// Only match the pattern as whole words, so "hr" skips "shred".
+    WholeWord,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FilterAction {
+    #[default]
+    None,
+    MarkRead,
+    Archive,
+}
+
+/// How `import_filters` combines an imported file with the existing filters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportMode {
+    /// Keep existing filters and add imported ones not already present
+    /// (same name, pattern, and field).
+    Merge,
+    /// Drop existing filters and use the imported set.
+    Replace,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -31,6 +69,8 @@ pub struct FilterPattern {
 pub enum FilterField {
     Subject,
     Sender,
+    /// Any address in `To` or `Cc`.
+    Recipient,
     Any,
 }
 
@@ -60,11 +100,11 @@ fn get_config_path() -> Result<PathBuf, String> {
 
 /// Load filters from disk
 pub fn load_filters() -> Result<FilterConfig, String> {
-    log!("Loading filters from disk...");
+    info!("Loading filters from disk...");
     let path = get_config_path()?;
 
     if !path.exists() {
-        log!("No filters file found, using defaults");
+        info!("No filters file found, using defaults");
         return Ok(FilterConfig::default());
     }
 
@@ -73,7 +113,7 @@ pub fn load_filters() -> Result<FilterConfig, String> {
 
     let config: FilterConfig = serde_json::from_str(&content)
         .map_err(|e| format!("Failed to parse filters file: {}", e))?;
-    log!("Loaded {} filters", config.patterns.len());
+    info!("Loaded {} filters", config.patterns.len());
     Ok(config)
 }
 
@@ -86,3 +126,14 @@ pub fn save_filters(config: &FilterConfig) -> Result<(), String> {
 
     fs::write(&path, content).map_err(|e| format!("Failed to write filters file: {}", e))
 }
+
+/// Check that every regex filter compiles, naming the first one that doesn't.
+pub fn validate_patterns(patterns: &[FilterPattern]) -> Result<(), String> {
+    for filter in patterns.iter().filter(|filter| filter.is_regex) {
+        regex::RegexBuilder::new(&filter.pattern)
+            .case_insensitive(!filter.case_sensitive)
+            .build()
+            .map_err(|e| format!("Invalid regex in filter \"{}\": {}", filter.name, e))?;
+    }
+    Ok(())
+}
@@ -5,26 +5,27 @@
 
 use imap::Session;
 use native_tls::TlsStream;
+use security_framework::item::{ItemClass, ItemSearchOptions, Limit};
 use security_framework::passwords::{delete_generic_password, get_generic_password, set_generic_password};
 use serde::{Deserialize, Serialize};
-use std::net::TcpStream;
+use std::net::{IpAddr, SocketAddr, TcpStream, ToSocketAddrs};
+use std::time::Duration;
 use base64::engine::general_purpose;
 use base64::Engine;
-use mail_parser::MessageParser;
+use mail_parser::{Message, MessageParser, MimeHeaders};
 use imap::types::Flag;
 use chrono::DateTime;
-use std::collections::HashSet;
+use log::{debug, info, warn};
+use std::collections::{HashMap, HashSet};
+use std::ops::ControlFlow;
+use std::sync::{Mutex, OnceLock};
 
 const KEYCHAIN_SERVICE: &str = "com.inboxcleanup.gmail";
 const IMAP_HOST: &str = "imap.gmail.com";
 const IMAP_PORT: u16 = 993;
-
-/// Log a message to stdout for debugging
-macro_rules! log {
-    ($($arg:tt)*) => {
-        println!("[InboxCleanup:Gmail] {}", format!($($arg)*));
-    };
-}
+/// Upper bound on IMAP connections used for body prefetch. Gmail rejects more than
+/// 15 simultaneous connections per account, and other clients may hold some of them.
+const MAX_BODY_CONCURRENCY: usize = 4;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GmailEmail {
@@ -35,12 +36,61 @@ pub struct GmailEmail {
     pub date: String,
     pub date_epoch: i64,
     pub is_read: bool,
+    /// Starred in Gmail (`\Flagged`).
+    #[serde(default)]
+    pub is_flagged: bool,
+    /// Replied to (`\Answered`).
+    #[serde(default)]
+    pub is_answered: bool,
+    /// `RFC822.SIZE` in bytes, when the fetch asked for it.
+    #[serde(default)]
+    pub size_bytes: Option<u32>,
+    /// `To` recipients, formatted like `sender` and joined with ", ".
+    #[serde(default)]
+    pub to: Option<String>,
+    /// `Cc` recipients, formatted like `sender` and joined with ", ".
+    #[serde(default)]
+    pub cc: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EmailBody {
+    /// Sanitized HTML with remote images blocked; safe to hand to the webview.
     pub html: Option<String>,
+    /// HTML as received, kept server-side so remote images can be loaded on request.
+    #[serde(skip)]
+    pub html_raw: Option<String>,
     pub text: Option<String>,
+    /// First `http(s):` target from `List-Unsubscribe`.
+    #[serde(default)]
+    pub unsubscribe_url: Option<String>,
+    /// First `mailto:` target from `List-Unsubscribe`.
+    #[serde(default)]
+    pub unsubscribe_mailto: Option<String>,
+    /// Whether `List-Unsubscribe-Post` advertises RFC 8058 one-click unsubscribe.
+    #[serde(default)]
+    pub unsubscribe_oneclick: bool,
+    #[serde(default)]
+    pub attachments: Vec<AttachmentMeta>,
+    /// Threading headers, only used to group conversations in storage.
+    #[serde(skip)]
+    pub in_reply_to: Option<String>,
+    #[serde(skip)]
+    pub references: Vec<String>,
+    /// Short plain-text preview for the list view; see [`make_snippet`].
+    #[serde(default)]
+    pub snippet: Option<String>,
+    /// Top-level headers in message order, decoded and unfolded; at most [`MAX_HEADERS`].
+    #[serde(skip)]
+    pub headers: Vec<(String, String)>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AttachmentMeta {
+    pub filename: Option<String>,
+    pub content_type: String,
+    /// Decoded size in bytes.
+    pub size: u64,
 }
 
 
@@ -50,20 +100,27 @@ pub struct EmailBody {
 
 /// Store Gmail credentials in the macOS Keychain
 pub fn store_credentials(email: &str, app_password: &str) -> Result<(), String> {
-    log!("Storing credentials for {} in Keychain", email);
+    info!("Storing credentials for {} in Keychain", email);
     
     // Store the password with email as the account name
     set_generic_password(KEYCHAIN_SERVICE, email, app_password.as_bytes())
         .map_err(|e| format!("Failed to store in Keychain: {}", e))?;
     
-    log!("Credentials stored successfully");
+    info!("Credentials stored successfully");
     Ok(())
 }
 
-/// Retrieve Gmail credentials from the macOS Keychain
+/// Retrieve Gmail credentials from the macOS Keychain. A missing entry (e.g. the
+/// account was removed while a sync was queued) gets its own message, starting with
+/// [`CREDENTIALS_MISSING_ERROR`], so it can be told apart from Keychain failures.
 pub fn get_credentials(email: &str) -> Result<String, String> {
-    let password_bytes = get_generic_password(KEYCHAIN_SERVICE, email)
-        .map_err(|e| format!("Failed to retrieve from Keychain: {}", e))?;
+    let password_bytes = get_generic_password(KEYCHAIN_SERVICE, email).map_err(|e| {
+        if e.code() == ERR_SEC_ITEM_NOT_FOUND {
+            format!("{} for {}", CREDENTIALS_MISSING_ERROR, email)
+        } else {
+            format!("Failed to retrieve from Keychain: {}", e)
+        }
+    })?;
     
     String::from_utf8(password_bytes.to_vec())
         .map_err(|e| format!("Invalid password encoding: {}", e))
@@ -71,12 +128,17 @@ pub fn get_credentials(email: &str) -> Result<String, String> {
 
 /// Delete Gmail credentials from the macOS Keychain
 pub fn delete_credentials(email: &str) -> Result<(), String> {
-    log!("Deleting credentials for {} from Keychain", email);
+    info!("Deleting credentials for {} from Keychain", email);
     
     delete_generic_password(KEYCHAIN_SERVICE, email)
         .map_err(|e| format!("Failed to delete from Keychain: {}", e))?;
+    if let Ok(mut sessions) = ping_sessions().lock() {
+        if let Some(mut session) = sessions.remove(email) {
+            session.logout().ok();
+        }
+    }
     
-    log!("Credentials deleted successfully");
+    info!("Credentials deleted successfully");
     Ok(())
 }
 
@@ -85,41 +147,356 @@ pub fn has_credentials(email: &str) -> bool {
     get_generic_password(KEYCHAIN_SERVICE, email).is_ok()
 }
 
+/// `errSecItemNotFound`: the search matched nothing.
+const ERR_SEC_ITEM_NOT_FOUND: i32 = -25300;
+
+/// Every account with credentials stored under our Keychain service, sorted.
+pub fn list_configured_accounts() -> Result<Vec<String>, String> {
+    let results = match ItemSearchOptions::new()
+        .class(ItemClass::generic_password())
+        .service(KEYCHAIN_SERVICE)
+        .load_attributes(true)
+        .limit(Limit::All)
+        .search()
+    {
+        Ok(results) => results,
+        Err(e) if e.code() == ERR_SEC_ITEM_NOT_FOUND => return Ok(Vec::new()),
+        Err(e) => return Err(format!("Failed to search Keychain: {}", e)),
+    };
+
+    let mut accounts: Vec<String> = results
+        .iter()
+        .filter_map(|result| result.simplify_dict()?.remove("acct"))
+        .filter(|account| !account.is_empty())
+        .collect();
+    accounts.sort();
+    accounts.dedup();
+    Ok(accounts)
+}
+
 // =============================================================================
 // IMAP Connection
 // =============================================================================
 
+type ImapSession = Session<TlsStream<TcpStream>>;
+
+/// Backoff between attempts for transient IMAP failures (three retries: 1s, 2s, 4s).
+const RETRY_DELAYS: [std::time::Duration; 3] = [
+    std::time::Duration::from_secs(1),
+    std::time::Duration::from_secs(2),
+    std::time::Duration::from_secs(4),
+];
+
+/// Dropped connections and socket errors are worth retrying; server rejections
+/// (including bad credentials) are not.
+fn is_transient(err: &imap::Error) -> bool {
+    matches!(err, imap::Error::Io(_) | imap::Error::ConnectionLost)
+}
+
+/// Run `op`, retrying transient failures with exponential backoff.
+fn with_retry<T>(label: &str, mut op: impl FnMut() -> imap::Result<T>) -> imap::Result<T> {
+    let mut attempt = 0;
+    loop {
+        match op() {
+            Err(err) if attempt < RETRY_DELAYS.len() && is_transient(&err) => {
+                let delay = RETRY_DELAYS[attempt];
+                attempt += 1;
+                warn!(
+                    "{} failed ({}), retry {}/{} in {:?}",
+                    label,
+                    err,
+                    attempt,
+                    RETRY_DELAYS.len(),
+                    delay
+                );
+                std::thread::sleep(delay);
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Socket timeouts for IMAP connections. Without them a stalled network blocks
+/// the sync task forever.
+#[derive(Debug, Clone, Copy)]
+pub struct ImapTimeouts {
+    /// Time allowed to establish the TCP connection.
+    pub connect: Duration,
+    /// Time allowed for any single read or write once connected.
+    pub io: Duration,
+}
+
+impl Default for ImapTimeouts {
+    fn default() -> Self {
+        Self {
+            connect: Duration::from_secs(30),
+            io: Duration::from_secs(30),
+        }
+    }
+}
+
+impl ImapTimeouts {
+    /// Build from optional second values, using the defaults for missing or zero ones.
+    pub fn from_secs(connect_secs: Option<u64>, io_secs: Option<u64>) -> Self {
+        let defaults = Self::default();
+        Self {
+            connect: connect_secs
+                .filter(|secs| *secs > 0)
+                .map_or(defaults.connect, Duration::from_secs),
+            io: io_secs
+                .filter(|secs| *secs > 0)
+                .map_or(defaults.io, Duration::from_secs),
+        }
+    }
+}
+
+const TIMEOUT_ERROR: &str = "Connection timed out";
+/// Prefix of the error `get_credentials` returns when the Keychain has no entry.
+const CREDENTIALS_MISSING_ERROR: &str = "No credentials configured";
+
+/// Socket read timeouts surface as `WouldBlock` on Unix and `TimedOut` on Windows.
+fn is_timeout(err: &imap::Error) -> bool {
+    matches!(
+        err,
+        imap::Error::Io(io) if matches!(io.kind(), std::io::ErrorKind::TimedOut | std::io::ErrorKind::WouldBlock)
+    )
+}
+
+/// Open a TLS connection with the socket timeouts applied before the handshake.
+fn open_client(
+    tls: &native_tls::TlsConnector,
+    timeouts: ImapTimeouts,
+) -> imap::Result<imap::Client<TlsStream<TcpStream>>> {
+    let mut last_error = None;
+    let mut tcp = None;
+    for addr in (IMAP_HOST, IMAP_PORT).to_socket_addrs()? {
+        match TcpStream::connect_timeout(&addr, timeouts.connect) {
+            Ok(stream) => {
+                tcp = Some(stream);
+                break;
+            }
+            Err(err) => last_error = Some(err),
+        }
+    }
+    let tcp = match tcp {
+        Some(stream) => stream,
+        None => {
+            return Err(imap::Error::Io(last_error.unwrap_or_else(|| {
+                std::io::Error::new(std::io::ErrorKind::NotFound, "No address for IMAP host")
+            })))
+        }
+    };
+    tcp.set_read_timeout(Some(timeouts.io))?;
+    tcp.set_write_timeout(Some(timeouts.io))?;
+
+    let stream = tls.connect(IMAP_HOST, tcp).map_err(imap::Error::TlsHandshake)?;
+    let mut client = imap::Client::new(stream);
+    client.read_greeting()?;
+    Ok(client)
+}
+
+/// Why a sync failed, so the UI can offer the right fix (e.g. a new App Password).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SyncErrorKind {
+    AuthFailed,
+    /// No Keychain entry for the account; retrying won't help until it's added again.
+    CredentialsMissing,
+    Network,
+    RateLimited,
+    Unknown,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SyncError {
+    pub kind: SyncErrorKind,
+    /// Human-readable description, same text the plain `String` errors carry.
+    pub message: String,
+}
+
+impl SyncError {
+    fn new(kind: SyncErrorKind, message: impl Into<String>) -> Self {
+        Self {
+            kind,
+            message: message.into(),
+        }
+    }
+
+    fn from_imap(err: &imap::Error) -> SyncErrorKind {
+        match err {
+            imap::Error::No(text) | imap::Error::Bad(text) => {
+                if is_rate_limit_text(text) {
+                    SyncErrorKind::RateLimited
+                } else {
+                    SyncErrorKind::AuthFailed
+                }
+            }
+            imap::Error::Io(_)
+            | imap::Error::ConnectionLost
+            | imap::Error::Tls(_)
+            | imap::Error::TlsHandshake(_) => SyncErrorKind::Network,
+            _ => SyncErrorKind::Unknown,
+        }
+    }
+}
+
+/// Gmail's responses when too many connections or commands are in flight.
+fn is_rate_limit_text(text: &str) -> bool {
+    ["[THROTTLED]", "[OVERQUOTA]", "Too many simultaneous connections", "bandwidth limits"]
+        .iter()
+        .any(|marker| text.contains(marker))
+}
+
+/// Mutation failures worth pausing and retrying: rate limits, plus `[TRYCREATE]`,
+/// which asks the client to retry the COPY/MOVE later.
+pub fn is_throttle_response(text: &str) -> bool {
+    is_rate_limit_text(text) || text.contains("[TRYCREATE]")
+}
+
+/// Plain string errors from the rest of the sync are classified by their text.
+impl From<String> for SyncError {
+    fn from(message: String) -> Self {
+        let kind = if message.starts_with(CREDENTIALS_MISSING_ERROR) {
+            SyncErrorKind::CredentialsMissing
+        } else if is_rate_limit_text(&message) {
+            SyncErrorKind::RateLimited
+        } else if message.starts_with(TIMEOUT_ERROR) || message.contains("Connection lost") {
+            SyncErrorKind::Network
+        } else {
+            SyncErrorKind::Unknown
+        };
+        Self::new(kind, message)
+    }
+}
+
+impl From<SyncError> for String {
+    fn from(err: SyncError) -> Self {
+        err.message
+    }
+}
+
 /// Create an authenticated IMAP session
-fn connect_imap(email: &str, app_password: &str) -> Result<Session<TlsStream<TcpStream>>, String> {
-    log!("Connecting to {} for {}...", IMAP_HOST, email);
+fn connect_imap(
+    email: &str,
+    app_password: &str,
+    timeouts: ImapTimeouts,
+) -> Result<ImapSession, SyncError> {
+    login_imap(email, app_password, timeouts, true)
+}
+
+/// Connect and log in; transient failures are retried with backoff only when `retry`
+/// is set.
+fn login_imap(
+    email: &str,
+    app_password: &str,
+    timeouts: ImapTimeouts,
+    retry: bool,
+) -> Result<ImapSession, SyncError> {
+    debug!("Connecting to {} for {}...", IMAP_HOST, email);
     
     let tls = native_tls::TlsConnector::new()
-        .map_err(|e| format!("TLS error: {}", e))?;
-    
-    let client = imap::connect((IMAP_HOST, IMAP_PORT), IMAP_HOST, &tls)
-        .map_err(|e| format!("Connection failed: {}", e))?;
+        .map_err(|e| SyncError::new(SyncErrorKind::Unknown, format!("TLS error: {}", e)))?;
     
-    let session = client
-        .login(email, app_password)
-        .map_err(|e| format!("Login failed: {}. Ensure you're using an App Password (not your regular password). Generate one at myaccount.google.com/apppasswords", e.0))?;
+    let login = || {
+        let client = open_client(&tls, timeouts)?;
+        client.login(email, app_password).map_err(|e| e.0)
+    };
+    let session = if retry { with_retry("Connect", login) } else { login() }.map_err(|e| {
+        let kind = SyncError::from_imap(&e);
+        let message = match kind {
+            _ if is_timeout(&e) => TIMEOUT_ERROR.to_string(),
+            SyncErrorKind::AuthFailed => format!("Login failed: {}. Ensure you're using an App Password (not your regular password). Generate one at myaccount.google.com/apppasswords", e),
+            _ => format!("Connection failed: {}", e),
+        };
+        SyncError::new(kind, message)
+    })?;
     
-    log!("Connected successfully");
+    debug!("Connected successfully");
+    Ok(session)
+}
+
+/// Connect and select INBOX, for replacing a session that dropped mid-sync.
+fn reconnect_inbox(
+    email: &str,
+    app_password: &str,
+    timeouts: ImapTimeouts,
+) -> Result<ImapSession, String> {
+    let mut session = connect_imap(email, app_password, timeouts)?;
+    session
+        .select("INBOX")
+        .map_err(|e| format!("Failed to select INBOX: {}", e))?;
     Ok(session)
 }
 
+/// `UID FETCH` with retries. A dropped connection is replaced in place so the caller
+/// resumes with the same chunk instead of restarting the sync.
+fn uid_fetch_with_retry(
+    session: &mut ImapSession,
+    email: &str,
+    app_password: &str,
+    timeouts: ImapTimeouts,
+    uids: &[u32],
+    query: &str,
+) -> Result<imap::types::ZeroCopy<Vec<imap::types::Fetch>>, String> {
+    let uid_list: Vec<String> = uids.iter().map(|uid| uid.to_string()).collect();
+    let sequence = uid_list.join(",");
+    let label = format!(
+        "Fetch {} for UIDs {}..{}",
+        query,
+        uids.first().copied().unwrap_or_default(),
+        uids.last().copied().unwrap_or_default()
+    );
+
+    let mut needs_reconnect = false;
+    let mut reconnect_error = None;
+    let result = with_retry(&label, || {
+        if needs_reconnect {
+            // connect_imap already retries on its own; stop here if it gives up.
+            *session = reconnect_inbox(email, app_password, timeouts).map_err(|e| {
+                reconnect_error = Some(e);
+                imap::Error::Bad("reconnect failed".to_string())
+            })?;
+        }
+        let result = session.uid_fetch(&sequence, query);
+        needs_reconnect = matches!(&result, Err(err) if is_transient(err));
+        result
+    });
+    if let Some(err) = reconnect_error {
+        return Err(err);
+    }
+    result.map_err(|e| {
+        if is_timeout(&e) {
+            TIMEOUT_ERROR.to_string()
+        } else {
+            format!("Fetch failed: {}", e)
+        }
+    })
+}
+
 // =============================================================================
 // Email Operations
 // =============================================================================
 
+/// A `get_credentials` failure as a `SyncError`: `CredentialsMissing` when there is no
+/// entry, otherwise `AuthFailed`.
+fn credentials_error(message: String) -> SyncError {
+    let err = SyncError::from(message);
+    if err.kind == SyncErrorKind::CredentialsMissing {
+        err
+    } else {
+        SyncError::new(SyncErrorKind::AuthFailed, err.message)
+    }
+}
+
 /// Fetch unread emails from Gmail inbox via IMAP
 /// This is much faster than OAuth-based approaches
-pub fn fetch_unread_emails(email: &str) -> Result<Vec<GmailEmail>, String> {
-    let app_password = get_credentials(email)?;
+pub fn fetch_unread_emails(email: &str, timeouts: ImapTimeouts) -> Result<Vec<GmailEmail>, SyncError> {
+    let app_password = get_credentials(email).map_err(credentials_error)?;
     
-    log!("Fetching unread emails for {}...", email);
+    info!("Fetching unread emails for {}...", email);
     let start = std::time::Instant::now();
     
-    let mut session = connect_imap(email, &app_password)?;
+    let mut session = connect_imap(email, &app_password, timeouts)?;
     
     // Select INBOX
     session.select("INBOX")
@@ -130,96 +507,78 @@ pub fn fetch_unread_emails(email: &str) -> Result<Vec<GmailEmail>, String> {
         .map_err(|e| format!("Search failed: {}", e))?;
     
     if uids.is_empty() {
-        log!("No unread emails found");
+        info!("No unread emails found");
         session.logout().ok();
         return Ok(vec![]);
     }
     
-    log!("Found {} unread emails, fetching headers...", uids.len());
+    info!("Found {} unread emails, fetching headers...", uids.len());
     
     // Build UID sequence for batch fetch
     let uid_list: Vec<String> = uids.iter().map(|u| u.to_string()).collect();
     let uid_sequence = uid_list.join(",");
     
     // Fetch headers for all unread messages in one request
-    let messages = session.uid_fetch(&uid_sequence, "(UID ENVELOPE)")
+    let messages = session.uid_fetch(&uid_sequence, "(UID ENVELOPE FLAGS RFC822.SIZE)")
         .map_err(|e| format!("Fetch failed: {}", e))?;
     
     let emails: Vec<GmailEmail> = messages
         .iter()
-        .filter_map(|msg| {
-            let uid = msg.uid?;
-            let envelope = msg.envelope()?;
-            
-            let subject = envelope.subject
-                .map(|s| decode_mime_header(s))
-                .unwrap_or_else(|| "(No Subject)".to_string());
-            
-            let sender = envelope.from
-                .as_ref()
-                .and_then(|addrs| addrs.first())
-                .map(|addr| {
-                    let mailbox = addr.mailbox
-                        .map(|m| String::from_utf8_lossy(m).to_string())
-                        .unwrap_or_default();
-                    let host = addr.host
-                        .map(|h| String::from_utf8_lossy(h).to_string())
-                        .unwrap_or_default();
-                    let email = if mailbox.is_empty() || host.is_empty() {
-                        String::new()
-                    } else {
-                        format!("{}@{}", mailbox, host)
-                    };
-                    let name = addr.name
-                        .map(|n| decode_mime_header(n))
-                        .unwrap_or_default();
-
-                    if !name.is_empty() && !email.is_empty() {
-                        format!("{} <{}>", name, email)
-                    } else if !email.is_empty() {
-                        email
-                    } else {
-                        "Unknown".to_string()
-                    }
-                })
-                .unwrap_or_else(|| "Unknown".to_string());
-            
-            let (date, date_epoch) = envelope
-                .date
-                .map(|d| {
-                    let date_str = String::from_utf8_lossy(d).to_string();
-                    let epoch = parse_imap_date_epoch(&date_str).unwrap_or(0);
-                    (date_str, epoch)
-                })
-                .unwrap_or_else(|| (String::new(), 0));
-            
-            let message_id = envelope.message_id
-                .map(|m| String::from_utf8_lossy(m).to_string())
-                .unwrap_or_default();
-            
-            Some(GmailEmail {
-                uid,
-                message_id,
-                subject,
-                sender,
-                date,
-                date_epoch,
-                is_read: false,
-            })
-        })
+        .filter_map(fetch_to_gmail_email)
         .collect();
     
     session.logout().ok();
     
-    log!("Fetched {} emails in {:?}", emails.len(), start.elapsed());
+    info!("Fetched {} emails in {:?}", emails.len(), start.elapsed());
     Ok(emails)
 }
 
+#[derive(Debug, Clone, Serialize)]
 pub struct GmailEmailBody {
     pub uid: u32,
     pub body: EmailBody,
 }
 
+/// How many bodies a sync downloads up front: every unread email plus `read_buffer` of
+/// the newest read ones, kept within `min..=max`.
+#[derive(Debug, Clone, Copy)]
+pub struct BodyPrefetch {
+    pub min: usize,
+    pub max: usize,
+    pub read_buffer: usize,
+}
+
+impl BodyPrefetch {
+    fn limit(&self, unread: usize) -> usize {
+        unread
+            .saturating_add(self.read_buffer)
+            .clamp(self.min, self.max.max(self.min))
+    }
+
+    /// Pick which of `uids` (ascending) get their body prefetched: the newest unread
+    /// first, then the newest read ones until the limit is reached.
+    fn select(&self, uids: &[u32], unread: &HashSet<u32>) -> HashSet<u32> {
+        let unread_count = uids.iter().filter(|uid| unread.contains(uid)).count();
+        let limit = self.limit(unread_count).min(uids.len());
+        let mut selected: HashSet<u32> = uids
+            .iter()
+            .rev()
+            .filter(|uid| unread.contains(uid))
+            .take(limit)
+            .copied()
+            .collect();
+        let remaining = limit - selected.len();
+        selected.extend(
+            uids.iter()
+                .rev()
+                .filter(|uid| !unread.contains(uid))
+                .take(remaining)
+                .copied(),
+        );
+        selected
+    }
+}
+
 pub struct GmailFetchChunk {
     pub emails: Vec<GmailEmail>,
     pub bodies: Vec<GmailEmailBody>,
@@ -227,144 +586,126 @@ pub struct GmailFetchChunk {
     pub total: usize,
 }
 
-/// Fetch emails since a UID from Gmail inbox via IMAP
-pub fn fetch_emails_since<F>(
+/// Fetch emails since a UID from Gmail inbox via IMAP. On a first sync
+/// (`since_uid == 0`), `initial_since` limits it to mail received on or after that epoch.
+/// `minimal_headers` skips the ENVELOPE for a cheaper fetch; see [`MINIMAL_HEADER_QUERY`].
+/// The ENVELOPE path spends an extra fetch per chunk on senders without a display name
+/// (see [`fill_sender_names`]); the minimal path reads `From` directly and needs none.
+/// `on_found` gets the number of emails to fetch once the UID search returns.
+/// `on_chunk` returning `Break` stops after that chunk; the returned max UID then only
+/// covers what was fetched, so the next sync resumes from there.
+#[allow(clippy::too_many_arguments)]
+pub fn fetch_emails_since<G, F>(
     email: &str,
     since_uid: u32,
+    initial_since: Option<i64>,
+    minimal_headers: bool,
     batch_size: usize,
-    body_prefetch_limit: usize,
+    body_prefetch: BodyPrefetch,
+    body_concurrency: usize,
+    timeouts: ImapTimeouts,
+    on_found: G,
     mut on_chunk: F,
-) -> Result<(usize, Option<u32>), String>
+) -> Result<(usize, Option<u32>), SyncError>
 where
-    F: FnMut(GmailFetchChunk),
+    G: FnOnce(usize),
+    F: FnMut(GmailFetchChunk) -> ControlFlow<()>,
 {
-    let app_password = get_credentials(email)?;
+    let app_password = get_credentials(email).map_err(credentials_error)?;
 
-    log!("Fetching emails for {} (since UID {})...", email, since_uid);
+    info!("Fetching emails for {} (since UID {})...", email, since_uid);
     let start = std::time::Instant::now();
 
-    let mut session = connect_imap(email, &app_password)?;
+    let mut session = connect_imap(email, &app_password, timeouts)?;
 
     session
         .select("INBOX")
         .map_err(|e| format!("Failed to select INBOX: {}", e))?;
 
-    let search_query = if since_uid > 0 {
-        format!("UID {}:*", since_uid + 1)
-    } else {
-        "ALL".to_string()
+    let search_query = match (since_uid, initial_since) {
+        (0, Some(since)) => format!("SINCE {}", imap_search_date(since)?),
+        (0, None) => "ALL".to_string(),
+        _ => format!("UID {}:*", since_uid + 1),
     };
 
     let mut uids: Vec<u32> = session
-        .uid_search(search_query)
+        .uid_search(&search_query)
         .map_err(|e| format!("Search failed: {}", e))?
         .into_iter()
         .collect();
     uids.sort_unstable();
+    on_found(uids.len());
+
+    // A date-limited first sync skips older mail, but the next incremental sync must
+    // still start after the newest UID in the mailbox, not just the newest one fetched.
+    let mut mailbox_max: Option<u32> = None;
+    if since_uid == 0 && initial_since.is_some() {
+        mailbox_max = session
+            .uid_search("UID *")
+            .map_err(|e| format!("Search failed: {}", e))?
+            .into_iter()
+            .max();
+    }
 
     if uids.is_empty() {
-        log!("No emails found");
+        info!("No emails found");
         session.logout().ok();
-        return Ok((0, None));
+        return Ok((0, mailbox_max));
     }
 
-    log!("Found {} emails, fetching headers...", uids.len());
+    info!("Found {} emails, fetching headers...", uids.len());
 
     let total = uids.len();
-    let body_limit = body_prefetch_limit.min(total);
-    let body_uids: HashSet<u32> = uids
-        .iter()
-        .rev()
-        .take(body_limit)
-        .copied()
-        .collect();
+    let unread: HashSet<u32> = if body_prefetch.max > 0 {
+        session
+            .uid_search(format!("{} UNSEEN", search_query))
+            .map_err(|e| format!("Search failed: {}", e))?
+    } else {
+        HashSet::new()
+    };
+    let body_uids = body_prefetch.select(&uids, &unread);
+    info!(
+        "Prefetching bodies for {} of {} emails ({} unread)",
+        body_uids.len(),
+        total,
+        unread.len()
+    );
+
+    let mut body_sessions = if !body_uids.is_empty() {
+        open_body_sessions(email, &app_password, body_concurrency, timeouts)
+    } else {
+        Vec::new()
+    };
 
     let mut processed = 0;
-
     let mut max_uid: Option<u32> = None;
+    let mut cancelled = false;
+    let (query, to_email): (&str, fn(&imap::types::Fetch) -> Option<GmailEmail>) = if minimal_headers {
+        (MINIMAL_HEADER_QUERY, minimal_fetch_to_gmail_email)
+    } else {
+        ("(UID ENVELOPE FLAGS RFC822.SIZE)", fetch_to_gmail_email)
+    };
 
     for chunk in uids.chunks(batch_size) {
-        log!(
+        debug!(
             "Fetching chunk {}/{} (batch size: {})",
             processed / batch_size + 1,
             (total + batch_size - 1) / batch_size,
             chunk.len()
         );
-        let uid_list: Vec<String> = chunk.iter().map(|u| u.to_string()).collect();
-        let uid_sequence = uid_list.join(",");
-
-        let messages = session
-            .uid_fetch(&uid_sequence, "(UID ENVELOPE FLAGS)")
-            .map_err(|e| format!("Fetch failed: {}", e))?;
-
-        let emails: Vec<GmailEmail> = messages
-            .iter()
-            .filter_map(|msg| {
-                let uid = msg.uid?;
-                let envelope = msg.envelope()?;
-
-                let subject = envelope
-                    .subject
-                    .map(|s| decode_mime_header(s))
-                    .unwrap_or_else(|| "(No Subject)".to_string());
-
-                let sender = envelope
-                    .from
-                    .as_ref()
-                    .and_then(|addrs| addrs.first())
-                    .map(|addr| {
-                        let mailbox = addr
-                            .mailbox
-                            .map(|m| String::from_utf8_lossy(m).to_string())
-                            .unwrap_or_default();
-                        let host = addr
-                            .host
-                            .map(|h| String::from_utf8_lossy(h).to_string())
-                            .unwrap_or_default();
-                        let email = if mailbox.is_empty() || host.is_empty() {
-                            String::new()
-                        } else {
-                            format!("{}@{}", mailbox, host)
-                        };
-                        let name = addr.name.map(|n| decode_mime_header(n)).unwrap_or_default();
-
-                        if !name.is_empty() && !email.is_empty() {
-                            format!("{} <{}>", name, email)
-                        } else if !email.is_empty() {
-                            email
-                        } else {
-                            "Unknown".to_string()
-                        }
-                    })
-                    .unwrap_or_else(|| "Unknown".to_string());
-
-                let (date, date_epoch) = envelope
-                    .date
-                    .map(|d| {
-                        let date_str = String::from_utf8_lossy(d).to_string();
-                        let epoch = parse_imap_date_epoch(&date_str).unwrap_or(0);
-                        (date_str, epoch)
-                    })
-                    .unwrap_or_else(|| (String::new(), 0));
-
-                let message_id = envelope
-                    .message_id
-                    .map(|m| String::from_utf8_lossy(m).to_string())
-                    .unwrap_or_default();
-
-                let is_read = msg.flags().iter().any(|flag| matches!(flag, Flag::Seen));
-
-                Some(GmailEmail {
-                    uid,
-                    message_id,
-                    subject,
-                    sender,
-                    date,
-                    date_epoch,
-                    is_read,
-                })
-            })
-            .collect();
+        let messages = uid_fetch_with_retry(
+            &mut session,
+            email,
+            &app_password,
+            timeouts,
+            chunk,
+            query,
+        )?;
+
+        let mut emails: Vec<GmailEmail> = messages.iter().filter_map(to_email).collect();
+        if !minimal_headers {
+            fill_sender_names(&mut session, email, &app_password, timeouts, &mut emails);
+        }
 
         let body_targets: Vec<u32> = chunk
             .iter()
@@ -374,186 +715,1496 @@ where
 
         let mut bodies = Vec::new();
         if !body_targets.is_empty() {
-            log!("Prefetching {} bodies in this chunk...", body_targets.len());
-            let body_uid_list: Vec<String> =
-                body_targets.iter().map(|uid| uid.to_string()).collect();
-            let body_sequence = body_uid_list.join(",");
-            let body_messages = session
-                .uid_fetch(&body_sequence, "BODY.PEEK[]")
-                .map_err(|e| format!("Fetch bodies failed: {}", e))?;
-
-            for message in body_messages.iter() {
-                let uid = match message.uid {
-                    Some(uid) => uid,
-                    None => continue,
-                };
-                let raw_body = match message.body() {
-                    Some(body) => body,
-                    None => continue,
-                };
-                let body = parse_email_body(raw_body)?;
-                bodies.push(GmailEmailBody { uid, body });
-            }
+            debug!(
+                "Prefetching {} bodies in this chunk ({} connection(s))...",
+                body_targets.len(),
+                body_sessions.len() + 1
+            );
+            bodies = fetch_bodies_parallel(
+                &mut session,
+                &mut body_sessions,
+                email,
+                &app_password,
+                timeouts,
+                &body_targets,
+            )?;
         }
 
         processed += chunk.len();
         if let Some(last) = chunk.last() {
             max_uid = Some(max_uid.map_or(*last, |current| current.max(*last)));
         }
-        log!("Processed {}/{} emails", processed, total);
-        on_chunk(GmailFetchChunk {
+        debug!("Processed {}/{} emails", processed, total);
+        let flow = on_chunk(GmailFetchChunk {
             emails,
             bodies,
             processed,
             total,
         });
+        if flow.is_break() {
+            cancelled = true;
+            break;
+        }
     }
 
     session.logout().ok();
-
-    log!("Fetched {} emails in {:?}", total, start.elapsed());
-    Ok((total, max_uid))
-}
-
-/// Mark emails as read using batch IMAP STORE command
-/// This is O(1) network request vs O(n) for individual updates
-pub fn mark_emails_as_read(email: &str, uids: Vec<u32>) -> Result<usize, String> {
-    if uids.is_empty() {
-        return Ok(0);
+    for mut extra in body_sessions {
+        extra.logout().ok();
     }
-    
-    let app_password = get_credentials(email)?;
-    
-    log!("Marking {} emails as read for {}...", uids.len(), email);
-    let start = std::time::Instant::now();
-    
-    let mut session = connect_imap(email, &app_password)?;
-    
-    session.select("INBOX")
-        .map_err(|e| format!("Failed to select INBOX: {}", e))?;
-    
-    // Build UID sequence for batch operation
-    let uid_list: Vec<String> = uids.iter().map(|u| u.to_string()).collect();
-    let uid_sequence = uid_list.join(",");
-    
-    // Single STORE command to mark all as read
-    session.uid_store(&uid_sequence, "+FLAGS (\\Seen)")
-        .map_err(|e| format!("Failed to mark as read: {}", e))?;
-    
-    session.logout().ok();
-    
-    let count = uids.len();
-    log!("Marked {} emails as read in {:?}", count, start.elapsed());
-    Ok(count)
-}
 
-/// Mark emails as unread using batch IMAP STORE command
-pub fn mark_emails_as_unread(email: &str, uids: Vec<u32>) -> Result<usize, String> {
-    if uids.is_empty() {
-        return Ok(0);
+    if cancelled {
+        info!("Sync cancelled after {}/{} emails", processed, total);
+        return Ok((processed, max_uid));
     }
+    info!("Fetched {} emails in {:?}", total, start.elapsed());
+    Ok((total, max_uid.max(mailbox_max)))
+}
 
-    let app_password = get_credentials(email)?;
+/// Fetch headers for INBOX mail received in a date range, `batch_size` UIDs per
+/// request, handing each chunk (with no bodies) to `on_chunk`. IMAP date search has
+/// no time of day, so the range is widened to whole UTC days; see [`range_search_query`].
+pub fn fetch_range<F>(
+    email: &str,
+    since_epoch: i64,
+    before_epoch: i64,
+    batch_size: usize,
+    mut on_chunk: F,
+) -> Result<usize, SyncError>
+where
+    F: FnMut(GmailFetchChunk),
+{
+    let query = range_search_query(since_epoch, before_epoch)?;
+    let app_password = get_credentials(email).map_err(credentials_error)?;
+    let timeouts = ImapTimeouts::default();
 
-    log!("Marking {} emails as unread for {}...", uids.len(), email);
+    info!("Fetching {} emails with {}...", email, query);
     let start = std::time::Instant::now();
 
-    let mut session = connect_imap(email, &app_password)?;
-
+    let mut session = connect_imap(email, &app_password, timeouts)?;
     session
         .select("INBOX")
         .map_err(|e| format!("Failed to select INBOX: {}", e))?;
 
-    let uid_list: Vec<String> = uids.iter().map(|u| u.to_string()).collect();
-    let uid_sequence = uid_list.join(",");
-
-    session
-        .uid_store(&uid_sequence, "-FLAGS (\\Seen)")
-        .map_err(|e| format!("Failed to mark as unread: {}", e))?;
+    let mut uids: Vec<u32> = session
+        .uid_search(&query)
+        .map_err(|e| format!("Search failed: {}", e))?
+        .into_iter()
+        .collect();
+    uids.sort_unstable();
 
-    session.logout().ok();
+    let total = uids.len();
+    let mut processed = 0;
+    for chunk in uids.chunks(batch_size.max(1)) {
+        let messages = uid_fetch_with_retry(
+            &mut session,
+            email,
+            &app_password,
+            timeouts,
+            chunk,
+            "(UID ENVELOPE FLAGS RFC822.SIZE)",
+        )?;
+        let mut emails: Vec<GmailEmail> = messages.iter().filter_map(fetch_to_gmail_email).collect();
+        fill_sender_names(&mut session, email, &app_password, timeouts, &mut emails);
 
-    let count = uids.len();
-    log!("Marked {} emails as unread in {:?}", count, start.elapsed());
-    Ok(count)
-}
+        processed += chunk.len();
+        debug!("Fetched {}/{} emails in range", processed, total);
+        on_chunk(GmailFetchChunk {
+            emails,
+            bodies: Vec::new(),
+            processed,
+            total,
+        });
+    }
 
-/// Test connection with provided credentials (without storing)
-pub fn test_connection(email: &str, app_password: &str) -> Result<String, String> {
-    log!("Testing connection for {}...", email);
-    
-    let mut session = connect_imap(email, app_password)?;
-    
-    // Get mailbox info
-    let mailbox = session.select("INBOX")
-        .map_err(|e| format!("Failed to select INBOX: {}", e))?;
-    
-    let message_count = mailbox.exists;
-    
     session.logout().ok();
-    
-    Ok(format!("Connection successful! Inbox has {} messages.", message_count))
+    info!("Fetched {} emails in range in {:?}", total, start.elapsed());
+    Ok(total)
 }
 
-// =============================================================================
-// Helpers
-// =============================================================================
+/// Fetch headers of up to `limit` INBOX messages older than `before_uid`, newest first,
+/// `batch_size` UIDs per request. Every chunk holds lower UIDs than the one before it,
+/// so the caller can lower its low-water mark as each chunk is stored without leaving
+/// a gap. Stops early when `on_chunk` breaks. Returns how many emails were fetched.
+pub fn fetch_emails_before<F>(
+    email: &str,
+    before_uid: u32,
+    limit: usize,
+    batch_size: usize,
+    mut on_chunk: F,
+) -> Result<usize, SyncError>
+where
+    F: FnMut(GmailFetchChunk) -> ControlFlow<()>,
+{
+    let Some(query) = older_uid_query(before_uid) else {
+        return Ok(0);
+    };
+    let app_password = get_credentials(email).map_err(credentials_error)?;
+    let timeouts = ImapTimeouts::default();
 
-/// Decode MIME encoded header (basic implementation)
-fn decode_mime_header(bytes: &[u8]) -> String {
-    let input = String::from_utf8_lossy(bytes).to_string();
-    decode_rfc2047_words(&input)
-}
+    info!("Fetching up to {} emails below UID {} for {}...", limit, before_uid, email);
+    let start = std::time::Instant::now();
 
-fn decode_rfc2047_words(input: &str) -> String {
-    let mut output = String::new();
-    let mut index = 0;
+    let mut session = connect_imap(email, &app_password, timeouts)?;
+    session
+        .select("INBOX")
+        .map_err(|e| format!("Failed to select INBOX: {}", e))?;
 
-    while let Some(start_rel) = input[index..].find("=?") {
-        let start = index + start_rel;
-        output.push_str(&input[index..start]);
+    let found = session
+        .uid_search(&query)
+        .map_err(|e| format!("Search failed: {}", e))?;
+    let uids = older_uids_newest_first(found, before_uid, limit);
 
-        let rest = &input[start + 2..];
-        let Some(q1) = rest.find('?') else {
-            output.push_str("=?");
-            index = start + 2;
-            continue;
-        };
-        let charset = &rest[..q1];
-        let rest = &rest[q1 + 1..];
-        let Some(q2) = rest.find('?') else {
-            output.push_str("=?");
-            index = start + 2;
-            continue;
-        };
-        let encoding = &rest[..q2];
-        let rest = &rest[q2 + 1..];
-        let Some(q3) = rest.find("?=") else {
-            output.push_str("=?");
-            index = start + 2;
-            continue;
-        };
-        let encoded = &rest[..q3];
+    let total = uids.len();
+    let mut processed = 0;
+    for chunk in uids.chunks(batch_size.max(1)) {
+        let messages = uid_fetch_with_retry(
+            &mut session,
+            email,
+            &app_password,
+            timeouts,
+            chunk,
+            "(UID ENVELOPE FLAGS RFC822.SIZE)",
+        )?;
+        let mut emails: Vec<GmailEmail> = messages.iter().filter_map(fetch_to_gmail_email).collect();
+        fill_sender_names(&mut session, email, &app_password, timeouts, &mut emails);
 
-        let decoded = decode_encoded_word(charset, encoding, encoded);
-        output.push_str(&decoded);
-        index = start + 2 + q1 + 1 + q2 + 1 + q3 + 2;
+        processed += chunk.len();
+        debug!("Fetched {}/{} older emails", processed, total);
+        let flow = on_chunk(GmailFetchChunk {
+            emails,
+            bodies: Vec::new(),
+            processed,
+            total,
+        });
+        if flow.is_break() {
+            break;
+        }
     }
 
-    output.push_str(&input[index..]);
-    output
+    session.logout().ok();
+    info!("Fetched {} older emails in {:?}", processed, start.elapsed());
+    Ok(processed)
 }
 
-fn decode_encoded_word(charset: &str, encoding: &str, encoded: &str) -> String {
-    let bytes = match encoding.to_ascii_lowercase().as_str() {
-        "q" => decode_q(encoded),
+/// Gmail's inbox tabs, as accepted by `category:` in Gmail search.
+pub const GMAIL_CATEGORIES: &[&str] = &["primary", "social", "promotions", "updates", "forums"];
+
+/// Fetch headers for every INBOX message in a Gmail category tab (e.g. `promotions`),
+/// `batch_size` UIDs per request. Tabs aren't IMAP folders, so this relies on Gmail's
+/// `X-GM-RAW` search extension and fails clearly on servers without `X-GM-EXT-1`.
+/// Returns the UIDs currently in the category.
+pub fn fetch_category<F>(
+    email: &str,
+    category: &str,
+    batch_size: usize,
+    mut on_chunk: F,
+) -> Result<Vec<u32>, SyncError>
+where
+    F: FnMut(GmailFetchChunk),
+{
+    let query = category_search_query(category)?;
+    let app_password = get_credentials(email).map_err(credentials_error)?;
+    let timeouts = ImapTimeouts::default();
+
+    let mut session = connect_imap(email, &app_password, timeouts)?;
+    let is_gmail = session
+        .capabilities()
+        .map(|caps| caps.has_str("X-GM-EXT-1"))
+        .unwrap_or(false);
+    if !is_gmail {
+        session.logout().ok();
+        return Err(SyncError::from(
+            "Category tabs need Gmail's IMAP extensions (X-GM-EXT-1), which this server doesn't offer"
+                .to_string(),
+        ));
+    }
+    session
+        .select("INBOX")
+        .map_err(|e| format!("Failed to select INBOX: {}", e))?;
+
+    info!("Fetching {} emails with {}...", email, query);
+    let start = std::time::Instant::now();
+    let mut uids: Vec<u32> = session
+        .uid_search(&query)
+        .map_err(|e| format!("Search failed: {}", e))?
+        .into_iter()
+        .collect();
+    uids.sort_unstable();
+
+    let total = uids.len();
+    let mut processed = 0;
+    for chunk in uids.chunks(batch_size.max(1)) {
+        let messages = uid_fetch_with_retry(
+            &mut session,
+            email,
+            &app_password,
+            timeouts,
+            chunk,
+            "(UID ENVELOPE FLAGS RFC822.SIZE)",
+        )?;
+        let mut emails: Vec<GmailEmail> = messages.iter().filter_map(fetch_to_gmail_email).collect();
+        fill_sender_names(&mut session, email, &app_password, timeouts, &mut emails);
+
+        processed += chunk.len();
+        debug!("Fetched {}/{} {} emails", processed, total, category);
+        on_chunk(GmailFetchChunk {
+            emails,
+            bodies: Vec::new(),
+            processed,
+            total,
+        });
+    }
+
+    session.logout().ok();
+    info!("Fetched {} {} emails in {:?}", total, category, start.elapsed());
+    Ok(uids)
+}
+
+/// `X-GM-RAW "category:<name>"` for one of [`GMAIL_CATEGORIES`].
+fn category_search_query(category: &str) -> Result<String, String> {
+    let category = category.trim().to_ascii_lowercase();
+    if !GMAIL_CATEGORIES.contains(&category.as_str()) {
+        return Err(format!(
+            "Unknown Gmail category '{}'; expected one of {}",
+            category,
+            GMAIL_CATEGORIES.join(", ")
+        ));
+    }
+    Ok(format!("X-GM-RAW {}", imap_quote(&format!("category:{}", category))))
+}
+
+/// `UID 1:n` covering every UID below `before_uid`, or `None` when there are none.
+fn older_uid_query(before_uid: u32) -> Option<String> {
+    (before_uid > 1).then(|| format!("UID 1:{}", before_uid - 1))
+}
+
+/// The `limit` highest UIDs below `before_uid`, highest first. Anything at or above
+/// `before_uid` is dropped in case the server answers a range with a neighbour.
+fn older_uids_newest_first(found: impl IntoIterator<Item = u32>, before_uid: u32, limit: usize) -> Vec<u32> {
+    let mut uids: Vec<u32> = found.into_iter().filter(|uid| *uid < before_uid).collect();
+    uids.sort_unstable_by(|a, b| b.cmp(a));
+    uids.truncate(limit);
+    uids
+}
+
+/// `SINCE a BEFORE b` for a date range. SINCE is inclusive of its day and BEFORE
+/// exclusive of its day, so `since_epoch` rounds down to the start of its UTC day and
+/// `before_epoch` rounds up to the next UTC midnight (unless it already is one). The
+/// search therefore covers every whole day the range touches.
+fn range_search_query(since_epoch: i64, before_epoch: i64) -> Result<String, String> {
+    const DAY: i64 = 24 * 60 * 60;
+    if before_epoch <= since_epoch {
+        return Err("The end of the range must be after its start".to_string());
+    }
+    let since_day = since_epoch.div_euclid(DAY) * DAY;
+    let before_day = (before_epoch + DAY - 1).div_euclid(DAY) * DAY;
+    Ok(format!(
+        "SINCE {} BEFORE {}",
+        imap_search_date(since_day)?,
+        imap_search_date(before_day)?
+    ))
+}
+
+/// Messages whose flags are re-read when there is no MODSEQ to sync from.
+const FLAG_FALLBACK_LIMIT: u32 = 5000;
+
+/// Current read/flagged/answered state of one INBOX message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FlagUpdate {
+    pub uid: u32,
+    pub is_read: bool,
+    pub is_flagged: bool,
+    pub is_answered: bool,
+}
+
+pub struct FlagSyncResult {
+    pub updates: Vec<FlagUpdate>,
+    /// INBOX's HIGHESTMODSEQ to pass as `modseq` next time; `None` without CONDSTORE.
+    pub highest_modseq: Option<u64>,
+}
+
+/// Pull flag changes made elsewhere (e.g. read in Gmail's web UI). With CONDSTORE and
+/// a `modseq` from the last run, only messages changed since then are fetched
+/// (`UID FETCH 1:* (UID FLAGS) (CHANGEDSINCE modseq)`). Otherwise the flags of the
+/// newest `FLAG_FALLBACK_LIMIT` messages are fetched.
+pub fn sync_flag_changes(email: &str, modseq: Option<u64>) -> Result<FlagSyncResult, SyncError> {
+    let app_password = get_credentials(email).map_err(credentials_error)?;
+    let mut session = connect_imap(email, &app_password, ImapTimeouts::default())?;
+
+    let condstore = session
+        .capabilities()
+        .map(|caps| caps.has_str("CONDSTORE"))
+        .unwrap_or(false);
+    let (exists, highest_modseq) = if condstore {
+        // `Session::select` quotes its argument, so the CONDSTORE parameter needs a raw command.
+        let response = session
+            .run_command_and_read_response("SELECT INBOX (CONDSTORE)")
+            .map_err(|e| format!("Failed to select INBOX: {}", e))?;
+        parse_select_status(&response)
+    } else {
+        let mailbox = session
+            .select("INBOX")
+            .map_err(|e| format!("Failed to select INBOX: {}", e))?;
+        (mailbox.exists, None)
+    };
+
+    let messages = match (modseq, highest_modseq) {
+        (Some(since), Some(_)) => {
+            debug!("Fetching flags changed since MODSEQ {} for {}", since, email);
+            session
+                .uid_fetch("1:*", format!("(UID FLAGS) (CHANGEDSINCE {})", since))
+                .map_err(|e| format!("Failed to fetch changed flags: {}", e))?
+        }
+        _ if exists == 0 => {
+            session.logout().ok();
+            return Ok(FlagSyncResult {
+                updates: Vec::new(),
+                highest_modseq,
+            });
+        }
+        _ => {
+            let first = exists.saturating_sub(FLAG_FALLBACK_LIMIT) + 1;
+            debug!("Fetching flags of messages {}:* for {}", first, email);
+            session
+                .fetch(format!("{}:*", first), "(UID FLAGS)")
+                .map_err(|e| format!("Failed to fetch flags: {}", e))?
+        }
+    };
+
+    let updates: Vec<FlagUpdate> = messages
+        .iter()
+        .filter_map(|msg| {
+            Some(FlagUpdate {
+                uid: msg.uid?,
+                is_read: msg.flags().iter().any(|flag| matches!(flag, Flag::Seen)),
+                is_flagged: msg.flags().iter().any(|flag| matches!(flag, Flag::Flagged)),
+                is_answered: msg.flags().iter().any(|flag| matches!(flag, Flag::Answered)),
+            })
+        })
+        .collect();
+    session.logout().ok();
+
+    info!("Fetched flags of {} emails for {}", updates.len(), email);
+    Ok(FlagSyncResult {
+        updates,
+        highest_modseq,
+    })
+}
+
+/// Cached UIDs checked per `UID SEARCH`, so a huge mailbox never needs one giant response.
+const RECONCILE_CHUNK_SIZE: usize = 5000;
+
+/// Which of `cached_uids` no longer exist in `mailbox`, e.g. because they were deleted
+/// from another client. The server is searched over the UID range of each chunk of
+/// cached UIDs rather than with one `UID SEARCH ALL`.
+pub fn reconcile_deletions(email: &str, mailbox: &str, cached_uids: &[u32]) -> Result<Vec<u32>, SyncError> {
+    if cached_uids.is_empty() {
+        return Ok(Vec::new());
+    }
+    let app_password = get_credentials(email).map_err(credentials_error)?;
+    let mut session = connect_imap(email, &app_password, ImapTimeouts::default())?;
+    session
+        .select(mailbox)
+        .map_err(|e| format!("Failed to select {}: {}", mailbox, e))?;
+
+    let mut sorted = cached_uids.to_vec();
+    sorted.sort_unstable();
+    sorted.dedup();
+    let mut gone = Vec::new();
+    for chunk in sorted.chunks(RECONCILE_CHUNK_SIZE) {
+        let (first, last) = (chunk[0], chunk[chunk.len() - 1]);
+        let present = session
+            .uid_search(format!("UID {}:{}", first, last))
+            .map_err(|e| format!("Failed to search UIDs {}:{}: {}", first, last, e))?;
+        gone.extend(chunk.iter().copied().filter(|uid| !present.contains(uid)));
+    }
+    session.logout().ok();
+
+    info!(
+        "{} of {} cached {} emails are gone from the server for {}",
+        gone.len(),
+        sorted.len(),
+        mailbox,
+        email
+    );
+    Ok(gone)
+}
+
+/// `EXISTS` count and `HIGHESTMODSEQ` from a raw `SELECT ... (CONDSTORE)` response.
+/// A `NOMODSEQ` mailbox yields `None` for the latter.
+fn parse_select_status(response: &[u8]) -> (u32, Option<u64>) {
+    let text = String::from_utf8_lossy(response);
+    let mut exists = 0;
+    let mut highest_modseq = None;
+    for line in text.lines() {
+        let mut words = line.trim_start_matches("* ").split_whitespace();
+        match (words.next(), words.next()) {
+            (Some(count), Some("EXISTS")) => exists = count.parse().unwrap_or(0),
+            (Some("OK"), Some("[HIGHESTMODSEQ")) => {
+                highest_modseq = words
+                    .next()
+                    .and_then(|value| value.trim_end_matches(']').parse().ok());
+            }
+            _ => {}
+        }
+    }
+    (exists, highest_modseq)
+}
+
+/// Fetch bodies for already-cached emails, `batch_size` UIDs per request. Each batch
+/// is handed to `on_chunk` (with no envelopes) so it can be stored as it arrives.
+pub fn backfill_bodies<F>(
+    email: &str,
+    uids: &[u32],
+    batch_size: usize,
+    mut on_chunk: F,
+) -> Result<usize, String>
+where
+    F: FnMut(GmailFetchChunk),
+{
+    if uids.is_empty() {
+        return Ok(0);
+    }
+
+    let app_password = get_credentials(email)?;
+    let timeouts = ImapTimeouts::default();
+
+    info!("Backfilling {} bodies for {}...", uids.len(), email);
+    let start = std::time::Instant::now();
+
+    let mut session = connect_imap(email, &app_password, timeouts)?;
+    session
+        .select("INBOX")
+        .map_err(|e| format!("Failed to select INBOX: {}", e))?;
+
+    let total = uids.len();
+    let mut processed = 0;
+    let mut fetched = 0;
+    for chunk in uids.chunks(batch_size.max(1)) {
+        let bodies = fetch_bodies(&mut session, email, &app_password, timeouts, chunk)?;
+        processed += chunk.len();
+        fetched += bodies.len();
+        debug!("Backfilled {}/{} bodies", processed, total);
+        on_chunk(GmailFetchChunk {
+            emails: Vec::new(),
+            bodies,
+            processed,
+            total,
+        });
+    }
+
+    session.logout().ok();
+    info!("Backfilled {} bodies in {:?}", fetched, start.elapsed());
+    Ok(fetched)
+}
+
+/// `(uid, RFC822.SIZE)` for already-cached emails synced before sizes were stored.
+pub fn fetch_sizes(email: &str, uids: &[u32]) -> Result<Vec<(u32, u32)>, String> {
+    if uids.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let app_password = get_credentials(email)?;
+    let timeouts = ImapTimeouts::default();
+    let mut session = connect_imap(email, &app_password, timeouts)?;
+    session
+        .select("INBOX")
+        .map_err(|e| format!("Failed to select INBOX: {}", e))?;
+
+    let mut sizes = Vec::with_capacity(uids.len());
+    for chunk in uids.chunks(1000) {
+        let messages =
+            uid_fetch_with_retry(&mut session, email, &app_password, timeouts, chunk, "(UID RFC822.SIZE)")?;
+        sizes.extend(
+            messages
+                .iter()
+                .filter_map(|msg| Some((msg.uid?, msg.size?))),
+        );
+    }
+
+    session.logout().ok();
+    info!("Fetched sizes for {} emails", sizes.len());
+    Ok(sizes)
+}
+
+/// Open up to `concurrency - 1` extra sessions for body prefetch (the main session
+/// makes up the rest). Connections that fail to open are skipped, so this degrades
+/// to serial fetching on the main session.
+fn open_body_sessions(
+    email: &str,
+    app_password: &str,
+    concurrency: usize,
+    timeouts: ImapTimeouts,
+) -> Vec<ImapSession> {
+    let extra = concurrency.clamp(1, MAX_BODY_CONCURRENCY) - 1;
+    let mut sessions = Vec::with_capacity(extra);
+    for _ in 0..extra {
+        match reconnect_inbox(email, app_password, timeouts) {
+            Ok(session) => sessions.push(session),
+            Err(err) => {
+                warn!("Could not open extra body connection, continuing with {}: {}", sessions.len() + 1, err);
+                break;
+            }
+        }
+    }
+    sessions
+}
+
+/// Fetch bodies for `uids`, splitting them across the main session and any extra
+/// sessions. A batch that fails on an extra session is retried on the main one and
+/// that session is dropped for the rest of the sync.
+fn fetch_bodies_parallel(
+    session: &mut ImapSession,
+    extra_sessions: &mut Vec<ImapSession>,
+    email: &str,
+    app_password: &str,
+    timeouts: ImapTimeouts,
+    uids: &[u32],
+) -> Result<Vec<GmailEmailBody>, String> {
+    if extra_sessions.is_empty() || uids.len() < 2 {
+        return fetch_bodies(session, email, app_password, timeouts, uids);
+    }
+
+    let workers = (extra_sessions.len() + 1).min(uids.len());
+    let per_worker = uids.len().div_ceil(workers);
+    let mut parts = uids.chunks(per_worker);
+    let own_part = parts.next().unwrap_or_default();
+
+    let (own_result, extra_results) = std::thread::scope(|scope| {
+        let handles: Vec<_> = extra_sessions
+            .iter_mut()
+            .zip(parts)
+            .map(|(extra, part)| (part, scope.spawn(move || fetch_bodies(extra, email, app_password, timeouts, part))))
+            .collect();
+        let own_result = fetch_bodies(session, email, app_password, timeouts, own_part);
+        let extra_results: Vec<_> = handles
+            .into_iter()
+            .map(|(part, handle)| {
+                let result = handle
+                    .join()
+                    .unwrap_or_else(|_| Err("Body fetch thread panicked".to_string()));
+                (part, result)
+            })
+            .collect();
+        (own_result, extra_results)
+    });
+
+    let mut bodies = own_result?;
+    let mut failed = Vec::new();
+    for (index, (part, result)) in extra_results.into_iter().enumerate() {
+        match result {
+            Ok(part_bodies) => bodies.extend(part_bodies),
+            Err(err) => {
+                warn!("Parallel body fetch failed, retrying serially: {}", err);
+                bodies.extend(fetch_bodies(session, email, app_password, timeouts, part)?);
+                failed.push(index);
+            }
+        }
+    }
+    for index in failed.into_iter().rev() {
+        extra_sessions.remove(index);
+    }
+    Ok(bodies)
+}
+
+fn fetch_bodies(
+    session: &mut ImapSession,
+    email: &str,
+    app_password: &str,
+    timeouts: ImapTimeouts,
+    uids: &[u32],
+) -> Result<Vec<GmailEmailBody>, String> {
+    if uids.is_empty() {
+        return Ok(Vec::new());
+    }
+    let body_messages = uid_fetch_with_retry(session, email, app_password, timeouts, uids, "BODY.PEEK[]")
+        .map_err(|e| format!("Fetch bodies failed: {}", e))?;
+
+    let mut bodies = Vec::new();
+    for message in body_messages.iter() {
+        let uid = match message.uid {
+            Some(uid) => uid,
+            None => continue,
+        };
+        let raw_body = match message.body() {
+            Some(body) => body,
+            None => continue,
+        };
+        let body = parse_email_body(raw_body)?;
+        bodies.push(GmailEmailBody { uid, body });
+    }
+    Ok(bodies)
+}
+
+/// Map a `(UID ENVELOPE FLAGS RFC822.SIZE)` fetch response into a `GmailEmail`.
+fn fetch_to_gmail_email(msg: &imap::types::Fetch) -> Option<GmailEmail> {
+    let mut email = envelope_to_gmail_email(msg.uid?, msg.envelope()?, msg.flags());
+    email.size_bytes = msg.size;
+    Some(email)
+}
+
+/// The one place an IMAP envelope becomes a `GmailEmail`; `is_read`/`is_flagged`
+/// come from `flags`. Leaves `size_bytes` unset.
+fn envelope_to_gmail_email(
+    uid: u32,
+    envelope: &imap_proto::types::Envelope,
+    flags: &[Flag],
+) -> GmailEmail {
+    let subject = envelope
+        .subject
+        .map(|s| decode_mime_header(s))
+        .unwrap_or_else(|| "(No Subject)".to_string());
+
+    let sender = envelope
+        .from
+        .as_ref()
+        .and_then(|addrs| addrs.first())
+        .map(|addr| {
+            let mailbox = addr
+                .mailbox
+                .map(|m| String::from_utf8_lossy(m).to_string())
+                .unwrap_or_default();
+            let host = addr
+                .host
+                .map(|h| String::from_utf8_lossy(h).to_string())
+                .unwrap_or_default();
+            let email = if mailbox.is_empty() || host.is_empty() {
+                String::new()
+            } else {
+                format!("{}@{}", mailbox, host)
+            };
+            let name = addr.name.map(|n| decode_mime_header(n)).unwrap_or_default();
+
+            if !name.is_empty() && !email.is_empty() {
+                format!("{} <{}>", name, email)
+            } else if !email.is_empty() {
+                email
+            } else {
+                "Unknown".to_string()
+            }
+        })
+        .unwrap_or_else(|| "Unknown".to_string());
+
+    let (date, date_epoch) = envelope
+        .date
+        .map(|d| {
+            let date_str = String::from_utf8_lossy(d).to_string();
+            let epoch = parse_imap_date_epoch(&date_str).unwrap_or(0);
+            (date_str, epoch)
+        })
+        .unwrap_or_else(|| (String::new(), 0));
+
+    let message_id = envelope
+        .message_id
+        .map(|m| String::from_utf8_lossy(m).to_string())
+        .unwrap_or_default();
+
+    let is_read = flags.iter().any(|flag| matches!(flag, Flag::Seen));
+    let is_flagged = flags.iter().any(|flag| matches!(flag, Flag::Flagged));
+    let is_answered = flags.iter().any(|flag| matches!(flag, Flag::Answered));
+
+    let to = format_address_list(envelope.to.as_deref());
+    let cc = format_address_list(envelope.cc.as_deref());
+
+    GmailEmail {
+        uid,
+        message_id,
+        subject,
+        sender,
+        date,
+        date_epoch,
+        is_read,
+        is_flagged,
+        is_answered,
+        size_bytes: None,
+        to,
+        cc,
+    }
+}
+
+/// Lighter alternative to fetching the ENVELOPE: only the headers the list view needs,
+/// with INTERNALDATE standing in for the Date header. Recipients are left empty.
+const MINIMAL_HEADER_QUERY: &str =
+    "(UID FLAGS INTERNALDATE RFC822.SIZE BODY.PEEK[HEADER.FIELDS (SUBJECT FROM MESSAGE-ID)])";
+
+/// Map a [`MINIMAL_HEADER_QUERY`] fetch response into a `GmailEmail`.
+fn minimal_fetch_to_gmail_email(msg: &imap::types::Fetch) -> Option<GmailEmail> {
+    let uid = msg.uid?;
+    let (subject, sender, message_id) = parse_minimal_headers(msg.header().unwrap_or_default());
+    let (date, date_epoch) = msg
+        .internal_date()
+        .map(|d| (d.to_rfc2822(), d.timestamp()))
+        .unwrap_or_else(|| (String::new(), 0));
+
+    Some(GmailEmail {
+        uid,
+        message_id,
+        subject,
+        sender,
+        date,
+        date_epoch,
+        is_read: msg.flags().iter().any(|flag| matches!(flag, Flag::Seen)),
+        is_flagged: msg.flags().iter().any(|flag| matches!(flag, Flag::Flagged)),
+        is_answered: msg.flags().iter().any(|flag| matches!(flag, Flag::Answered)),
+        size_bytes: msg.size,
+        to: None,
+        cc: None,
+    })
+}
+
+/// `(subject, sender, message_id)` from a `HEADER.FIELDS` block, formatted the same way
+/// as the envelope path so both sync modes store identical values.
+fn parse_minimal_headers(raw: &[u8]) -> (String, String, String) {
+    let parsed = MessageParser::default().parse_headers(raw);
+    let subject = parsed
+        .as_ref()
+        .and_then(|m| m.subject())
+        .map(str::to_string)
+        .unwrap_or_else(|| "(No Subject)".to_string());
+    let sender = parsed
+        .as_ref()
+        .and_then(|m| m.from())
+        .and_then(|from| from.first())
+        .map(format_parsed_sender)
+        .unwrap_or_else(|| "Unknown".to_string());
+    // The envelope keeps the angle brackets; mail-parser strips them.
+    let message_id = parsed
+        .as_ref()
+        .and_then(|m| m.message_id())
+        .map(|id| format!("<{}>", id))
+        .unwrap_or_default();
+    (subject, sender, message_id)
+}
+
+/// `"Name <email>"`, the bare address, or `"Unknown"`, as the envelope path formats senders.
+fn format_parsed_sender(addr: &mail_parser::Addr) -> String {
+    let name = addr.name().unwrap_or_default();
+    let email = addr.address().unwrap_or_default();
+    if !name.is_empty() && !email.is_empty() {
+        format!("{} <{}>", name, email)
+    } else if !email.is_empty() {
+        email.to_string()
+    } else {
+        "Unknown".to_string()
+    }
+}
+
+/// Formatted sender from a raw `From` header block.
+fn parse_from_header(raw: &[u8]) -> Option<String> {
+    let parsed = MessageParser::default().parse_headers(raw)?;
+    parsed
+        .from()
+        .and_then(|from| from.first())
+        .map(format_parsed_sender)
+}
+
+/// The envelope only carries a quoted display name, so senders like
+/// `alerts@service.com (Service Alerts)` arrive as a bare address. Re-read the `From`
+/// header for just those emails, where mail-parser takes the comment as the name.
+/// Best effort: on failure the bare addresses are kept.
+fn fill_sender_names(
+    session: &mut ImapSession,
+    email: &str,
+    app_password: &str,
+    timeouts: ImapTimeouts,
+    emails: &mut [GmailEmail],
+) {
+    let nameless: Vec<u32> = emails
+        .iter()
+        .filter(|e| !e.sender.contains('<') && e.sender != "Unknown")
+        .map(|e| e.uid)
+        .collect();
+    if nameless.is_empty() {
+        return;
+    }
+
+    let messages = match uid_fetch_with_retry(
+        session,
+        email,
+        app_password,
+        timeouts,
+        &nameless,
+        "(UID BODY.PEEK[HEADER.FIELDS (FROM)])",
+    ) {
+        Ok(messages) => messages,
+        Err(err) => {
+            warn!("Could not fetch From headers for {} senders: {}", nameless.len(), err);
+            return;
+        }
+    };
+    let mut names: HashMap<u32, String> = messages
+        .iter()
+        .filter_map(|msg| {
+            let sender = parse_from_header(msg.header()?)?;
+            sender.contains('<').then_some((msg.uid?, sender))
+        })
+        .collect();
+    debug!("Recovered {}/{} sender names from From headers", names.len(), nameless.len());
+    for e in emails.iter_mut() {
+        if let Some(sender) = names.remove(&e.uid) {
+            e.sender = sender;
+        }
+    }
+}
+
+/// UIDs per STORE command; Gmail rejects command lines that grow too long.
+const STORE_CHUNK_SIZE: usize = 500;
+
+/// Comma-separated UID sets of at most `chunk_size` UIDs each, one per STORE.
+fn uid_store_sequences(uids: &[u32], chunk_size: usize) -> Vec<String> {
+    uids.chunks(chunk_size.max(1))
+        .map(|chunk| {
+            chunk
+                .iter()
+                .map(|uid| uid.to_string())
+                .collect::<Vec<_>>()
+                .join(",")
+        })
+        .collect()
+}
+
+/// Mark emails as read with batched IMAP STORE commands
+/// (one per `STORE_CHUNK_SIZE` UIDs rather than one per email)
+pub fn mark_emails_as_read(email: &str, uids: Vec<u32>) -> Result<usize, String> {
+    if uids.is_empty() {
+        return Ok(0);
+    }
+    
+    let app_password = get_credentials(email)?;
+    
+    info!("Marking {} emails as read for {}...", uids.len(), email);
+    let start = std::time::Instant::now();
+    
+    let timeouts = ImapTimeouts::default();
+    let mut session = connect_imap(email, &app_password, timeouts)?;
+    
+    session.select("INBOX")
+        .map_err(|e| format!("Failed to select INBOX: {}", e))?;
+    
+    let mut count = 0;
+    for (chunk, uid_sequence) in uids
+        .chunks(STORE_CHUNK_SIZE)
+        .zip(uid_store_sequences(&uids, STORE_CHUNK_SIZE))
+    {
+        if let Err(err) = session.uid_store(&uid_sequence, "+FLAGS (\\Seen)") {
+            // One fresh connection per failed chunk; a second failure aborts.
+            warn!("Mark as read failed ({}), reconnecting...", err);
+            session = reconnect_inbox(email, &app_password, timeouts)?;
+            session
+                .uid_store(&uid_sequence, "+FLAGS (\\Seen)")
+                .map_err(|e| format!("Failed to mark as read: {}", e))?;
+        }
+        count += chunk.len();
+    }
+    
+    session.logout().ok();
+    
+    info!("Marked {} emails as read in {:?}", count, start.elapsed());
+    Ok(count)
+}
+
+/// Mark emails as unread, one IMAP STORE per `STORE_CHUNK_SIZE` UIDs
+pub fn mark_emails_as_unread(email: &str, uids: Vec<u32>) -> Result<usize, String> {
+    if uids.is_empty() {
+        return Ok(0);
+    }
+
+    let app_password = get_credentials(email)?;
+
+    info!("Marking {} emails as unread for {}...", uids.len(), email);
+    let start = std::time::Instant::now();
+
+    let mut session = connect_imap(email, &app_password, ImapTimeouts::default())?;
+
+    session
+        .select("INBOX")
+        .map_err(|e| format!("Failed to select INBOX: {}", e))?;
+
+    for uid_sequence in uid_store_sequences(&uids, STORE_CHUNK_SIZE) {
+        session
+            .uid_store(&uid_sequence, "-FLAGS (\\Seen)")
+            .map_err(|e| format!("Failed to mark as unread: {}", e))?;
+    }
+
+    session.logout().ok();
+
+    let count = uids.len();
+    info!("Marked {} emails as unread in {:?}", count, start.elapsed());
+    Ok(count)
+}
+
+/// Gmail's archive: everything lives in All Mail, and moving a message there
+/// from INBOX just drops the Inbox label.
+const ARCHIVE_MAILBOX: &str = "[Gmail]/All Mail";
+
+/// Star or unstar emails by adding/removing `\Flagged`, `STORE_CHUNK_SIZE` UIDs at a time.
+pub fn set_flagged(email: &str, uids: &[u32], flagged: bool) -> Result<usize, String> {
+    if uids.is_empty() {
+        return Ok(0);
+    }
+
+    let app_password = get_credentials(email)?;
+
+    info!("Setting flagged={} on {} emails for {}...", flagged, uids.len(), email);
+
+    let mut session = connect_imap(email, &app_password, ImapTimeouts::default())?;
+
+    session
+        .select("INBOX")
+        .map_err(|e| format!("Failed to select INBOX: {}", e))?;
+
+    let query = if flagged {
+        "+FLAGS (\\Flagged)"
+    } else {
+        "-FLAGS (\\Flagged)"
+    };
+
+    for uid_sequence in uid_store_sequences(uids, STORE_CHUNK_SIZE) {
+        session
+            .uid_store(&uid_sequence, query)
+            .map_err(|e| format!("Failed to update flag: {}", e))?;
+    }
+
+    session.logout().ok();
+    Ok(uids.len())
+}
+
+/// Permanently delete emails: set `\Deleted` and expunge just these UIDs, in chunks
+/// of `STORE_CHUNK_SIZE` so emptying a large trash stays under the command-length limit.
+pub fn delete_emails(email: &str, uids: &[u32]) -> Result<usize, String> {
+    if uids.is_empty() {
+        return Ok(0);
+    }
+
+    let app_password = get_credentials(email)?;
+
+    info!("Deleting {} emails for {}...", uids.len(), email);
+    let start = std::time::Instant::now();
+
+    let mut session = connect_imap(email, &app_password, ImapTimeouts::default())?;
+
+    session
+        .select("INBOX")
+        .map_err(|e| format!("Failed to select INBOX: {}", e))?;
+
+    for uid_sequence in uid_store_sequences(uids, STORE_CHUNK_SIZE) {
+        session
+            .uid_store(&uid_sequence, "+FLAGS (\\Deleted)")
+            .map_err(|e| format!("Failed to flag for deletion: {}", e))?;
+        session
+            .uid_expunge(&uid_sequence)
+            .map_err(|e| format!("Failed to expunge: {}", e))?;
+    }
+
+    session.logout().ok();
+
+    info!("Deleted {} emails in {:?}", uids.len(), start.elapsed());
+    Ok(uids.len())
+}
+
+/// Archive = take the messages out of INBOX; on Gmail they stay in All Mail. Uses
+/// `UID MOVE` when the server advertises it, otherwise `UID COPY` + `\Deleted` + expunge.
+pub fn archive_emails(email: &str, uids: &[u32]) -> Result<usize, String> {
+    if uids.is_empty() {
+        return Ok(0);
+    }
+
+    let app_password = get_credentials(email)?;
+
+    info!("Archiving {} emails for {}...", uids.len(), email);
+    let start = std::time::Instant::now();
+
+    let mut session = connect_imap(email, &app_password, ImapTimeouts::default())?;
+
+    let supports_move = session
+        .capabilities()
+        .map(|caps| caps.has_str("MOVE"))
+        .unwrap_or(false);
+    session
+        .select("INBOX")
+        .map_err(|e| format!("Failed to select INBOX: {}", e))?;
+    move_uids(&mut session, uids, ARCHIVE_MAILBOX, supports_move)?;
+
+    session.logout().ok();
+
+    info!("Archived {} emails in {:?}", uids.len(), start.elapsed());
+    Ok(uids.len())
+}
+
+/// File INBOX messages under another mailbox (a Gmail label such as `Receipts`, or a
+/// system folder such as `[Gmail]/Spam`), taking them out of INBOX. The target must
+/// already exist; see [`list_mailboxes`].
+pub fn move_emails(email: &str, uids: &[u32], target_mailbox: &str) -> Result<usize, String> {
+    if target_mailbox.eq_ignore_ascii_case("INBOX") {
+        return Err("Emails are already in INBOX".to_string());
+    }
+    if uids.is_empty() {
+        return Ok(0);
+    }
+
+    let app_password = get_credentials(email)?;
+
+    info!("Moving {} emails for {} to {}...", uids.len(), email, target_mailbox);
+    let start = std::time::Instant::now();
+
+    let mut session = connect_imap(email, &app_password, ImapTimeouts::default())?;
+    if !selectable_mailboxes(&mut session)?
+        .iter()
+        .any(|name| name == target_mailbox)
+    {
+        session.logout().ok();
+        return Err(format!("Mailbox \"{}\" does not exist", target_mailbox));
+    }
+
+    let supports_move = session
+        .capabilities()
+        .map(|caps| caps.has_str("MOVE"))
+        .unwrap_or(false);
+    session
+        .select("INBOX")
+        .map_err(|e| format!("Failed to select INBOX: {}", e))?;
+    move_uids(&mut session, uids, target_mailbox, supports_move)?;
+
+    session.logout().ok();
+
+    info!("Moved {} emails in {:?}", uids.len(), start.elapsed());
+    Ok(uids.len())
+}
+
+/// `UID MOVE` from the selected mailbox to `target` when the server advertises MOVE,
+/// otherwise `UID COPY` + `\Deleted` + expunge.
+fn move_uids(
+    session: &mut ImapSession,
+    uids: &[u32],
+    target: &str,
+    supports_move: bool,
+) -> Result<(), String> {
+    for sequence in uid_store_sequences(uids, STORE_CHUNK_SIZE) {
+        if supports_move {
+            session
+                .uid_mv(&sequence, target)
+                .map_err(|e| format!("Failed to move to {}: {}", target, e))?;
+        } else {
+            session
+                .uid_copy(&sequence, target)
+                .map_err(|e| format!("Failed to copy to {}: {}", target, e))?;
+            session
+                .uid_store(&sequence, "+FLAGS (\\Deleted)")
+                .map_err(|e| format!("Failed to flag moved emails: {}", e))?;
+            session
+                .uid_expunge(&sequence)
+                .map_err(|e| format!("Failed to expunge moved emails: {}", e))?;
+        }
+    }
+    Ok(())
+}
+
+/// Names of the account's selectable mailboxes: Gmail labels plus system folders
+/// like `[Gmail]/All Mail`, as IMAP names.
+pub fn list_mailboxes(email: &str) -> Result<Vec<String>, String> {
+    let app_password = get_credentials(email)?;
+    let mut session = connect_imap(email, &app_password, ImapTimeouts::default())?;
+    let names = selectable_mailboxes(&mut session)?;
+    session.logout().ok();
+    Ok(names)
+}
+
+fn selectable_mailboxes(session: &mut ImapSession) -> Result<Vec<String>, String> {
+    let names = session
+        .list(Some(""), Some("*"))
+        .map_err(|e| format!("Failed to list mailboxes: {}", e))?;
+    let mut mailboxes: Vec<String> = names
+        .iter()
+        .filter(|name| {
+            !name
+                .attributes()
+                .iter()
+                .any(|attribute| matches!(attribute, imap::types::NameAttribute::NoSelect))
+        })
+        .map(|name| name.name().to_string())
+        .collect();
+    mailboxes.sort();
+    Ok(mailboxes)
+}
+
+/// Test connection with provided credentials (without storing)
+pub fn test_connection(email: &str, app_password: &str) -> Result<String, String> {
+    info!("Testing connection for {}...", email);
+    
+    let mut session = connect_imap(email, app_password, ImapTimeouts::default())?;
+    
+    // Get mailbox info
+    let mailbox = session.select("INBOX")
+        .map_err(|e| format!("Failed to select INBOX: {}", e))?;
+    
+    let message_count = mailbox.exists;
+    
+    session.logout().ok();
+    
+    Ok(format!("Connection successful! Inbox has {} messages.", message_count))
+}
+
+/// What the server advertises after login (`IDLE`, `MOVE`, `CONDSTORE`, `X-GM-EXT-1`, ...),
+/// sorted. Selects nothing and logs out straight away.
+pub fn server_capabilities(email: &str, app_password: &str) -> Result<Vec<String>, String> {
+    let mut session = connect_imap(email, app_password, ImapTimeouts::default())?;
+    let capabilities = session
+        .capabilities()
+        .map_err(|e| format!("Failed to read capabilities: {}", e));
+    session.logout().ok();
+
+    let mut names: Vec<String> = capabilities?.iter().map(capability_name).collect();
+    names.sort();
+    names.dedup();
+    debug!("{} advertises {}", email, names.join(" "));
+    Ok(names)
+}
+
+/// A capability as the server spelled it in its CAPABILITY response.
+fn capability_name(capability: &imap_proto::types::Capability) -> String {
+    match capability {
+        imap_proto::types::Capability::Imap4rev1 => "IMAP4rev1".to_string(),
+        imap_proto::types::Capability::Auth(mechanism) => format!("AUTH={}", mechanism),
+        imap_proto::types::Capability::Atom(name) => name.to_string(),
+    }
+}
+
+/// Sessions kept open for `ping`, one per account, so frequent health checks are a
+/// single NOOP round trip instead of a TLS handshake and login.
+fn ping_sessions() -> &'static Mutex<HashMap<String, ImapSession>> {
+    static SESSIONS: OnceLock<Mutex<HashMap<String, ImapSession>>> = OnceLock::new();
+    SESSIONS.get_or_init(Default::default)
+}
+
+/// Log out and drop the sessions kept open for `ping`; used at exit.
+pub fn close_cached_sessions() {
+    let Ok(mut sessions) = ping_sessions().lock() else {
+        return;
+    };
+    for (email, mut session) in sessions.drain() {
+        debug!("Logging out kept-alive session for {}", email);
+        session.logout().ok();
+    }
+}
+
+/// Short timeouts so an offline check answers quickly.
+const PING_TIMEOUTS: ImapTimeouts = ImapTimeouts {
+    connect: Duration::from_secs(5),
+    io: Duration::from_secs(5),
+};
+
+/// Check the connection with `NOOP`. A dead kept-alive session is replaced by a
+/// fresh one and the NOOP retried once. The reconnect isn't retried with backoff, so
+/// an offline check fails within `PING_TIMEOUTS`, and the session map is only locked
+/// to take a session out or put it back, never across a network call.
+pub fn ping(email: &str) -> Result<(), String> {
+    let kept = ping_sessions()
+        .lock()
+        .map_err(|_| "Failed to lock ping sessions".to_string())?
+        .remove(email);
+
+    let session = match kept {
+        Some(mut session) => match session.noop() {
+            Ok(()) => Some(session),
+            Err(err) => {
+                warn!("NOOP failed for {} ({}), reconnecting...", email, err);
+                None
+            }
+        },
+        None => None,
+    };
+    let session = match session {
+        Some(session) => session,
+        None => {
+            let app_password = get_credentials(email)?;
+            let mut session = login_imap(email, &app_password, PING_TIMEOUTS, false)?;
+            session.noop().map_err(|e| match e {
+                _ if is_timeout(&e) => TIMEOUT_ERROR.to_string(),
+                _ => format!("NOOP failed: {}", e),
+            })?;
+            session
+        }
+    };
+
+    ping_sessions()
+        .lock()
+        .map_err(|_| "Failed to lock ping sessions".to_string())?
+        .insert(email.to_string(), session);
+    Ok(())
+}
+
+// =============================================================================
+// Server Search
+// =============================================================================
+
+/// Structured search run on the server, so mail that isn't cached yet is found too.
+/// Dates are unix timestamps; IMAP only compares whole days (UTC here).
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SearchCriteria {
+    #[serde(default)]
+    pub from: Option<String>,
+    #[serde(default)]
+    pub subject: Option<String>,
+    #[serde(default)]
+    pub since: Option<i64>,
+    #[serde(default)]
+    pub before: Option<i64>,
+    #[serde(default)]
+    pub unseen: bool,
+}
+
+/// Build the `SEARCH` arguments for `criteria`, e.g. `FROM "a@b.com" SINCE 01-Feb-2024`.
+fn build_search_query(criteria: &SearchCriteria) -> Result<String, String> {
+    let mut terms = Vec::new();
+    let mut needs_utf8 = false;
+    for (key, value) in [("FROM", &criteria.from), ("SUBJECT", &criteria.subject)] {
+        if let Some(value) = value.as_deref().filter(|v| !v.trim().is_empty()) {
+            if value.is_ascii() {
+                terms.push(format!("{} {}", key, imap_quote(value)));
+            } else {
+                needs_utf8 = true;
+                terms.push(format!("{} {}", key, imap_literal(value)));
+            }
+        }
+    }
+    if let Some(since) = criteria.since {
+        terms.push(format!("SINCE {}", imap_search_date(since)?));
+    }
+    if let Some(before) = criteria.before {
+        terms.push(format!("BEFORE {}", imap_search_date(before)?));
+    }
+    if criteria.unseen {
+        terms.push("UNSEEN".to_string());
+    }
+
+    let query = if terms.is_empty() {
+        "ALL".to_string()
+    } else {
+        terms.join(" ")
+    };
+    Ok(if needs_utf8 {
+        format!("CHARSET UTF-8 {}", query)
+    } else {
+        query
+    })
+}
+
+/// Quote a user-supplied value as an IMAP quoted string. CR/LF can't appear in a
+/// quoted string at all, so they are dropped rather than escaped.
+fn imap_quote(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for ch in value.chars() {
+        match ch {
+            '\\' | '"' => {
+                quoted.push('\\');
+                quoted.push(ch);
+            }
+            '\r' | '\n' => {}
+            _ => quoted.push(ch),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Encode a value as an IMAP literal (`{n}\r\n<bytes>`). Quoted strings may only
+/// carry 7-bit text, so non-ASCII search terms have to go this way.
+fn imap_literal(value: &str) -> String {
+    let value: String = value.chars().filter(|c| !matches!(c, '\r' | '\n')).collect();
+    format!("{{{}}}\r\n{}", value.len(), value)
+}
+
+/// Format a timestamp as an IMAP search date (`DD-Mon-YYYY`).
+fn imap_search_date(epoch: i64) -> Result<String, String> {
+    DateTime::from_timestamp(epoch, 0)
+        .map(|dt| dt.format("%d-%b-%Y").to_string())
+        .ok_or_else(|| format!("Invalid search date: {}", epoch))
+}
+
+/// Search INBOX on the server and return matching UIDs in ascending order.
+pub fn search(email: &str, criteria: &SearchCriteria) -> Result<Vec<u32>, String> {
+    let query = build_search_query(criteria)?;
+    let app_password = get_credentials(email)?;
+
+    debug!("Searching {} with: {}", email, query);
+    let mut session = connect_imap(email, &app_password, ImapTimeouts::default())?;
+    session
+        .select("INBOX")
+        .map_err(|e| format!("Failed to select INBOX: {}", e))?;
+
+    // `Session::uid_search` can't parse the `+` continuation a literal draws, so the
+    // command is sent raw (literal bytes included) and the response parsed here.
+    let response = session
+        .run_command_and_read_response(format!("UID SEARCH {}", query))
+        .map_err(|e| format!("Search failed: {}", e))?;
+    let mut uids = parse_search_uids(&response);
+    uids.sort_unstable();
+    uids.dedup();
+
+    session.logout().ok();
+    info!("Search matched {} emails", uids.len());
+    Ok(uids)
+}
+
+/// UIDs listed in the `* SEARCH` lines of a response; continuation lines are skipped.
+fn parse_search_uids(response: &[u8]) -> Vec<u32> {
+    let text = String::from_utf8_lossy(response);
+    let mut uids = Vec::new();
+    for line in text.lines() {
+        if let Some(ids) = line.strip_prefix("* SEARCH") {
+            uids.extend(ids.split_whitespace().filter_map(|id| id.parse::<u32>().ok()));
+        }
+    }
+    uids
+}
+
+/// Fetch headers for specific UIDs in INBOX, whether or not they're cached.
+pub fn fetch_emails_by_uid(email: &str, uids: &[u32]) -> Result<Vec<GmailEmail>, String> {
+    if uids.is_empty() {
+        return Ok(Vec::new());
+    }
+    let app_password = get_credentials(email)?;
+    let timeouts = ImapTimeouts::default();
+
+    let mut session = connect_imap(email, &app_password, timeouts)?;
+    session
+        .select("INBOX")
+        .map_err(|e| format!("Failed to select INBOX: {}", e))?;
+
+    let mut emails = Vec::with_capacity(uids.len());
+    for chunk in uids.chunks(1000) {
+        let messages = uid_fetch_with_retry(
+            &mut session,
+            email,
+            &app_password,
+            timeouts,
+            chunk,
+            "(UID ENVELOPE FLAGS RFC822.SIZE)",
+        )?;
+        emails.extend(messages.iter().filter_map(fetch_to_gmail_email));
+    }
+
+    session.logout().ok();
+    Ok(emails)
+}
+
+// =============================================================================
+// Helpers
+// =============================================================================
+
+/// Decode MIME encoded header (basic implementation)
+fn decode_mime_header(bytes: &[u8]) -> String {
+    let input = String::from_utf8_lossy(bytes).to_string();
+    decode_rfc2047_words(&input)
+}
+
+fn decode_rfc2047_words(input: &str) -> String {
+    let mut output = String::new();
+    // Raw bytes of the current run of adjacent encoded words sharing a charset.
+    // Decoding the run as a whole keeps multibyte characters split across words intact.
+    let mut pending: Option<(&str, Vec<u8>)> = None;
+    let mut index = 0;
+
+    while let Some(start_rel) = input[index..].find("=?") {
+        let start = index + start_rel;
+        let Some((charset, encoding, encoded, len)) = parse_encoded_word(&input[start..]) else {
+            flush_encoded_words(&mut output, &mut pending);
+            output.push_str(&input[index..start + 2]);
+            index = start + 2;
+            continue;
+        };
+
+        // Whitespace between two encoded words is not part of the text (RFC 2047 §6.2).
+        let between = &input[index..start];
+        let adjacent = pending.is_some() && between.chars().all(char::is_whitespace);
+        if !adjacent {
+            flush_encoded_words(&mut output, &mut pending);
+            output.push_str(between);
+        }
+
+        let bytes = decode_word_bytes(encoding, encoded);
+        match pending.as_mut() {
+            Some((pending_charset, pending_bytes)) if pending_charset.eq_ignore_ascii_case(charset) => {
+                pending_bytes.extend(bytes);
+            }
+            _ => {
+                flush_encoded_words(&mut output, &mut pending);
+                pending = Some((charset, bytes));
+            }
+        }
+        index = start + len;
+    }
+
+    flush_encoded_words(&mut output, &mut pending);
+    output.push_str(&input[index..]);
+    output
+}
+
+/// Split `=?charset?encoding?text?=` at the start of `input`, returning the parts
+/// and the total length consumed.
+fn parse_encoded_word(input: &str) -> Option<(&str, &str, &str, usize)> {
+    let rest = input.strip_prefix("=?")?;
+    let q1 = rest.find('?')?;
+    let charset = &rest[..q1];
+    let rest = &rest[q1 + 1..];
+    let q2 = rest.find('?')?;
+    let encoding = &rest[..q2];
+    let rest = &rest[q2 + 1..];
+    let q3 = rest.find("?=")?;
+    let encoded = &rest[..q3];
+    Some((charset, encoding, encoded, 2 + q1 + 1 + q2 + 1 + q3 + 2))
+}
+
+fn flush_encoded_words(output: &mut String, pending: &mut Option<(&str, Vec<u8>)>) {
+    if let Some((charset, bytes)) = pending.take() {
+        output.push_str(&decode_charset(charset, &bytes));
+    }
+}
+
+fn decode_word_bytes(encoding: &str, encoded: &str) -> Vec<u8> {
+    match encoding.to_ascii_lowercase().as_str() {
+        "q" => decode_q(encoded),
         "b" => decode_b(encoded),
         _ => encoded.as_bytes().to_vec(),
-    };
+    }
+}
+
+/// Format envelope addresses as `Name <mailbox@host>` (or the bare address) and
+/// join them with ", ". Returns `None` when the list is absent or has no usable entries.
+fn format_address_list(addrs: Option<&[imap_proto::types::Address]>) -> Option<String> {
+    let formatted: Vec<String> = addrs?
+        .iter()
+        .filter_map(|addr| {
+            let mailbox = addr.mailbox.map(|m| String::from_utf8_lossy(m).to_string())?;
+            let host = addr.host.map(|h| String::from_utf8_lossy(h).to_string())?;
+            if mailbox.is_empty() || host.is_empty() {
+                return None;
+            }
+            let email = format!("{}@{}", mailbox, host);
+            let name = addr.name.map(decode_mime_header).unwrap_or_default();
+            Some(if name.is_empty() {
+                email
+            } else {
+                format!("{} <{}>", name, email)
+            })
+        })
+        .collect();
+    if formatted.is_empty() {
+        None
+    } else {
+        Some(formatted.join(", "))
+    }
+}
 
-    match charset.to_ascii_lowercase().as_str() {
-        "utf-8" | "utf8" => String::from_utf8_lossy(&bytes).to_string(),
-        _ => String::from_utf8_lossy(&bytes).to_string(),
+/// Decode raw header bytes using the declared charset, falling back to lossy
+/// UTF-8 when the label is unknown.
+fn decode_charset(charset: &str, bytes: &[u8]) -> String {
+    // RFC 2231 allows a language suffix, e.g. `utf-8*en`.
+    let label = charset.split('*').next().unwrap_or(charset).trim();
+    match encoding_rs::Encoding::for_label(label.as_bytes()) {
+        Some(encoding) => encoding.decode(bytes).0.into_owned(),
+        None => String::from_utf8_lossy(bytes).to_string(),
     }
 }
 
@@ -595,14 +2246,127 @@ fn hex_val(byte: u8) -> Option<u8> {
     }
 }
 
+/// Outcome of an unsubscribe attempt. `ComposeEmail` and `OpenUrl` mean the
+/// frontend has to finish the job because no one-click endpoint was offered.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum UnsubscribeResult {
+    Posted { status: u16 },
+    ComposeEmail { mailto: String },
+    OpenUrl { url: String },
+}
+
+const UNSUBSCRIBE_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Unsubscribe using the stored `List-Unsubscribe` data. Performs the RFC 8058
+/// one-click POST when advertised over HTTPS; otherwise tells the caller what to open.
+pub fn unsubscribe(
+    email: &str,
+    uid: u32,
+    info: &crate::storage::UnsubscribeInfo,
+) -> Result<UnsubscribeResult, String> {
+    match (&info.unsubscribe_url, &info.unsubscribe_mailto) {
+        (Some(url), _) if info.unsubscribe_oneclick && is_https(url) => {
+            info!("One-click unsubscribe for UID {} ({})...", uid, email);
+            let url = reqwest::Url::parse(url)
+                .map_err(|e| format!("Invalid unsubscribe URL: {}", e))?;
+            let mut builder = reqwest::blocking::Client::builder()
+                .timeout(UNSUBSCRIBE_TIMEOUT)
+                .redirect(reqwest::redirect::Policy::none());
+            // Pin the checked address so a second lookup can't swap in a private one.
+            if let (Some(host), Some(addr)) = (url.domain(), public_unsubscribe_addr(&url)?) {
+                builder = builder.resolve(host, addr);
+            }
+            let client = builder
+                .build()
+                .map_err(|e| format!("HTTP client error: {}", e))?;
+            let response = client
+                .post(url)
+                .header("Content-Type", "application/x-www-form-urlencoded")
+                .body("List-Unsubscribe=One-Click")
+                .send()
+                .map_err(|e| {
+                    if e.is_timeout() {
+                        "Unsubscribe request timed out".to_string()
+                    } else {
+                        format!("Unsubscribe request failed: {}", e)
+                    }
+                })?;
+            let status = response.status();
+            if status.is_success() || status.is_redirection() {
+                Ok(UnsubscribeResult::Posted {
+                    status: status.as_u16(),
+                })
+            } else {
+                Err(format!("Unsubscribe failed with HTTP status {}", status))
+            }
+        }
+        (_, Some(mailto)) => Ok(UnsubscribeResult::ComposeEmail {
+            mailto: mailto.clone(),
+        }),
+        (Some(url), None) => Ok(UnsubscribeResult::OpenUrl { url: url.clone() }),
+        (None, None) => Err("This email has no List-Unsubscribe header".to_string()),
+    }
+}
+
+fn is_https(url: &str) -> bool {
+    url.get(..8).is_some_and(|scheme| scheme.eq_ignore_ascii_case("https://"))
+}
+
+/// Resolve the one-click target and refuse loopback, link-local and private
+/// addresses, so a crafted header can't aim the POST at this machine or the LAN.
+/// Returns the address to connect to when the host is a name rather than an IP.
+fn public_unsubscribe_addr(url: &reqwest::Url) -> Result<Option<SocketAddr>, String> {
+    let host = url
+        .host_str()
+        .ok_or_else(|| "Unsubscribe URL has no host".to_string())?;
+    let port = url.port_or_known_default().unwrap_or(443);
+    let literal = host.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>();
+    let addrs: Vec<SocketAddr> = match literal {
+        Ok(ip) => vec![SocketAddr::new(ip, port)],
+        Err(_) => (host, port)
+            .to_socket_addrs()
+            .map_err(|e| format!("Failed to resolve {}: {}", host, e))?
+            .collect(),
+    };
+    if addrs.is_empty() {
+        return Err(format!("Failed to resolve {}", host));
+    }
+    if addrs.iter().any(|addr| !is_public_ip(addr.ip())) {
+        return Err(format!("Refusing to unsubscribe via private address {}", host));
+    }
+    Ok(if literal.is_ok() { None } else { Some(addrs[0]) })
+}
+
+fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => {
+            !(v4.is_loopback()
+                || v4.is_private()
+                || v4.is_link_local()
+                || v4.is_unspecified()
+                || v4.is_broadcast())
+        }
+        IpAddr::V6(v6) => match v6.to_ipv4_mapped() {
+            Some(v4) => is_public_ip(IpAddr::V4(v4)),
+            None => {
+                !(v6.is_loopback()
+                    || v6.is_unspecified()
+                    || v6.is_unique_local()
+                    || v6.is_unicast_link_local())
+            }
+        },
+    }
+}
+
 /// Fetch email body by UID and parse it properly
 pub fn fetch_email_body(email: &str, uid: u32) -> Result<EmailBody, String> {
     let app_password = get_credentials(email)?;
 
-    log!("Fetching email body for UID {} from {}...", uid, email);
+    info!("Fetching email body for UID {} from {}...", uid, email);
     let start = std::time::Instant::now();
 
-    let mut session = connect_imap(email, &app_password)?;
+    let mut session = connect_imap(email, &app_password, ImapTimeouts::default())?;
 
     session.select("INBOX")
         .map_err(|e| format!("Failed to select INBOX: {}", e))?;
@@ -621,25 +2385,824 @@ pub fn fetch_email_body(email: &str, uid: u32) -> Result<EmailBody, String> {
 
     let body = parse_email_body(raw_body)?;
 
-    log!("Fetched and parsed email body in {:?}", start.elapsed());
+    info!("Fetched and parsed email body in {:?}", start.elapsed());
 
     Ok(body)
 }
 
+/// Raw sources longer than this are cut off so the IPC payload stays small.
+pub const RAW_SOURCE_LIMIT: usize = 1024 * 1024;
+
+/// The message's raw RFC 822 source, for pasting into bug reports. Uses
+/// `BODY.PEEK[]` so viewing the source doesn't mark the email read.
+pub fn fetch_raw(email: &str, uid: u32) -> Result<String, String> {
+    let app_password = get_credentials(email)?;
+    let mut session = connect_imap(email, &app_password, ImapTimeouts::default())?;
+
+    session.select("INBOX")
+        .map_err(|e| format!("Failed to select INBOX: {}", e))?;
+
+    let messages = session.uid_fetch(uid.to_string(), "BODY.PEEK[]")
+        .map_err(|e| format!("Failed to fetch raw source: {}", e))?;
+    let raw = messages
+        .iter()
+        .next()
+        .and_then(|msg| msg.body())
+        .ok_or_else(|| format!("UID {} is no longer in INBOX", uid))?;
+
+    session.logout().ok();
+    Ok(raw_source_text(raw, RAW_SOURCE_LIMIT))
+}
+
+/// Render at most `limit` bytes of `raw` as text. Sources that aren't UTF-8 come back
+/// base64-encoded so no byte is lost; a trailing marker notes any truncation.
+fn raw_source_text(raw: &[u8], limit: usize) -> String {
+    let shown = &raw[..raw.len().min(limit)];
+    let mut text = match std::str::from_utf8(shown) {
+        Ok(text) => text.to_string(),
+        // The cut landed inside a multi-byte character; drop the partial character.
+        Err(err) if err.error_len().is_none() => {
+            String::from_utf8_lossy(&shown[..err.valid_up_to()]).into_owned()
+        }
+        Err(_) => format!(
+            "[Source is not valid UTF-8; base64-encoded below]\n{}",
+            general_purpose::STANDARD.encode(shown)
+        ),
+    };
+    if shown.len() < raw.len() {
+        text.push_str(&format!("\n[Truncated: showing {} of {} bytes]", shown.len(), raw.len()));
+    }
+    text
+}
+
+/// Fetch and parse several bodies in one `UID FETCH`. UIDs no longer on the server
+/// are left out; the rest come back in server order.
+pub fn fetch_email_bodies(email: &str, uids: &[u32]) -> Result<Vec<GmailEmailBody>, String> {
+    if uids.is_empty() {
+        return Ok(Vec::new());
+    }
+    let app_password = get_credentials(email)?;
+    let timeouts = ImapTimeouts::default();
+
+    info!("Fetching {} email bodies from {}...", uids.len(), email);
+    let start = std::time::Instant::now();
+
+    let mut session = connect_imap(email, &app_password, timeouts)?;
+    session
+        .select("INBOX")
+        .map_err(|e| format!("Failed to select INBOX: {}", e))?;
+    let bodies = fetch_bodies(&mut session, email, &app_password, timeouts, uids)?;
+    session.logout().ok();
+
+    info!("Fetched {} of {} bodies in {:?}", bodies.len(), uids.len(), start.elapsed());
+    Ok(bodies)
+}
+
 fn parse_email_body(raw_body: &[u8]) -> Result<EmailBody, String> {
     let parser = MessageParser::default();
     let message = parser
         .parse(raw_body)
         .ok_or_else(|| "Failed to parse email".to_string())?;
 
-    let html = message.body_html(0).map(|s| s.to_string());
+    let html_raw = message.body_html(0).map(|s| s.to_string());
+    let html = html_raw
+        .as_deref()
+        .map(|raw| crate::sanitize::sanitize_html(raw, false));
     let text = message.body_text(0).map(|s| s.to_string());
+    let snippet = make_snippet(text.as_deref(), html_raw.as_deref());
+    let headers = message
+        .headers_raw()
+        .take(MAX_HEADERS)
+        .map(|(name, raw)| (name.to_string(), unfold_header(raw)))
+        .collect();
+
+    let (unsubscribe_url, unsubscribe_mailto) = message
+        .header_raw("List-Unsubscribe")
+        .map(parse_list_unsubscribe)
+        .unwrap_or_default();
+    // RFC 8058 one-click is only valid over HTTPS; plain HTTP targets are opened instead.
+    let unsubscribe_oneclick = unsubscribe_url.as_deref().is_some_and(is_https)
+        && message
+            .header_raw("List-Unsubscribe-Post")
+            .is_some_and(is_one_click_post);
+
+    let mut attachments = Vec::new();
+    collect_attachments(&message, &mut attachments);
+
+    let in_reply_to = message
+        .header_raw("In-Reply-To")
+        .and_then(|raw| parse_message_ids(raw).into_iter().next());
+    let references = message
+        .header_raw("References")
+        .map(parse_message_ids)
+        .unwrap_or_default();
+
+    Ok(EmailBody {
+        html,
+        html_raw,
+        text,
+        unsubscribe_url,
+        unsubscribe_mailto,
+        unsubscribe_oneclick,
+        attachments,
+        in_reply_to,
+        references,
+        snippet,
+        headers,
+    })
+}
+
+/// Headers kept per message; relayed mail can carry hundreds of `Received:` lines.
+pub const MAX_HEADERS: usize = 100;
+
+/// Decode encoded words and collapse folded continuation lines into single spaces.
+fn unfold_header(raw: &str) -> String {
+    decode_mime_header(raw.as_bytes())
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Characters kept in a list-view snippet.
+const SNIPPET_LEN: usize = 140;
 
-    Ok(EmailBody { html, text })
+/// First `SNIPPET_LEN` characters of the body with whitespace collapsed, preferring
+/// the text part and falling back to the HTML converted to text. Quoted reply lines
+/// (`> ...`) and everything from an `On ... wrote:` attribution onward are dropped.
+fn make_snippet(text: Option<&str>, html: Option<&str>) -> Option<String> {
+    let source = match text.filter(|t| !t.trim().is_empty()) {
+        Some(text) => text.to_string(),
+        None => mail_parser::decoders::html::html_to_text(html?),
+    };
+
+    let mut words: Vec<&str> = Vec::new();
+    for line in source.lines() {
+        let trimmed = line.trim();
+        if trimmed.starts_with('>') {
+            continue;
+        }
+        if trimmed.starts_with("On ") && trimmed.ends_with("wrote:") {
+            break;
+        }
+        words.extend(trimmed.split_whitespace());
+    }
+
+    let snippet: String = words.join(" ").chars().take(SNIPPET_LEN).collect();
+    let snippet = snippet.trim_end();
+    if snippet.is_empty() {
+        None
+    } else {
+        Some(snippet.to_string())
+    }
+}
+
+/// Extract `<id@host>` tokens from a `References`/`In-Reply-To` header, keeping the
+/// angle brackets so they compare equal to the envelope `Message-ID`.
+fn parse_message_ids(header: &str) -> Vec<String> {
+    let mut ids = Vec::new();
+    let mut rest = header;
+    while let Some(open) = rest.find('<') {
+        let Some(close) = rest[open..].find('>') else {
+            break;
+        };
+        let id: String = rest[open..=open + close]
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .collect();
+        if id.len() > 2 {
+            ids.push(id);
+        }
+        rest = &rest[open + close + 1..];
+    }
+    ids
+}
+
+/// Gather attachment metadata, descending into attached messages (forwarded
+/// `message/rfc822` parts) so their attachments are listed as well.
+fn collect_attachments(message: &Message, out: &mut Vec<AttachmentMeta>) {
+    for part in message.attachments() {
+        let content_type = part
+            .content_type()
+            .map(|ct| match ct.subtype() {
+                Some(subtype) => format!("{}/{}", ct.ctype(), subtype),
+                None => ct.ctype().to_string(),
+            })
+            .unwrap_or_else(|| "application/octet-stream".to_string());
+        out.push(AttachmentMeta {
+            filename: part.attachment_name().map(|name| name.to_string()),
+            content_type,
+            size: part.len() as u64,
+        });
+        if let Some(nested) = part.message() {
+            collect_attachments(nested, out);
+        }
+    }
+}
+
+/// Split a `List-Unsubscribe` header (`<https://...>, <mailto:...>`) into its
+/// first HTTP(S) and first mailto target. An HTTPS target wins over an earlier HTTP one.
+fn parse_list_unsubscribe(header: &str) -> (Option<String>, Option<String>) {
+    let mut url: Option<String> = None;
+    let mut mailto = None;
+    let mut rest = header;
+    while let Some(open) = rest.find('<') {
+        let Some(close) = rest[open..].find('>') else {
+            break;
+        };
+        // Folded headers may leave whitespace inside the brackets.
+        let target: String = rest[open + 1..open + close]
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .collect();
+        let lower = target.to_ascii_lowercase();
+        if lower.starts_with("https://") {
+            if !url.as_deref().is_some_and(is_https) {
+                url = Some(target);
+            }
+        } else if lower.starts_with("http://") {
+            url.get_or_insert(target);
+        } else if lower.starts_with("mailto:") {
+            mailto.get_or_insert(target);
+        }
+        rest = &rest[open + close + 1..];
+    }
+    (url, mailto)
 }
 
-fn parse_imap_date_epoch(date_str: &str) -> Option<i64> {
-    DateTime::parse_from_rfc2822(date_str)
+fn is_one_click_post(header: &str) -> bool {
+    let compact: String = header.chars().filter(|c| !c.is_whitespace()).collect();
+    compact.eq_ignore_ascii_case("List-Unsubscribe=One-Click")
+}
+
+/// Formats tried after RFC 2822/3339 once the header has been normalized.
+const FALLBACK_DATE_FORMATS: &[&str] = &[
+    "%d %b %Y %H:%M:%S %z",
+    "%d %B %Y %H:%M:%S %z",
+    "%d %b %Y %H:%M %z",
+    "%Y-%m-%d %H:%M:%S %z",
+];
+
+/// Parse a `Date:` header into a unix timestamp, tolerating common deviations
+/// from RFC 2822 (wrong weekday, zone names, trailing comments, full month names).
+pub(crate) fn parse_imap_date_epoch(date_str: &str) -> Option<i64> {
+    let trimmed = date_str.trim();
+    if let Ok(dt) = DateTime::parse_from_rfc2822(trimmed) {
+        return Some(dt.timestamp());
+    }
+    if let Ok(dt) = DateTime::parse_from_rfc3339(trimmed) {
+        return Some(dt.timestamp());
+    }
+
+    let normalized = normalize_date_header(trimmed);
+    if let Ok(dt) = DateTime::parse_from_rfc2822(&normalized) {
+        return Some(dt.timestamp());
+    }
+    FALLBACK_DATE_FORMATS
+        .iter()
+        .find_map(|format| DateTime::parse_from_str(&normalized, format).ok())
         .map(|dt| dt.timestamp())
-        .ok()
+}
+
+/// Strip the weekday, trailing `(Zone)` comments and textual UTC zone names.
+fn normalize_date_header(value: &str) -> String {
+    let mut value = value.trim();
+    if let Some(open) = value.rfind('(') {
+        if value.ends_with(')') {
+            value = value[..open].trim_end();
+        }
+    }
+    if let Some((weekday, rest)) = value.split_once(',') {
+        if weekday.trim().chars().all(|c| c.is_ascii_alphabetic()) {
+            value = rest.trim_start();
+        }
+    }
+
+    let mut parts: Vec<&str> = value.split_whitespace().collect();
+    if let Some(zone) = parts.last_mut() {
+        if matches!(
+            zone.to_ascii_uppercase().as_str(),
+            "UTC" | "GMT" | "UT" | "Z"
+        ) {
+            *zone = "+0000";
+        }
+    }
+    parts.join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn raw_source_is_capped_and_base64_when_not_utf8() {
+        let source = "Subject: caf\u{e9}\r\n\r\nbody".as_bytes();
+        assert_eq!(raw_source_text(source, 1024), "Subject: caf\u{e9}\r\n\r\nbody");
+
+        // "é" is two bytes; cutting after its first byte drops it.
+        assert_eq!(
+            raw_source_text(source, 13),
+            format!("Subject: caf\n[Truncated: showing 13 of {} bytes]", source.len())
+        );
+
+        let latin1 = b"Subject: caf\xe9\r\n";
+        assert_eq!(
+            raw_source_text(latin1, 1024),
+            format!(
+                "[Source is not valid UTF-8; base64-encoded below]\n{}",
+                general_purpose::STANDARD.encode(latin1)
+            )
+        );
+    }
+
+    #[test]
+    fn parses_well_formed_dates() {
+        assert_eq!(
+            parse_imap_date_epoch("Mon, 14 Aug 2023 09:00:00 +0000"),
+            Some(1692003600)
+        );
+        assert_eq!(
+            parse_imap_date_epoch("2023-08-14T09:00:00Z"),
+            Some(1692003600)
+        );
+    }
+
+    #[test]
+    fn parses_malformed_date_headers() {
+        let cases = [
+            // Weekday doesn't match the date.
+            "Wed, 14 Aug 2023 09:00:00 +0000",
+            // Textual zone names chrono's RFC 2822 parser rejects.
+            "Mon, 14 Aug 2023 09:00:00 UTC",
+            "Mon, 14 Aug 2023 9:00:00 GMT",
+            "Mon, 14 Aug 2023 09:00:00 UTC (UTC)",
+            // Colon in the offset.
+            "Mon, 14 Aug 2023 09:00:00 +00:00",
+            // Full month name.
+            "Monday, 14 August 2023 09:00:00 +0000",
+        ];
+        for case in cases {
+            assert_eq!(parse_imap_date_epoch(case), Some(1692003600), "{}", case);
+        }
+    }
+
+    #[test]
+    fn decodes_latin1_quoted_printable() {
+        assert_eq!(decode_rfc2047_words("=?iso-8859-1?Q?caf=E9?="), "café");
+        assert_eq!(
+            decode_rfc2047_words("=?windows-1252?Q?=93quoted=94?="),
+            "\u{201c}quoted\u{201d}"
+        );
+    }
+
+    #[test]
+    fn decodes_shift_jis_base64() {
+        assert_eq!(decode_rfc2047_words("=?Shift_JIS?B?k/qWe4zq?="), "日本語");
+    }
+
+    #[test]
+    fn unknown_charset_falls_back_to_utf8() {
+        assert_eq!(decode_rfc2047_words("=?x-unknown?Q?plain?="), "plain");
+    }
+
+    #[test]
+    fn joins_adjacent_encoded_words() {
+        assert_eq!(
+            decode_rfc2047_words("=?UTF-8?Q?Hello?= =?UTF-8?Q?_World?="),
+            "Hello World"
+        );
+        assert_eq!(
+            decode_rfc2047_words("Re: =?UTF-8?Q?caf=C3=A9?= time"),
+            "Re: café time"
+        );
+    }
+
+    #[test]
+    fn joins_words_split_mid_utf8_sequence() {
+        // "日本語" split after the first byte of the second character.
+        assert_eq!(
+            decode_rfc2047_words("=?UTF-8?B?5pel5g==?=\r\n =?UTF-8?B?nKzoqp4=?="),
+            "日本語"
+        );
+    }
+
+    #[test]
+    fn does_not_retry_server_rejections() {
+        let mut calls = 0;
+        let result: imap::Result<()> = with_retry("Login", || {
+            calls += 1;
+            Err(imap::Error::No("[AUTHENTICATIONFAILED] Invalid credentials".to_string()))
+        });
+        assert!(result.is_err());
+        assert_eq!(calls, 1);
+        assert!(is_transient(&imap::Error::ConnectionLost));
+    }
+
+    #[test]
+    fn timeouts_fall_back_to_defaults() {
+        let timeouts = ImapTimeouts::from_secs(Some(5), Some(0));
+        assert_eq!(timeouts.connect, Duration::from_secs(5));
+        assert_eq!(timeouts.io, ImapTimeouts::default().io);
+        assert!(is_timeout(&imap::Error::Io(std::io::ErrorKind::WouldBlock.into())));
+        assert!(!is_timeout(&imap::Error::ConnectionLost));
+    }
+
+    #[test]
+    fn parses_list_unsubscribe_targets() {
+        let (url, mailto) = parse_list_unsubscribe(
+            " <mailto:unsub@example.com?subject=unsubscribe>,\r\n <https://example.com/u?id=1>",
+        );
+        assert_eq!(url.as_deref(), Some("https://example.com/u?id=1"));
+        assert_eq!(mailto.as_deref(), Some("mailto:unsub@example.com?subject=unsubscribe"));
+
+        assert_eq!(parse_list_unsubscribe("<mailto:a@b.com>"), (None, Some("mailto:a@b.com".to_string())));
+        assert!(is_one_click_post(" List-Unsubscribe=One-Click"));
+        assert!(!is_one_click_post("List-Unsubscribe=Later"));
+    }
+
+    #[test]
+    fn unsubscribe_without_one_click_defers_to_frontend() {
+        let mut info = crate::storage::UnsubscribeInfo {
+            unsubscribe_url: Some("https://example.com/u".to_string()),
+            unsubscribe_mailto: Some("mailto:u@example.com".to_string()),
+            unsubscribe_oneclick: false,
+        };
+        assert!(matches!(
+            unsubscribe("me@example.com", 1, &info),
+            Ok(UnsubscribeResult::ComposeEmail { mailto }) if mailto == "mailto:u@example.com"
+        ));
+        info.unsubscribe_mailto = None;
+        assert!(matches!(
+            unsubscribe("me@example.com", 1, &info),
+            Ok(UnsubscribeResult::OpenUrl { .. })
+        ));
+        info.unsubscribe_url = None;
+        assert!(unsubscribe("me@example.com", 1, &info).is_err());
+    }
+
+    #[test]
+    fn one_click_unsubscribe_requires_https() {
+        let (url, _) =
+            parse_list_unsubscribe("<http://example.com/u>, <https://example.com/one-click>");
+        assert_eq!(url.as_deref(), Some("https://example.com/one-click"));
+
+        // Cached before one-click was limited to HTTPS: open the page instead of POSTing.
+        let info = crate::storage::UnsubscribeInfo {
+            unsubscribe_url: Some("http://example.com/u".to_string()),
+            unsubscribe_mailto: None,
+            unsubscribe_oneclick: true,
+        };
+        assert!(matches!(
+            unsubscribe("me@example.com", 1, &info),
+            Ok(UnsubscribeResult::OpenUrl { url }) if url == "http://example.com/u"
+        ));
+
+        let raw = concat!(
+            "From: news@example.com\r\n",
+            "Subject: News\r\n",
+            "List-Unsubscribe: <http://example.com/u>\r\n",
+            "List-Unsubscribe-Post: List-Unsubscribe=One-Click\r\n",
+            "Content-Type: text/plain\r\n\r\n",
+            "Hi\r\n",
+        );
+        let body = parse_email_body(raw.as_bytes()).unwrap();
+        assert_eq!(body.unsubscribe_url.as_deref(), Some("http://example.com/u"));
+        assert!(!body.unsubscribe_oneclick);
+    }
+
+    #[test]
+    fn one_click_unsubscribe_refuses_private_hosts() {
+        for url in [
+            "https://127.0.0.1/u",
+            "https://10.1.2.3/u",
+            "https://192.168.0.1/u",
+            "https://169.254.169.254/latest",
+            "https://[::1]/u",
+            "https://[fd00::1]/u",
+            "https://[::ffff:127.0.0.1]/u",
+        ] {
+            let info = crate::storage::UnsubscribeInfo {
+                unsubscribe_url: Some(url.to_string()),
+                unsubscribe_mailto: None,
+                unsubscribe_oneclick: true,
+            };
+            let err = unsubscribe("me@example.com", 1, &info).unwrap_err();
+            assert!(err.contains("private address"), "{}: {}", url, err);
+        }
+        assert!(is_public_ip("93.184.216.34".parse().unwrap()));
+        assert!(is_public_ip("2606:2800:220:1::".parse().unwrap()));
+    }
+
+    #[test]
+    fn collects_nested_attachments() {
+        let raw = concat!(
+            "From: a@example.com\r\n",
+            "Subject: Fwd\r\n",
+            "MIME-Version: 1.0\r\n",
+            "Content-Type: multipart/mixed; boundary=\"outer\"\r\n\r\n",
+            "--outer\r\n",
+            "Content-Type: text/plain\r\n\r\n",
+            "See attached\r\n",
+            "--outer\r\n",
+            "Content-Type: application/pdf; name=\"report.pdf\"\r\n",
+            "Content-Disposition: attachment; filename=\"report.pdf\"\r\n",
+            "Content-Transfer-Encoding: base64\r\n\r\n",
+            "JVBERi0xLjQ=\r\n",
+            "--outer\r\n",
+            "Content-Type: message/rfc822\r\n\r\n",
+            "From: b@example.com\r\n",
+            "Subject: Inner\r\n",
+            "MIME-Version: 1.0\r\n",
+            "Content-Type: multipart/mixed; boundary=\"inner\"\r\n\r\n",
+            "--inner\r\n",
+            "Content-Type: text/plain\r\n\r\n",
+            "Inner body\r\n",
+            "--inner\r\n",
+            "Content-Type: image/png\r\n",
+            "Content-Disposition: attachment; filename=\"photo.png\"\r\n",
+            "Content-Transfer-Encoding: base64\r\n\r\n",
+            "iVBORw0KGgo=\r\n",
+            "--inner--\r\n",
+            "--outer--\r\n",
+        );
+        let body = parse_email_body(raw.as_bytes()).unwrap();
+        assert!(body.references.is_empty());
+        let names: Vec<_> = body
+            .attachments
+            .iter()
+            .filter_map(|a| a.filename.as_deref())
+            .collect();
+        assert!(names.contains(&"report.pdf"));
+        assert!(names.contains(&"photo.png"));
+        let pdf = body
+            .attachments
+            .iter()
+            .find(|a| a.filename.as_deref() == Some("report.pdf"))
+            .unwrap();
+        assert_eq!(pdf.content_type, "application/pdf");
+        assert_eq!(pdf.size, 8);
+    }
+
+    #[test]
+    fn builds_quoted_search_query() {
+        let criteria = SearchCriteria {
+            from: Some("news@shop.com".to_string()),
+            subject: Some("50% \"off\" \\ today\r\n".to_string()),
+            since: Some(1706745600), // 2024-02-01
+            before: Some(1709251200), // 2024-03-01
+            unseen: true,
+        };
+        assert_eq!(
+            build_search_query(&criteria).unwrap(),
+            r#"FROM "news@shop.com" SUBJECT "50% \"off\" \\ today" SINCE 01-Feb-2024 BEFORE 01-Mar-2024 UNSEEN"#
+        );
+        assert_eq!(build_search_query(&SearchCriteria::default()).unwrap(), "ALL");
+
+        let utf8 = SearchCriteria {
+            subject: Some("café".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(build_search_query(&utf8).unwrap(), "CHARSET UTF-8 SUBJECT {5}\r\ncafé");
+
+        let mixed = SearchCriteria {
+            from: Some("José <jose@example.com>".to_string()),
+            subject: Some("Invoice".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            build_search_query(&mixed).unwrap(),
+            "CHARSET UTF-8 FROM {24}\r\nJosé <jose@example.com> SUBJECT \"Invoice\""
+        );
+    }
+
+    #[test]
+    fn parses_search_response_after_literal_continuation() {
+        let response = b"+ go ahead\r\n* SEARCH 42 7 1001\r\n* SEARCH\r\n";
+        assert_eq!(parse_search_uids(response), vec![42, 7, 1001]);
+    }
+
+    #[test]
+    fn parses_threading_headers() {
+        let raw = "From: a@example.com\r\nMessage-ID: <c@x>\r\nIn-Reply-To: <b@x>\r\nReferences: <a@x>\r\n <b@x>\r\nSubject: Re: Plans\r\n\r\nok\r\n";
+        let body = parse_email_body(raw.as_bytes()).unwrap();
+        assert_eq!(body.in_reply_to.as_deref(), Some("<b@x>"));
+        assert_eq!(body.references, vec!["<a@x>".to_string(), "<b@x>".to_string()]);
+    }
+
+    #[test]
+    fn rejects_garbage_dates() {
+        assert_eq!(parse_imap_date_epoch(""), None);
+        assert_eq!(parse_imap_date_epoch("not a date"), None);
+    }
+
+    #[test]
+    fn classifies_sync_errors() {
+        let kind = SyncError::from_imap(&imap::Error::No("[AUTHENTICATIONFAILED] Invalid credentials".to_string()));
+        assert_eq!(kind, SyncErrorKind::AuthFailed);
+        let kind = SyncError::from_imap(&imap::Error::No("[THROTTLED] Too many requests".to_string()));
+        assert_eq!(kind, SyncErrorKind::RateLimited);
+        assert_eq!(SyncError::from_imap(&imap::Error::ConnectionLost), SyncErrorKind::Network);
+
+        let err = SyncError::from(TIMEOUT_ERROR.to_string());
+        assert_eq!(err.kind, SyncErrorKind::Network);
+        let json = serde_json::to_value(&err).unwrap();
+        assert_eq!(json["kind"], "network");
+        assert_eq!(json["message"], TIMEOUT_ERROR);
+    }
+
+    #[test]
+    fn body_prefetch_prefers_unread_then_newest_read() {
+        let prefetch = BodyPrefetch {
+            min: 2,
+            max: 4,
+            read_buffer: 1,
+        };
+        let uids: Vec<u32> = (1..=10).collect();
+
+        let unread: HashSet<u32> = [2, 5].into_iter().collect();
+        let selected = prefetch.select(&uids, &unread);
+        assert_eq!(selected, [2, 5, 10].into_iter().collect());
+
+        let unread: HashSet<u32> = [1, 2, 3, 4, 5, 6].into_iter().collect();
+        assert_eq!(prefetch.select(&uids, &unread), [3, 4, 5, 6].into_iter().collect());
+
+        assert_eq!(prefetch.select(&uids, &HashSet::new()), [9, 10].into_iter().collect());
+        assert_eq!(prefetch.select(&[7], &HashSet::new()), [7].into_iter().collect());
+    }
+
+    #[test]
+    fn select_status_reads_exists_and_highest_modseq() {
+        let response = b"* FLAGS (\\Answered \\Flagged \\Seen)\r\n\
+* OK [PERMANENTFLAGS ()] Flags permitted.\r\n\
+* OK [UIDVALIDITY 3] UIDs valid.\r\n\
+* 1234 EXISTS\r\n\
+* 0 RECENT\r\n\
+* OK [UIDNEXT 5678] Predicted next UID.\r\n\
+* OK [HIGHESTMODSEQ 9876543]\r\n\
+A4 OK [READ-WRITE] INBOX selected. (Success)\r\n";
+        assert_eq!(parse_select_status(response), (1234, Some(9876543)));
+
+        let nomodseq = b"* 2 EXISTS\r\n* OK [NOMODSEQ] Sorry\r\nA1 OK done\r\n";
+        assert_eq!(parse_select_status(nomodseq), (2, None));
+    }
+
+    #[test]
+    fn capabilities_are_named_as_advertised() {
+        use imap_proto::types::Capability;
+        assert_eq!(capability_name(&Capability::Imap4rev1), "IMAP4rev1");
+        assert_eq!(capability_name(&Capability::Auth("XOAUTH2")), "AUTH=XOAUTH2");
+        assert_eq!(capability_name(&Capability::Atom("X-GM-EXT-1")), "X-GM-EXT-1");
+    }
+
+    #[test]
+    fn category_search_is_quoted_and_limited_to_known_tabs() {
+        assert_eq!(
+            category_search_query("Promotions").unwrap(),
+            "X-GM-RAW \"category:promotions\""
+        );
+        assert!(category_search_query("promotions\" OR ALL").is_err());
+        assert!(category_search_query("").is_err());
+    }
+
+    #[test]
+    fn older_fetch_stays_strictly_below_the_low_water_mark() {
+        assert_eq!(older_uid_query(0), None);
+        assert_eq!(older_uid_query(1), None);
+        assert_eq!(older_uid_query(2).as_deref(), Some("UID 1:1"));
+        assert_eq!(older_uid_query(500).as_deref(), Some("UID 1:499"));
+
+        let found = vec![3, 499, 17, 500, 250, 1];
+        assert_eq!(older_uids_newest_first(found.clone(), 500, 3), vec![499, 250, 17]);
+        assert_eq!(older_uids_newest_first(found, 500, 10), vec![499, 250, 17, 3, 1]);
+        assert!(older_uids_newest_first(vec![7], 7, 10).is_empty());
+    }
+
+    #[test]
+    fn range_search_widens_to_whole_days() {
+        // 2021-01-01T00:00:00Z .. 2022-01-01T00:00:00Z: already on day boundaries.
+        assert_eq!(
+            range_search_query(1_609_459_200, 1_640_995_200).unwrap(),
+            "SINCE 01-Jan-2021 BEFORE 01-Jan-2022"
+        );
+        // 2021-03-05T13:00Z .. 2021-03-07T09:30Z keeps the partial days at both ends.
+        assert_eq!(
+            range_search_query(1_614_949_200, 1_615_109_400).unwrap(),
+            "SINCE 05-Mar-2021 BEFORE 08-Mar-2021"
+        );
+        assert!(range_search_query(1_640_995_200, 1_609_459_200).is_err());
+    }
+
+    #[test]
+    fn missing_credentials_are_not_auth_failures() {
+        let err = credentials_error(format!("{} for a@b.com", CREDENTIALS_MISSING_ERROR));
+        assert_eq!(err.kind, SyncErrorKind::CredentialsMissing);
+        assert_eq!(err.message, "No credentials configured for a@b.com");
+        assert_eq!(serde_json::to_value(&err).unwrap()["kind"], "credentials_missing");
+
+        let err = credentials_error("Failed to retrieve from Keychain: denied".to_string());
+        assert_eq!(err.kind, SyncErrorKind::AuthFailed);
+    }
+
+    #[test]
+    fn large_store_is_split_into_chunks() {
+        let uids: Vec<u32> = (1..=5000).collect();
+        let sequences = uid_store_sequences(&uids, STORE_CHUNK_SIZE);
+        assert_eq!(sequences.len(), 10);
+        assert!(sequences[0].starts_with("1,2,"));
+        assert!(sequences[9].ends_with(",5000"));
+        assert_eq!(sequences.iter().map(|seq| seq.split(',').count()).sum::<usize>(), 5000);
+    }
+
+    #[test]
+    fn minimal_headers_match_envelope_formatting() {
+        let raw = b"Subject: =?UTF-8?B?SGVsbG8gd29ybGQ=?=\r\n\
+From: \"Shop News\" <news@shop.com>\r\n\
+Message-ID: <abc123@shop.com>\r\n\r\n";
+        let (subject, sender, message_id) = parse_minimal_headers(raw);
+        assert_eq!(subject, "Hello world");
+        assert_eq!(sender, "Shop News <news@shop.com>");
+        assert_eq!(message_id, "<abc123@shop.com>");
+
+        let (subject, sender, message_id) = parse_minimal_headers(b"From: a@b.com\r\n\r\n");
+        assert_eq!(subject, "(No Subject)");
+        assert_eq!(sender, "a@b.com");
+        assert_eq!(message_id, "");
+    }
+
+    #[test]
+    fn from_header_comment_becomes_the_sender_name() {
+        assert_eq!(
+            parse_from_header(b"From: alerts@service.com (Service Alerts)\r\n\r\n").as_deref(),
+            Some("Service Alerts <alerts@service.com>")
+        );
+        assert_eq!(
+            parse_from_header(b"From: alerts@service.com\r\n\r\n").as_deref(),
+            Some("alerts@service.com")
+        );
+    }
+
+    #[test]
+    fn envelope_maps_flags_and_addresses() {
+        let from = vec![imap_proto::types::Address {
+            name: Some(b"=?UTF-8?Q?Caf=C3=A9?="),
+            adl: None,
+            mailbox: Some(b"news"),
+            host: Some(b"cafe.com"),
+        }];
+        let envelope = imap_proto::types::Envelope {
+            date: Some(b"Tue, 2 Jan 2024 12:00:00 +0000"),
+            subject: Some(b"Weekly menu"),
+            from: Some(from),
+            sender: None,
+            reply_to: None,
+            to: None,
+            cc: None,
+            bcc: None,
+            in_reply_to: None,
+            message_id: Some(b"<menu@cafe.com>"),
+        };
+
+        let email = envelope_to_gmail_email(7, &envelope, &[Flag::Seen, Flag::Flagged]);
+        assert_eq!(email.uid, 7);
+        assert_eq!(email.subject, "Weekly menu");
+        assert_eq!(email.sender, "Café <news@cafe.com>");
+        assert_eq!(email.date_epoch, 1704196800);
+        assert_eq!(email.message_id, "<menu@cafe.com>");
+        assert!(email.is_read && email.is_flagged);
+
+        let unread = envelope_to_gmail_email(7, &envelope, &[]);
+        assert!(!unread.is_read && !unread.is_flagged);
+    }
+
+    #[test]
+    fn snippet_collapses_whitespace_and_skips_quotes() {
+        let text = "Hi Sam,\n\n  Lunch   tomorrow?\n> old quoted line\nSee you.\n\nOn Mon, Jan 1, Ann wrote:\nolder thread";
+        assert_eq!(
+            make_snippet(Some(text), None).as_deref(),
+            Some("Hi Sam, Lunch tomorrow? See you.")
+        );
+
+        let html = "<p>Big <b>sale</b> today</p>";
+        assert_eq!(make_snippet(None, Some(html)).as_deref(), Some("Big sale today"));
+
+        let long = "word ".repeat(100);
+        assert_eq!(make_snippet(Some(&long), None).unwrap().chars().count(), SNIPPET_LEN - 1);
+        assert_eq!(make_snippet(Some("> only quoted"), None), None);
+    }
+
+    #[test]
+    fn parsed_headers_are_unfolded_and_capped() {
+        let mut raw = String::new();
+        for hop in 0..150 {
+            raw.push_str(&format!("Received: from relay{} by mx.example.com\r\n", hop));
+        }
+        raw.push_str("Subject: =?UTF-8?B?SGVsbG8=?=\r\n  world\r\n\r\nBody\r\n");
+
+        let body = parse_email_body(raw.as_bytes()).unwrap();
+        assert_eq!(body.headers.len(), MAX_HEADERS);
+        assert_eq!(body.headers[0], ("Received".to_string(), "from relay0 by mx.example.com".to_string()));
+
+        let body = parse_email_body(b"Subject: =?UTF-8?B?SGVsbG8=?=\r\n  world\r\nReply-To: a@b.com\r\n\r\nBody\r\n").unwrap();
+        assert_eq!(body.headers[0], ("Subject".to_string(), "Hello world".to_string()));
+        assert_eq!(body.headers[1], ("Reply-To".to_string(), "a@b.com".to_string()));
+    }
 }
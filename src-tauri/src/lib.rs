@@ -1,20 +1,32 @@
 mod filters;
 mod gmail;
+mod logging;
+mod rate_limit;
+mod sanitize;
 mod storage;
 
 use filters::FilterPattern;
+use log::{debug, error, info, warn};
 use std::sync::Arc;
 use tauri::AppHandle;
 use tauri::Emitter;
 use tauri::Manager;
 use tauri::State;
 use tokio::sync::mpsc;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::ops::ControlFlow;
+use std::sync::atomic::{AtomicBool, Ordering};
 
 struct AppState {
     storage: Arc<dyn storage::Storage>,
     syncing: Arc<tokio::sync::Mutex<HashSet<String>>>,
     filter_syncing: Arc<tokio::sync::Mutex<HashSet<String>>>,
+    /// Paces STORE/MOVE/EXPUNGE per account so Gmail doesn't throttle bulk cleanup.
+    imap_limiter: Arc<rate_limit::RateLimiter>,
+    /// Cancel flags for running background syncs, by account.
+    sync_cancel: Arc<std::sync::Mutex<HashMap<String, Arc<AtomicBool>>>>,
+    /// Where a corrupt database was moved at startup, if it was.
+    db_recovered_from: Option<String>,
 }
 
 #[derive(serde::Serialize, Clone)]
@@ -23,6 +35,49 @@ struct SyncProgress {
     processed: usize,
     total: usize,
     message: Option<String>,
+    /// Estimated seconds left, once some emails have been fetched.
+    eta_secs: Option<u64>,
+    /// Envelopes fetched per round trip, on the `start` event of a sync.
+    batch_size: Option<usize>,
+}
+
+/// Log a background sync that failed, either with a `SyncError` or because its
+/// task died, and report it to the UI as an `error` progress event. `phase`
+/// names the sync in the log. A successful result is ignored.
+fn emit_sync_failure<T>(
+    app: &AppHandle,
+    phase: &str,
+    result: Result<Result<T, gmail::SyncError>, tokio::task::JoinError>,
+) {
+    let message = match result {
+        Ok(Ok(_)) => return,
+        Ok(Err(err)) => {
+            if err.kind == gmail::SyncErrorKind::CredentialsMissing {
+                // Credentials were removed while the sync was queued; nothing to retry.
+                warn!("{} skipped: {}", phase, err.message);
+            } else {
+                error!("{} failed: {}", phase, err.message);
+            }
+            // Structured `{ kind, message }` so the UI can tell auth failures from
+            // network trouble; `message` stays human-readable.
+            serde_json::to_string(&err).unwrap_or(err.message)
+        }
+        Err(err) => {
+            error!("{} task error: {}", phase, err);
+            format!("Task error: {}", err)
+        }
+    };
+    let _ = app.emit(
+        "imap_sync_progress",
+        SyncProgress {
+            stage: "error".to_string(),
+            processed: 0,
+            total: 0,
+            message: Some(message),
+            eta_secs: None,
+            batch_size: None,
+        },
+    );
 }
 
 #[tauri::command]
@@ -38,6 +93,45 @@ fn save_filter_patterns(
     state.storage.save_filters(&patterns)
 }
 
+/// Current filters as a shareable `FilterConfig` JSON document.
+#[tauri::command]
+fn export_filters(state: State<AppState>) -> Result<String, String> {
+    state.storage.export_filters()
+}
+
+#[tauri::command]
+fn import_filters(
+    state: State<AppState>,
+    json: String,
+    mode: filters::ImportMode,
+) -> Result<Vec<FilterPattern>, String> {
+    state.storage.import_filters(&json, mode)
+}
+
+/// What a forced filter rebuild would add and remove, without changing anything.
+#[tauri::command]
+fn preview_filter_refresh(
+    state: State<AppState>,
+    email: String,
+) -> Result<storage::FilterRefreshPreview, String> {
+    state.storage.preview_filter_refresh(&email)
+}
+
+/// Hand-assign an email to filters, replacing its automatic matches. With `protect`,
+/// later filter refreshes (even full rebuilds) leave the assignment alone.
+#[tauri::command]
+fn gmail_set_email_filters(
+    state: State<AppState>,
+    email: String,
+    uid: u32,
+    filter_ids: Vec<i64>,
+    protect: bool,
+) -> Result<(), String> {
+    state
+        .storage
+        .set_email_filters(&email, uid, &filter_ids, protect)
+}
+
 #[derive(serde::Serialize)]
 struct FilterMatchCount {
     id: i64,
@@ -50,12 +144,18 @@ fn gmail_list_filtered_emails(
     email: String,
     filter_ids: Vec<i64>,
     unread_only: bool,
+    sort: Option<storage::SortBy>,
     limit: u32,
     offset: u32,
 ) -> Result<Vec<storage::StoredEmail>, String> {
-    state
-        .storage
-        .list_filtered_emails(&email, &filter_ids, unread_only, limit, offset)
+    state.storage.list_filtered_emails(
+        &email,
+        &filter_ids,
+        unread_only,
+        sort.unwrap_or_default(),
+        limit,
+        offset,
+    )
 }
 
 #[tauri::command]
@@ -70,6 +170,51 @@ fn gmail_count_filtered_emails(
         .count_filtered_emails(&email, &filter_ids, unread_only)
 }
 
+/// Emails no enabled filter matches, for the "everything else" view.
+#[tauri::command]
+fn gmail_list_unfiltered(
+    state: State<AppState>,
+    email: String,
+    unread_only: bool,
+    limit: u32,
+    offset: u32,
+) -> Result<Vec<storage::StoredEmail>, String> {
+    state
+        .storage
+        .list_unfiltered_emails(&email, unread_only, limit, offset)
+}
+
+#[tauri::command]
+fn gmail_count_unfiltered(state: State<AppState>, email: String, unread_only: bool) -> Result<u64, String> {
+    state.storage.count_unfiltered_emails(&email, unread_only)
+}
+
+/// Emails matching more than one of `filter_ids`; explains why the per-filter counts
+/// of a multi-filter selection sum to more than `gmail_count_filtered_emails`.
+#[tauri::command]
+fn gmail_count_filtered_overlap(
+    state: State<AppState>,
+    email: String,
+    filter_ids: Vec<i64>,
+    unread_only: bool,
+) -> Result<u64, String> {
+    state
+        .storage
+        .count_filtered_emails_overlap(&email, &filter_ids, unread_only)
+}
+
+/// How many emails a bulk action on `filter_ids` would change, shown before the user
+/// confirms it.
+#[tauri::command]
+fn estimate_bulk_action(
+    state: State<AppState>,
+    email: String,
+    filter_ids: Vec<i64>,
+    action: storage::BulkAction,
+) -> Result<storage::BulkEstimate, String> {
+    state.storage.estimate_bulk_action(&email, &filter_ids, action)
+}
+
 #[tauri::command]
 fn gmail_filter_match_counts(
     state: State<AppState>,
@@ -83,6 +228,54 @@ fn gmail_filter_match_counts(
         .collect())
 }
 
+/// Like `gmail_filter_match_counts`, but each email only counts toward its
+/// highest-priority matching filter.
+#[tauri::command]
+fn gmail_primary_filter_counts(
+    state: State<AppState>,
+    email: String,
+) -> Result<Vec<FilterMatchCount>, String> {
+    let mut counts: HashMap<i64, u64> = HashMap::new();
+    for (_, filter_id) in state.storage.primary_filter_for_emails(&email)? {
+        *counts.entry(filter_id).or_default() += 1;
+    }
+    let mut counts: Vec<FilterMatchCount> = counts
+        .into_iter()
+        .map(|(id, count)| FilterMatchCount { id, count })
+        .collect();
+    counts.sort_by_key(|entry| entry.id);
+    Ok(counts)
+}
+
+/// Preview which cached emails a filter would match without saving it
+#[tauri::command]
+#[allow(clippy::too_many_arguments)] // Arguments mirror the JS call's named fields.
+fn test_filter_preview(
+    state: State<AppState>,
+    email: String,
+    pattern: String,
+    field: filters::FilterField,
+    is_regex: bool,
+    case_sensitive: Option<bool>,
+    match_mode: Option<filters::MatchMode>,
+    limit: u32,
+) -> Result<Vec<storage::StoredEmail>, String> {
+    let filter = FilterPattern {
+        id: 0,
+        name: String::new(),
+        pattern,
+        field,
+        is_regex,
+        case_sensitive: case_sensitive.unwrap_or(false),
+        priority: 0,
+        action: filters::FilterAction::None,
+        action_dry_run: false,
+        match_mode: match_mode.unwrap_or_default(),
+        enabled: true,
+    };
+    state.storage.preview_filter(&email, &filter, limit)
+}
+
 // =============================================================================
 // Gmail IMAP Commands (App Passwords)
 // =============================================================================
@@ -103,12 +296,70 @@ async fn gmail_test_connection(email: String, app_password: String) -> Result<St
         .map_err(|e| format!("Task error: {}", e))?
 }
 
+/// Log in and list the server's capabilities, to see which optional features (MOVE,
+/// CONDSTORE, Gmail extensions) an account supports. Read-only.
+#[tauri::command]
+async fn gmail_server_capabilities(email: String, app_password: String) -> Result<Vec<String>, String> {
+    tokio::task::spawn_blocking(move || gmail::server_capabilities(&email, &app_password))
+        .await
+        .map_err(|e| format!("Task error: {}", e))?
+}
+
+#[derive(serde::Serialize)]
+struct ConnectionStatus {
+    online: bool,
+    message: Option<String>,
+}
+
+/// Cheap connectivity check (IMAP NOOP on a kept-alive session) for the offline banner
+#[tauri::command]
+async fn gmail_ping(email: String) -> Result<ConnectionStatus, String> {
+    let result = tokio::task::spawn_blocking(move || gmail::ping(&email))
+        .await
+        .map_err(|e| format!("Task error: {}", e))?;
+    Ok(match result {
+        Ok(()) => ConnectionStatus {
+            online: true,
+            message: None,
+        },
+        Err(err) => ConnectionStatus {
+            online: false,
+            message: Some(err),
+        },
+    })
+}
+
 /// Check if Gmail account is configured
 #[tauri::command]
 fn gmail_is_configured(email: String) -> bool {
     gmail::has_credentials(&email)
 }
 
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+struct ConfiguredAccount {
+    email: String,
+    /// False when the Keychain entry outlived the account's cache, e.g. after
+    /// `gmail_delete_account_data`; the UI should start a fresh sync.
+    has_cache: bool,
+}
+
+/// Accounts with credentials in the Keychain, so the account list can be rebuilt on startup.
+#[tauri::command]
+async fn gmail_list_accounts(state: State<'_, AppState>) -> Result<Vec<ConfiguredAccount>, String> {
+    let storage = state.storage.clone();
+    tokio::task::spawn_blocking(move || {
+        gmail::list_configured_accounts()?
+            .into_iter()
+            .map(|email| {
+                let has_cache = storage.get_max_uid(&email, "INBOX")?.is_some();
+                Ok(ConfiguredAccount { email, has_cache })
+            })
+            .collect()
+    })
+    .await
+    .map_err(|e| format!("Task error: {}", e))?
+}
+
 /// Delete Gmail credentials from Keychain
 #[tauri::command]
 async fn gmail_delete_credentials(email: String) -> Result<(), String> {
@@ -117,6 +368,24 @@ async fn gmail_delete_credentials(email: String) -> Result<(), String> {
         .map_err(|e| format!("Task error: {}", e))?
 }
 
+/// Remove all cached data and stored credentials for an account
+#[tauri::command]
+async fn gmail_delete_account_data(
+    state: State<'_, AppState>,
+    email: String,
+) -> Result<usize, String> {
+    let storage = state.storage.clone();
+    tokio::task::spawn_blocking(move || {
+        let deleted = storage.delete_account_emails(&email)?;
+        if gmail::has_credentials(&email) {
+            gmail::delete_credentials(&email)?;
+        }
+        Ok(deleted)
+    })
+    .await
+    .map_err(|e| format!("Task error: {}", e))?
+}
+
 /// Fetch unread emails from Gmail via IMAP
 #[tauri::command]
 async fn gmail_fetch_unread(
@@ -125,7 +394,7 @@ async fn gmail_fetch_unread(
 ) -> Result<Vec<gmail::GmailEmail>, String> {
     let storage = state.storage.clone();
     tokio::task::spawn_blocking(move || {
-        let emails = gmail::fetch_unread_emails(&email)?;
+        let emails = gmail::fetch_unread_emails(&email, gmail::ImapTimeouts::default())?;
         storage.upsert_emails(&email, "INBOX", &emails)?;
         Ok(emails)
     })
@@ -133,243 +402,1503 @@ async fn gmail_fetch_unread(
     .map_err(|e| format!("Task error: {}", e))?
 }
 
-/// Mark Gmail emails as read (batch operation)
+/// Outcome of marking emails read on the server and then in the cache. `error` is
+/// set when either step failed; a server count above the cache count means the
+/// cache is behind and a sync will catch it up.
+#[derive(Debug, PartialEq, serde::Serialize)]
+struct MarkReadResult {
+    server_marked: usize,
+    cache_marked: usize,
+    error: Option<String>,
+}
+
+impl MarkReadResult {
+    /// Run the server update, then the cache update only if the server accepted it.
+    fn run(
+        server: impl FnOnce() -> Result<usize, String>,
+        cache: impl FnOnce() -> Result<usize, String>,
+    ) -> Self {
+        let server_marked = match server() {
+            Ok(count) => count,
+            Err(err) => {
+                return Self {
+                    server_marked: 0,
+                    cache_marked: 0,
+                    error: Some(err),
+                }
+            }
+        };
+        match cache() {
+            Ok(cache_marked) => Self {
+                server_marked,
+                cache_marked,
+                error: None,
+            },
+            Err(err) => {
+                warn!("Marked {} emails read on the server but not in the cache: {}", server_marked, err);
+                Self {
+                    server_marked,
+                    cache_marked: 0,
+                    error: Some(err),
+                }
+            }
+        }
+    }
+}
+
+/// Mark Gmail emails as read (batch operation); cache only when the account is local-only.
 #[tauri::command]
 async fn gmail_mark_as_read(
     state: State<'_, AppState>,
     email: String,
     uids: Vec<u32>,
-) -> Result<usize, String> {
+) -> Result<MarkReadResult, String> {
     let storage = state.storage.clone();
+    let limiter = state.imap_limiter.clone();
     tokio::task::spawn_blocking(move || {
-        let count = gmail::mark_emails_as_read(&email, uids.clone())?;
-        storage.mark_emails_read(&email, &uids)?;
-        Ok(count)
+        if local_only(storage.as_ref(), &email) {
+            return MarkReadResult::run(|| Ok(0), || storage.mark_emails_read_locally(&email, &uids));
+        }
+        MarkReadResult::run(
+            || limiter.run(&email, || gmail::mark_emails_as_read(&email, uids.clone())),
+            || storage.mark_emails_read(&email, &uids),
+        )
     })
     .await
-    .map_err(|e| format!("Task error: {}", e))?
+    .map_err(|e| format!("Task error: {}", e))
 }
 
-/// Mark Gmail emails as unread (batch operation)
+/// Mark every unread email from one sender as read, on the server and in the cache
+/// (the cache only when the account is local-only).
 #[tauri::command]
-async fn gmail_mark_as_unread(
+async fn gmail_mark_sender_read(
     state: State<'_, AppState>,
     email: String,
-    uids: Vec<u32>,
+    sender: String,
 ) -> Result<usize, String> {
     let storage = state.storage.clone();
+    let limiter = state.imap_limiter.clone();
     tokio::task::spawn_blocking(move || {
-        let count = gmail::mark_emails_as_unread(&email, uids.clone())?;
-        storage.mark_emails_unread(&email, &uids)?;
-        Ok(count)
+        let uids = storage.uids_for_sender(&email, &sender, true)?;
+        mark_read(storage.as_ref(), &limiter, &email, &uids)
     })
     .await
     .map_err(|e| format!("Task error: {}", e))?
 }
 
-/// Run IMAP fetch in the background and emit progress events.
+/// UIDs per STORE/MOVE in bulk operations; Gmail rejects overly long sequences.
+const BULK_CHUNK_SIZE: usize = 500;
+
+/// Mark every cached unread email in the account as read (in the cache only when the
+/// account is local-only), emitting `imap_sync_progress` per chunk. Refuses to run
+/// while a sync is active.
 #[tauri::command]
-async fn gmail_sync_unread_background(
+async fn gmail_mark_all_read(
     app: AppHandle,
     state: State<'_, AppState>,
     email: String,
-) -> Result<(), String> {
+) -> Result<usize, String> {
+    let syncing = state.syncing.clone();
+    {
+        let mut guard = syncing.lock().await;
+        if guard.contains(&email) {
+            return Err("A sync is already running for this account".to_string());
+        }
+        // Hold the account so a sync doesn't start mid-way.
+        guard.insert(email.clone());
+    }
+
     let storage = state.storage.clone();
-    let handle = app.clone();
-    tokio::spawn(async move {
-        let _ = handle.emit(
+    let limiter = state.imap_limiter.clone();
+    let account = email.clone();
+    let result = tokio::task::spawn_blocking(move || {
+        let uids = storage.all_unread_uids(&account)?;
+        let total = uids.len();
+        let _ = app.emit(
             "imap_sync_progress",
             SyncProgress {
                 stage: "start".to_string(),
                 processed: 0,
-                total: 0,
+                total,
                 message: None,
+                eta_secs: None,
+                batch_size: None,
             },
         );
 
-        let result = tokio::task::spawn_blocking(move || {
-            let emails = gmail::fetch_unread_emails(&email)?;
-            storage.upsert_emails(&email, "INBOX", &emails)?;
-            Ok::<usize, String>(emails.len())
-        })
-        .await;
-
-        match result {
-            Ok(Ok(count)) => {
-                let _ = handle.emit(
-                    "imap_sync_progress",
-                    SyncProgress {
-                        stage: "complete".to_string(),
-                        processed: count,
-                        total: count,
-                        message: None,
-                    },
-                );
-            }
-            Ok(Err(err)) => {
-                let _ = handle.emit(
-                    "imap_sync_progress",
-                    SyncProgress {
-                        stage: "error".to_string(),
-                        processed: 0,
-                        total: 0,
-                        message: Some(err),
-                    },
-                );
-            }
-            Err(err) => {
-                let _ = handle.emit(
-                    "imap_sync_progress",
-                    SyncProgress {
-                        stage: "error".to_string(),
-                        processed: 0,
-                        total: 0,
-                        message: Some(format!("Task error: {}", err)),
-                    },
-                );
-            }
+        let mut processed = 0;
+        for chunk in uids.chunks(BULK_CHUNK_SIZE) {
+            processed += mark_read(storage.as_ref(), &limiter, &account, chunk)?;
+            let _ = app.emit(
+                "imap_sync_progress",
+                SyncProgress {
+                    stage: "progress".to_string(),
+                    processed,
+                    total,
+                    message: None,
+                    eta_secs: None,
+                    batch_size: None,
+                },
+            );
         }
-    });
 
-    Ok(())
+        let _ = app.emit(
+            "imap_sync_progress",
+            SyncProgress {
+                stage: "complete".to_string(),
+                processed,
+                total,
+                message: None,
+                eta_secs: None,
+                batch_size: None,
+            },
+        );
+        Ok(processed)
+    })
+    .await
+    .map_err(|e| format!("Task error: {}", e));
+
+    syncing.lock().await.remove(&email);
+    result?
 }
 
-/// Run IMAP fetch for all emails in the background and emit progress events.
-#[tauri::command]
-async fn gmail_sync_all_background(
+/// What a filter-wide bulk operation does to each chunk of matched emails.
+#[derive(Clone, Copy)]
+enum FilterBulkOp {
+    MarkRead,
+    Trash,
+    Archive,
+}
+
+/// Outcome of a filter-wide bulk operation. `failed` counts emails in chunks the
+/// server rejected; they are left untouched in the cache.
+#[derive(serde::Serialize)]
+struct BulkResult {
+    matched: usize,
+    applied: usize,
+    failed: usize,
+}
+
+/// Apply `op` to every email matched by `filter_id`, in chunks, emitting
+/// `imap_sync_progress` as it goes. A failed chunk is counted and skipped so one
+/// bad batch doesn't abandon the rest. Refuses to run while a sync is active.
+async fn apply_to_filter(
     app: AppHandle,
-    state: State<'_, AppState>,
+    state: &AppState,
     email: String,
-) -> Result<(), String> {
-    let storage = state.storage.clone();
+    filter_id: i64,
+    op: FilterBulkOp,
+) -> Result<BulkResult, String> {
     let syncing = state.syncing.clone();
-    let handle = app.clone();
-
     {
         let mut guard = syncing.lock().await;
         if guard.contains(&email) {
-            println!("[InboxCleanup] Sync already running for {}", email);
-            return Ok(());
+            return Err("A sync is already running for this account".to_string());
         }
         guard.insert(email.clone());
     }
 
-    tokio::spawn(async move {
-        println!("[InboxCleanup] Background sync started for {}", email);
-        let _ = handle.emit(
-            "imap_sync_progress",
-            SyncProgress {
-                stage: "start".to_string(),
-                processed: 0,
-                total: 0,
-                message: None,
-            },
-        );
-
-        let (tx, mut rx) = mpsc::unbounded_channel::<(usize, usize)>();
-        let progress_handle = handle.clone();
-        let progress_task = tokio::spawn(async move {
-            while let Some((processed, total)) = rx.recv().await {
-                println!(
-                    "[InboxCleanup] Sync progress: {}/{} ({:.0}%)",
+    let storage = state.storage.clone();
+    let limiter = state.imap_limiter.clone();
+    let account = email.clone();
+    let result = tokio::task::spawn_blocking(move || {
+        let unread_only = matches!(op, FilterBulkOp::MarkRead);
+        let uids = storage.uids_for_filter(&account, filter_id, unread_only)?;
+        let matched = uids.len();
+        let emit = |stage: &str, processed: usize| {
+            let _ = app.emit(
+                "imap_sync_progress",
+                SyncProgress {
+                    stage: stage.to_string(),
                     processed,
-                    total,
-                    if total > 0 {
-                        (processed as f64 / total as f64) * 100.0
-                    } else {
-                        0.0
-                    }
-                );
-                let _ = progress_handle.emit(
-                    "imap_sync_progress",
-                    SyncProgress {
-                        stage: "progress".to_string(),
-                        processed,
-                        total,
-                        message: None,
-                    },
-                );
-            }
-        });
+                    total: matched,
+                    message: None,
+                    eta_secs: None,
+                    batch_size: None,
+                },
+            );
+        };
+        emit("start", 0);
 
-        let storage_for_sync = storage.clone();
+        let mut applied = 0;
+        let mut failed = 0;
+        for chunk in uids.chunks(BULK_CHUNK_SIZE) {
+            let outcome = match op {
+                FilterBulkOp::MarkRead => mark_read(storage.as_ref(), &limiter, &account, chunk),
+                // Trash is local until emptied, so there is no server round trip.
+                FilterBulkOp::Trash => storage.trash_emails(&account, chunk),
+                FilterBulkOp::Archive => limiter
+                    .run(&account, || gmail::archive_emails(&account, chunk))
+                    .and_then(|_| storage.remove_emails(&account, chunk)),
+            };
+            match outcome {
+                Ok(_) => applied += chunk.len(),
+                Err(err) => {
+                    warn!("Bulk operation failed for {} emails of filter {}: {}", chunk.len(), filter_id, err);
+                    failed += chunk.len();
+                }
+            }
+            emit("progress", applied + failed);
+        }
+
+        emit("complete", applied + failed);
+        Ok(BulkResult {
+            matched,
+            applied,
+            failed,
+        })
+    })
+    .await
+    .map_err(|e| format!("Task error: {}", e));
+
+    syncing.lock().await.remove(&email);
+    result?
+}
+
+/// Mark every unread email matched by a filter as read, on the server and in the cache.
+#[tauri::command]
+async fn gmail_mark_filter_read(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    email: String,
+    filter_id: i64,
+) -> Result<BulkResult, String> {
+    apply_to_filter(app, &state, email, filter_id, FilterBulkOp::MarkRead).await
+}
+
+/// Move every email matched by a filter to the local trash.
+#[tauri::command]
+async fn gmail_trash_filter(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    email: String,
+    filter_id: i64,
+) -> Result<BulkResult, String> {
+    apply_to_filter(app, &state, email, filter_id, FilterBulkOp::Trash).await
+}
+
+/// Archive every email matched by a filter on the server and drop them from the cache.
+#[tauri::command]
+async fn gmail_archive_filter(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    email: String,
+    filter_id: i64,
+) -> Result<BulkResult, String> {
+    apply_to_filter(app, &state, email, filter_id, FilterBulkOp::Archive).await
+}
+
+/// Star or unstar emails on the server and in the cache.
+#[tauri::command]
+async fn gmail_set_flagged(
+    state: State<'_, AppState>,
+    email: String,
+    uids: Vec<u32>,
+    flagged: bool,
+) -> Result<usize, String> {
+    let storage = state.storage.clone();
+    let limiter = state.imap_limiter.clone();
+    tokio::task::spawn_blocking(move || {
+        let count = limiter.run(&email, || gmail::set_flagged(&email, &uids, flagged))?;
+        storage.set_flagged(&email, &uids, flagged)?;
+        Ok(count)
+    })
+    .await
+    .map_err(|e| format!("Task error: {}", e))?
+}
+
+#[tauri::command]
+fn gmail_list_flagged(
+    state: State<AppState>,
+    email: String,
+    limit: u32,
+    offset: u32,
+) -> Result<Vec<storage::StoredEmail>, String> {
+    state.storage.list_flagged(&email, limit, offset)
+}
+
+/// Move emails to the local trash. Nothing changes on the server until the trash is emptied.
+#[tauri::command]
+fn gmail_trash_emails(state: State<AppState>, email: String, uids: Vec<u32>) -> Result<usize, String> {
+    state.storage.trash_emails(&email, &uids)
+}
+
+#[tauri::command]
+fn gmail_untrash_emails(state: State<AppState>, email: String, uids: Vec<u32>) -> Result<usize, String> {
+    state.storage.untrash_emails(&email, &uids)
+}
+
+/// Archive emails on the server (out of INBOX, kept in All Mail) and drop them from the cache.
+#[tauri::command]
+async fn gmail_archive_emails(
+    state: State<'_, AppState>,
+    email: String,
+    uids: Vec<u32>,
+) -> Result<usize, String> {
+    let storage = state.storage.clone();
+    let limiter = state.imap_limiter.clone();
+    tokio::task::spawn_blocking(move || {
+        let count = limiter.run(&email, || gmail::archive_emails(&email, &uids))?;
+        storage.remove_emails(&email, &uids)?;
+        Ok(count)
+    })
+    .await
+    .map_err(|e| format!("Task error: {}", e))?
+}
+
+/// Move emails out of INBOX into `target_mailbox` (e.g. a Gmail label) on the server
+/// and drop them from the cache, which only mirrors INBOX.
+#[tauri::command]
+async fn gmail_move_emails(
+    state: State<'_, AppState>,
+    email: String,
+    uids: Vec<u32>,
+    target_mailbox: String,
+) -> Result<usize, String> {
+    let storage = state.storage.clone();
+    let limiter = state.imap_limiter.clone();
+    tokio::task::spawn_blocking(move || {
+        let count = limiter.run(&email, || gmail::move_emails(&email, &uids, &target_mailbox))?;
+        storage.remove_emails(&email, &uids)?;
+        Ok(count)
+    })
+    .await
+    .map_err(|e| format!("Task error: {}", e))?
+}
+
+/// Mailboxes emails can be moved to, sorted by name.
+#[tauri::command]
+async fn gmail_list_mailboxes(email: String) -> Result<Vec<String>, String> {
+    tokio::task::spawn_blocking(move || gmail::list_mailboxes(&email))
+        .await
+        .map_err(|e| format!("Task error: {}", e))?
+}
+
+/// Message-IDs cached more than once, with the UIDs of each copy.
+#[tauri::command]
+fn gmail_find_duplicates(state: State<AppState>, email: String) -> Result<Vec<(String, Vec<u32>)>, String> {
+    state.storage.duplicate_message_ids(&email)
+}
+
+/// Move all but one copy of each duplicate to the local trash.
+#[tauri::command]
+fn gmail_dedupe_emails(
+    state: State<AppState>,
+    email: String,
+    keep: Option<storage::KeepStrategy>,
+) -> Result<usize, String> {
+    state
+        .storage
+        .dedupe_emails(&email, keep.unwrap_or(storage::KeepStrategy::Inbox))
+}
+
+/// Delete trashed emails from the server (`\Deleted` + expunge), then from the cache.
+#[tauri::command]
+async fn gmail_empty_trash(state: State<'_, AppState>, email: String) -> Result<usize, String> {
+    let storage = state.storage.clone();
+    let limiter = state.imap_limiter.clone();
+    tokio::task::spawn_blocking(move || {
+        let uids = storage.trashed_uids(&email)?;
+        limiter.run(&email, || gmail::delete_emails(&email, &uids))?;
+        storage.purge_trashed(&email, &uids)
+    })
+    .await
+    .map_err(|e| format!("Task error: {}", e))?
+}
+
+/// Drop cached read emails older than `retention_days` to keep the database small.
+/// Unread and flagged emails are always kept. Returns how many were removed.
+#[tauri::command]
+async fn purge_old_read(
+    state: State<'_, AppState>,
+    email: String,
+    retention_days: u32,
+) -> Result<usize, String> {
+    if retention_days == 0 {
+        return Err("Retention must be at least one day".to_string());
+    }
+    let cutoff = chrono::Utc::now().timestamp() - i64::from(retention_days) * 24 * 60 * 60;
+    let storage = state.storage.clone();
+    tokio::task::spawn_blocking(move || {
+        let purged = storage.purge_read_older_than(&email, cutoff)?;
+        info!("Purged {} read emails older than {} days for {}", purged, retention_days, email);
+        Ok(purged)
+    })
+    .await
+    .map_err(|e| format!("Task error: {}", e))?
+}
+
+/// Mark Gmail emails as unread (batch operation)
+#[tauri::command]
+async fn gmail_mark_as_unread(
+    state: State<'_, AppState>,
+    email: String,
+    uids: Vec<u32>,
+) -> Result<usize, String> {
+    let storage = state.storage.clone();
+    let limiter = state.imap_limiter.clone();
+    tokio::task::spawn_blocking(move || {
+        let count = limiter.run(&email, || gmail::mark_emails_as_unread(&email, uids.clone()))?;
+        storage.mark_emails_unread(&email, &uids)?;
+        Ok(count)
+    })
+    .await
+    .map_err(|e| format!("Task error: {}", e))?
+}
+
+/// Run IMAP fetch in the background and emit progress events.
+#[tauri::command]
+async fn gmail_sync_unread_background(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    email: String,
+    connect_timeout_secs: Option<u64>,
+    io_timeout_secs: Option<u64>,
+) -> Result<(), String> {
+    let timeouts = gmail::ImapTimeouts::from_secs(connect_timeout_secs, io_timeout_secs);
+    let storage = state.storage.clone();
+    let handle = app.clone();
+    tokio::spawn(async move {
+        let _ = handle.emit(
+            "imap_sync_progress",
+            SyncProgress {
+                stage: "start".to_string(),
+                processed: 0,
+                total: 0,
+                message: None,
+                eta_secs: None,
+                batch_size: None,
+            },
+        );
+
+        let result = tokio::task::spawn_blocking(move || {
+            let emails = gmail::fetch_unread_emails(&email, timeouts)?;
+            storage.upsert_emails(&email, "INBOX", &emails)?;
+            Ok::<usize, String>(emails.len())
+        })
+        .await;
+
+        match result {
+            Ok(Ok(count)) => {
+                let _ = handle.emit(
+                    "imap_sync_progress",
+                    SyncProgress {
+                        stage: "complete".to_string(),
+                        processed: count,
+                        total: count,
+                        message: None,
+                        eta_secs: None,
+                        batch_size: None,
+                    },
+                );
+            }
+            Ok(Err(err)) => {
+                let _ = handle.emit(
+                    "imap_sync_progress",
+                    SyncProgress {
+                        stage: "error".to_string(),
+                        processed: 0,
+                        total: 0,
+                        message: Some(err),
+                        eta_secs: None,
+                        batch_size: None,
+                    },
+                );
+            }
+            Err(err) => {
+                let _ = handle.emit(
+                    "imap_sync_progress",
+                    SyncProgress {
+                        stage: "error".to_string(),
+                        processed: 0,
+                        total: 0,
+                        message: Some(format!("Task error: {}", err)),
+                        eta_secs: None,
+                        batch_size: None,
+                    },
+                );
+            }
+        }
+    });
+
+    Ok(())
+}
+
+/// Display name, color and last full sync of every known account.
+#[tauri::command]
+fn list_accounts(state: State<AppState>) -> Result<Vec<storage::AccountInfo>, String> {
+    state.storage.list_accounts()
+}
+
+/// Set an account's display name and badge color; `None` clears either. Colors are
+/// `#rgb` or `#rrggbb` so they can go straight into a style attribute.
+#[tauri::command]
+fn upsert_account(
+    state: State<AppState>,
+    email: String,
+    display_name: Option<String>,
+    color: Option<String>,
+) -> Result<(), String> {
+    let display_name = display_name
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty());
+    if let Some(color) = &color {
+        let hex = color.strip_prefix('#').unwrap_or("");
+        if !matches!(hex.len(), 3 | 6) || !hex.chars().all(|ch| ch.is_ascii_hexdigit()) {
+            return Err(format!("'{}' is not a hex color like #3b82f6", color));
+        }
+    }
+    state
+        .storage
+        .upsert_account(&email, display_name.as_deref(), color.as_deref())
+}
+
+/// Read a stored preference (see `SETTING_*` for the keys the backend uses).
+#[tauri::command]
+fn get_setting(state: State<AppState>, key: String) -> Result<Option<String>, String> {
+    state.storage.get_setting(&key)
+}
+
+#[tauri::command]
+fn set_setting(state: State<AppState>, key: String, value: String) -> Result<(), String> {
+    state.storage.set_setting(&key, &value)
+}
+
+/// Envelopes fetched per IMAP round trip during a sync, clamped to
+/// `MIN_SYNC_BATCH_SIZE..=MAX_SYNC_BATCH_SIZE`.
+const SETTING_SYNC_BATCH_SIZE: &str = "sync_batch_size";
+const DEFAULT_SYNC_BATCH_SIZE: usize = 1000;
+const MIN_SYNC_BATCH_SIZE: usize = 50;
+const MAX_SYNC_BATCH_SIZE: usize = 5000;
+/// Bounds on how many bodies a sync downloads; within them it prefetches every unread
+/// email plus `BODY_PREFETCH_READ_BUFFER` recent read ones (see `gmail::BodyPrefetch`).
+const SETTING_BODY_PREFETCH_MIN: &str = "body_prefetch_min";
+const DEFAULT_BODY_PREFETCH_MIN: usize = 50;
+const SETTING_BODY_PREFETCH_MAX: &str = "body_prefetch_max";
+const DEFAULT_BODY_PREFETCH_MAX: usize = 2000;
+/// Fixed cap from before prefetching adapted; still honoured as the maximum if set.
+const SETTING_BODY_PREFETCH_LIMIT: &str = "body_prefetch_limit";
+const BODY_PREFETCH_READ_BUFFER: usize = 50;
+
+/// Per-account switch (`local_only:<account>`): mark emails read in the cache only and
+/// leave the server's \Seen flags alone.
+const SETTING_LOCAL_ONLY_PREFIX: &str = "local_only:";
+
+fn local_only(storage: &dyn storage::Storage, account: &str) -> bool {
+    storage
+        .get_setting(&format!("{}{}", SETTING_LOCAL_ONLY_PREFIX, account))
+        .ok()
+        .flatten()
+        .is_some_and(|value| value == "true")
+}
+
+/// Mark `uids` read on the server and then in the cache, or only in the cache when the
+/// account is local-only. Every bulk mark-read path goes through here.
+fn mark_read(
+    storage: &dyn storage::Storage,
+    limiter: &rate_limit::RateLimiter,
+    account: &str,
+    uids: &[u32],
+) -> Result<usize, String> {
+    if local_only(storage, account) {
+        return storage.mark_emails_read_locally(account, uids);
+    }
+    let count = limiter.run(account, || gmail::mark_emails_as_read(account, uids.to_vec()))?;
+    storage.mark_emails_read(account, uids)?;
+    Ok(count)
+}
+
+#[tauri::command]
+fn get_local_only(state: State<AppState>, email: String) -> bool {
+    local_only(state.storage.as_ref(), &email)
+}
+
+#[tauri::command]
+fn set_local_only(state: State<AppState>, email: String, enabled: bool) -> Result<(), String> {
+    state.storage.set_setting(
+        &format!("{}{}", SETTING_LOCAL_ONLY_PREFIX, email),
+        if enabled { "true" } else { "false" },
+    )
+}
+
+/// A numeric setting, or `default` when it's unset or not a number.
+fn numeric_setting(storage: &dyn storage::Storage, key: &str, default: usize) -> usize {
+    storage
+        .get_setting(key)
+        .ok()
+        .flatten()
+        .and_then(|value| value.trim().parse().ok())
+        .unwrap_or(default)
+}
+
+fn sync_batch_size(storage: &dyn storage::Storage) -> usize {
+    numeric_setting(storage, SETTING_SYNC_BATCH_SIZE, DEFAULT_SYNC_BATCH_SIZE)
+        .clamp(MIN_SYNC_BATCH_SIZE, MAX_SYNC_BATCH_SIZE)
+}
+
+/// Seconds left at the average rate so far, or `None` before anything was processed.
+fn estimate_eta(elapsed: std::time::Duration, processed: usize, total: usize) -> Option<u64> {
+    if processed == 0 || total == 0 {
+        return None;
+    }
+    let remaining = total.saturating_sub(processed) as f64;
+    Some((elapsed.as_secs_f64() / processed as f64 * remaining).round() as u64)
+}
+
+/// Run IMAP fetch for all emails in the background and emit progress events.
+#[tauri::command]
+async fn gmail_sync_all_background(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    email: String,
+    connect_timeout_secs: Option<u64>,
+    io_timeout_secs: Option<u64>,
+    minimal_headers: Option<bool>,
+) -> Result<(), String> {
+    let timeouts = gmail::ImapTimeouts::from_secs(connect_timeout_secs, io_timeout_secs);
+    start_background_sync(
+        app,
+        &state,
+        email,
+        None,
+        minimal_headers.unwrap_or(false),
+        false,
+        timeouts,
+    )
+    .await
+}
+
+/// Like `gmail_sync_all_background`, but a first sync only fetches emails received
+/// on or after `since_epoch` (e.g. the last 90 days). Later syncs are incremental
+/// as usual.
+#[tauri::command]
+async fn gmail_sync_since_background(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    email: String,
+    since_epoch: i64,
+    connect_timeout_secs: Option<u64>,
+    io_timeout_secs: Option<u64>,
+    minimal_headers: Option<bool>,
+) -> Result<(), String> {
+    let timeouts = gmail::ImapTimeouts::from_secs(connect_timeout_secs, io_timeout_secs);
+    start_background_sync(
+        app,
+        &state,
+        email,
+        Some(since_epoch),
+        minimal_headers.unwrap_or(false),
+        false,
+        timeouts,
+    )
+    .await
+}
+
+/// Pull INBOX mail received between `since_epoch` and `before_epoch` into the cache
+/// without touching incremental sync state; the range is widened to whole days (see
+/// `gmail::fetch_range`). Returns once started; progress arrives as
+/// `imap_sync_progress` events.
+#[tauri::command]
+async fn gmail_fetch_range_background(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    email: String,
+    since_epoch: i64,
+    before_epoch: i64,
+) -> Result<(), String> {
+    let syncing = state.syncing.clone();
+    {
+        let mut guard = syncing.lock().await;
+        if guard.contains(&email) {
+            return Err("A sync is already running for this account".to_string());
+        }
+        guard.insert(email.clone());
+    }
+
+    let storage = state.storage.clone();
+    tokio::spawn(async move {
+        let handle = app.clone();
+        let account = email.clone();
+        let result = tokio::task::spawn_blocking(move || {
+            let emit = |stage: &str, processed: usize, total: usize| {
+                let _ = handle.emit(
+                    "imap_sync_progress",
+                    SyncProgress {
+                        stage: stage.to_string(),
+                        processed,
+                        total,
+                        message: None,
+                        eta_secs: None,
+                        batch_size: None,
+                    },
+                );
+            };
+            emit("start", 0, 0);
+            let batch_size = sync_batch_size(storage.as_ref());
+            let refresh_chunk = u32::try_from(batch_size).unwrap_or(u32::MAX);
+            let fetched = gmail::fetch_range(&account, since_epoch, before_epoch, batch_size, |chunk| {
+                if let Err(err) = storage.upsert_emails(&account, "INBOX", &chunk.emails) {
+                    warn!("Failed to store fetched range chunk: {}", err);
+                }
+                if let Err(err) = storage.refresh_filtered_emails(&account, refresh_chunk, false) {
+                    warn!("Filter refresh during range fetch failed: {}", err);
+                }
+                emit("progress", chunk.processed, chunk.total);
+            })?;
+            emit("complete", fetched, fetched);
+            Ok::<_, gmail::SyncError>(fetched)
+        })
+        .await;
+
+        match result {
+            Ok(Ok(count)) => info!("Range fetch complete ({} emails)", count),
+            failure => emit_sync_failure(&app, "Range fetch", failure),
+        }
+
+        syncing.lock().await.remove(&email);
+    });
+
+    Ok(())
+}
+
+/// Emails per "load more history" when the UI doesn't ask for a count.
+const DEFAULT_LOAD_OLDER_COUNT: usize = 500;
+
+/// Load up to `count` INBOX emails older than anything synced so far, newest first.
+/// Works down from the low-water mark (`oldest_synced_uid`) while incremental sync works
+/// up from `last_uid`, so the two never overlap; the mark is lowered only after each
+/// chunk is stored, so a failure can't leave a gap. Returns once started; progress
+/// arrives as `imap_sync_progress` events and `complete` carries the number loaded.
+#[tauri::command]
+async fn gmail_load_older_background(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    email: String,
+    count: Option<usize>,
+) -> Result<(), String> {
+    let syncing = state.syncing.clone();
+    {
+        let mut guard = syncing.lock().await;
+        if guard.contains(&email) {
+            return Err("A sync is already running for this account".to_string());
+        }
+        guard.insert(email.clone());
+    }
+
+    let storage = state.storage.clone();
+    let limit = count.unwrap_or(DEFAULT_LOAD_OLDER_COUNT).max(1);
+    tokio::spawn(async move {
+        let handle = app.clone();
+        let account = email.clone();
+        let result = tokio::task::spawn_blocking(move || {
+            let emit = |stage: &str, processed: usize, total: usize| {
+                let _ = handle.emit(
+                    "imap_sync_progress",
+                    SyncProgress {
+                        stage: stage.to_string(),
+                        processed,
+                        total,
+                        message: None,
+                        eta_secs: None,
+                        batch_size: None,
+                    },
+                );
+            };
+            emit("start", 0, 0);
+            let Some(before_uid) = storage.get_oldest_synced_uid(&account, "INBOX")? else {
+                return Err(gmail::SyncError::from("Sync the account before loading older mail".to_string()));
+            };
+            let batch_size = sync_batch_size(storage.as_ref());
+            let refresh_chunk = u32::try_from(batch_size).unwrap_or(u32::MAX);
+            let mut stored_all = true;
+            let fetched = gmail::fetch_emails_before(&account, before_uid, limit, batch_size, |chunk| {
+                if let Err(err) = storage.upsert_emails(&account, "INBOX", &chunk.emails) {
+                    warn!("Failed to store older emails: {}", err);
+                    stored_all = false;
+                    return ControlFlow::Break(());
+                }
+                if let Some(lowest) = chunk.emails.iter().map(|email| email.uid).min() {
+                    let _ = storage.set_oldest_synced_uid(&account, "INBOX", lowest);
+                }
+                if let Err(err) = storage.refresh_filtered_emails(&account, refresh_chunk, false) {
+                    warn!("Filter refresh while loading older mail failed: {}", err);
+                }
+                emit("progress", chunk.processed, chunk.total);
+                ControlFlow::Continue(())
+            })?;
+            // Fewer than asked for means the search ran out: nothing older is left.
+            if stored_all && fetched < limit {
+                storage.set_oldest_synced_uid(&account, "INBOX", 1)?;
+            }
+            emit("complete", fetched, fetched);
+            Ok::<_, gmail::SyncError>(fetched)
+        })
+        .await;
+
+        match result {
+            Ok(Ok(count)) => info!("Loaded {} older emails", count),
+            failure => emit_sync_failure(&app, "Loading older mail", failure),
+        }
+
+        syncing.lock().await.remove(&email);
+    });
+
+    Ok(())
+}
+
+/// Pull every INBOX email in a Gmail category tab (`promotions`, `social`, ...) into
+/// the cache and tag it, so listings can narrow by `category`. Returns once started;
+/// progress arrives as `imap_sync_progress` and `complete` carries the tab's size.
+#[tauri::command]
+async fn gmail_sync_category_background(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    email: String,
+    category: String,
+) -> Result<(), String> {
+    let category = category.trim().to_ascii_lowercase();
+    if !gmail::GMAIL_CATEGORIES.contains(&category.as_str()) {
+        return Err(format!("Unknown Gmail category '{}'", category));
+    }
+    let syncing = state.syncing.clone();
+    {
+        let mut guard = syncing.lock().await;
+        if guard.contains(&email) {
+            return Err("A sync is already running for this account".to_string());
+        }
+        guard.insert(email.clone());
+    }
+
+    let storage = state.storage.clone();
+    tokio::spawn(async move {
+        let handle = app.clone();
+        let account = email.clone();
+        let result = tokio::task::spawn_blocking(move || {
+            let emit = |stage: &str, processed: usize, total: usize| {
+                let _ = handle.emit(
+                    "imap_sync_progress",
+                    SyncProgress {
+                        stage: stage.to_string(),
+                        processed,
+                        total,
+                        message: None,
+                        eta_secs: None,
+                        batch_size: None,
+                    },
+                );
+            };
+            emit("start", 0, 0);
+            let batch_size = sync_batch_size(storage.as_ref());
+            let refresh_chunk = u32::try_from(batch_size).unwrap_or(u32::MAX);
+            let uids = gmail::fetch_category(&account, &category, batch_size, |chunk| {
+                if let Err(err) = storage.upsert_emails(&account, "INBOX", &chunk.emails) {
+                    warn!("Failed to store {} emails: {}", category, err);
+                }
+                if let Err(err) = storage.refresh_filtered_emails(&account, refresh_chunk, false) {
+                    warn!("Filter refresh during category sync failed: {}", err);
+                }
+                emit("progress", chunk.processed, chunk.total);
+            })?;
+            let tagged = storage.replace_gmail_category(&account, &category, &uids)?;
+            emit("complete", tagged, uids.len());
+            Ok::<_, gmail::SyncError>(tagged)
+        })
+        .await;
+
+        match result {
+            Ok(Ok(count)) => info!("Category sync complete ({} emails)", count),
+            failure => emit_sync_failure(&app, "Category sync", failure),
+        }
+
+        syncing.lock().await.remove(&email);
+    });
+
+    Ok(())
+}
+
+/// Bring cached read/flagged state in line with the server without refetching
+/// headers; see `gmail::sync_flag_changes`. Returns once started; `imap_sync_progress`
+/// reports `start`, then `complete` with the number of emails changed, or `error`.
+#[tauri::command]
+async fn gmail_sync_flags_background(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    email: String,
+) -> Result<(), String> {
+    let syncing = state.syncing.clone();
+    {
+        let mut guard = syncing.lock().await;
+        if guard.contains(&email) {
+            return Err("A sync is already running for this account".to_string());
+        }
+        guard.insert(email.clone());
+    }
+
+    let storage = state.storage.clone();
+    tokio::spawn(async move {
+        let _ = app.emit(
+            "imap_sync_progress",
+            SyncProgress {
+                stage: "start".to_string(),
+                processed: 0,
+                total: 0,
+                message: None,
+                eta_secs: None,
+                batch_size: None,
+            },
+        );
+        let account = email.clone();
+        let result = tokio::task::spawn_blocking(move || {
+            let modseq = storage.get_highest_modseq(&account)?;
+            let synced = gmail::sync_flag_changes(&account, modseq)?;
+            let changed = storage.apply_flag_updates(&account, &synced.updates)?;
+            if let Some(highest) = synced.highest_modseq {
+                storage.set_highest_modseq(&account, highest)?;
+            }
+            info!(
+                "Flag sync for {}: {} reported, {} changed",
+                account,
+                synced.updates.len(),
+                changed
+            );
+            Ok::<_, gmail::SyncError>(changed)
+        })
+        .await;
+
+        match result {
+            Ok(Ok(changed)) => {
+                let _ = app.emit(
+                    "imap_sync_progress",
+                    SyncProgress {
+                        stage: "complete".to_string(),
+                        processed: changed,
+                        total: changed,
+                        message: None,
+                        eta_secs: None,
+                        batch_size: None,
+                    },
+                );
+            }
+            failure => emit_sync_failure(&app, "Flag sync", failure),
+        }
+
+        syncing.lock().await.remove(&email);
+    });
+
+    Ok(())
+}
+
+/// Re-sync one account from UID 0. With `delete_existing` the cache is wiped and
+/// refetched; otherwise cached rows are kept and overwritten as the server reports
+/// them (re-verify). Returns once the sync is spawned; progress arrives as
+/// `imap_sync_progress` events.
+#[tauri::command]
+async fn gmail_resync_account(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    email: String,
+    delete_existing: bool,
+) -> Result<(), String> {
+    if state.syncing.lock().await.contains(&email) {
+        return Err("A sync is already running for this account".to_string());
+    }
+
+    let storage = state.storage.clone();
+    let account = email.clone();
+    tokio::task::spawn_blocking(move || {
+        if delete_existing {
+            storage.delete_account_emails(&account).map(|_| ())
+        } else {
+            storage.reset_sync_state(&account)
+        }
+    })
+    .await
+    .map_err(|e| format!("Task error: {}", e))??;
+
+    start_background_sync(app, &state, email, None, false, true, gmail::ImapTimeouts::default()).await
+}
+
+/// `from_scratch` starts at UID 0 even when emails are cached; otherwise an
+/// account without sync state resumes after the newest cached UID.
+async fn start_background_sync(
+    app: AppHandle,
+    state: &AppState,
+    email: String,
+    initial_since: Option<i64>,
+    minimal_headers: bool,
+    from_scratch: bool,
+    timeouts: gmail::ImapTimeouts,
+) -> Result<(), String> {
+    let storage = state.storage.clone();
+    let syncing = state.syncing.clone();
+    let sync_cancel = state.sync_cancel.clone();
+    let limiter = state.imap_limiter.clone();
+    let handle = app.clone();
+
+    {
+        let mut guard = syncing.lock().await;
+        if guard.contains(&email) {
+            info!("Sync already running for {}", email);
+            return Ok(());
+        }
+        guard.insert(email.clone());
+    }
+    let cancel = Arc::new(AtomicBool::new(false));
+    sync_cancel
+        .lock()
+        .map_err(|_| "Failed to lock sync state".to_string())?
+        .insert(email.clone(), cancel.clone());
+
+    tokio::spawn(async move {
+        info!("Background sync started for {}", email);
+        // Read once up front so the `start` event can report it; the blocking task
+        // below gets its own copy.
+        let settings_storage = storage.clone();
+        let batch_size = tokio::task::spawn_blocking(move || sync_batch_size(settings_storage.as_ref()))
+            .await
+            .unwrap_or(DEFAULT_SYNC_BATCH_SIZE);
+        let _ = handle.emit(
+            "imap_sync_progress",
+            SyncProgress {
+                stage: "start".to_string(),
+                processed: 0,
+                total: 0,
+                message: None,
+                eta_secs: None,
+                batch_size: Some(batch_size),
+            },
+        );
+
+        let (tx, mut rx) = mpsc::unbounded_channel::<(&'static str, usize, usize)>();
+        let progress_handle = handle.clone();
+        let progress_task = tokio::spawn(async move {
+            // The ETA is timed from when the search finished, not from the start.
+            let mut fetch_started: Option<std::time::Instant> = None;
+            while let Some((stage, processed, total)) = rx.recv().await {
+                if stage == "progress" && fetch_started.is_none() {
+                    fetch_started = Some(std::time::Instant::now());
+                }
+                debug!(
+                    "Sync progress: {}/{} ({:.0}%)",
+                    processed,
+                    total,
+                    if total > 0 {
+                        (processed as f64 / total as f64) * 100.0
+                    } else {
+                        0.0
+                    }
+                );
+                let eta_secs = fetch_started
+                    .and_then(|started| estimate_eta(started.elapsed(), processed, total));
+                let _ = progress_handle.emit(
+                    "imap_sync_progress",
+                    SyncProgress {
+                        stage: stage.to_string(),
+                        processed,
+                        total,
+                        message: None,
+                        eta_secs,
+                        batch_size: None,
+                    },
+                );
+            }
+        });
+
+        let storage_for_sync = storage.clone();
         let email_for_sync = email.clone();
+        let actions_handle = handle.clone();
+        let cancel_for_sync = cancel.clone();
         let result = tokio::task::spawn_blocking(move || {
-            let mut last_uid = storage_for_sync.get_last_uid(&email_for_sync)?;
-            if last_uid == 0 {
-                if let Ok(Some(max_uid)) = storage_for_sync.get_max_uid(&email_for_sync) {
-                    let _ = storage_for_sync.set_last_uid(&email_for_sync, max_uid);
+            let mut last_uid = storage_for_sync.get_last_uid(&email_for_sync, "INBOX")?;
+            if last_uid == 0 && !from_scratch {
+                if let Ok(Some(max_uid)) = storage_for_sync.get_max_uid(&email_for_sync, "INBOX") {
+                    let _ = storage_for_sync.set_last_uid(&email_for_sync, "INBOX", max_uid);
                     last_uid = max_uid;
                 }
             }
-            println!(
-                "[InboxCleanup] Sync starting from last UID {} (batch size: 1000)",
-                last_uid
+            let body_prefetch = gmail::BodyPrefetch {
+                min: numeric_setting(
+                    storage_for_sync.as_ref(),
+                    SETTING_BODY_PREFETCH_MIN,
+                    DEFAULT_BODY_PREFETCH_MIN,
+                ),
+                max: numeric_setting(
+                    storage_for_sync.as_ref(),
+                    SETTING_BODY_PREFETCH_MAX,
+                    numeric_setting(
+                        storage_for_sync.as_ref(),
+                        SETTING_BODY_PREFETCH_LIMIT,
+                        DEFAULT_BODY_PREFETCH_MAX,
+                    ),
+                ),
+                read_buffer: BODY_PREFETCH_READ_BUFFER,
+            };
+            let refresh_chunk = u32::try_from(batch_size).unwrap_or(u32::MAX);
+            info!(
+                "Sync starting from last UID {} (batch size: {})",
+                last_uid, batch_size
             );
-            gmail::fetch_emails_since(&email_for_sync, last_uid, 1000, 500, |chunk| {
+            // The UID search can take a while on big mailboxes; say so before the total is known.
+            let _ = tx.send(("searching", 0, 0));
+            let found_tx = tx.clone();
+            let on_found = move |total: usize| {
+                let _ = found_tx.send(("progress", 0, total));
+            };
+            let synced = gmail::fetch_emails_since(&email_for_sync, last_uid, initial_since, minimal_headers, batch_size, body_prefetch, 3, timeouts, on_found, |chunk| {
                 let _ = storage_for_sync.upsert_emails(&email_for_sync, "INBOX", &chunk.emails);
                 let _ = storage_for_sync.set_email_bodies(&email_for_sync, &chunk.bodies);
                 if let Some(max_uid) = chunk.emails.iter().map(|email| email.uid).max() {
-                    let _ = storage_for_sync.set_last_uid(&email_for_sync, max_uid);
+                    let _ = storage_for_sync.set_last_uid(&email_for_sync, "INBOX", max_uid);
                 }
-                let _ = tx.send((chunk.processed, chunk.total));
-            })
+                // Match the new emails right away so filter badges update during the sync.
+                // Incremental by last matched email id, so only this chunk is scanned.
+                match storage_for_sync.refresh_filtered_emails(&email_for_sync, refresh_chunk, false) {
+                    Ok(0) => {}
+                    Ok(matched) => {
+                        let _ = actions_handle.emit(
+                            "filter_refresh_progress",
+                            FilterSyncProgress {
+                                stage: "progress".to_string(),
+                                processed: matched,
+                                total: chunk.emails.len(),
+                                message: None,
+                            },
+                        );
+                    }
+                    Err(err) => warn!("Filter refresh during sync failed: {}", err),
+                }
+                let uids: Vec<u32> = chunk.emails.iter().map(|email| email.uid).collect();
+                match apply_filter_actions(storage_for_sync.as_ref(), &limiter, &email_for_sync, &uids) {
+                    Ok(actions) if !actions.is_empty() => {
+                        let _ = actions_handle.emit("filter_actions_applied", actions);
+                    }
+                    Ok(_) => {}
+                    Err(err) => warn!("Filter actions failed: {}", err),
+                }
+                let _ = tx.send(("progress", chunk.processed, chunk.total));
+                // Checked after the chunk is stored, so `last_uid` already covers it.
+                if cancel_for_sync.load(Ordering::Relaxed) {
+                    ControlFlow::Break(())
+                } else {
+                    ControlFlow::Continue(())
+                }
+            })?;
+            if let Err(err) = storage_for_sync.assign_threads(&email_for_sync) {
+                warn!("Failed to group synced emails into threads: {}", err);
+            }
+            if !cancel_for_sync.load(Ordering::Relaxed) {
+                backfill_email_sizes(storage_for_sync.as_ref(), &email_for_sync);
+                if reconcile_due(storage_for_sync.as_ref(), &email_for_sync) {
+                    match reconcile_account(storage_for_sync.as_ref(), &email_for_sync) {
+                        Ok(removed) => info!("Removed {} emails deleted on the server", removed),
+                        Err(err) => warn!("Reconcile after sync failed: {}", err.message),
+                    }
+                }
+            }
+            Ok::<_, gmail::SyncError>(synced)
         })
         .await;
 
         drop(progress_task);
+        if let Ok(mut flags) = sync_cancel.lock() {
+            flags.remove(&email);
+        }
+        let cancelled = cancel.load(Ordering::Relaxed);
 
         match result {
             Ok(Ok((count, max_uid))) => {
                 if let Some(max_uid) = max_uid {
-                    let _ = storage.set_last_uid(&email, max_uid);
-                } else if let Ok(Some(max_uid)) = storage.get_max_uid(&email) {
-                    let _ = storage.set_last_uid(&email, max_uid);
+                    let _ = storage.set_last_uid(&email, "INBOX", max_uid);
+                } else if let Ok(Some(max_uid)) = storage.get_max_uid(&email, "INBOX") {
+                    let _ = storage.set_last_uid(&email, "INBOX", max_uid);
+                }
+                if cancelled {
+                    info!("Background sync cancelled ({} emails)", count);
+                } else {
+                    info!("Background sync complete ({} emails)", count);
+                    if let Err(err) = storage.touch_last_synced(&email, chrono::Utc::now().timestamp()) {
+                        warn!("Failed to record sync time: {}", err);
+                    }
                 }
-                println!("[InboxCleanup] Background sync complete ({} emails)", count);
                 let _ = handle.emit(
                     "imap_sync_progress",
                     SyncProgress {
-                        stage: "complete".to_string(),
+                        stage: if cancelled { "cancelled" } else { "complete" }.to_string(),
                         processed: count,
                         total: count,
                         message: None,
+                        eta_secs: None,
+                        batch_size: None,
                     },
                 );
             }
-            Ok(Err(err)) => {
-                println!("[InboxCleanup] Background sync failed: {}", err);
-                let _ = handle.emit(
-                    "imap_sync_progress",
+            failure => emit_sync_failure(&handle, "Background sync", failure),
+        }
+
+        let mut guard = syncing.lock().await;
+        guard.remove(&email);
+    });
+
+    Ok(())
+}
+
+/// Ask a running background sync to stop after its current chunk. Returns false when
+/// no sync is running for `email`.
+#[tauri::command]
+fn gmail_cancel_sync(state: State<AppState>, email: String) -> Result<bool, String> {
+    let flags = state
+        .sync_cancel
+        .lock()
+        .map_err(|_| "Failed to lock sync state".to_string())?;
+    Ok(flags
+        .get(&email)
+        .map(|flag| flag.store(true, Ordering::Relaxed))
+        .is_some())
+}
+
+/// Accounts with a background sync in flight, so a reloaded window can restore its
+/// syncing state.
+#[tauri::command]
+async fn list_syncing_accounts(state: State<'_, AppState>) -> Result<Vec<String>, String> {
+    let mut accounts: Vec<String> = state.syncing.lock().await.iter().cloned().collect();
+    accounts.sort();
+    Ok(accounts)
+}
+
+/// Fetch bodies for cached emails that don't have one yet (newest first, up to
+/// `limit`), emitting `body_backfill_progress` events. Skipped while a sync runs.
+#[tauri::command]
+async fn gmail_backfill_bodies_background(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    email: String,
+    limit: Option<u32>,
+    batch_size: Option<usize>,
+) -> Result<(), String> {
+    let storage = state.storage.clone();
+    let syncing = state.syncing.clone();
+    let handle = app.clone();
+
+    {
+        let mut guard = syncing.lock().await;
+        if guard.contains(&email) {
+            info!("Sync already running for {}", email);
+            return Ok(());
+        }
+        guard.insert(email.clone());
+    }
+
+    tokio::spawn(async move {
+        let email_for_task = email.clone();
+        let progress_handle = handle.clone();
+        let result = tokio::task::spawn_blocking(move || {
+            let uids = storage.uids_without_body(&email_for_task, limit.unwrap_or(5000))?;
+            let _ = progress_handle.emit(
+                "body_backfill_progress",
+                SyncProgress {
+                    stage: "start".to_string(),
+                    processed: 0,
+                    total: uids.len(),
+                    message: None,
+                    eta_secs: None,
+                    batch_size: None,
+                },
+            );
+            gmail::backfill_bodies(&email_for_task, &uids, batch_size.unwrap_or(50), |chunk| {
+                let _ = storage.set_email_bodies(&email_for_task, &chunk.bodies);
+                let _ = progress_handle.emit(
+                    "body_backfill_progress",
                     SyncProgress {
-                        stage: "error".to_string(),
-                        processed: 0,
-                        total: 0,
-                        message: Some(err),
+                        stage: "progress".to_string(),
+                        processed: chunk.processed,
+                        total: chunk.total,
+                        message: None,
+                        eta_secs: None,
+                        batch_size: None,
                     },
                 );
-            }
+            })
+        })
+        .await
+        .map_err(|e| format!("Task error: {}", e))
+        .and_then(|result| result);
+
+        let progress = match result {
+            Ok(count) => SyncProgress {
+                stage: "complete".to_string(),
+                processed: count,
+                total: count,
+                message: None,
+                eta_secs: None,
+                batch_size: None,
+            },
             Err(err) => {
-                println!("[InboxCleanup] Background sync task error: {}", err);
-                let _ = handle.emit(
+                error!("Body backfill failed: {}", err);
+                SyncProgress {
+                    stage: "error".to_string(),
+                    processed: 0,
+                    total: 0,
+                    message: Some(err),
+                    eta_secs: None,
+                    batch_size: None,
+                }
+            }
+        };
+        let _ = handle.emit("body_backfill_progress", progress);
+
+        syncing.lock().await.remove(&email);
+    });
+
+    Ok(())
+}
+
+/// Emails synced per run that get their missing size filled in.
+const SIZE_BACKFILL_LIMIT: u32 = 2000;
+
+/// Hours between the deletion checks that run at the end of a sync; 0 turns them off.
+const SETTING_RECONCILE_INTERVAL_HOURS: &str = "reconcile_interval_hours";
+const DEFAULT_RECONCILE_INTERVAL_HOURS: usize = 24;
+/// Unix time of an account's last deletion check (`reconciled_at:<account>`).
+const SETTING_RECONCILED_AT_PREFIX: &str = "reconciled_at:";
+
+fn reconcile_due(storage: &dyn storage::Storage, account: &str) -> bool {
+    let hours = numeric_setting(storage, SETTING_RECONCILE_INTERVAL_HOURS, DEFAULT_RECONCILE_INTERVAL_HOURS);
+    if hours == 0 {
+        return false;
+    }
+    let last = storage
+        .get_setting(&format!("{}{}", SETTING_RECONCILED_AT_PREFIX, account))
+        .ok()
+        .flatten()
+        .and_then(|value| value.parse::<i64>().ok())
+        .unwrap_or(0);
+    chrono::Utc::now().timestamp() - last >= hours as i64 * 3600
+}
+
+/// Drop cached INBOX emails that no longer exist on the server. Comparing every UID is
+/// expensive on big mailboxes, so syncs only do it every `reconcile_interval_hours`.
+fn reconcile_account(storage: &dyn storage::Storage, account: &str) -> Result<usize, gmail::SyncError> {
+    let cached = storage.cached_uids(account, "INBOX")?;
+    let gone = gmail::reconcile_deletions(account, "INBOX", &cached)?;
+    let mut removed = 0;
+    for chunk in gone.chunks(BULK_CHUNK_SIZE) {
+        removed += storage.remove_emails(account, chunk)?;
+    }
+    storage.set_setting(
+        &format!("{}{}", SETTING_RECONCILED_AT_PREFIX, account),
+        &chrono::Utc::now().timestamp().to_string(),
+    )?;
+    Ok(removed)
+}
+
+/// Check the whole cache for emails deleted elsewhere now, instead of waiting for the
+/// next periodic check. Emits `imap_sync_progress`; `processed` is the number removed.
+#[tauri::command]
+async fn gmail_reconcile_background(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    email: String,
+) -> Result<(), String> {
+    let syncing = state.syncing.clone();
+    {
+        let mut guard = syncing.lock().await;
+        if guard.contains(&email) {
+            return Err("A sync is already running for this account".to_string());
+        }
+        guard.insert(email.clone());
+    }
+
+    let storage = state.storage.clone();
+    tokio::spawn(async move {
+        let _ = app.emit(
+            "imap_sync_progress",
+            SyncProgress {
+                stage: "start".to_string(),
+                processed: 0,
+                total: 0,
+                message: None,
+                eta_secs: None,
+                batch_size: None,
+            },
+        );
+        let account = email.clone();
+        let result =
+            tokio::task::spawn_blocking(move || reconcile_account(storage.as_ref(), &account)).await;
+
+        match result {
+            Ok(Ok(removed)) => {
+                let _ = app.emit(
                     "imap_sync_progress",
                     SyncProgress {
-                        stage: "error".to_string(),
-                        processed: 0,
-                        total: 0,
-                        message: Some(format!("Task error: {}", err)),
+                        stage: "complete".to_string(),
+                        processed: removed,
+                        total: removed,
+                        message: None,
+                        eta_secs: None,
+                        batch_size: None,
                     },
                 );
             }
+            failure => emit_sync_failure(&app, "Reconcile", failure),
         }
 
-        let mut guard = syncing.lock().await;
-        guard.remove(&email);
+        syncing.lock().await.remove(&email);
     });
 
     Ok(())
 }
 
+/// Fill in sizes for emails cached before sizes were recorded, a bounded batch per
+/// sync. Failures are only logged; the next sync tries again.
+fn backfill_email_sizes(storage: &dyn storage::Storage, account: &str) {
+    let result = storage
+        .uids_without_size(account, SIZE_BACKFILL_LIMIT)
+        .and_then(|uids| gmail::fetch_sizes(account, &uids))
+        .and_then(|sizes| storage.set_email_sizes(account, &sizes));
+    if let Err(err) = result {
+        warn!("Size backfill failed: {}", err);
+    }
+}
+
+/// Run filter actions for freshly synced emails. The caller refreshes filter
+/// matches for the chunk first, as the sync loop does right before calling this.
+/// Dry-run actions are returned without touching the mailbox.
+fn apply_filter_actions(
+    storage: &dyn storage::Storage,
+    limiter: &rate_limit::RateLimiter,
+    account: &str,
+    uids: &[u32],
+) -> Result<Vec<storage::PlannedFilterAction>, String> {
+    let has_actions = storage
+        .get_filters()?
+        .iter()
+        .any(|filter| filter.enabled && filter.action != filters::FilterAction::None);
+    if !has_actions || uids.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let planned = storage.filter_actions_for_uids(account, uids)?;
+
+    let live_uids = |action: filters::FilterAction| -> Vec<u32> {
+        planned
+            .iter()
+            .filter(|planned| planned.action == action && !planned.dry_run)
+            .map(|planned| planned.uid)
+            .collect()
+    };
+    let read = live_uids(filters::FilterAction::MarkRead);
+    if !read.is_empty() {
+        mark_read(storage, limiter, account, &read)?;
+    }
+    let archive = live_uids(filters::FilterAction::Archive);
+    if !archive.is_empty() {
+        limiter.run(account, || gmail::archive_emails(account, &archive))?;
+        storage.remove_emails(account, &archive)?;
+    }
+
+    Ok(planned)
+}
+
 #[derive(serde::Serialize, Clone)]
 struct FilterSyncProgress {
     stage: String,
@@ -378,6 +1907,9 @@ struct FilterSyncProgress {
     message: Option<String>,
 }
 
+/// Re-run filter matching for `email` on demand, 500 emails per chunk until none are
+/// left, emitting `filter_sync_progress`. `force_full` clears existing matches and
+/// rebuilds them. At most one refresh runs per account.
 #[tauri::command]
 async fn gmail_refresh_filtered_emails(
     app: AppHandle,
@@ -399,10 +1931,12 @@ async fn gmail_refresh_filtered_emails(
 
     tokio::spawn(async move {
         let chunk_size = 500;
-        let total = storage.count_emails(&email, false).unwrap_or(0) as usize;
+        let total = storage
+            .count_emails(&email, false, &storage::EmailQuery::default())
+            .unwrap_or(0) as usize;
         let mut processed_total = 0usize;
-        println!(
-            "[InboxCleanup] Filter refresh started for {} (total emails: {})",
+        info!(
+            "Filter refresh started for {} (total emails: {})",
             email, total
         );
         let _ = handle.emit(
@@ -415,7 +1949,7 @@ async fn gmail_refresh_filtered_emails(
             },
         );
         loop {
-            println!("[InboxCleanup] Filter refresh requesting next chunk...");
+            debug!("Filter refresh requesting next chunk...");
             let storage_for_refresh = storage.clone();
             let email_for_refresh = email.clone();
             let force_full = force_full && processed_total == 0;
@@ -427,7 +1961,7 @@ async fn gmail_refresh_filtered_emails(
                 )
             })
             .await;
-            println!("[InboxCleanup] Filter refresh chunk returned.");
+            debug!("Filter refresh chunk returned.");
 
             match chunk {
                 Ok(Ok(processed)) => {
@@ -444,12 +1978,12 @@ async fn gmail_refresh_filtered_emails(
                         break;
                     }
                     processed_total += processed;
-                    println!(
-                        "[InboxCleanup] Filter refresh processed chunk: {} emails (total {}/{})",
+                    debug!(
+                        "Filter refresh processed chunk: {} emails (total {}/{})",
                         processed, processed_total, total
                     );
-                    println!(
-                        "[InboxCleanup] Filter refresh progress: {}/{}",
+                    debug!(
+                        "Filter refresh progress: {}/{}",
                         processed_total, total
                     );
                     let _ = handle.emit(
@@ -463,7 +1997,7 @@ async fn gmail_refresh_filtered_emails(
                     );
                 }
                 Ok(Err(err)) => {
-                    println!("[InboxCleanup] Filter refresh failed: {}", err);
+                    error!("Filter refresh failed: {}", err);
                     let _ = handle.emit(
                         "filter_sync_progress",
                         FilterSyncProgress {
@@ -476,7 +2010,7 @@ async fn gmail_refresh_filtered_emails(
                     break;
                 }
                 Err(err) => {
-                    println!("[InboxCleanup] Filter refresh task error: {}", err);
+                    error!("Filter refresh task error: {}", err);
                     let _ = handle.emit(
                         "filter_sync_progress",
                         FilterSyncProgress {
@@ -495,28 +2029,156 @@ async fn gmail_refresh_filtered_emails(
         guard.remove(&email);
     });
 
-    Ok(())
+    Ok(())
+}
+
+/// List cached emails from SQLite
+#[tauri::command]
+fn gmail_list_cached_unread(
+    state: State<AppState>,
+    email: String,
+    sort: Option<storage::SortBy>,
+    limit: u32,
+    offset: u32,
+) -> Result<Vec<storage::StoredEmail>, String> {
+    state.storage.list_emails(
+        &email,
+        true,
+        &storage::EmailQuery::default(),
+        sort.unwrap_or_default(),
+        limit,
+        offset,
+    )
+}
+
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+fn gmail_list_cached_all(
+    state: State<AppState>,
+    email: String,
+    sort: Option<storage::SortBy>,
+    after_epoch: Option<i64>,
+    before_epoch: Option<i64>,
+    include_snoozed: Option<bool>,
+    answered_only: Option<bool>,
+    category: Option<String>,
+    limit: u32,
+    offset: u32,
+) -> Result<Vec<storage::StoredEmail>, String> {
+    let query = storage::EmailQuery {
+        after_epoch,
+        before_epoch,
+        include_snoozed: include_snoozed.unwrap_or(false),
+        answered_only,
+        gmail_category: category,
+    };
+    state.storage.list_emails(
+        &email,
+        false,
+        &query,
+        sort.unwrap_or_default(),
+        limit,
+        offset,
+    )
+}
+
+/// Hide emails from the inbox until `until_epoch`. Local only; nothing changes on the server.
+#[tauri::command]
+fn gmail_snooze_emails(
+    state: State<AppState>,
+    email: String,
+    uids: Vec<u32>,
+    until_epoch: i64,
+) -> Result<usize, String> {
+    if until_epoch <= chrono::Utc::now().timestamp() {
+        return Err("Snooze time must be in the future".to_string());
+    }
+    state.storage.snooze_emails(&email, &uids, until_epoch)
+}
+
+#[tauri::command]
+fn gmail_unsnooze_emails(state: State<AppState>, email: String, uids: Vec<u32>) -> Result<usize, String> {
+    state.storage.unsnooze_emails(&email, &uids)
+}
+
+#[tauri::command]
+fn gmail_list_snoozed(
+    state: State<AppState>,
+    email: String,
+    limit: u32,
+    offset: u32,
+) -> Result<Vec<storage::SnoozedEmail>, String> {
+    state.storage.list_snoozed(&email, limit, offset)
 }
 
-/// List cached emails from SQLite
+/// Emails from a sender domain and its subdomains (e.g. `mailchimp.com` also matches
+/// `us5.mailchimp.com`), newest first.
 #[tauri::command]
-fn gmail_list_cached_unread(
+fn gmail_list_by_domain(
     state: State<AppState>,
     email: String,
+    domain: String,
+    unread_only: bool,
     limit: u32,
     offset: u32,
 ) -> Result<Vec<storage::StoredEmail>, String> {
-    state.storage.list_emails(&email, true, limit, offset)
+    state
+        .storage
+        .list_by_sender_domain(&email, &domain, unread_only, limit, offset)
 }
 
+/// Unified inbox across accounts, newest first. Each row carries its `account`.
 #[tauri::command]
-fn gmail_list_cached_all(
+fn gmail_list_cached_multi(
     state: State<AppState>,
-    email: String,
+    accounts: Vec<String>,
+    unread_only: bool,
     limit: u32,
     offset: u32,
 ) -> Result<Vec<storage::StoredEmail>, String> {
-    state.storage.list_emails(&email, false, limit, offset)
+    state.storage.list_emails_multi(&accounts, unread_only, limit, offset)
+}
+
+#[tauri::command]
+fn gmail_count_cached_multi(
+    state: State<AppState>,
+    accounts: Vec<String>,
+    unread_only: bool,
+) -> Result<u64, String> {
+    state.storage.count_emails_multi(&accounts, unread_only)
+}
+
+/// A page of cached emails with the total, so the UI needs one round trip.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+fn gmail_list_cached_page(
+    state: State<AppState>,
+    email: String,
+    unread_only: bool,
+    sort: Option<storage::SortBy>,
+    after_epoch: Option<i64>,
+    before_epoch: Option<i64>,
+    include_snoozed: Option<bool>,
+    answered_only: Option<bool>,
+    category: Option<String>,
+    limit: u32,
+    offset: u32,
+) -> Result<storage::EmailPage, String> {
+    let query = storage::EmailQuery {
+        after_epoch,
+        before_epoch,
+        include_snoozed: include_snoozed.unwrap_or(false),
+        answered_only,
+        gmail_category: category,
+    };
+    state.storage.list_emails_page(
+        &email,
+        unread_only,
+        &query,
+        sort.unwrap_or_default(),
+        limit,
+        offset,
+    )
 }
 
 #[derive(serde::Serialize)]
@@ -525,13 +2187,135 @@ struct EmailCounts {
     unread: u64,
 }
 
+/// Count cached emails, optionally restricted to a date range
+#[tauri::command]
+fn gmail_count_cached_all(
+    state: State<AppState>,
+    email: String,
+    after_epoch: Option<i64>,
+    before_epoch: Option<i64>,
+) -> Result<u64, String> {
+    let query = storage::EmailQuery {
+        after_epoch,
+        before_epoch,
+        ..Default::default()
+    };
+    state.storage.count_emails(&email, false, &query)
+}
+
 #[tauri::command]
 fn gmail_cached_counts(state: State<AppState>, email: String) -> Result<EmailCounts, String> {
-    let total = state.storage.count_emails(&email, false)?;
-    let unread = state.storage.count_emails(&email, true)?;
+    let query = storage::EmailQuery::default();
+    let total = state.storage.count_emails(&email, false, &query)?;
+    let unread = state.storage.count_emails(&email, true, &query)?;
     Ok(EmailCounts { total, unread })
 }
 
+/// Just the unread number, for a badge that polls often; cheaper than `gmail_cached_counts`.
+#[tauri::command]
+fn gmail_unread_count(state: State<AppState>, email: String) -> Result<u64, String> {
+    state.storage.unread_count(&email)
+}
+
+#[derive(serde::Serialize)]
+struct MailboxCount {
+    mailbox: String,
+    total: u64,
+    unread: u64,
+}
+
+/// Per-mailbox totals for the folder sidebar, most unread first
+#[tauri::command]
+fn gmail_mailbox_counts(state: State<AppState>, email: String) -> Result<Vec<MailboxCount>, String> {
+    let counts = state.storage.count_emails_by_mailbox(&email)?;
+    Ok(counts
+        .into_iter()
+        .map(|(mailbox, total, unread)| MailboxCount { mailbox, total, unread })
+        .collect())
+}
+
+/// The last `lines` log lines (default 200), for attaching to bug reports.
+#[tauri::command]
+fn get_recent_logs(lines: Option<usize>) -> Vec<String> {
+    logging::recent_lines(lines.unwrap_or(200))
+}
+
+/// Bytes used per sender, to estimate what clearing their mail would reclaim
+#[tauri::command]
+fn gmail_size_stats(state: State<AppState>, email: String) -> Result<Vec<storage::SenderSize>, String> {
+    state.storage.total_size_by_sender(&email)
+}
+
+/// Inbox-zero progress: how much of the account has been read, archived or trashed
+#[tauri::command]
+fn gmail_cleanup_stats(state: State<AppState>, email: String) -> Result<storage::CleanupStats, String> {
+    state.storage.cleanup_stats(&email)
+}
+
+/// Senders with the most cached emails, grouped by bare address
+#[tauri::command]
+fn gmail_sender_stats(
+    state: State<AppState>,
+    email: String,
+    unread_only: bool,
+    limit: u32,
+) -> Result<Vec<storage::SenderStat>, String> {
+    state.storage.sender_stats(&email, unread_only, limit)
+}
+
+#[derive(serde::Serialize)]
+struct CompactResult {
+    reclaimed_bytes: u64,
+    message: String,
+}
+
+/// VACUUM the SQLite database to reclaim space left by deleted rows
+#[tauri::command]
+async fn compact_database(state: State<'_, AppState>) -> Result<CompactResult, String> {
+    let sync_running = !state.syncing.lock().await.is_empty()
+        || !state.filter_syncing.lock().await.is_empty();
+    let storage = state.storage.clone();
+    let reclaimed_bytes = tokio::task::spawn_blocking(move || storage.compact())
+        .await
+        .map_err(|e| format!("Task error: {}", e))??;
+
+    let mut message = format!("Reclaimed {} bytes.", reclaimed_bytes);
+    if sync_running {
+        message.push_str(" A sync was in progress and may have been blocked while compacting.");
+    }
+    Ok(CompactResult {
+        reclaimed_bytes,
+        message,
+    })
+}
+
+/// Re-sanitize cached bodies whose HTML was cleaned by an older sanitizer, from the
+/// stored raw HTML. Returns how many bodies were rewritten.
+#[tauri::command]
+async fn reprocess_bodies(state: State<'_, AppState>, email: String) -> Result<usize, String> {
+    let storage = state.storage.clone();
+    tokio::task::spawn_blocking(move || {
+        let uids = storage.stale_body_uids(&email, sanitize::SANITIZER_VERSION)?;
+        let mut updated = 0;
+        for chunk in uids.chunks(BULK_CHUNK_SIZE) {
+            updated += storage.resanitize_bodies(&email, chunk)?;
+        }
+        info!("Re-sanitized {} of {} stale bodies for {}", updated, uids.len(), email);
+        Ok(updated)
+    })
+    .await
+    .map_err(|e| format!("Task error: {}", e))?
+}
+
+/// Set when the database was found corrupt at startup and replaced with an empty
+/// one: the path the damaged file was moved to. The UI asks for this once it has
+/// loaded (an event emitted during setup would arrive before anything listens) and
+/// tells the user to re-sync.
+#[tauri::command]
+fn get_db_recovery(state: State<AppState>) -> Option<String> {
+    state.db_recovered_from.clone()
+}
+
 #[tauri::command]
 fn get_db_directory() -> Result<String, String> {
     storage::get_db_dir()
@@ -546,31 +2330,331 @@ fn get_db_file_path() -> Result<String, String> {
         .map_err(|e| e)
 }
 
+/// Search the Gmail server (not just the cache), cache the newest `limit` matches and return them.
+#[tauri::command]
+async fn gmail_server_search(
+    state: State<'_, AppState>,
+    email: String,
+    criteria: gmail::SearchCriteria,
+    limit: Option<usize>,
+) -> Result<Vec<gmail::GmailEmail>, String> {
+    let storage = state.storage.clone();
+    tokio::task::spawn_blocking(move || {
+        let uids = gmail::search(&email, &criteria)?;
+        let keep = limit.unwrap_or(500).min(uids.len());
+        let newest = &uids[uids.len() - keep..];
+        let emails = gmail::fetch_emails_by_uid(&email, newest)?;
+        storage.upsert_emails(&email, "INBOX", &emails)?;
+        Ok(emails)
+    })
+    .await
+    .map_err(|e| format!("Task error: {}", e))?
+}
+
+/// Fetch headers for specific UIDs from the server (e.g. search hits that aren't
+/// cached yet) and add them to the cache.
+#[tauri::command]
+async fn gmail_fetch_headers(
+    state: State<'_, AppState>,
+    email: String,
+    uids: Vec<u32>,
+) -> Result<Vec<gmail::GmailEmail>, String> {
+    let storage = state.storage.clone();
+    tokio::task::spawn_blocking(move || {
+        let emails = gmail::fetch_emails_by_uid(&email, &uids)?;
+        storage.upsert_emails(&email, "INBOX", &emails)?;
+        Ok(emails)
+    })
+    .await
+    .map_err(|e| format!("Task error: {}", e))?
+}
+
+/// List cached emails grouped into conversations, newest first
+#[tauri::command]
+async fn gmail_list_threads(
+    state: State<'_, AppState>,
+    email: String,
+    limit: u32,
+    offset: u32,
+) -> Result<Vec<storage::ThreadSummary>, String> {
+    let storage = state.storage.clone();
+    tokio::task::spawn_blocking(move || {
+        storage.assign_threads(&email)?;
+        storage.list_threads(&email, limit, offset)
+    })
+    .await
+    .map_err(|e| format!("Task error: {}", e))?
+}
+
+/// Export cached emails (all, or those matching `filter_ids`) to `path` as CSV or JSON.
+/// Returns the number of emails written.
+#[tauri::command]
+async fn gmail_export_emails(
+    state: State<'_, AppState>,
+    email: String,
+    filter_ids: Option<Vec<i64>>,
+    format: storage::ExportFormat,
+    path: String,
+) -> Result<u64, String> {
+    let storage = state.storage.clone();
+    tokio::task::spawn_blocking(move || {
+        let file = std::fs::File::create(&path)
+            .map_err(|e| format!("Failed to create {}: {}", path, e))?;
+        let mut out = std::io::BufWriter::new(file);
+        storage.export_emails_to(&email, &filter_ids.unwrap_or_default(), format, &mut out)
+    })
+    .await
+    .map_err(|e| format!("Task error: {}", e))?
+}
+
 /// Fetch Gmail email body by UID
 #[tauri::command]
 async fn gmail_fetch_body(
     state: State<'_, AppState>,
     email: String,
     uid: u32,
+    load_remote_images: Option<bool>,
 ) -> Result<gmail::EmailBody, String> {
     let storage = state.storage.clone();
     tokio::task::spawn_blocking(move || {
-        if let Some(body) = storage.get_email_body(&email, uid)? {
-            return Ok(body);
+        let mut body = cached_or_fetched_body(storage.as_ref(), &email, uid, false)?;
+        if load_remote_images.unwrap_or(false) {
+            body.html = body
+                .html_raw
+                .as_deref()
+                .map(|raw| sanitize::sanitize_html(raw, true));
         }
-        let body = gmail::fetch_email_body(&email, uid)?;
-        storage.set_email_bodies(
-            &email,
-            &[gmail::GmailEmailBody { uid, body: body.clone() }],
-        )?;
         Ok(body)
     })
     .await
     .map_err(|e| format!("Task error: {}", e))?
 }
 
+/// Bodies for several emails (e.g. a thread), in the order of `uids`. Cached bodies
+/// are served from storage; the rest are fetched in one round trip and cached. UIDs
+/// that no longer exist on the server are left out.
+#[tauri::command]
+async fn gmail_fetch_bodies(
+    state: State<'_, AppState>,
+    email: String,
+    uids: Vec<u32>,
+) -> Result<Vec<gmail::GmailEmailBody>, String> {
+    let storage = state.storage.clone();
+    tokio::task::spawn_blocking(move || {
+        let mut found: HashMap<u32, gmail::EmailBody> = HashMap::new();
+        let mut missing = Vec::new();
+        for &uid in &uids {
+            if found.contains_key(&uid) || missing.contains(&uid) {
+                continue;
+            }
+            match storage.get_email_body(&email, uid)? {
+                Some(body) => {
+                    found.insert(uid, body);
+                }
+                None => missing.push(uid),
+            }
+        }
+
+        if !missing.is_empty() {
+            let fetched = gmail::fetch_email_bodies(&email, &missing)?;
+            storage.set_email_bodies(&email, &fetched)?;
+            for body in fetched {
+                found.insert(body.uid, body.body);
+            }
+        }
+
+        Ok(uids
+            .iter()
+            .filter_map(|uid| {
+                found.get(uid).map(|body| gmail::GmailEmailBody {
+                    uid: *uid,
+                    body: body.clone(),
+                })
+            })
+            .collect())
+    })
+    .await
+    .map_err(|e| format!("Task error: {}", e))?
+}
+
+/// The cached body for `uid`, downloading and caching it first if it never was.
+/// With `need_headers`, bodies cached before headers were stored are refetched too.
+fn cached_or_fetched_body(
+    storage: &dyn storage::Storage,
+    email: &str,
+    uid: u32,
+    need_headers: bool,
+) -> Result<gmail::EmailBody, String> {
+    if let Some(body) = storage.get_email_body(email, uid)? {
+        if !need_headers || !body.headers.is_empty() {
+            return Ok(body);
+        }
+    }
+    let body = gmail::fetch_email_body(email, uid)?;
+    storage.set_email_bodies(email, &[gmail::GmailEmailBody { uid, body: body.clone() }])?;
+    Ok(body)
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+struct EmailDetails {
+    body: gmail::EmailBody,
+    headers: Vec<(String, String)>,
+    to: Option<String>,
+    cc: Option<String>,
+    reply_to: Option<String>,
+}
+
+/// Body plus the raw header list, for debugging and power users.
+#[tauri::command]
+async fn gmail_fetch_full(
+    state: State<'_, AppState>,
+    email: String,
+    uid: u32,
+) -> Result<EmailDetails, String> {
+    let storage = state.storage.clone();
+    tokio::task::spawn_blocking(move || {
+        let mut body = cached_or_fetched_body(storage.as_ref(), &email, uid, true)?;
+        let headers = std::mem::take(&mut body.headers);
+        let header = |name: &str| {
+            headers
+                .iter()
+                .find(|(key, _)| key.eq_ignore_ascii_case(name))
+                .map(|(_, value)| value.clone())
+        };
+        Ok(EmailDetails {
+            to: header("To"),
+            cc: header("Cc"),
+            reply_to: header("Reply-To"),
+            body,
+            headers,
+        })
+    })
+    .await
+    .map_err(|e| format!("Task error: {}", e))?
+}
+
+/// Raw RFC 822 source of an email, capped at `gmail::RAW_SOURCE_LIMIT` bytes.
+#[tauri::command]
+async fn gmail_fetch_raw(email: String, uid: u32) -> Result<String, String> {
+    tokio::task::spawn_blocking(move || gmail::fetch_raw(&email, uid))
+        .await
+        .map_err(|e| format!("Task error: {}", e))?
+}
+
+/// List-Unsubscribe details for a cached email. Only populated once its body was fetched.
+#[tauri::command]
+async fn gmail_email_unsubscribe_info(
+    state: State<'_, AppState>,
+    email: String,
+    uid: u32,
+) -> Result<Option<storage::UnsubscribeInfo>, String> {
+    let storage = state.storage.clone();
+    tokio::task::spawn_blocking(move || storage.get_unsubscribe_info(&email, uid))
+        .await
+        .map_err(|e| format!("Task error: {}", e))?
+}
+
+/// Unsubscribe from the sender of a cached email via its List-Unsubscribe header.
+/// Fetches the body first if it was never downloaded, since that is when the header is captured.
+#[tauri::command]
+async fn gmail_unsubscribe(
+    state: State<'_, AppState>,
+    email: String,
+    uid: u32,
+) -> Result<gmail::UnsubscribeResult, String> {
+    let storage = state.storage.clone();
+    tokio::task::spawn_blocking(move || {
+        let info = match cached_unsubscribe_info(storage.as_ref(), &email, uid)? {
+            Some(info) => info,
+            None => {
+                let body = gmail::fetch_email_body(&email, uid)?;
+                let fetched = gmail::GmailEmailBody { uid, body: body.clone() };
+                storage.set_email_bodies(&email, &[fetched])?;
+                storage::UnsubscribeInfo {
+                    unsubscribe_url: body.unsubscribe_url,
+                    unsubscribe_mailto: body.unsubscribe_mailto,
+                    unsubscribe_oneclick: body.unsubscribe_oneclick,
+                }
+            }
+        };
+        gmail::unsubscribe(&email, uid, &info)
+    })
+    .await
+    .map_err(|e| format!("Task error: {}", e))?
+}
+
+/// Unsubscribe targets from the cached body, or `None` when the headers have to be
+/// fetched: the body isn't cached yet, or it was cached before the unsubscribe
+/// columns existed and both targets read back as NULL.
+fn cached_unsubscribe_info(
+    storage: &dyn storage::Storage,
+    email: &str,
+    uid: u32,
+) -> Result<Option<storage::UnsubscribeInfo>, String> {
+    if storage.get_email_body(email, uid)?.is_none() {
+        return Ok(None);
+    }
+    Ok(storage
+        .get_unsubscribe_info(email, uid)?
+        .filter(|info| info.unsubscribe_url.is_some() || info.unsubscribe_mailto.is_some()))
+}
+
+/// Attachment metadata for a cached email (empty until its body has been fetched)
+#[tauri::command]
+async fn gmail_attachments(
+    state: State<'_, AppState>,
+    email: String,
+    uid: u32,
+) -> Result<Vec<gmail::AttachmentMeta>, String> {
+    let storage = state.storage.clone();
+    tokio::task::spawn_blocking(move || storage.attachments_for(&email, uid))
+        .await
+        .map_err(|e| format!("Task error: {}", e))?
+}
+
+/// Upper bound on cleanup at exit, so a stuck server can't keep the app from quitting.
+const SHUTDOWN_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Best-effort cleanup when the app exits: ask running syncs to stop after their
+/// current chunk and give them a moment to store it, log out kept-alive IMAP sessions,
+/// and checkpoint the WAL into the database file. Bounded by `SHUTDOWN_TIMEOUT`.
+fn shutdown(state: &AppState) {
+    let deadline = std::time::Instant::now() + SHUTDOWN_TIMEOUT;
+    if let Ok(flags) = state.sync_cancel.lock() {
+        if !flags.is_empty() {
+            info!("Stopping {} running sync(s) before exit", flags.len());
+        }
+        for flag in flags.values() {
+            flag.store(true, Ordering::Relaxed);
+        }
+    }
+    // A sync drops its cancel flag once its last chunk is stored.
+    while state
+        .sync_cancel
+        .lock()
+        .map(|flags| !flags.is_empty())
+        .unwrap_or(false)
+        && std::time::Instant::now() < deadline
+    {
+        std::thread::sleep(std::time::Duration::from_millis(50));
+    }
+
+    let storage = state.storage.clone();
+    let (tx, rx) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        gmail::close_cached_sessions();
+        let _ = tx.send(storage.checkpoint());
+    });
+    match rx.recv_timeout(deadline.saturating_duration_since(std::time::Instant::now())) {
+        Ok(Ok(())) => info!("Shutdown cleanup complete"),
+        Ok(Err(err)) => warn!("Shutdown cleanup failed: {}", err),
+        Err(_) => warn!("Shutdown cleanup timed out; exiting anyway"),
+    }
+}
+
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
+    logging::init();
     tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
         .plugin(tauri_plugin_process::init())
@@ -578,24 +2662,97 @@ pub fn run() {
         .invoke_handler(tauri::generate_handler![
             get_filters,
             save_filter_patterns,
+            export_filters,
+            import_filters,
+            get_setting,
+            set_setting,
+            get_local_only,
+            list_accounts,
+            upsert_account,
+            set_local_only,
             // Gmail IMAP commands
             gmail_store_credentials,
             gmail_test_connection,
+            gmail_server_capabilities,
+            gmail_ping,
             gmail_is_configured,
+            gmail_list_accounts,
             gmail_delete_credentials,
+            gmail_delete_account_data,
             gmail_fetch_unread,
             gmail_mark_as_read,
             gmail_mark_as_unread,
+            gmail_mark_all_read,
+            gmail_mark_sender_read,
+            gmail_set_flagged,
+            gmail_list_flagged,
+            gmail_list_by_domain,
+            gmail_trash_emails,
+            gmail_untrash_emails,
+            gmail_snooze_emails,
+            gmail_unsnooze_emails,
+            gmail_list_snoozed,
+            gmail_empty_trash,
+            purge_old_read,
+            gmail_archive_emails,
+            gmail_move_emails,
+            gmail_list_mailboxes,
+            gmail_mark_filter_read,
+            gmail_trash_filter,
+            gmail_archive_filter,
+            gmail_find_duplicates,
+            gmail_dedupe_emails,
             gmail_fetch_body,
+            gmail_fetch_full,
+            gmail_fetch_raw,
+            gmail_fetch_bodies,
+            gmail_email_unsubscribe_info,
+            gmail_unsubscribe,
+            gmail_attachments,
+            gmail_server_search,
+            gmail_fetch_headers,
+            gmail_list_threads,
+            gmail_export_emails,
             gmail_sync_unread_background,
             gmail_sync_all_background,
+            gmail_sync_since_background,
+            gmail_cancel_sync,
+            list_syncing_accounts,
+            gmail_resync_account,
+            gmail_fetch_range_background,
+            gmail_sync_flags_background,
+            gmail_load_older_background,
+            gmail_sync_category_background,
+            gmail_reconcile_background,
+            gmail_backfill_bodies_background,
             gmail_refresh_filtered_emails,
             gmail_list_cached_unread,
             gmail_list_cached_all,
+            gmail_list_cached_page,
+            gmail_list_cached_multi,
+            gmail_count_cached_multi,
             gmail_list_filtered_emails,
             gmail_count_filtered_emails,
+            gmail_count_filtered_overlap,
+            gmail_list_unfiltered,
+            gmail_count_unfiltered,
+            estimate_bulk_action,
             gmail_filter_match_counts,
+            gmail_primary_filter_counts,
+            gmail_set_email_filters,
+            preview_filter_refresh,
+            test_filter_preview,
             gmail_cached_counts,
+            gmail_unread_count,
+            gmail_mailbox_counts,
+            gmail_count_cached_all,
+            gmail_sender_stats,
+            gmail_size_stats,
+            gmail_cleanup_stats,
+            get_recent_logs,
+            compact_database,
+            reprocess_bodies,
+            get_db_recovery,
             get_db_directory,
             get_db_file_path
         ])
@@ -603,10 +2760,25 @@ pub fn run() {
             let storage = storage::SqliteStorage::new().map_err(|e| {
                 std::io::Error::new(std::io::ErrorKind::Other, format!("Storage init failed: {}", e))
             })?;
+            let db_recovered_from = storage
+                .recovered_from()
+                .map(|path| path.to_string_lossy().to_string());
+            let storage: Arc<dyn storage::Storage> = Arc::new(storage);
+            // Snoozes that ran out while the app was closed.
+            match storage.clear_elapsed_snoozes() {
+                Ok(0) => {}
+                Ok(cleared) => info!("Un-snoozed {} emails whose snooze elapsed", cleared),
+                Err(e) => warn!("Failed to clear elapsed snoozes: {}", e),
+            }
             app.manage(AppState {
-                storage: Arc::new(storage),
+                storage,
                 syncing: Arc::new(tokio::sync::Mutex::new(HashSet::new())),
                 filter_syncing: Arc::new(tokio::sync::Mutex::new(HashSet::new())),
+                imap_limiter: Arc::new(rate_limit::RateLimiter::new(
+                    rate_limit::MUTATIONS_PER_SECOND,
+                )),
+                sync_cancel: Arc::new(std::sync::Mutex::new(HashMap::new())),
+                db_recovered_from,
             });
             let window = app.get_webview_window("main").unwrap();
 
@@ -619,6 +2791,145 @@ pub fn run() {
 
             Ok(())
         })
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|app, event| {
+            if let tauri::RunEvent::Exit = event {
+                shutdown(&app.state::<AppState>());
+            }
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{SqliteStorage, Storage};
+
+    fn unread_email(uid: u32, subject: &str) -> gmail::GmailEmail {
+        gmail::GmailEmail {
+            uid,
+            message_id: format!("msg-{}", uid),
+            subject: subject.to_string(),
+            sender: "news@example.com".to_string(),
+            date: "2024-01-02T12:00:00Z".to_string(),
+            date_epoch: 1704196800,
+            is_read: false,
+            is_flagged: false,
+            is_answered: false,
+            size_bytes: None,
+            to: None,
+            cc: None,
+        }
+    }
+
+    #[test]
+    fn bulk_mark_read_stays_local_for_local_only_accounts() {
+        let storage = SqliteStorage::new_in_memory().unwrap();
+        let limiter = rate_limit::RateLimiter::new(rate_limit::MUTATIONS_PER_SECOND);
+        let account = "local@example.com";
+        storage
+            .upsert_emails(
+                account,
+                "INBOX",
+                &[
+                    unread_email(1, "Weekly digest"),
+                    unread_email(2, "Digest #2"),
+                    unread_email(3, "Hello"),
+                ],
+            )
+            .unwrap();
+        storage
+            .save_filters(&[FilterPattern {
+                id: 0,
+                name: "Digests".to_string(),
+                pattern: "digest".to_string(),
+                field: filters::FilterField::Subject,
+                is_regex: false,
+                case_sensitive: false,
+                enabled: true,
+                priority: 0,
+                action: filters::FilterAction::MarkRead,
+                action_dry_run: false,
+                match_mode: filters::MatchMode::Substring,
+            }])
+            .unwrap();
+        storage
+            .set_setting(&format!("{}{}", SETTING_LOCAL_ONLY_PREFIX, account), "true")
+            .unwrap();
+
+        // No credentials exist here, so reaching the server would fail these calls.
+        let planned = apply_filter_actions(&storage, &limiter, account, &[1, 2, 3]).unwrap();
+        assert_eq!(planned.len(), 2);
+        assert_eq!(storage.unread_count(account).unwrap(), 1);
+
+        let uids = storage.all_unread_uids(account).unwrap();
+        assert_eq!(mark_read(&storage, &limiter, account, &uids).unwrap(), 1);
+        assert_eq!(storage.unread_count(account).unwrap(), 0);
+    }
+
+    #[test]
+    fn unsubscribe_refetches_bodies_cached_without_targets() {
+        let storage = SqliteStorage::new_in_memory().unwrap();
+        let account = "unsub@example.com";
+        storage
+            .upsert_emails(account, "INBOX", &[unread_email(1, "Deals"), unread_email(2, "Sale")])
+            .unwrap();
+        assert!(cached_unsubscribe_info(&storage, account, 1).unwrap().is_none());
+
+        let body = |unsubscribe_url: Option<&str>| gmail::EmailBody {
+            html: None,
+            html_raw: None,
+            text: Some("hi".to_string()),
+            unsubscribe_url: unsubscribe_url.map(str::to_string),
+            unsubscribe_mailto: None,
+            unsubscribe_oneclick: false,
+            attachments: Vec::new(),
+            in_reply_to: None,
+            references: Vec::new(),
+            snippet: Some("hi".to_string()),
+            headers: Vec::new(),
+        };
+        storage
+            .set_email_bodies(
+                account,
+                &[
+                    // Stands in for a body cached before the unsubscribe columns existed.
+                    gmail::GmailEmailBody { uid: 1, body: body(None) },
+                    gmail::GmailEmailBody { uid: 2, body: body(Some("https://example.com/u")) },
+                ],
+            )
+            .unwrap();
+
+        assert!(cached_unsubscribe_info(&storage, account, 1).unwrap().is_none());
+        let info = cached_unsubscribe_info(&storage, account, 2).unwrap().unwrap();
+        assert_eq!(info.unsubscribe_url.as_deref(), Some("https://example.com/u"));
+    }
+
+    #[test]
+    fn mark_read_reports_server_success_when_cache_fails() {
+        let result = MarkReadResult::run(|| Ok(3), || Err("database is locked".to_string()));
+        assert_eq!(
+            result,
+            MarkReadResult {
+                server_marked: 3,
+                cache_marked: 0,
+                error: Some("database is locked".to_string()),
+            }
+        );
+
+        let mut cache_ran = false;
+        let result = MarkReadResult::run(
+            || Err("connection reset".to_string()),
+            || {
+                cache_ran = true;
+                Ok(3)
+            },
+        );
+        assert!(!cache_ran);
+        assert_eq!(result.server_marked, 0);
+        assert_eq!(result.error.as_deref(), Some("connection reset"));
+
+        let result = MarkReadResult::run(|| Ok(2), || Ok(2));
+        assert_eq!((result.server_marked, result.cache_marked, result.error), (2, 2, None));
+    }
 }
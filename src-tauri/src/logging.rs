@@ -0,0 +1,123 @@
+use chrono::DateTime;
+use log::{LevelFilter, Log, Metadata, Record};
+use std::collections::VecDeque;
+use std::sync::{Mutex, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Log lines kept in memory for `get_recent_logs`.
+const LOG_BUFFER_CAPACITY: usize = 2000;
+
+/// Fixed-size buffer of the most recent log lines; the oldest line is dropped when full.
+struct LogBuffer {
+    lines: VecDeque<String>,
+    capacity: usize,
+}
+
+impl LogBuffer {
+    fn new(capacity: usize) -> Self {
+        Self {
+            lines: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    fn push(&mut self, line: String) {
+        if self.lines.len() == self.capacity {
+            self.lines.pop_front();
+        }
+        self.lines.push_back(line);
+    }
+
+    /// The last `count` lines, oldest first.
+    fn recent(&self, count: usize) -> Vec<String> {
+        let skip = self.lines.len().saturating_sub(count);
+        self.lines.iter().skip(skip).cloned().collect()
+    }
+}
+
+/// Prints to stdout and mirrors every line into a `LogBuffer`. Our own modules log at
+/// the configured level; dependencies only surface warnings and errors.
+struct AppLogger {
+    level: LevelFilter,
+    buffer: Mutex<LogBuffer>,
+}
+
+impl Log for AppLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        if metadata.target().starts_with(env!("CARGO_CRATE_NAME")) {
+            metadata.level() <= self.level
+        } else {
+            metadata.level() <= log::Level::Warn
+        }
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let secs = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or(0);
+        let timestamp = DateTime::from_timestamp(secs, 0)
+            .map(|dt| dt.format("%Y-%m-%d %H:%M:%S").to_string())
+            .unwrap_or_default();
+        let target = record
+            .target()
+            .strip_prefix(concat!(env!("CARGO_CRATE_NAME"), "::"))
+            .unwrap_or(record.target());
+        let line = format!(
+            "{} {:<5} [InboxCleanup:{}] {}",
+            timestamp,
+            record.level(),
+            target,
+            record.args()
+        );
+        println!("{}", line);
+        if let Ok(mut buffer) = self.buffer.lock() {
+            buffer.push(line);
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: OnceLock<AppLogger> = OnceLock::new();
+
+/// Install the logger. The level comes from `RUST_LOG` (e.g. `debug`) and defaults to `info`.
+pub fn init() {
+    let level = std::env::var("RUST_LOG")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(LevelFilter::Info);
+    let logger = LOGGER.get_or_init(|| AppLogger {
+        level,
+        buffer: Mutex::new(LogBuffer::new(LOG_BUFFER_CAPACITY)),
+    });
+    if log::set_logger(logger).is_ok() {
+        log::set_max_level(level);
+    }
+}
+
+/// The last `count` log lines, oldest first. Empty if the logger isn't installed.
+pub fn recent_lines(count: usize) -> Vec<String> {
+    LOGGER
+        .get()
+        .and_then(|logger| logger.buffer.lock().ok().map(|buffer| buffer.recent(count)))
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buffer_keeps_only_the_newest_lines() {
+        let mut buffer = LogBuffer::new(3);
+        for i in 1..=5 {
+            buffer.push(format!("line {}", i));
+        }
+        assert_eq!(buffer.recent(10), vec!["line 3", "line 4", "line 5"]);
+        assert_eq!(buffer.recent(2), vec!["line 4", "line 5"]);
+    }
+}
@@ -0,0 +1,141 @@
+//! Per-account pacing for IMAP mutations (STORE/MOVE/EXPUNGE).
+//!
+//! Bursts of mutations get `[THROTTLED]` replies from Gmail and the affected
+//! commands are lost, so every mutation goes through `RateLimiter::run`, which
+//! blocks the calling (blocking-pool) thread until the account has budget.
+
+use crate::gmail;
+use log::warn;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// IMAP mutations allowed per account per second; also the burst size.
+pub const MUTATIONS_PER_SECOND: u32 = 4;
+/// How long an account is paused after Gmail says to slow down.
+pub const THROTTLE_BACKOFF: Duration = Duration::from_secs(10);
+
+struct Bucket {
+    tokens: f64,
+    refilled_at: Instant,
+    paused_until: Option<Instant>,
+}
+
+impl Bucket {
+    fn new(now: Instant, capacity: f64) -> Self {
+        Self {
+            tokens: capacity,
+            refilled_at: now,
+            paused_until: None,
+        }
+    }
+
+    /// Take a token, or return how long to wait before trying again.
+    fn try_take(&mut self, now: Instant, rate: f64) -> Result<(), Duration> {
+        if let Some(until) = self.paused_until {
+            if now < until {
+                return Err(until - now);
+            }
+            self.paused_until = None;
+        }
+        let elapsed = now.saturating_duration_since(self.refilled_at).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * rate).min(rate);
+        self.refilled_at = now;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - self.tokens) / rate))
+        }
+    }
+
+    fn pause(&mut self, now: Instant, backoff: Duration) {
+        self.tokens = 0.0;
+        self.paused_until = Some(now + backoff);
+    }
+}
+
+pub struct RateLimiter {
+    per_second: f64,
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+impl RateLimiter {
+    pub fn new(per_second: u32) -> Self {
+        Self {
+            per_second: f64::from(per_second.max(1)),
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Block until `account` may send another mutation.
+    fn acquire(&self, account: &str) {
+        loop {
+            let wait = {
+                let Ok(mut buckets) = self.buckets.lock() else {
+                    return;
+                };
+                let now = Instant::now();
+                let bucket = buckets
+                    .entry(account.to_string())
+                    .or_insert_with(|| Bucket::new(now, self.per_second));
+                match bucket.try_take(now, self.per_second) {
+                    Ok(()) => return,
+                    Err(wait) => wait,
+                }
+            };
+            std::thread::sleep(wait);
+        }
+    }
+
+    fn back_off(&self, account: &str) {
+        if let Ok(mut buckets) = self.buckets.lock() {
+            let now = Instant::now();
+            buckets
+                .entry(account.to_string())
+                .or_insert_with(|| Bucket::new(now, self.per_second))
+                .pause(now, THROTTLE_BACKOFF);
+        }
+    }
+
+    /// Run one mutation for `account` once it has budget. If Gmail answers with a
+    /// slow-down response, pause the account and retry once rather than drop it.
+    pub fn run<T>(
+        &self,
+        account: &str,
+        mut op: impl FnMut() -> Result<T, String>,
+    ) -> Result<T, String> {
+        self.acquire(account);
+        match op() {
+            Err(err) if gmail::is_throttle_response(&err) => {
+                warn!("Throttled for {} ({}), backing off {:?}", account, err, THROTTLE_BACKOFF);
+                self.back_off(account);
+                self.acquire(account);
+                op()
+            }
+            result => result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bucket_allows_a_burst_then_paces() {
+        let rate = 4.0;
+        let start = Instant::now();
+        let mut bucket = Bucket::new(start, rate);
+        for _ in 0..4 {
+            assert!(bucket.try_take(start, rate).is_ok());
+        }
+        let wait = bucket.try_take(start, rate).unwrap_err();
+        assert_eq!(wait, Duration::from_millis(250));
+        assert!(bucket.try_take(start + wait, rate).is_ok());
+
+        bucket.pause(start, THROTTLE_BACKOFF);
+        assert!(bucket.try_take(start + Duration::from_secs(5), rate).is_err());
+        assert!(bucket.try_take(start + THROTTLE_BACKOFF + Duration::from_secs(1), rate).is_ok());
+    }
+}
@@ -0,0 +1,188 @@
+//! HTML sanitizing for email bodies shown in the webview.
+//!
+//! Strips scripts, event handlers and other active content, and swaps remote
+//! image sources (including `url(...)` in inline styles) for a blank pixel so
+//! opening an email doesn't report back to the sender. Inline images (`cid:`, and
+//! `data:image/...` in `img src`) are kept.
+
+use std::borrow::Cow;
+
+/// Stored with each sanitized body. Bump it whenever `sanitize_html`'s output changes
+/// so `reprocess_bodies` re-sanitizes bodies cached by older versions.
+pub const SANITIZER_VERSION: i64 = 3;
+
+/// 1x1 transparent GIF used in place of blocked remote images.
+const BLOCKED_IMAGE_PLACEHOLDER: &str =
+    "data:image/gif;base64,R0lGODlhAQABAIAAAAAAAP///yH5BAEAAAAALAAAAAABAAEAAAIBRAA7";
+
+/// Presentational attributes that are common in email HTML and carry no script.
+const EMAIL_ATTRIBUTES: &[&str] = &[
+    "style",
+    "class",
+    "width",
+    "height",
+    "align",
+    "valign",
+    "bgcolor",
+    "border",
+    "cellpadding",
+    "cellspacing",
+    "dir",
+];
+
+/// Attributes ammonia treats as URLs, where a `data:` URL could smuggle in a document.
+const URL_ATTRIBUTES: &[&str] = &[
+    "href",
+    "xlink:href",
+    "src",
+    "action",
+    "data",
+    "formaction",
+    "ping",
+    "poster",
+];
+
+/// Sanitize an email's HTML. Remote images are replaced with a placeholder
+/// unless `load_remote_images` is set.
+pub fn sanitize_html(html: &str, load_remote_images: bool) -> String {
+    let mut builder = ammonia::Builder::default();
+    builder
+        .add_generic_attributes(EMAIL_ATTRIBUTES)
+        .add_tags(&["center", "font"])
+        .add_tag_attributes("font", &["color", "face", "size"])
+        .add_url_schemes(&["cid", "data"])
+        .attribute_filter(move |element, attribute, value| {
+            if URL_ATTRIBUTES.contains(&attribute) && has_scheme(value, "data:") {
+                // `data:` is only for inline images; a `data:text/html` link opens arbitrary HTML.
+                if element == "img" && attribute == "src" && has_scheme(value, "data:image/") {
+                    Some(Cow::Borrowed(value))
+                } else {
+                    None
+                }
+            } else if load_remote_images {
+                Some(Cow::Borrowed(value))
+            } else if element == "img" && attribute == "src" && is_remote_url(value) {
+                Some(Cow::Borrowed(BLOCKED_IMAGE_PLACEHOLDER))
+            } else if attribute == "style" {
+                block_remote_css(value).map(Cow::Owned)
+            } else {
+                Some(Cow::Borrowed(value))
+            }
+        });
+    builder.clean(html).to_string()
+}
+
+/// Point remote `url(...)` references in a `style` value at the placeholder. Returns
+/// `None`, dropping the attribute, when remote content could still be reached some
+/// other way: CSS escapes (`u\72l(`) or bare strings such as `image-set("https://…")`.
+fn block_remote_css(style: &str) -> Option<String> {
+    if style.contains('\\') {
+        return None;
+    }
+    let mut out = String::with_capacity(style.len());
+    let mut outside_urls = String::new();
+    let mut rest = style;
+    // ASCII lowercasing keeps byte offsets, so positions found in the copy fit `rest`.
+    while let Some(start) = rest.to_ascii_lowercase().find("url(") {
+        let arg_start = start + "url(".len();
+        let arg_len = rest[arg_start..].find(')')?;
+        let arg = &rest[arg_start..arg_start + arg_len];
+        outside_urls.push_str(&rest[..start]);
+        out.push_str(&rest[..arg_start]);
+        if is_remote_url(arg.trim().trim_matches(|c| c == '"' || c == '\'')) {
+            out.push_str(&format!("\"{}\"", BLOCKED_IMAGE_PLACEHOLDER));
+        } else {
+            out.push_str(arg);
+        }
+        out.push(')');
+        rest = &rest[arg_start + arg_len + 1..];
+    }
+    outside_urls.push_str(rest);
+    out.push_str(rest);
+
+    let outside_urls = outside_urls.to_ascii_lowercase();
+    if ["http:", "https:", "//"].iter().any(|marker| outside_urls.contains(marker)) {
+        return None;
+    }
+    Some(out)
+}
+
+/// Whether `value` starts with `prefix` the way a URL parser reads it: ignoring case,
+/// leading spaces/control characters and embedded tabs or newlines.
+fn has_scheme(value: &str, prefix: &str) -> bool {
+    let normalized: String = value
+        .trim_start_matches(|c: char| c <= ' ')
+        .chars()
+        .filter(|c| !matches!(c, '\t' | '\n' | '\r'))
+        .take(prefix.len())
+        .collect();
+    normalized.eq_ignore_ascii_case(prefix)
+}
+
+fn is_remote_url(value: &str) -> bool {
+    let lower = value.trim_start().to_ascii_lowercase();
+    lower.starts_with("http://") || lower.starts_with("https://") || lower.starts_with("//")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strips_scripts_and_event_handlers() {
+        let html = r#"<p onclick="steal()">Hi</p><script>alert(1)</script><link rel="stylesheet" href="https://t.example/x.css">"#;
+        let clean = sanitize_html(html, false);
+        assert!(!clean.contains("script"));
+        assert!(!clean.contains("onclick"));
+        assert!(!clean.contains("<link"));
+        assert!(clean.contains("Hi"));
+    }
+
+    #[test]
+    fn blocks_remote_images_unless_requested() {
+        let html = r#"<img src="https://t.example/open.gif" width="1"><img src="cid:logo">"#;
+        let blocked = sanitize_html(html, false);
+        assert!(!blocked.contains("t.example"));
+        assert!(blocked.contains(BLOCKED_IMAGE_PLACEHOLDER));
+        assert!(blocked.contains("cid:logo"));
+
+        let loaded = sanitize_html(html, true);
+        assert!(loaded.contains("https://t.example/open.gif"));
+    }
+
+    #[test]
+    fn blocks_remote_urls_in_inline_styles() {
+        let html = concat!(
+            r#"<p style="color: red; background-image: url('https://t.example/px.gif')">a</p>"#,
+            r#"<div style="background: URL(cid:bg) no-repeat">b</div>"#,
+            r#"<div style="background-image: image-set(&quot;https://t.example/x.png&quot; 1x)">c</div>"#,
+            r#"<div style="background: u\72l(https://t.example/y.png)">d</div>"#,
+        );
+        let blocked = sanitize_html(html, false);
+        assert!(!blocked.contains("t.example"), "{}", blocked);
+        assert!(blocked.contains("color: red"));
+        assert!(blocked.contains(BLOCKED_IMAGE_PLACEHOLDER));
+        assert!(blocked.contains("URL(cid:bg) no-repeat"));
+
+        let loaded = sanitize_html(html, true);
+        assert!(loaded.contains("url('https://t.example/px.gif')"));
+    }
+
+    #[test]
+    fn allows_data_urls_only_for_inline_images() {
+        let html = concat!(
+            r#"<img src="data:image/png;base64,iVBORw0KGgo=">"#,
+            r#"<img src="data:text/html,<b>x</b>">"#,
+            r#"<a href="data:text/html;base64,PHNjcmlwdD4=">open</a>"#,
+            r#"<a href="DA&#9;TA:text/html,hi">tab</a>"#,
+            r#"<a href="data:image/png;base64,iVBORw0KGgo=">image link</a>"#,
+        );
+        for load_remote_images in [false, true] {
+            let clean = sanitize_html(html, load_remote_images);
+            assert!(clean.contains(r#"src="data:image/png;base64,iVBORw0KGgo=""#), "{}", clean);
+            assert!(!clean.contains("text/html"), "{}", clean);
+            assert!(!clean.contains("href"), "{}", clean);
+            assert!(clean.contains("open"));
+        }
+    }
+}
@@ -0,0 +1,97 @@
+//! Writers for `export_emails`. Rows arrive in chunks so large exports never
+//! need the whole result set in memory; each writer keeps just enough state
+//! to emit the surrounding header/brackets once.
+
+use super::StoredEmail;
+use std::io::Write;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Csv,
+    Json,
+}
+
+pub(crate) struct ExportWriter<'a> {
+    out: &'a mut dyn Write,
+    format: ExportFormat,
+    written: u64,
+}
+
+impl<'a> ExportWriter<'a> {
+    pub fn new(out: &'a mut dyn Write, format: ExportFormat) -> Result<Self, String> {
+        match format {
+            ExportFormat::Csv => out.write_all(b"uid,subject,sender,date,is_read\n"),
+            ExportFormat::Json => out.write_all(b"["),
+        }
+        .map_err(|e| format!("Failed to write export: {}", e))?;
+        Ok(Self {
+            out,
+            format,
+            written: 0,
+        })
+    }
+
+    pub fn write_chunk(&mut self, emails: &[StoredEmail]) -> Result<(), String> {
+        for email in emails {
+            match self.format {
+                ExportFormat::Csv => writeln!(
+                    self.out,
+                    "{},{},{},{},{}",
+                    email.uid,
+                    csv_field(&email.subject),
+                    csv_field(&email.sender),
+                    csv_field(&email.date),
+                    email.is_read
+                )
+                .map_err(|e| format!("Failed to write export: {}", e))?,
+                ExportFormat::Json => {
+                    if self.written > 0 {
+                        self.out
+                            .write_all(b",")
+                            .map_err(|e| format!("Failed to write export: {}", e))?;
+                    }
+                    serde_json::to_writer(&mut *self.out, email)
+                        .map_err(|e| format!("Failed to serialize email: {}", e))?;
+                }
+            }
+            self.written += 1;
+        }
+        Ok(())
+    }
+
+    /// Close the document and return the number of emails written.
+    pub fn finish(self) -> Result<u64, String> {
+        if self.format == ExportFormat::Json {
+            self.out
+                .write_all(b"]")
+                .map_err(|e| format!("Failed to write export: {}", e))?;
+        }
+        self.out
+            .flush()
+            .map_err(|e| format!("Failed to write export: {}", e))?;
+        Ok(self.written)
+    }
+}
+
+/// Quote a CSV field (RFC 4180) when it contains a comma, quote, or line break.
+fn csv_field(value: &str) -> std::borrow::Cow<'_, str> {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\"")).into()
+    } else {
+        value.into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csv_field_quotes_only_when_needed() {
+        assert_eq!(csv_field("plain"), "plain");
+        assert_eq!(csv_field("a, b"), "\"a, b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_field("two\nlines"), "\"two\nlines\"");
+    }
+}
@@ -1,13 +1,19 @@
-use crate::filters::{FilterField, FilterPattern};
+use crate::filters::{FilterAction, FilterConfig, FilterField, FilterPattern, ImportMode, MatchMode};
 use crate::gmail::GmailEmail;
-use rusqlite::{params, Connection, OptionalExtension, ToSql};
-use chrono::DateTime;
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension, ToSql};
+use log::{debug, info, warn};
 use regex::RegexBuilder;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::time::Duration;
 use std::fs;
-use std::path::PathBuf;
-use std::sync::Mutex;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard};
+
+mod export;
+mod threads;
+
+pub use export::ExportFormat;
 
 /// Storage interface so we can swap implementations later.
 pub trait Storage: Send + Sync {
@@ -15,47 +21,249 @@ pub trait Storage: Send + Sync {
         &self,
         account: &str,
         unread_only: bool,
+        query: &EmailQuery,
+        sort: SortBy,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<StoredEmail>, String>;
+    fn count_emails(
+        &self,
+        account: &str,
+        unread_only: bool,
+        query: &EmailQuery,
+    ) -> Result<u64, String>;
+    /// Unread, untrashed emails in the account; the same number as `count_emails(_, true, ..)`
+    /// with no query, answered from the `(account, is_read, is_trashed)` index alone.
+    fn unread_count(&self, account: &str) -> Result<u64, String>;
+    /// `list_emails` plus the matching total, read from one snapshot.
+    fn list_emails_page(
+        &self,
+        account: &str,
+        unread_only: bool,
+        query: &EmailQuery,
+        sort: SortBy,
+        limit: u32,
+        offset: u32,
+    ) -> Result<EmailPage, String>;
+    /// Newest-first listing across several accounts for the unified inbox. Snoozed
+    /// emails are hidden, as in the per-account inbox.
+    fn list_emails_multi(
+        &self,
+        accounts: &[String],
+        unread_only: bool,
         limit: u32,
         offset: u32,
     ) -> Result<Vec<StoredEmail>, String>;
-    fn count_emails(&self, account: &str, unread_only: bool) -> Result<u64, String>;
+    /// Counts what `list_emails_multi` lists, so snoozed emails are left out and the
+    /// count can drive its paging.
+    fn count_emails_multi(&self, accounts: &[String], unread_only: bool) -> Result<u64, String>;
     fn list_filtered_emails(
         &self,
         account: &str,
         filter_ids: &[i64],
         unread_only: bool,
+        sort: SortBy,
         limit: u32,
         offset: u32,
     ) -> Result<Vec<StoredEmail>, String>;
+    /// Counts snoozed emails too: like the account totals, a filter's count covers
+    /// everything it matches, while `list_filtered_emails` hides snoozed ones.
     fn count_filtered_emails(
         &self,
         account: &str,
         filter_ids: &[i64],
         unread_only: bool,
     ) -> Result<u64, String>;
+    /// Untrashed, unsnoozed emails no enabled filter matches, newest first: the
+    /// "everything else" bucket next to the per-filter views.
+    fn list_unfiltered_emails(
+        &self,
+        account: &str,
+        unread_only: bool,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<StoredEmail>, String>;
+    fn count_unfiltered_emails(&self, account: &str, unread_only: bool) -> Result<u64, String>;
+    /// How many of the emails counted by `count_filtered_emails` match two or more of
+    /// `filter_ids`, which is why the per-filter counts add up to more than the total.
+    fn count_filtered_emails_overlap(
+        &self,
+        account: &str,
+        filter_ids: &[i64],
+        unread_only: bool,
+    ) -> Result<u64, String>;
     fn filter_match_counts(
         &self,
         account: &str,
         unread_only: bool,
     ) -> Result<Vec<(i64, u64)>, String>;
+    /// How many emails matched by any of `filter_ids` `action` would change, without
+    /// changing anything. Trashed emails only count towards `Trash`.
+    fn estimate_bulk_action(
+        &self,
+        account: &str,
+        filter_ids: &[i64],
+        action: BulkAction,
+    ) -> Result<BulkEstimate, String>;
+    /// Inbox-zero progress for one account; see [`CleanupStats`].
+    fn cleanup_stats(&self, account: &str) -> Result<CleanupStats, String>;
+    /// `(mailbox, total, unread)` of untrashed emails per mailbox, noisiest (most unread) first.
+    fn count_emails_by_mailbox(&self, account: &str) -> Result<Vec<(String, u64, u64)>, String>;
+    /// Assign each email to its single highest-priority enabled matching filter,
+    /// returned as `(uid, filter_id)` pairs.
+    fn primary_filter_for_emails(&self, account: &str) -> Result<Vec<(u32, i64)>, String>;
+    /// Pending filter actions for the given emails: the highest-priority enabled
+    /// filter with an action, per email. Mark-read is skipped for emails already read.
+    fn filter_actions_for_uids(
+        &self,
+        account: &str,
+        uids: &[u32],
+    ) -> Result<Vec<PlannedFilterAction>, String>;
+    fn preview_filter(
+        &self,
+        account: &str,
+        filter: &FilterPattern,
+        limit: u32,
+    ) -> Result<Vec<StoredEmail>, String>;
+    fn sender_stats(
+        &self,
+        account: &str,
+        unread_only: bool,
+        limit: u32,
+    ) -> Result<Vec<SenderStat>, String>;
+    /// Untrashed UIDs from one sender, matched on the bare address (case-insensitive)
+    /// whether or not the stored `sender` carries a display name.
+    fn uids_for_sender(
+        &self,
+        account: &str,
+        sender_address: &str,
+        unread_only: bool,
+    ) -> Result<Vec<u32>, String>;
+    /// UIDs of untrashed, unsnoozed emails matched by one filter.
+    fn uids_for_filter(&self, account: &str, filter_id: i64, unread_only: bool) -> Result<Vec<u32>, String>;
+    /// Emails whose sender's domain is `domain` or a subdomain of it, newest first.
+    /// Senders without a parseable address never match.
+    fn list_by_sender_domain(
+        &self,
+        account: &str,
+        domain: &str,
+        unread_only: bool,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<StoredEmail>, String>;
     fn refresh_filtered_emails(
         &self,
         account: &str,
         chunk_size: u32,
         force_full: bool,
     ) -> Result<usize, String>;
-    fn get_last_uid(&self, account: &str) -> Result<u32, String>;
-    fn set_last_uid(&self, account: &str, last_uid: u32) -> Result<(), String>;
-    fn get_max_uid(&self, account: &str) -> Result<Option<u32>, String>;
+    /// What `refresh_filtered_emails(account, _, true)` would change, computed in memory.
+    /// Read-only: neither `filtered_emails` nor the filter sync state is touched.
+    fn preview_filter_refresh(&self, account: &str) -> Result<FilterRefreshPreview, String>;
+    /// Create the account's row or replace its display name and color.
+    fn upsert_account(&self, email: &str, display_name: Option<&str>, color: Option<&str>) -> Result<(), String>;
+    fn list_accounts(&self) -> Result<Vec<AccountInfo>, String>;
+    /// Record that a sync of `email` finished at `epoch`, creating the row if needed.
+    fn touch_last_synced(&self, email: &str, epoch: i64) -> Result<(), String>;
+    fn get_setting(&self, key: &str) -> Result<Option<String>, String>;
+    fn set_setting(&self, key: &str, value: &str) -> Result<(), String>;
+    /// UIDs only mean something within one mailbox, so sync progress is kept per mailbox.
+    fn get_last_uid(&self, account: &str, mailbox: &str) -> Result<u32, String>;
+    fn set_last_uid(&self, account: &str, mailbox: &str, last_uid: u32) -> Result<(), String>;
+    fn get_max_uid(&self, account: &str, mailbox: &str) -> Result<Option<u32>, String>;
+    /// Low-water mark for loading older history: every UID from here up to `last_uid` is
+    /// cached. Falls back to the lowest cached UID, then to just above `last_uid`;
+    /// `None` when nothing has been synced yet.
+    fn get_oldest_synced_uid(&self, account: &str, mailbox: &str) -> Result<Option<u32>, String>;
+    /// Lower the low-water mark to `uid`; a higher value than the current one is ignored.
+    fn set_oldest_synced_uid(&self, account: &str, mailbox: &str, uid: u32) -> Result<(), String>;
+    /// INBOX HIGHESTMODSEQ recorded by the last flag sync, if any.
+    fn get_highest_modseq(&self, account: &str) -> Result<Option<u64>, String>;
+    fn set_highest_modseq(&self, account: &str, modseq: u64) -> Result<(), String>;
+    /// Store read/flagged/answered state pulled from the server. UIDs that aren't cached are
+    /// ignored. Returns how many emails changed.
+    fn apply_flag_updates(&self, account: &str, updates: &[crate::gmail::FlagUpdate]) -> Result<usize, String>;
+    /// Unread emails outside the trash, which is what mark-all-read acts on.
+    fn all_unread_uids(&self, account: &str) -> Result<Vec<u32>, String>;
+    /// Record that exactly `uids` are in Gmail category tab `category`: they are tagged
+    /// with it and emails tagged with it before but missing now are untagged. Returns
+    /// how many emails carry the category afterwards.
+    fn replace_gmail_category(&self, account: &str, category: &str, uids: &[u32]) -> Result<usize, String>;
+    /// Every cached UID in `mailbox`, ascending. Trashed emails are left out; they were
+    /// moved away on purpose and are kept for undo.
+    fn cached_uids(&self, account: &str, mailbox: &str) -> Result<Vec<u32>, String>;
+    /// UIDs of cached emails with no recorded size (synced before sizes were stored).
+    fn uids_without_size(&self, account: &str, limit: u32) -> Result<Vec<u32>, String>;
+    fn set_email_sizes(&self, account: &str, sizes: &[(u32, u32)]) -> Result<(), String>;
+    /// Bytes used per bare sender address, largest first. Unsized and trashed emails
+    /// are skipped.
+    fn total_size_by_sender(&self, account: &str) -> Result<Vec<SenderSize>, String>;
+    /// Newest-first UIDs of cached emails whose body hasn't been fetched yet.
+    fn uids_without_body(&self, account: &str, limit: u32) -> Result<Vec<u32>, String>;
+    /// UIDs whose cached HTML was sanitized by a version older than `current_version`.
+    fn stale_body_uids(&self, account: &str, current_version: i64) -> Result<Vec<u32>, String>;
+    /// Re-sanitize the stored raw HTML of these emails with the current sanitizer.
+    /// Nothing is fetched from IMAP. Returns how many bodies were rewritten.
+    fn resanitize_bodies(&self, account: &str, uids: &[u32]) -> Result<usize, String>;
     fn upsert_emails(
         &self,
         account: &str,
         mailbox: &str,
         emails: &[GmailEmail],
     ) -> Result<(), String>;
+    fn delete_account_emails(&self, account: &str) -> Result<usize, String>;
+    /// Forget sync and filter-match progress so the next sync starts from UID 0.
+    /// Cached emails are kept.
+    fn reset_sync_state(&self, account: &str) -> Result<(), String>;
+    /// Drop emails from the cache, e.g. after they were archived out of INBOX.
+    fn remove_emails(&self, account: &str, uids: &[u32]) -> Result<usize, String>;
+    /// Hide emails from listings until they are untrashed or purged.
+    fn trash_emails(&self, account: &str, uids: &[u32]) -> Result<usize, String>;
+    fn untrash_emails(&self, account: &str, uids: &[u32]) -> Result<usize, String>;
+    fn trashed_uids(&self, account: &str) -> Result<Vec<u32>, String>;
+    /// Hide emails from listings until `until_epoch`. They still count in totals.
+    fn snooze_emails(&self, account: &str, uids: &[u32], until_epoch: i64) -> Result<usize, String>;
+    fn unsnooze_emails(&self, account: &str, uids: &[u32]) -> Result<usize, String>;
+    /// Emails still snoozed, soonest to reappear first.
+    fn list_snoozed(&self, account: &str, limit: u32, offset: u32) -> Result<Vec<SnoozedEmail>, String>;
+    /// Clear snoozes that have elapsed, across all accounts. Returns how many were cleared.
+    fn clear_elapsed_snoozes(&self) -> Result<usize, String>;
+    /// Message-IDs cached more than once (e.g. INBOX and All Mail), with their UIDs.
+    /// Empty Message-IDs are never grouped together.
+    fn duplicate_message_ids(&self, account: &str) -> Result<Vec<(String, Vec<u32>)>, String>;
+    /// Keep one copy of each duplicate per `keep` and trash the rest. Returns how many were trashed.
+    fn dedupe_emails(&self, account: &str, keep: KeepStrategy) -> Result<usize, String>;
+    /// Drop the given emails from the cache, but only those still trashed.
+    fn purge_trashed(&self, account: &str, uids: &[u32]) -> Result<usize, String>;
+    /// Drop read emails dated before `epoch` from the cache; bodies, attachments and
+    /// filter matches go with them. Unread, flagged, trashed and still-snoozed emails
+    /// are kept, as are emails without a parsed date. The server copies are untouched.
+    fn purge_read_older_than(&self, account: &str, epoch: i64) -> Result<usize, String>;
+    /// Run VACUUM, checkpoint the WAL it produced, and return how many bytes the
+    /// database and its `-wal` file shrank by on disk.
+    fn compact(&self) -> Result<u64, String>;
+    /// Copy the WAL into the main database file and truncate it. Waits for any
+    /// write in progress, since it takes the writer.
+    fn checkpoint(&self) -> Result<(), String>;
     fn mark_emails_read(&self, account: &str, uids: &[u32]) -> Result<usize, String>;
+    /// Mark read in the cache only. Syncs keep these read until the server agrees,
+    /// and `mark_emails_unread` clears the override.
+    fn mark_emails_read_locally(&self, account: &str, uids: &[u32]) -> Result<usize, String>;
     fn mark_emails_unread(&self, account: &str, uids: &[u32]) -> Result<usize, String>;
+    fn set_flagged(&self, account: &str, uids: &[u32], flagged: bool) -> Result<usize, String>;
+    fn list_flagged(&self, account: &str, limit: u32, offset: u32) -> Result<Vec<StoredEmail>, String>;
     fn get_email_body(&self, account: &str, uid: u32) -> Result<Option<crate::gmail::EmailBody>, String>;
+    fn get_unsubscribe_info(&self, account: &str, uid: u32) -> Result<Option<UnsubscribeInfo>, String>;
+    fn list_threads(&self, account: &str, limit: u32, offset: u32) -> Result<Vec<ThreadSummary>, String>;
+    /// Group emails that were added, or whose reply headers changed, since the last
+    /// call. This rescans the whole account, so syncs call it once at the end rather
+    /// than per chunk; until then such emails list as threads of their own.
+    fn assign_threads(&self, account: &str) -> Result<(), String>;
+    fn attachments_for(
+        &self,
+        account: &str,
+        uid: u32,
+    ) -> Result<Vec<crate::gmail::AttachmentMeta>, String>;
     fn set_email_bodies(
         &self,
         account: &str,
@@ -63,12 +271,38 @@ pub trait Storage: Send + Sync {
     ) -> Result<(), String>;
     fn get_filters(&self) -> Result<Vec<FilterPattern>, String>;
     fn save_filters(&self, patterns: &[FilterPattern]) -> Result<Vec<FilterPattern>, String>;
+    /// Serialize the current filters as `FilterConfig` JSON.
+    fn export_filters(&self) -> Result<String, String>;
+    /// Load filters from `FilterConfig` JSON. Rejects the whole file if any regex is invalid.
+    fn import_filters(&self, json: &str, mode: ImportMode) -> Result<Vec<FilterPattern>, String>;
+    /// Replace one email's filter matches. With `protect`, the mappings are stored as
+    /// `manual` and automatic matching leaves the email alone from then on, `force_full`
+    /// refreshes included; without it, the next `force_full` refresh recomputes them.
     fn set_email_filters(
         &self,
         account: &str,
         uid: u32,
         filter_ids: &[i64],
+        protect: bool,
     ) -> Result<(), String>;
+    /// Export the account's emails, or only those matching `filter_ids` when non-empty.
+    /// The app streams exports to disk with `export_emails_to`; this in-memory form is
+    /// for small exports and tests.
+    #[allow(dead_code)]
+    fn export_emails(
+        &self,
+        account: &str,
+        filter_ids: &[i64],
+        format: ExportFormat,
+    ) -> Result<String, String>;
+    /// Streaming form of `export_emails`; returns the number of emails written.
+    fn export_emails_to(
+        &self,
+        account: &str,
+        filter_ids: &[i64],
+        format: ExportFormat,
+        out: &mut dyn std::io::Write,
+    ) -> Result<u64, String>;
 }
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
@@ -82,148 +316,652 @@ pub struct StoredEmail {
     pub mailbox: String,
     pub account: String,
     pub is_read: bool,
+    pub unsubscribe_url: Option<String>,
+    pub unsubscribe_mailto: Option<String>,
+    pub unsubscribe_oneclick: bool,
+    pub to: Option<String>,
+    pub cc: Option<String>,
+    /// Whether attachment metadata was recorded when the body was fetched.
+    pub has_attachments: bool,
+    pub is_flagged: bool,
+    /// `None` for emails synced before sizes were recorded, until backfilled.
+    pub size_bytes: Option<u32>,
+    /// Start of the body text; `None` until the body has been fetched.
+    pub snippet: Option<String>,
+    /// Replied to (`\Answered`).
+    pub is_answered: bool,
+}
+
+/// A snoozed email and when it reappears.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct SnoozedEmail {
+    #[serde(flatten)]
+    pub email: StoredEmail,
+    pub snoozed_until: i64,
+}
+
+/// One page of emails with the total it was cut from. `total` includes snoozed
+/// emails even when the page hides them.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct EmailPage {
+    pub emails: Vec<StoredEmail>,
+    pub total: u64,
+    pub has_more: bool,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct PlannedFilterAction {
+    pub uid: u32,
+    pub filter_id: i64,
+    pub action: FilterAction,
+    pub dry_run: bool,
+}
+
+/// One conversation in `list_threads`; `subject` is taken from the latest email.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ThreadSummary {
+    pub thread_id: i64,
+    pub subject: String,
+    pub latest_epoch: i64,
+    pub count: u32,
+    pub unread: u32,
+}
+
+/// `List-Unsubscribe` details captured when an email body was fetched.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct UnsubscribeInfo {
+    pub unsubscribe_url: Option<String>,
+    pub unsubscribe_mailto: Option<String>,
+    pub unsubscribe_oneclick: bool,
+}
+
+/// A Message-ID and its cached copies as `(uid, mailbox)`.
+type DuplicateGroup = (String, Vec<(u32, String)>);
+
+/// Which copy survives when collapsing duplicates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeepStrategy {
+    /// The INBOX copy, falling back to the lowest UID when none is in INBOX.
+    Inbox,
+    LowestUid,
+}
+
+#[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortField {
+    Date,
+    Sender,
+    Subject,
+}
+
+/// Ordering for email listings. Defaults to newest first.
+#[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize)]
+pub struct SortBy {
+    pub field: SortField,
+    #[serde(default)]
+    pub ascending: bool,
+}
+
+impl Default for SortBy {
+    fn default() -> Self {
+        Self {
+            field: SortField::Date,
+            ascending: false,
+        }
+    }
+}
+
+impl SortBy {
+    /// Fixed ORDER BY clause for this sort; never built from user input.
+    fn order_by_clause(&self) -> &'static str {
+        match (self.field, self.ascending) {
+            (SortField::Date, false) => "date_epoch DESC, uid DESC",
+            (SortField::Date, true) => "date_epoch ASC, uid ASC",
+            (SortField::Sender, true) => "sender COLLATE NOCASE ASC, date_epoch DESC",
+            (SortField::Sender, false) => "sender COLLATE NOCASE DESC, date_epoch DESC",
+            (SortField::Subject, true) => "subject COLLATE NOCASE ASC, date_epoch DESC",
+            (SortField::Subject, false) => "subject COLLATE NOCASE DESC, date_epoch DESC",
+        }
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct SenderStat {
+    pub sender: String,
+    pub total: u64,
+    pub unread: u64,
+}
+
+/// Per-account metadata the UI shows next to each account.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct AccountInfo {
+    pub email: String,
+    pub display_name: Option<String>,
+    /// CSS color for the account's badge, e.g. `#3b82f6`.
+    pub color: Option<String>,
+    /// When a full sync last finished, in unix seconds.
+    pub last_synced_epoch: Option<i64>,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct SenderSize {
+    pub sender: String,
+    pub count: u64,
+    pub total_bytes: u64,
+}
+
+/// Delta of a forced filter rebuild. `per_filter` holds `(filter_id, net change in
+/// matched emails)` for each filter whose count would change, by filter id.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct FilterRefreshPreview {
+    pub would_add: u64,
+    pub would_remove: u64,
+    pub per_filter: Vec<(i64, i64)>,
+}
+
+/// A bulk action applied to every email matched by a set of filters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BulkAction {
+    MarkRead,
+    Archive,
+    Trash,
+}
+
+/// What a [`BulkAction`] would do: `affected` emails would change and
+/// `already_in_state` are matched but already read, archived or trashed.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct BulkEstimate {
+    pub affected: u64,
+    pub already_in_state: u64,
+}
+
+/// Every cached email falls in exactly one of `unread`, `read_in_inbox` and
+/// `archived` (outside INBOX or trashed), so the three add up to `total`.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct CleanupStats {
+    pub total: u64,
+    pub unread: u64,
+    pub read_in_inbox: u64,
+    pub archived: u64,
+}
+
+/// Optional narrowing applied on top of the account/unread conditions.
+#[derive(Debug, Clone, Default)]
+pub struct EmailQuery {
+    pub after_epoch: Option<i64>,
+    pub before_epoch: Option<i64>,
+    /// List emails whose snooze hasn't elapsed yet. Counts always include them.
+    pub include_snoozed: bool,
+    /// `Some(true)` keeps only emails that were replied to, `Some(false)` only those
+    /// that weren't.
+    pub answered_only: Option<bool>,
+    /// Only emails last seen in this Gmail category tab (see `replace_gmail_category`).
+    pub gmail_category: Option<String>,
+}
+
+impl EmailQuery {
+    /// Append ` AND ...` conditions for this query and push their parameters.
+    fn append_conditions<'a>(&'a self, sql: &mut String, params: &mut Vec<&'a dyn ToSql>) {
+        if self.after_epoch.is_some() || self.before_epoch.is_some() {
+            // Rows with unparseable dates carry epoch 0 and can't be placed in a range.
+            sql.push_str(" AND date_epoch > 0");
+        }
+        if let Some(after_epoch) = &self.after_epoch {
+            sql.push_str(" AND date_epoch >= ?");
+            params.push(after_epoch);
+        }
+        if let Some(before_epoch) = &self.before_epoch {
+            sql.push_str(" AND date_epoch <= ?");
+            params.push(before_epoch);
+        }
+        if let Some(answered) = &self.answered_only {
+            sql.push_str(" AND is_answered = ?");
+            params.push(answered);
+        }
+        if let Some(category) = &self.gmail_category {
+            sql.push_str(" AND gmail_category = ?");
+            params.push(category);
+        }
+    }
+
+    /// Extra condition for listings: hide emails that are still snoozed.
+    fn snooze_condition(&self) -> &'static str {
+        if self.include_snoozed {
+            ""
+        } else {
+            NOT_SNOOZED
+        }
+    }
 }
 
+/// Matches emails that aren't snoozed or whose snooze has elapsed.
+const NOT_SNOOZED: &str =
+    " AND (snoozed_until IS NULL OR snoozed_until <= CAST(strftime('%s', 'now') AS INTEGER))";
+
+/// Rows read per query while exporting.
+const EXPORT_CHUNK_SIZE: u32 = 500;
+
+/// Number of read-only connections kept alongside the single writer.
+const READ_POOL_SIZE: usize = 3;
+
 pub struct SqliteStorage {
+    /// Writer connection; all mutations go through here.
     conn: Mutex<Connection>,
+    /// Read-only connections so listings don't queue behind the writer (WAL mode).
+    readers: Vec<Mutex<Connection>>,
+    next_reader: AtomicUsize,
+    /// Where a corrupt database found at open was moved before starting afresh.
+    recovered_from: Option<PathBuf>,
 }
 
 impl SqliteStorage {
     pub fn new() -> Result<Self, String> {
-        let path = get_db_path()?;
-        let mut conn = Connection::open(path).map_err(|e| format!("Failed to open DB: {}", e))?;
+        Self::open(get_db_path()?)
+    }
+
+    #[cfg(test)]
+    pub fn new_with_path(path: PathBuf) -> Result<Self, String> {
+        Self::open(path)
+    }
+
+    /// Private in-memory database for tests; it lives as long as the writer connection
+    /// held in `conn`. There are no read connections, since each would open its own
+    /// empty database, so reads go through the writer. Filters are not imported.
+    #[cfg(test)]
+    pub fn new_in_memory() -> Result<Self, String> {
+        let mut conn =
+            Connection::open_in_memory().map_err(|e| format!("Failed to open DB: {}", e))?;
         conn.pragma_update(None, "foreign_keys", &"ON")
             .map_err(|e| format!("Failed to enable foreign keys: {}", e))?;
         migrate(&mut conn)?;
-        maybe_import_filters(&mut conn)?;
+
         Ok(Self {
             conn: Mutex::new(conn),
+            readers: Vec::new(),
+            next_reader: AtomicUsize::new(0),
+            recovered_from: None,
         })
     }
 
-    #[cfg(test)]
-    pub fn new_with_path(path: PathBuf) -> Result<Self, String> {
+    /// Set when the database failed its integrity check at open: the corrupt file was
+    /// moved here and the cache started empty. Credentials live in the Keychain and
+    /// are unaffected.
+    pub fn recovered_from(&self) -> Option<&Path> {
+        self.recovered_from.as_deref()
+    }
+
+    fn open(path: PathBuf) -> Result<Self, String> {
+        // `quick_check` skips verifying indexes so launch stays fast on big caches; the
+        // full check only runs once opening the database has failed anyway.
+        let mut recovered_from = recover_if_corrupt(&path, "quick_check")?;
+        let mut storage = match Self::connect(&path) {
+            Ok(storage) => storage,
+            Err(err) if recovered_from.is_none() => {
+                match recover_if_corrupt(&path, "integrity_check")? {
+                    Some(moved) => {
+                        recovered_from = Some(moved);
+                        Self::connect(&path)?
+                    }
+                    None => return Err(err),
+                }
+            }
+            Err(err) => return Err(err),
+        };
+        storage.recovered_from = recovered_from;
+        Ok(storage)
+    }
+
+    /// Open the writer and the read pool on `path`, creating or migrating the schema.
+    fn connect(path: &Path) -> Result<Self, String> {
         let mut conn = Connection::open(path).map_err(|e| format!("Failed to open DB: {}", e))?;
         conn.pragma_update(None, "foreign_keys", &"ON")
             .map_err(|e| format!("Failed to enable foreign keys: {}", e))?;
+        conn.pragma_update_and_check(None, "journal_mode", "WAL", |row| row.get::<_, String>(0))
+            .map_err(|e| format!("Failed to enable WAL: {}", e))?;
+        conn.pragma_update(None, "synchronous", "NORMAL")
+            .map_err(|e| format!("Failed to set synchronous mode: {}", e))?;
+        conn.busy_timeout(Duration::from_secs(5))
+            .map_err(|e| format!("Failed to set busy timeout: {}", e))?;
+        // Schema changes happen on the writer before any reader is opened.
         migrate(&mut conn)?;
         maybe_import_filters(&mut conn)?;
+
+        let mut readers = Vec::with_capacity(READ_POOL_SIZE);
+        for _ in 0..READ_POOL_SIZE {
+            let reader = Connection::open_with_flags(
+                path,
+                OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+            )
+            .map_err(|e| format!("Failed to open read connection: {}", e))?;
+            reader
+                .busy_timeout(Duration::from_secs(5))
+                .map_err(|e| format!("Failed to set busy timeout: {}", e))?;
+            readers.push(Mutex::new(reader));
+        }
+
         Ok(Self {
             conn: Mutex::new(conn),
+            readers,
+            next_reader: AtomicUsize::new(0),
+            recovered_from: None,
         })
     }
-}
 
-impl Storage for SqliteStorage {
-    fn list_emails(
-        &self,
-        account: &str,
-        unread_only: bool,
-        limit: u32,
-        offset: u32,
-    ) -> Result<Vec<StoredEmail>, String> {
-        let conn = self
+    /// Apply `assignments` (e.g. `is_read = 1`) to the given UIDs in one transaction.
+    fn set_read_state(&self, account: &str, uids: &[u32], assignments: &str) -> Result<usize, String> {
+        if uids.is_empty() {
+            return Ok(0);
+        }
+
+        let mut conn = self
             .conn
             .lock()
             .map_err(|_| "Failed to lock DB".to_string())?;
-        let mut stmt = if unread_only {
-            conn.prepare(
-                "SELECT uid, message_id, subject, sender, date, IFNULL(date_epoch, 0), mailbox, account, is_read \
-                 FROM emails \
-                 WHERE account = ?1 AND is_read = 0 \
-                 ORDER BY date_epoch DESC \
-                 LIMIT ?2 OFFSET ?3",
-            )
-            .map_err(|e| format!("Failed to prepare query: {}", e))?
-        } else {
-            conn.prepare(
-                "SELECT uid, message_id, subject, sender, date, IFNULL(date_epoch, 0), mailbox, account, is_read \
-                 FROM emails \
-                 WHERE account = ?1 \
-                 ORDER BY date_epoch DESC \
-                 LIMIT ?2 OFFSET ?3",
-            )
-            .map_err(|e| format!("Failed to prepare query: {}", e))?
-        };
+        let tx = conn
+            .transaction()
+            .map_err(|e| format!("Failed to start transaction: {}", e))?;
+
+        let mut total = 0;
+        for chunk in uids.chunks(200) {
+            let placeholders = chunk
+                .iter()
+                .enumerate()
+                .map(|(i, _)| format!("?{}", i + 2))
+                .collect::<Vec<_>>()
+                .join(",");
+            let sql = format!(
+                "UPDATE emails SET {}, updated_at = CURRENT_TIMESTAMP \
+                 WHERE account = ?1 AND uid IN ({})",
+                assignments, placeholders
+            );
+
+            let mut params_vec: Vec<&dyn ToSql> = Vec::with_capacity(chunk.len() + 1);
+            params_vec.push(&account);
+            for uid in chunk {
+                params_vec.push(uid);
+            }
+
+            total += tx
+                .execute(&sql, params_vec.as_slice())
+                .map_err(|e| e.to_string())?;
+        }
+
+        tx.commit()
+            .map_err(|e| format!("Failed to commit transaction: {}", e))?;
+        Ok(total)
+    }
+
+    /// Borrow a read-only connection, preferring an idle one. Falls back to the
+    /// writer when no readers are configured.
+    fn reader(&self) -> Result<MutexGuard<'_, Connection>, String> {
+        if self.readers.is_empty() {
+            return self
+                .conn
+                .lock()
+                .map_err(|_| "Failed to lock DB".to_string());
+        }
+        for reader in &self.readers {
+            if let Ok(guard) = reader.try_lock() {
+                return Ok(guard);
+            }
+        }
+        let index = self.next_reader.fetch_add(1, Ordering::Relaxed) % self.readers.len();
+        self.readers[index]
+            .lock()
+            .map_err(|_| "Failed to lock DB".to_string())
+    }
 
+    /// Untrashed copies of each duplicated Message-ID as `(uid, mailbox)`, lowest UID first.
+    fn duplicate_groups(&self, account: &str) -> Result<Vec<DuplicateGroup>, String> {
+        let conn = self.reader()?;
+        let mut stmt = conn
+            .prepare(
+                "SELECT message_id, uid, mailbox FROM emails \
+                 WHERE account = ?1 AND is_trashed = 0 AND message_id IN ( \
+                    SELECT message_id FROM emails \
+                    WHERE account = ?1 AND is_trashed = 0 AND TRIM(IFNULL(message_id, '')) <> '' \
+                    GROUP BY message_id HAVING COUNT(*) > 1) \
+                 ORDER BY message_id, uid",
+            )
+            .map_err(|e| format!("Failed to prepare duplicate query: {}", e))?;
         let rows = stmt
-            .query_map(params![account, limit, offset], |row| {
-                Ok(StoredEmail {
-                    uid: row.get(0)?,
-                    message_id: row.get(1)?,
-                    subject: row.get(2)?,
-                    sender: row.get(3)?,
-                    date: row.get(4)?,
-                    date_epoch: row.get(5)?,
-                    mailbox: row.get(6)?,
-                    account: row.get(7)?,
-                    is_read: row.get::<_, i64>(8)? != 0,
-                })
+            .query_map(params![account], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, u32>(1)?, row.get::<_, String>(2)?))
             })
-            .map_err(|e| format!("Failed to query emails: {}", e))?;
+            .map_err(|e| format!("Failed to query duplicates: {}", e))?;
 
-        let mut results = Vec::new();
+        let mut groups: Vec<DuplicateGroup> = Vec::new();
         for row in rows {
-            results.push(row.map_err(|e| format!("Failed to read email: {}", e))?);
+            let (message_id, uid, mailbox) =
+                row.map_err(|e| format!("Failed to read duplicate: {}", e))?;
+            match groups.last_mut() {
+                Some((current, copies)) if *current == message_id => copies.push((uid, mailbox)),
+                _ => groups.push((message_id, vec![(uid, mailbox)])),
+            }
         }
-        Ok(results)
+        Ok(groups)
     }
 
-    fn count_emails(&self, account: &str, unread_only: bool) -> Result<u64, String> {
-        let conn = self
+    /// Run `sql` (with `?1` = account and the chunk's UIDs in `{}`) over `uids` in
+    /// chunks inside one transaction, returning the number of rows changed.
+    fn execute_for_uids(&self, sql: &str, account: &str, uids: &[u32]) -> Result<usize, String> {
+        if uids.is_empty() {
+            return Ok(0);
+        }
+
+        let mut conn = self
             .conn
             .lock()
             .map_err(|_| "Failed to lock DB".to_string())?;
-        let sql = if unread_only {
-            "SELECT COUNT(*) FROM emails WHERE account = ?1 AND is_read = 0"
-        } else {
-            "SELECT COUNT(*) FROM emails WHERE account = ?1"
-        };
-        let count: u64 = conn
-            .query_row(sql, params![account], |row| row.get(0))
-            .map_err(|e| format!("Failed to count emails: {}", e))?;
-        Ok(count)
+        let tx = conn
+            .transaction()
+            .map_err(|e| format!("Failed to start transaction: {}", e))?;
+
+        let mut total = 0;
+        for chunk in uids.chunks(500) {
+            let sql = sql.replace("{}", &vec!["?"; chunk.len()].join(","));
+            let mut params_vec: Vec<&dyn ToSql> = Vec::with_capacity(chunk.len() + 1);
+            params_vec.push(&account);
+            for uid in chunk {
+                params_vec.push(uid);
+            }
+            total += tx
+                .execute(&sql, params_vec.as_slice())
+                .map_err(|e| format!("Failed to update emails: {}", e))?;
+        }
+
+        tx.commit()
+            .map_err(|e| format!("Failed to commit transaction: {}", e))?;
+        Ok(total)
     }
+}
 
-    fn list_filtered_emails(
+impl Storage for SqliteStorage {
+    fn list_emails(
         &self,
         account: &str,
-        filter_ids: &[i64],
         unread_only: bool,
+        query: &EmailQuery,
+        sort: SortBy,
         limit: u32,
         offset: u32,
     ) -> Result<Vec<StoredEmail>, String> {
-        if filter_ids.is_empty() {
-            return Ok(Vec::new());
+        let conn = self.reader()?;
+        query_emails(&conn, account, unread_only, query, sort, limit, offset)
+    }
+
+    fn count_emails(
+        &self,
+        account: &str,
+        unread_only: bool,
+        query: &EmailQuery,
+    ) -> Result<u64, String> {
+        let conn = self.reader()?;
+        query_email_count(&conn, account, unread_only, query, false)
+    }
+
+    fn unread_count(&self, account: &str) -> Result<u64, String> {
+        let conn = self.reader()?;
+        conn.query_row(
+            "SELECT COUNT(*) FROM emails WHERE account = ?1 AND is_read = 0 AND is_trashed = 0",
+            params![account],
+            |row| row.get(0),
+        )
+        .map_err(|e| format!("Failed to count unread emails: {}", e))
+    }
+
+    fn list_emails_page(
+        &self,
+        account: &str,
+        unread_only: bool,
+        query: &EmailQuery,
+        sort: SortBy,
+        limit: u32,
+        offset: u32,
+    ) -> Result<EmailPage, String> {
+        let mut conn = self.reader()?;
+        // One read transaction so the rows and the total come from the same snapshot.
+        let tx = conn
+            .transaction()
+            .map_err(|e| format!("Failed to start read transaction: {}", e))?;
+        let emails = query_emails(&tx, account, unread_only, query, sort, limit, offset)?;
+        let total = query_email_count(&tx, account, unread_only, query, false)?;
+        // Paging follows what is listed, so snoozed emails can't leave a phantom next page.
+        let listed_total = if query.include_snoozed {
+            total
+        } else {
+            query_email_count(&tx, account, unread_only, query, true)?
+        };
+        tx.commit()
+            .map_err(|e| format!("Failed to finish read transaction: {}", e))?;
+        let has_more = u64::from(offset) + (emails.len() as u64) < listed_total;
+        Ok(EmailPage {
+            emails,
+            total,
+            has_more,
+        })
+    }
+
+    fn list_emails_multi(
+        &self,
+        accounts: &[String],
+        unread_only: bool,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<StoredEmail>, String> {
+        if accounts.is_empty() {
+            return Ok(Vec::new());
         }
 
-        let conn = self
-            .conn
-            .lock()
-            .map_err(|_| "Failed to lock DB".to_string())?;
+        let conn = self.reader()?;
+        // UIDs are only unique per account, so the account breaks remaining ties
+        // and keeps LIMIT/OFFSET pages stable.
+        let sql = format!(
+            "SELECT uid, message_id, subject, sender, date, IFNULL(date_epoch, 0), mailbox, account, is_read, \
+             unsubscribe_url, unsubscribe_mailto, unsubscribe_oneclick, to_recipients, cc_recipients, \
+             EXISTS(SELECT 1 FROM attachments a WHERE a.email_id = emails.id), is_flagged, size_bytes, snippet, is_answered \
+             FROM emails \
+             WHERE account IN ({}) AND is_trashed = 0 AND (? = 0 OR is_read = 0){} \
+             ORDER BY date_epoch DESC, uid DESC, account ASC \
+             LIMIT ? OFFSET ?",
+            vec!["?"; accounts.len()].join(","),
+            NOT_SNOOZED
+        );
+        let unread_flag = if unread_only { 1 } else { 0 };
+        let mut params: Vec<&dyn ToSql> = Vec::with_capacity(accounts.len() + 3);
+        for account in accounts {
+            params.push(account);
+        }
+        params.push(&unread_flag);
+        params.push(&limit);
+        params.push(&offset);
+
+        let mut stmt = conn
+            .prepare(&sql)
+            .map_err(|e| format!("Failed to prepare query: {}", e))?;
+        let rows = stmt
+            .query_map(params.as_slice(), stored_email_from_row)
+            .map_err(|e| format!("Failed to query emails: {}", e))?;
+
+        let mut results = Vec::new();
+        for row in rows {
+            results.push(row.map_err(|e| format!("Failed to read email: {}", e))?);
+        }
+        Ok(results)
+    }
+
+    fn count_emails_multi(&self, accounts: &[String], unread_only: bool) -> Result<u64, String> {
+        if accounts.is_empty() {
+            return Ok(0);
+        }
+
+        let conn = self.reader()?;
+        let sql = format!(
+            "SELECT COUNT(*) FROM emails \
+             WHERE account IN ({}) AND is_trashed = 0 AND (? = 0 OR is_read = 0){}",
+            vec!["?"; accounts.len()].join(","),
+            NOT_SNOOZED
+        );
+        let unread_flag = if unread_only { 1 } else { 0 };
+        let mut params: Vec<&dyn ToSql> = Vec::with_capacity(accounts.len() + 1);
+        for account in accounts {
+            params.push(account);
+        }
+        params.push(&unread_flag);
+
+        let count: u64 = conn
+            .query_row(&sql, params.as_slice(), |row| row.get(0))
+            .map_err(|e| format!("Failed to count emails: {}", e))?;
+        Ok(count)
+    }
+
+    fn list_filtered_emails(
+        &self,
+        account: &str,
+        filter_ids: &[i64],
+        unread_only: bool,
+        sort: SortBy,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<StoredEmail>, String> {
+        if filter_ids.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let conn = self.reader()?;
         let placeholders = std::iter::repeat("?")
             .take(filter_ids.len())
             .collect::<Vec<_>>()
             .join(",");
         let sql = if unread_only {
             format!(
-                "SELECT DISTINCT e.uid, e.message_id, e.subject, e.sender, e.date, IFNULL(e.date_epoch, 0), e.mailbox, e.account, e.is_read \
+                "SELECT DISTINCT e.uid, e.message_id, e.subject, e.sender, e.date, IFNULL(e.date_epoch, 0), e.mailbox, e.account, e.is_read, \
+                 e.unsubscribe_url, e.unsubscribe_mailto, e.unsubscribe_oneclick, e.to_recipients, e.cc_recipients, \
+                 EXISTS(SELECT 1 FROM attachments a WHERE a.email_id = e.id), e.is_flagged, e.size_bytes, e.snippet, e.is_answered \
                  FROM emails e \
                  JOIN filtered_emails fe ON fe.email_id = e.id \
-                 WHERE e.account = ?1 AND e.is_read = 0 AND fe.filter_id IN ({}) \
-                 ORDER BY e.date_epoch DESC \
+                 WHERE e.account = ?1 AND e.is_trashed = 0 AND e.is_read = 0 AND fe.filter_id IN ({}){} \
+                 ORDER BY {} \
                  LIMIT ? OFFSET ?",
-                placeholders
+                placeholders,
+                NOT_SNOOZED,
+                sort.order_by_clause()
             )
         } else {
             format!(
-                "SELECT DISTINCT e.uid, e.message_id, e.subject, e.sender, e.date, IFNULL(e.date_epoch, 0), e.mailbox, e.account, e.is_read \
+                "SELECT DISTINCT e.uid, e.message_id, e.subject, e.sender, e.date, IFNULL(e.date_epoch, 0), e.mailbox, e.account, e.is_read, \
+                 e.unsubscribe_url, e.unsubscribe_mailto, e.unsubscribe_oneclick, e.to_recipients, e.cc_recipients, \
+                 EXISTS(SELECT 1 FROM attachments a WHERE a.email_id = e.id), e.is_flagged, e.size_bytes, e.snippet, e.is_answered \
                  FROM emails e \
                  JOIN filtered_emails fe ON fe.email_id = e.id \
-                 WHERE e.account = ?1 AND fe.filter_id IN ({}) \
-                 ORDER BY e.date_epoch DESC \
+                 WHERE e.account = ?1 AND e.is_trashed = 0 AND fe.filter_id IN ({}){} \
+                 ORDER BY {} \
                  LIMIT ? OFFSET ?",
-                placeholders
+                placeholders,
+                NOT_SNOOZED,
+                sort.order_by_clause()
             )
         };
 
@@ -239,19 +977,7 @@ impl Storage for SqliteStorage {
             .prepare(&sql)
             .map_err(|e| format!("Failed to prepare filtered query: {}", e))?;
         let rows = stmt
-            .query_map(params.as_slice(), |row| {
-                Ok(StoredEmail {
-                    uid: row.get(0)?,
-                    message_id: row.get(1)?,
-                    subject: row.get(2)?,
-                    sender: row.get(3)?,
-                    date: row.get(4)?,
-                    date_epoch: row.get(5)?,
-                    mailbox: row.get(6)?,
-                    account: row.get(7)?,
-                    is_read: row.get::<_, i64>(8)? != 0,
-                })
-            })
+            .query_map(params.as_slice(), stored_email_from_row)
             .map_err(|e| format!("Failed to query filtered emails: {}", e))?;
 
         let mut results = Vec::new();
@@ -271,10 +997,7 @@ impl Storage for SqliteStorage {
             return Ok(0);
         }
 
-        let conn = self
-            .conn
-            .lock()
-            .map_err(|_| "Failed to lock DB".to_string())?;
+        let conn = self.reader()?;
         let placeholders = std::iter::repeat("?")
             .take(filter_ids.len())
             .collect::<Vec<_>>()
@@ -284,7 +1007,7 @@ impl Storage for SqliteStorage {
                 "SELECT COUNT(DISTINCT e.id) \
                  FROM emails e \
                  JOIN filtered_emails fe ON fe.email_id = e.id \
-                 WHERE e.account = ?1 AND e.is_read = 0 AND fe.filter_id IN ({})",
+                 WHERE e.account = ?1 AND e.is_trashed = 0 AND e.is_read = 0 AND fe.filter_id IN ({})",
                 placeholders
             )
         } else {
@@ -292,7 +1015,7 @@ impl Storage for SqliteStorage {
                 "SELECT COUNT(DISTINCT e.id) \
                  FROM emails e \
                  JOIN filtered_emails fe ON fe.email_id = e.id \
-                 WHERE e.account = ?1 AND fe.filter_id IN ({})",
+                 WHERE e.account = ?1 AND e.is_trashed = 0 AND fe.filter_id IN ({})",
                 placeholders
             )
         };
@@ -309,19 +1032,151 @@ impl Storage for SqliteStorage {
         Ok(count)
     }
 
+    fn list_unfiltered_emails(
+        &self,
+        account: &str,
+        unread_only: bool,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<StoredEmail>, String> {
+        let conn = self.reader()?;
+        let sql = format!(
+            "SELECT e.uid, e.message_id, e.subject, e.sender, e.date, IFNULL(e.date_epoch, 0), e.mailbox, e.account, e.is_read, \
+             e.unsubscribe_url, e.unsubscribe_mailto, e.unsubscribe_oneclick, e.to_recipients, e.cc_recipients, \
+             EXISTS(SELECT 1 FROM attachments a WHERE a.email_id = e.id), e.is_flagged, e.size_bytes, e.snippet, e.is_answered \
+             FROM emails e \
+             {} \
+             WHERE e.account = ?1 AND e.is_trashed = 0{}{} AND fe.email_id IS NULL \
+             ORDER BY e.date_epoch DESC, e.uid DESC \
+             LIMIT ?2 OFFSET ?3",
+            UNFILTERED_JOIN,
+            if unread_only { " AND e.is_read = 0" } else { "" },
+            NOT_SNOOZED
+        );
+        let mut stmt = conn
+            .prepare(&sql)
+            .map_err(|e| format!("Failed to prepare unfiltered query: {}", e))?;
+        let rows = stmt
+            .query_map(params![account, limit, offset], stored_email_from_row)
+            .map_err(|e| format!("Failed to query unfiltered emails: {}", e))?;
+
+        let mut results = Vec::new();
+        for row in rows {
+            results.push(row.map_err(|e| format!("Failed to read email: {}", e))?);
+        }
+        Ok(results)
+    }
+
+    fn count_unfiltered_emails(&self, account: &str, unread_only: bool) -> Result<u64, String> {
+        let conn = self.reader()?;
+        let sql = format!(
+            "SELECT COUNT(*) FROM emails e \
+             {} \
+             WHERE e.account = ?1 AND e.is_trashed = 0{} AND fe.email_id IS NULL",
+            UNFILTERED_JOIN,
+            if unread_only { " AND e.is_read = 0" } else { "" }
+        );
+        conn.query_row(&sql, params![account], |row| row.get(0))
+            .map_err(|e| format!("Failed to count unfiltered emails: {}", e))
+    }
+
+    fn count_filtered_emails_overlap(
+        &self,
+        account: &str,
+        filter_ids: &[i64],
+        unread_only: bool,
+    ) -> Result<u64, String> {
+        if filter_ids.len() < 2 {
+            return Ok(0);
+        }
+
+        let conn = self.reader()?;
+        let placeholders = std::iter::repeat_n("?", filter_ids.len())
+            .collect::<Vec<_>>()
+            .join(",");
+        let sql = format!(
+            "SELECT COUNT(*) FROM ( \
+                 SELECT e.id \
+                 FROM emails e \
+                 JOIN filtered_emails fe ON fe.email_id = e.id \
+                 WHERE e.account = ?1 AND e.is_trashed = 0{} AND fe.filter_id IN ({}) \
+                 GROUP BY e.id \
+                 HAVING COUNT(DISTINCT fe.filter_id) > 1 \
+             )",
+            if unread_only { " AND e.is_read = 0" } else { "" },
+            placeholders
+        );
+
+        let mut params: Vec<&dyn ToSql> = Vec::with_capacity(1 + filter_ids.len());
+        params.push(&account);
+        for filter_id in filter_ids {
+            params.push(filter_id);
+        }
+
+        let count: u64 = conn
+            .query_row(&sql, params.as_slice(), |row| row.get(0))
+            .map_err(|e| format!("Failed to count overlapping filter matches: {}", e))?;
+        Ok(count)
+    }
+
+    fn estimate_bulk_action(
+        &self,
+        account: &str,
+        filter_ids: &[i64],
+        action: BulkAction,
+    ) -> Result<BulkEstimate, String> {
+        if filter_ids.is_empty() {
+            return Ok(BulkEstimate {
+                affected: 0,
+                already_in_state: 0,
+            });
+        }
+
+        let conn = self.reader()?;
+        let placeholders = std::iter::repeat_n("?", filter_ids.len())
+            .collect::<Vec<_>>()
+            .join(",");
+        let (candidates, done) = match action {
+            BulkAction::MarkRead => ("e.is_trashed = 0", "is_read = 1"),
+            BulkAction::Archive => ("e.is_trashed = 0", "mailbox <> 'INBOX'"),
+            BulkAction::Trash => ("1", "is_trashed = 1"),
+        };
+        let sql = format!(
+            "SELECT IFNULL(SUM(NOT ({0})), 0), IFNULL(SUM({0}), 0) FROM ( \
+                 SELECT DISTINCT e.id, e.is_read, e.is_trashed, e.mailbox \
+                 FROM emails e \
+                 JOIN filtered_emails fe ON fe.email_id = e.id \
+                 WHERE e.account = ?1 AND {1} AND fe.filter_id IN ({2}) \
+             )",
+            done, candidates, placeholders
+        );
+
+        let mut params: Vec<&dyn ToSql> = Vec::with_capacity(1 + filter_ids.len());
+        params.push(&account);
+        for filter_id in filter_ids {
+            params.push(filter_id);
+        }
+
+        conn.query_row(&sql, params.as_slice(), |row| {
+            Ok(BulkEstimate {
+                affected: row.get(0)?,
+                already_in_state: row.get(1)?,
+            })
+        })
+        .map_err(|e| format!("Failed to estimate bulk action: {}", e))
+    }
+
     fn filter_match_counts(
         &self,
         account: &str,
         unread_only: bool,
     ) -> Result<Vec<(i64, u64)>, String> {
-        let conn = self
-            .conn
-            .lock()
-            .map_err(|_| "Failed to lock DB".to_string())?;
+        let conn = self.reader()?;
         let sql = "SELECT f.id, COUNT(e.id) \
             FROM filters f \
             LEFT JOIN filtered_emails fe ON fe.filter_id = f.id \
-            LEFT JOIN emails e ON e.id = fe.email_id AND e.account = ?1 AND (?2 = 0 OR e.is_read = 0) \
+            LEFT JOIN emails e ON e.id = fe.email_id AND e.account = ?1 AND e.is_trashed = 0 \
+                AND (?2 = 0 OR e.is_read = 0) \
             GROUP BY f.id \
             ORDER BY f.rowid ASC";
         let mut stmt = conn
@@ -339,99 +1194,363 @@ impl Storage for SqliteStorage {
         Ok(results)
     }
 
-    fn refresh_filtered_emails(
-        &self,
-        account: &str,
-        chunk_size: u32,
-        force_full: bool,
-    ) -> Result<usize, String> {
-        let mut attempts = 0u32;
-        let mut conn = loop {
-            match self.conn.try_lock() {
-                Ok(guard) => break guard,
-                Err(_) => {
-                    attempts += 1;
-                    if attempts % 20 == 0 {
-                        println!("[InboxCleanup] Waiting for DB lock to refresh filters...");
-                    }
-                    std::thread::sleep(Duration::from_millis(50));
-                }
-            }
-        };
+    fn cleanup_stats(&self, account: &str) -> Result<CleanupStats, String> {
+        let conn = self.reader()?;
+        conn.query_row(
+            "SELECT COUNT(*), \
+                IFNULL(SUM(is_trashed = 0 AND mailbox = 'INBOX' AND is_read = 0), 0), \
+                IFNULL(SUM(is_trashed = 0 AND mailbox = 'INBOX' AND is_read = 1), 0), \
+                IFNULL(SUM(is_trashed = 1 OR mailbox <> 'INBOX'), 0) \
+             FROM emails \
+             WHERE account = ?1",
+            params![account],
+            |row| {
+                Ok(CleanupStats {
+                    total: row.get(0)?,
+                    unread: row.get(1)?,
+                    read_in_inbox: row.get(2)?,
+                    archived: row.get(3)?,
+                })
+            },
+        )
+        .map_err(|e| format!("Failed to count cleanup stats: {}", e))
+    }
 
-        if force_full {
-            println!("[InboxCleanup] Filter refresh forcing full backfill (manual)");
-            conn.execute(
-                "DELETE FROM filtered_emails WHERE email_id IN (SELECT id FROM emails WHERE account = ?1)",
-                params![account],
-            )
-            .map_err(|e| format!("Failed to clear filtered emails: {}", e))?;
-            conn.execute(
-                "DELETE FROM filter_sync_state_v2 WHERE account = ?1 AND scope = ?2",
-                params![account, FILTER_SYNC_SCOPE],
+    fn count_emails_by_mailbox(&self, account: &str) -> Result<Vec<(String, u64, u64)>, String> {
+        let conn = self.reader()?;
+        let mut stmt = conn
+            .prepare(
+                "SELECT mailbox, COUNT(*), SUM(CASE WHEN is_read = 0 THEN 1 ELSE 0 END) AS unread \
+                 FROM emails \
+                 WHERE account = ?1 AND is_trashed = 0 \
+                 GROUP BY mailbox \
+                 ORDER BY unread DESC, mailbox ASC",
             )
-            .map_err(|e| format!("Failed to reset filter sync state: {}", e))?;
+            .map_err(|e| format!("Failed to prepare mailbox counts: {}", e))?;
+        let rows = stmt
+            .query_map(params![account], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, u64>(1)?, row.get::<_, u64>(2)?))
+            })
+            .map_err(|e| format!("Failed to query mailbox counts: {}", e))?;
+        let mut results = Vec::new();
+        for row in rows {
+            results.push(row.map_err(|e| format!("Failed to read mailbox count: {}", e))?);
         }
+        Ok(results)
+    }
 
-        let mut last_id = get_filter_last_email_id(&conn, account)?;
-        let filtered_count: u64 = conn
-            .query_row(
-                "SELECT COUNT(*) FROM filtered_emails fe \
+    fn primary_filter_for_emails(&self, account: &str) -> Result<Vec<(u32, i64)>, String> {
+        let conn = self.reader()?;
+        let mut stmt = conn
+            .prepare(
+                "SELECT e.uid, fe.filter_id \
+                 FROM filtered_emails fe \
                  JOIN emails e ON e.id = fe.email_id \
-                 WHERE e.account = ?1",
-                params![account],
-                |row| row.get(0),
+                 JOIN filters f ON f.id = fe.filter_id \
+                 WHERE e.account = ?1 AND f.enabled = 1 \
+                 ORDER BY e.id, f.priority ASC, f.rowid ASC",
             )
-            .map_err(|e| format!("Failed to count filtered emails: {}", e))?;
-        if filtered_count == 0 && last_id > 0 {
-            println!(
-                "[InboxCleanup] Filter refresh forcing full backfill (last_id was {})",
-                last_id
-            );
-            last_id = 0;
-            set_filter_last_email_id(&conn, account, last_id)?;
+            .map_err(|e| format!("Failed to prepare primary filter query: {}", e))?;
+        let rows = stmt
+            .query_map(params![account], |row| {
+                Ok((row.get::<_, u32>(0)?, row.get::<_, i64>(1)?))
+            })
+            .map_err(|e| format!("Failed to query primary filters: {}", e))?;
+
+        // Rows arrive grouped by email with the winning filter first.
+        let mut results: Vec<(u32, i64)> = Vec::new();
+        for row in rows {
+            let (uid, filter_id) = row.map_err(|e| format!("Failed to read primary filter: {}", e))?;
+            if results.last().map(|(last_uid, _)| *last_uid) != Some(uid) {
+                results.push((uid, filter_id));
+            }
         }
-        let filters = load_filters_from_conn(&conn)?;
-        let compiled_filters = compile_filters(&filters);
-        println!(
-            "[InboxCleanup] Filter refresh chunk start (last_id: {}, filters: {}, chunk_size: {})",
-            last_id,
-            compiled_filters.len(),
-            chunk_size
-        );
+        Ok(results)
+    }
 
-        let batch = {
-            let mut stmt = conn
-                .prepare(
-                    "SELECT id, uid, subject, sender \
-                     FROM emails \
-                     WHERE account = ?1 AND id > ?2 \
-                     ORDER BY id ASC \
-                     LIMIT ?3",
-                )
-                .map_err(|e| format!("Failed to prepare filter refresh query: {}", e))?;
+    fn filter_actions_for_uids(
+        &self,
+        account: &str,
+        uids: &[u32],
+    ) -> Result<Vec<PlannedFilterAction>, String> {
+        let conn = self.reader()?;
+        let mut planned: Vec<PlannedFilterAction> = Vec::new();
+        for chunk in uids.chunks(500) {
+            let placeholders = vec!["?"; chunk.len()].join(",");
+            let sql = format!(
+                "SELECT e.uid, f.id, f.action, f.action_dry_run, e.is_read \
+                 FROM filtered_emails fe \
+                 JOIN emails e ON e.id = fe.email_id \
+                 JOIN filters f ON f.id = fe.filter_id \
+                 WHERE e.account = ?1 AND e.uid IN ({}) AND f.enabled = 1 AND f.action != 'none' \
+                 ORDER BY e.id, f.priority ASC, f.rowid ASC",
+                placeholders
+            );
+            let mut params_vec: Vec<&dyn ToSql> = Vec::with_capacity(chunk.len() + 1);
+            params_vec.push(&account);
+            for uid in chunk {
+                params_vec.push(uid);
+            }
 
+            let mut stmt = conn
+                .prepare(&sql)
+                .map_err(|e| format!("Failed to prepare filter action query: {}", e))?;
             let rows = stmt
-                .query_map(params![account, last_id, chunk_size], |row| {
+                .query_map(params_vec.as_slice(), |row| {
                     Ok((
-                        row.get::<_, i64>(0)?,
-                        row.get::<_, u32>(1)?,
-                        row.get::<_, String>(2)?,
-                        row.get::<_, String>(3)?,
+                        PlannedFilterAction {
+                            uid: row.get(0)?,
+                            filter_id: row.get(1)?,
+                            action: parse_filter_action(&row.get::<_, String>(2)?),
+                            dry_run: row.get::<_, i64>(3)? != 0,
+                        },
+                        row.get::<_, i64>(4)? != 0,
                     ))
                 })
-                .map_err(|e| format!("Failed to query emails for filter refresh: {}", e))?;
+                .map_err(|e| format!("Failed to query filter actions: {}", e))?;
 
-            let mut batch = Vec::new();
+            let mut last_uid = None;
             for row in rows {
-                batch.push(row.map_err(|e| format!("Failed to read email row: {}", e))?);
+                let (action, is_read) =
+                    row.map_err(|e| format!("Failed to read filter action: {}", e))?;
+                if last_uid == Some(action.uid) {
+                    continue;
+                }
+                last_uid = Some(action.uid);
+                if action.action == FilterAction::MarkRead && is_read {
+                    continue;
+                }
+                planned.push(action);
             }
-            batch
-        };
-
-        if batch.is_empty() {
-            println!("[InboxCleanup] Filter refresh chunk empty; nothing to process.");
-            return Ok(0);
+        }
+        Ok(planned)
+    }
+
+    fn preview_filter(
+        &self,
+        account: &str,
+        filter: &FilterPattern,
+        limit: u32,
+    ) -> Result<Vec<StoredEmail>, String> {
+        if filter.is_regex {
+            RegexBuilder::new(&filter.pattern)
+                .case_insensitive(!filter.case_sensitive)
+                .build()
+                .map_err(|e| format!("Invalid regex: {}", e))?;
+        }
+        let compiled_filters = compile_filters(std::slice::from_ref(filter));
+
+        let conn = self.reader()?;
+        let mut stmt = conn
+            .prepare(
+                "SELECT uid, message_id, subject, sender, date, IFNULL(date_epoch, 0), mailbox, account, is_read, \
+                 unsubscribe_url, unsubscribe_mailto, unsubscribe_oneclick, to_recipients, cc_recipients, \
+                 EXISTS(SELECT 1 FROM attachments a WHERE a.email_id = emails.id), is_flagged, size_bytes, snippet, is_answered \
+                 FROM emails \
+                 WHERE account = ?1 \
+                 ORDER BY date_epoch DESC",
+            )
+            .map_err(|e| format!("Failed to prepare preview query: {}", e))?;
+        let rows = stmt
+            .query_map(params![account], stored_email_from_row)
+            .map_err(|e| format!("Failed to query emails for preview: {}", e))?;
+
+        let mut results = Vec::new();
+        for row in rows {
+            if results.len() >= limit as usize {
+                break;
+            }
+            let email = row.map_err(|e| format!("Failed to read email: {}", e))?;
+            let recipients = split_recipients(email.to.as_deref(), email.cc.as_deref());
+            if !match_filters(&email.subject, &email.sender, &recipients, &compiled_filters)
+                .is_empty()
+            {
+                results.push(email);
+            }
+        }
+        Ok(results)
+    }
+
+    fn sender_stats(
+        &self,
+        account: &str,
+        unread_only: bool,
+        limit: u32,
+    ) -> Result<Vec<SenderStat>, String> {
+        let conn = self.reader()?;
+        let sql = format!(
+            "SELECT {} AS address, COUNT(*) AS total, SUM(CASE WHEN is_read = 0 THEN 1 ELSE 0 END) \
+             FROM emails \
+             WHERE account = ?1 AND is_trashed = 0 AND (?2 = 0 OR is_read = 0) \
+             GROUP BY address \
+             ORDER BY total DESC, address ASC \
+             LIMIT ?3",
+            SENDER_ADDRESS_SQL
+        );
+        let mut stmt = conn
+            .prepare(&sql)
+            .map_err(|e| format!("Failed to prepare sender stats: {}", e))?;
+        let rows = stmt
+            .query_map(
+                params![account, if unread_only { 1 } else { 0 }, limit],
+                |row| {
+                    Ok(SenderStat {
+                        sender: row.get(0)?,
+                        total: row.get(1)?,
+                        unread: row.get(2)?,
+                    })
+                },
+            )
+            .map_err(|e| format!("Failed to query sender stats: {}", e))?;
+        let mut results = Vec::new();
+        for row in rows {
+            results.push(row.map_err(|e| format!("Failed to read sender stat: {}", e))?);
+        }
+        Ok(results)
+    }
+
+    fn uids_for_sender(
+        &self,
+        account: &str,
+        sender_address: &str,
+        unread_only: bool,
+    ) -> Result<Vec<u32>, String> {
+        let address = bare_address(sender_address);
+        let conn = self.reader()?;
+        let sql = format!(
+            "SELECT uid FROM emails \
+             WHERE account = ?1 AND is_trashed = 0 AND (?2 = 0 OR is_read = 0) AND {} = ?3 \
+             ORDER BY uid",
+            SENDER_ADDRESS_SQL
+        );
+        let mut stmt = conn
+            .prepare(&sql)
+            .map_err(|e| format!("Failed to prepare sender query: {}", e))?;
+        let rows = stmt
+            .query_map(params![account, if unread_only { 1 } else { 0 }, address], |row| {
+                row.get(0)
+            })
+            .map_err(|e| format!("Failed to query sender emails: {}", e))?;
+        let mut uids = Vec::new();
+        for row in rows {
+            uids.push(row.map_err(|e| format!("Failed to read uid: {}", e))?);
+        }
+        Ok(uids)
+    }
+
+    fn uids_for_filter(&self, account: &str, filter_id: i64, unread_only: bool) -> Result<Vec<u32>, String> {
+        let conn = self.reader()?;
+        let sql = format!(
+            "SELECT DISTINCT e.uid FROM emails e \
+             JOIN filtered_emails fe ON fe.email_id = e.id \
+             WHERE e.account = ?1 AND e.is_trashed = 0 AND (?2 = 0 OR e.is_read = 0) \
+               AND fe.filter_id = ?3{} \
+             ORDER BY e.uid",
+            NOT_SNOOZED
+        );
+        let mut stmt = conn
+            .prepare(&sql)
+            .map_err(|e| format!("Failed to prepare filter query: {}", e))?;
+        let rows = stmt
+            .query_map(params![account, if unread_only { 1 } else { 0 }, filter_id], |row| {
+                row.get(0)
+            })
+            .map_err(|e| format!("Failed to query filter emails: {}", e))?;
+        let mut uids = Vec::new();
+        for row in rows {
+            uids.push(row.map_err(|e| format!("Failed to read uid: {}", e))?);
+        }
+        Ok(uids)
+    }
+
+    fn refresh_filtered_emails(
+        &self,
+        account: &str,
+        chunk_size: u32,
+        force_full: bool,
+    ) -> Result<usize, String> {
+        let mut conn = self
+            .conn
+            .lock()
+            .map_err(|_| "Failed to lock DB".to_string())?;
+
+        if force_full {
+            info!("Filter refresh forcing full backfill (manual)");
+            conn.execute(
+                "DELETE FROM filtered_emails \
+                 WHERE source = 'auto' AND email_id IN (SELECT id FROM emails WHERE account = ?1)",
+                params![account],
+            )
+            .map_err(|e| format!("Failed to clear filtered emails: {}", e))?;
+            conn.execute(
+                "DELETE FROM filter_sync_state_v2 WHERE account = ?1 AND scope = ?2",
+                params![account, FILTER_SYNC_SCOPE],
+            )
+            .map_err(|e| format!("Failed to reset filter sync state: {}", e))?;
+        }
+
+        let mut last_id = get_filter_last_email_id(&conn, account)?;
+        let filtered_count: u64 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM filtered_emails fe \
+                 JOIN emails e ON e.id = fe.email_id \
+                 WHERE e.account = ?1",
+                params![account],
+                |row| row.get(0),
+            )
+            .map_err(|e| format!("Failed to count filtered emails: {}", e))?;
+        if filtered_count == 0 && last_id > 0 {
+            info!(
+                "Filter refresh forcing full backfill (last_id was {})",
+                last_id
+            );
+            last_id = 0;
+            set_filter_last_email_id(&conn, account, last_id)?;
+        }
+        let filters = load_filters_from_conn(&conn)?;
+        let compiled_filters = compile_filters(&filters);
+        debug!(
+            "Filter refresh chunk start (last_id: {}, filters: {}, chunk_size: {})",
+            last_id,
+            compiled_filters.len(),
+            chunk_size
+        );
+
+        let batch = {
+            let mut stmt = conn
+                .prepare(
+                    "SELECT id, uid, subject, sender, filters_manual, to_recipients, cc_recipients \
+                     FROM emails \
+                     WHERE account = ?1 AND id > ?2 \
+                     ORDER BY id ASC \
+                     LIMIT ?3",
+                )
+                .map_err(|e| format!("Failed to prepare filter refresh query: {}", e))?;
+
+            let rows = stmt
+                .query_map(params![account, last_id, chunk_size], |row| {
+                    Ok((
+                        row.get::<_, i64>(0)?,
+                        row.get::<_, u32>(1)?,
+                        row.get::<_, String>(2)?,
+                        row.get::<_, String>(3)?,
+                        row.get::<_, i64>(4)? != 0,
+                        row.get::<_, Option<String>>(5)?,
+                        row.get::<_, Option<String>>(6)?,
+                    ))
+                })
+                .map_err(|e| format!("Failed to query emails for filter refresh: {}", e))?;
+
+            let mut batch = Vec::new();
+            for row in rows {
+                batch.push(row.map_err(|e| format!("Failed to read email row: {}", e))?);
+            }
+            batch
+        };
+
+        if batch.is_empty() {
+            debug!("Filter refresh chunk empty; nothing to process.");
+            return Ok(0);
         }
 
         let max_id = batch.last().map(|row| row.0).unwrap_or(last_id);
@@ -447,8 +1566,13 @@ impl Storage for SqliteStorage {
                 )
                 .map_err(|e| format!("Failed to prepare filter insert: {}", e))?;
 
-            for (email_id, _uid, subject, sender) in &batch {
-                let matches = match_filters(subject, sender, &compiled_filters);
+            for (email_id, _uid, subject, sender, manual, to, cc) in &batch {
+                // Hand-assigned filters stay as the user left them.
+                if *manual {
+                    continue;
+                }
+                let recipients = split_recipients(to.as_deref(), cc.as_deref());
+                let matches = match_filters(subject, sender, &recipients, &compiled_filters);
                 for filter_id in matches {
                     insert_stmt
                         .execute(params![email_id, filter_id])
@@ -461,22 +1585,178 @@ impl Storage for SqliteStorage {
         tx.commit()
             .map_err(|e| format!("Failed to commit filter refresh: {}", e))?;
 
-        println!(
-            "[InboxCleanup] Filter refresh chunk committed (rows: {})",
+        debug!(
+            "Filter refresh chunk committed (rows: {})",
             batch.len()
         );
         Ok(batch.len())
     }
 
-    fn get_last_uid(&self, account: &str) -> Result<u32, String> {
+    fn preview_filter_refresh(&self, account: &str) -> Result<FilterRefreshPreview, String> {
+        let conn = self.reader()?;
+        let compiled_filters = compile_filters(&load_filters_from_conn(&conn)?);
+
+        // Current mappings, split the way a forced refresh treats them: manual rows stay.
+        let mut current: HashSet<(i64, i64)> = HashSet::new();
+        let mut after: HashSet<(i64, i64)> = HashSet::new();
+        {
+            let mut stmt = conn
+                .prepare(
+                    "SELECT fe.email_id, fe.filter_id, fe.source = 'manual' \
+                     FROM filtered_emails fe \
+                     JOIN emails e ON e.id = fe.email_id \
+                     WHERE e.account = ?1",
+                )
+                .map_err(|e| format!("Failed to prepare mapping query: {}", e))?;
+            let rows = stmt
+                .query_map(params![account], |row| {
+                    Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?, row.get::<_, bool>(2)?))
+                })
+                .map_err(|e| format!("Failed to query mappings: {}", e))?;
+            for row in rows {
+                let (email_id, filter_id, manual) =
+                    row.map_err(|e| format!("Failed to read mapping: {}", e))?;
+                current.insert((email_id, filter_id));
+                if manual {
+                    after.insert((email_id, filter_id));
+                }
+            }
+        }
+
+        {
+            let mut stmt = conn
+                .prepare(
+                    "SELECT id, subject, sender, to_recipients, cc_recipients FROM emails \
+                     WHERE account = ?1 AND filters_manual = 0",
+                )
+                .map_err(|e| format!("Failed to prepare filter preview query: {}", e))?;
+            let rows = stmt
+                .query_map(params![account], |row| {
+                    Ok((
+                        row.get::<_, i64>(0)?,
+                        row.get::<_, String>(1)?,
+                        row.get::<_, String>(2)?,
+                        row.get::<_, Option<String>>(3)?,
+                        row.get::<_, Option<String>>(4)?,
+                    ))
+                })
+                .map_err(|e| format!("Failed to query emails for filter preview: {}", e))?;
+            for row in rows {
+                let (email_id, subject, sender, to, cc) =
+                    row.map_err(|e| format!("Failed to read email row: {}", e))?;
+                let recipients = split_recipients(to.as_deref(), cc.as_deref());
+                for filter_id in match_filters(&subject, &sender, &recipients, &compiled_filters) {
+                    after.insert((email_id, filter_id));
+                }
+            }
+        }
+
+        let mut per_filter: HashMap<i64, i64> = HashMap::new();
+        let mut would_add = 0;
+        for (_, filter_id) in after.difference(&current) {
+            would_add += 1;
+            *per_filter.entry(*filter_id).or_default() += 1;
+        }
+        let mut would_remove = 0;
+        for (_, filter_id) in current.difference(&after) {
+            would_remove += 1;
+            *per_filter.entry(*filter_id).or_default() -= 1;
+        }
+        let mut per_filter: Vec<(i64, i64)> =
+            per_filter.into_iter().filter(|(_, delta)| *delta != 0).collect();
+        per_filter.sort_unstable();
+
+        Ok(FilterRefreshPreview {
+            would_add,
+            would_remove,
+            per_filter,
+        })
+    }
+
+    fn get_setting(&self, key: &str) -> Result<Option<String>, String> {
+        let conn = self.reader()?;
+        conn.query_row("SELECT value FROM settings WHERE key = ?1", params![key], |row| {
+            row.get(0)
+        })
+        .optional()
+        .map_err(|e| format!("Failed to read setting: {}", e))
+    }
+
+    fn set_setting(&self, key: &str, value: &str) -> Result<(), String> {
+        let conn = self
+            .conn
+            .lock()
+            .map_err(|_| "Failed to lock DB".to_string())?;
+        conn.execute(
+            "INSERT INTO settings (key, value) VALUES (?1, ?2) \
+             ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+            params![key, value],
+        )
+        .map_err(|e| format!("Failed to save setting: {}", e))?;
+        Ok(())
+    }
+
+    fn upsert_account(&self, email: &str, display_name: Option<&str>, color: Option<&str>) -> Result<(), String> {
+        let conn = self
+            .conn
+            .lock()
+            .map_err(|_| "Failed to lock DB".to_string())?;
+        conn.execute(
+            "INSERT INTO accounts (email, display_name, color) VALUES (?1, ?2, ?3) \
+             ON CONFLICT(email) DO UPDATE SET \
+                display_name = excluded.display_name, \
+                color = excluded.color",
+            params![email, display_name, color],
+        )
+        .map_err(|e| format!("Failed to save account: {}", e))?;
+        Ok(())
+    }
+
+    fn list_accounts(&self) -> Result<Vec<AccountInfo>, String> {
+        let conn = self.reader()?;
+        let mut stmt = conn
+            .prepare(
+                "SELECT email, display_name, color, last_synced_epoch FROM accounts \
+                 ORDER BY email COLLATE NOCASE",
+            )
+            .map_err(|e| format!("Failed to prepare account query: {}", e))?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok(AccountInfo {
+                    email: row.get(0)?,
+                    display_name: row.get(1)?,
+                    color: row.get(2)?,
+                    last_synced_epoch: row.get(3)?,
+                })
+            })
+            .map_err(|e| format!("Failed to query accounts: {}", e))?;
+        let mut accounts = Vec::new();
+        for row in rows {
+            accounts.push(row.map_err(|e| format!("Failed to read account: {}", e))?);
+        }
+        Ok(accounts)
+    }
+
+    fn touch_last_synced(&self, email: &str, epoch: i64) -> Result<(), String> {
         let conn = self
             .conn
             .lock()
             .map_err(|_| "Failed to lock DB".to_string())?;
+        conn.execute(
+            "INSERT INTO accounts (email, last_synced_epoch) VALUES (?1, ?2) \
+             ON CONFLICT(email) DO UPDATE SET last_synced_epoch = excluded.last_synced_epoch",
+            params![email, epoch],
+        )
+        .map_err(|e| format!("Failed to update account: {}", e))?;
+        Ok(())
+    }
+
+    fn get_last_uid(&self, account: &str, mailbox: &str) -> Result<u32, String> {
+        let conn = self.reader()?;
         let last_uid: Option<u32> = conn
             .query_row(
-                "SELECT last_uid FROM sync_state WHERE account = ?1",
-                params![account],
+                "SELECT last_uid FROM sync_state WHERE account = ?1 AND mailbox = ?2",
+                params![account, mailbox],
                 |row| row.get(0),
             )
             .optional()
@@ -484,97 +1764,169 @@ impl Storage for SqliteStorage {
         Ok(last_uid.unwrap_or(0))
     }
 
-    fn set_last_uid(&self, account: &str, last_uid: u32) -> Result<(), String> {
+    fn set_last_uid(&self, account: &str, mailbox: &str, last_uid: u32) -> Result<(), String> {
         let conn = self
             .conn
             .lock()
             .map_err(|_| "Failed to lock DB".to_string())?;
         conn.execute(
-            "INSERT INTO sync_state (account, last_uid, updated_at)\
-             VALUES (?1, ?2, CURRENT_TIMESTAMP)\
-             ON CONFLICT(account) DO UPDATE SET\
-                last_uid = excluded.last_uid,\
+            "INSERT INTO sync_state (account, mailbox, last_uid, updated_at) \
+             VALUES (?1, ?2, ?3, CURRENT_TIMESTAMP) \
+             ON CONFLICT(account, mailbox) DO UPDATE SET \
+                last_uid = excluded.last_uid, \
                 updated_at = CURRENT_TIMESTAMP",
-            params![account, last_uid],
+            params![account, mailbox, last_uid],
         )
         .map_err(|e| format!("Failed to update sync state: {}", e))?;
         Ok(())
     }
 
-    fn get_max_uid(&self, account: &str) -> Result<Option<u32>, String> {
+    fn get_oldest_synced_uid(&self, account: &str, mailbox: &str) -> Result<Option<u32>, String> {
+        let conn = self.reader()?;
+        let state: Option<(Option<u32>, u32)> = conn
+            .query_row(
+                "SELECT oldest_synced_uid, last_uid FROM sync_state WHERE account = ?1 AND mailbox = ?2",
+                params![account, mailbox],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()
+            .map_err(|e| format!("Failed to read sync state: {}", e))?;
+        let (oldest, last_uid) = state.unwrap_or((None, 0));
+        if oldest.is_some() {
+            return Ok(oldest);
+        }
+        let min_uid: Option<u32> = conn
+            .query_row(
+                "SELECT MIN(uid) FROM emails WHERE account = ?1 AND mailbox = ?2",
+                params![account, mailbox],
+                |row| row.get(0),
+            )
+            .map_err(|e| format!("Failed to read min uid: {}", e))?;
+        Ok(min_uid.or_else(|| (last_uid > 0).then(|| last_uid.saturating_add(1))))
+    }
+
+    fn set_oldest_synced_uid(&self, account: &str, mailbox: &str, uid: u32) -> Result<(), String> {
         let conn = self
             .conn
             .lock()
             .map_err(|_| "Failed to lock DB".to_string())?;
-        let max_uid: Option<u32> = conn
-            .query_row("SELECT MAX(uid) FROM emails WHERE account = ?1", params![account], |row| {
-                row.get(0)
-            })
+        conn.execute(
+            "INSERT INTO sync_state (account, mailbox, oldest_synced_uid, updated_at) \
+             VALUES (?1, ?2, ?3, CURRENT_TIMESTAMP) \
+             ON CONFLICT(account, mailbox) DO UPDATE SET \
+                oldest_synced_uid = MIN(COALESCE(oldest_synced_uid, excluded.oldest_synced_uid), \
+                                        excluded.oldest_synced_uid), \
+                updated_at = CURRENT_TIMESTAMP",
+            params![account, mailbox, uid],
+        )
+        .map_err(|e| format!("Failed to update sync state: {}", e))?;
+        Ok(())
+    }
+
+    fn get_highest_modseq(&self, account: &str) -> Result<Option<u64>, String> {
+        let conn = self.reader()?;
+        let modseq: Option<i64> = conn
+            .query_row(
+                "SELECT highest_modseq FROM sync_state WHERE account = ?1 AND mailbox = 'INBOX'",
+                params![account],
+                |row| row.get(0),
+            )
             .optional()
-            .map_err(|e| format!("Failed to read max uid: {}", e))?;
-        Ok(max_uid)
+            .map_err(|e| format!("Failed to read sync state: {}", e))?
+            .flatten();
+        Ok(modseq.and_then(|value| u64::try_from(value).ok()))
     }
 
-    fn upsert_emails(
-        &self,
-        account: &str,
-        mailbox: &str,
-        emails: &[GmailEmail],
-    ) -> Result<(), String> {
-        let mut conn = self
+    fn set_highest_modseq(&self, account: &str, modseq: u64) -> Result<(), String> {
+        let modseq = i64::try_from(modseq).map_err(|_| format!("MODSEQ {} out of range", modseq))?;
+        let conn = self
             .conn
             .lock()
             .map_err(|_| "Failed to lock DB".to_string())?;
-        let tx = conn
-            .transaction()
-            .map_err(|e| format!("Failed to start transaction: {}", e))?;
+        conn.execute(
+            "INSERT INTO sync_state (account, mailbox, highest_modseq, updated_at) \
+             VALUES (?1, 'INBOX', ?2, CURRENT_TIMESTAMP) \
+             ON CONFLICT(account, mailbox) DO UPDATE SET \
+                highest_modseq = excluded.highest_modseq, \
+                updated_at = CURRENT_TIMESTAMP",
+            params![account, modseq],
+        )
+        .map_err(|e| format!("Failed to update sync state: {}", e))?;
+        Ok(())
+    }
 
-        {
+    fn apply_flag_updates(&self, account: &str, updates: &[crate::gmail::FlagUpdate]) -> Result<usize, String> {
+        if updates.is_empty() {
+            return Ok(0);
+        }
+
+        let mut conn = self
+            .conn
+            .lock()
+            .map_err(|_| "Failed to lock DB".to_string())?;
+        let tx = conn
+            .transaction()
+            .map_err(|e| format!("Failed to start transaction: {}", e))?;
+        let mut changed = 0;
+        {
             let mut stmt = tx
                 .prepare(
-                    "INSERT INTO emails \
-                        (uid, message_id, subject, sender, date, date_epoch, mailbox, account, is_read) \
-                 VALUES \
-                    (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9) \
-                 ON CONFLICT(account, uid) DO UPDATE SET \
-                    message_id = excluded.message_id,\
-                    subject = excluded.subject,\
-                    sender = excluded.sender,\
-                    date = excluded.date,\
-                    date_epoch = excluded.date_epoch,\
-                    mailbox = excluded.mailbox,\
-                    account = excluded.account,\
-                    is_read = excluded.is_read,\
-                    updated_at = CURRENT_TIMESTAMP",
+                    "UPDATE emails SET is_read = MAX(?3, read_locally), \
+                     read_locally = CASE WHEN ?3 = 1 THEN 0 ELSE read_locally END, \
+                     is_flagged = ?4, is_answered = ?5, updated_at = CURRENT_TIMESTAMP \
+                     WHERE account = ?1 AND uid = ?2 \
+                       AND (is_read != MAX(?3, read_locally) OR is_flagged != ?4 OR is_answered != ?5 \
+                            OR (?3 = 1 AND read_locally = 1))",
                 )
-                .map_err(|e| format!("Failed to prepare upsert: {}", e))?;
-
-            for email in emails {
-                stmt.execute(params![
-                    email.uid,
-                    email.message_id,
-                    email.subject,
-                    email.sender,
-                    email.date,
-                    email.date_epoch,
-                    mailbox,
-                    account,
-                    if email.is_read { 1 } else { 0 }
-                ])
-                .map_err(|e| format!("Failed to upsert email: {}", e))?;
+                .map_err(|e| format!("Failed to prepare flag update: {}", e))?;
+            for update in updates {
+                changed += stmt
+                    .execute(params![
+                        account,
+                        update.uid,
+                        if update.is_read { 1 } else { 0 },
+                        if update.is_flagged { 1 } else { 0 },
+                        if update.is_answered { 1 } else { 0 }
+                    ])
+                    .map_err(|e| format!("Failed to update flags: {}", e))?;
             }
         }
-
         tx.commit()
             .map_err(|e| format!("Failed to commit transaction: {}", e))?;
-        Ok(())
+        Ok(changed)
     }
 
-    fn mark_emails_read(&self, account: &str, uids: &[u32]) -> Result<usize, String> {
-        if uids.is_empty() {
-            return Ok(0);
+    fn get_max_uid(&self, account: &str, mailbox: &str) -> Result<Option<u32>, String> {
+        let conn = self.reader()?;
+        let max_uid: Option<u32> = conn
+            .query_row(
+                "SELECT MAX(uid) FROM emails WHERE account = ?1 AND mailbox = ?2",
+                params![account, mailbox],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(|e| format!("Failed to read max uid: {}", e))?;
+        Ok(max_uid)
+    }
+
+    fn all_unread_uids(&self, account: &str) -> Result<Vec<u32>, String> {
+        let conn = self.reader()?;
+        let mut stmt = conn
+            .prepare(
+                "SELECT uid FROM emails WHERE account = ?1 AND is_read = 0 AND is_trashed = 0 ORDER BY uid",
+            )
+            .map_err(|e| format!("Failed to prepare unread uid query: {}", e))?;
+        let rows = stmt
+            .query_map(params![account], |row| row.get(0))
+            .map_err(|e| format!("Failed to query unread uids: {}", e))?;
+        let mut uids = Vec::new();
+        for row in rows {
+            uids.push(row.map_err(|e| format!("Failed to read uid: {}", e))?);
         }
+        Ok(uids)
+    }
 
+    fn replace_gmail_category(&self, account: &str, category: &str, uids: &[u32]) -> Result<usize, String> {
         let mut conn = self
             .conn
             .lock()
@@ -582,39 +1934,160 @@ impl Storage for SqliteStorage {
         let tx = conn
             .transaction()
             .map_err(|e| format!("Failed to start transaction: {}", e))?;
+        tx.execute(
+            "UPDATE emails SET gmail_category = NULL WHERE account = ?1 AND gmail_category = ?2",
+            params![account, category],
+        )
+        .map_err(|e| format!("Failed to clear category: {}", e))?;
+        let mut tagged = 0;
+        {
+            let mut stmt = tx
+                .prepare("UPDATE emails SET gmail_category = ?3 WHERE account = ?1 AND uid = ?2")
+                .map_err(|e| format!("Failed to prepare category update: {}", e))?;
+            for uid in uids {
+                tagged += stmt
+                    .execute(params![account, uid, category])
+                    .map_err(|e| format!("Failed to set category: {}", e))?;
+            }
+        }
+        tx.commit()
+            .map_err(|e| format!("Failed to commit transaction: {}", e))?;
+        Ok(tagged)
+    }
 
-        let mut total = 0;
-        for chunk in uids.chunks(200) {
-            let placeholders = chunk
-                .iter()
-                .enumerate()
-                .map(|(i, _)| format!("?{}", i + 2))
-                .collect::<Vec<_>>()
-                .join(",");
-            let sql = format!(
-                "UPDATE emails SET is_read = 1, updated_at = CURRENT_TIMESTAMP \
-                 WHERE account = ?1 AND uid IN ({})",
-                placeholders
-            );
+    fn cached_uids(&self, account: &str, mailbox: &str) -> Result<Vec<u32>, String> {
+        let conn = self.reader()?;
+        let mut stmt = conn
+            .prepare(
+                "SELECT uid FROM emails WHERE account = ?1 AND mailbox = ?2 AND is_trashed = 0 \
+                 ORDER BY uid",
+            )
+            .map_err(|e| format!("Failed to prepare cached uid query: {}", e))?;
+        let rows = stmt
+            .query_map(params![account, mailbox], |row| row.get(0))
+            .map_err(|e| format!("Failed to query cached uids: {}", e))?;
+        let mut uids = Vec::new();
+        for row in rows {
+            uids.push(row.map_err(|e| format!("Failed to read uid: {}", e))?);
+        }
+        Ok(uids)
+    }
 
-            let mut params_vec: Vec<&dyn ToSql> = Vec::with_capacity(chunk.len() + 1);
-            params_vec.push(&account);
-            for uid in chunk {
-                params_vec.push(uid);
-            }
+    fn uids_without_size(&self, account: &str, limit: u32) -> Result<Vec<u32>, String> {
+        let conn = self.reader()?;
+        let mut stmt = conn
+            .prepare(
+                "SELECT uid FROM emails \
+                 WHERE account = ?1 AND size_bytes IS NULL \
+                 ORDER BY uid DESC \
+                 LIMIT ?2",
+            )
+            .map_err(|e| format!("Failed to prepare size backfill query: {}", e))?;
+        let rows = stmt
+            .query_map(params![account, limit], |row| row.get(0))
+            .map_err(|e| format!("Failed to query emails without size: {}", e))?;
+        let mut uids = Vec::new();
+        for row in rows {
+            uids.push(row.map_err(|e| format!("Failed to read uid: {}", e))?);
+        }
+        Ok(uids)
+    }
 
-            let updated = tx
-                .execute(&sql, params_vec.as_slice())
-                .map_err(|e| format!("Failed to mark read: {}", e))?;
-            total += updated;
+    fn set_email_sizes(&self, account: &str, sizes: &[(u32, u32)]) -> Result<(), String> {
+        if sizes.is_empty() {
+            return Ok(());
         }
 
+        let mut conn = self
+            .conn
+            .lock()
+            .map_err(|_| "Failed to lock DB".to_string())?;
+        let tx = conn
+            .transaction()
+            .map_err(|e| format!("Failed to start transaction: {}", e))?;
+        {
+            let mut stmt = tx
+                .prepare("UPDATE emails SET size_bytes = ?3 WHERE account = ?1 AND uid = ?2")
+                .map_err(|e| format!("Failed to prepare size update: {}", e))?;
+            for (uid, size) in sizes {
+                stmt.execute(params![account, uid, size])
+                    .map_err(|e| format!("Failed to store size: {}", e))?;
+            }
+        }
         tx.commit()
             .map_err(|e| format!("Failed to commit transaction: {}", e))?;
-        Ok(total)
+        Ok(())
     }
 
-    fn mark_emails_unread(&self, account: &str, uids: &[u32]) -> Result<usize, String> {
+    fn total_size_by_sender(&self, account: &str) -> Result<Vec<SenderSize>, String> {
+        let conn = self.reader()?;
+        let sql = format!(
+            "SELECT {} AS address, COUNT(*), SUM(size_bytes) AS total_bytes \
+             FROM emails \
+             WHERE account = ?1 AND is_trashed = 0 AND size_bytes IS NOT NULL \
+             GROUP BY address \
+             ORDER BY total_bytes DESC, address ASC",
+            SENDER_ADDRESS_SQL
+        );
+        let mut stmt = conn
+            .prepare(&sql)
+            .map_err(|e| format!("Failed to prepare size stats: {}", e))?;
+        let rows = stmt
+            .query_map(params![account], |row| {
+                Ok(SenderSize {
+                    sender: row.get(0)?,
+                    count: row.get(1)?,
+                    total_bytes: row.get(2)?,
+                })
+            })
+            .map_err(|e| format!("Failed to query size stats: {}", e))?;
+        let mut results = Vec::new();
+        for row in rows {
+            results.push(row.map_err(|e| format!("Failed to read size stat: {}", e))?);
+        }
+        Ok(results)
+    }
+
+    fn uids_without_body(&self, account: &str, limit: u32) -> Result<Vec<u32>, String> {
+        let conn = self.reader()?;
+        let mut stmt = conn
+            .prepare(
+                "SELECT uid FROM emails \
+                 WHERE account = ?1 AND body_html IS NULL AND body_text IS NULL \
+                 ORDER BY uid DESC \
+                 LIMIT ?2",
+            )
+            .map_err(|e| format!("Failed to prepare body backfill query: {}", e))?;
+        let rows = stmt
+            .query_map(params![account, limit], |row| row.get(0))
+            .map_err(|e| format!("Failed to query emails without body: {}", e))?;
+        let mut uids = Vec::new();
+        for row in rows {
+            uids.push(row.map_err(|e| format!("Failed to read uid: {}", e))?);
+        }
+        Ok(uids)
+    }
+
+    fn stale_body_uids(&self, account: &str, current_version: i64) -> Result<Vec<u32>, String> {
+        let conn = self.reader()?;
+        let mut stmt = conn
+            .prepare(
+                "SELECT uid FROM emails \
+                 WHERE account = ?1 AND body_html IS NOT NULL AND body_version < ?2 \
+                 ORDER BY uid",
+            )
+            .map_err(|e| format!("Failed to prepare stale body query: {}", e))?;
+        let rows = stmt
+            .query_map(params![account, current_version], |row| row.get(0))
+            .map_err(|e| format!("Failed to query stale bodies: {}", e))?;
+        let mut uids = Vec::new();
+        for row in rows {
+            uids.push(row.map_err(|e| format!("Failed to read uid: {}", e))?);
+        }
+        Ok(uids)
+    }
+
+    fn resanitize_bodies(&self, account: &str, uids: &[u32]) -> Result<usize, String> {
         if uids.is_empty() {
             return Ok(0);
         }
@@ -626,71 +2099,91 @@ impl Storage for SqliteStorage {
         let tx = conn
             .transaction()
             .map_err(|e| format!("Failed to start transaction: {}", e))?;
-
-        let mut total = 0;
-        for chunk in uids.chunks(200) {
-            let placeholders = chunk
-                .iter()
-                .enumerate()
-                .map(|(i, _)| format!("?{}", i + 2))
-                .collect::<Vec<_>>()
-                .join(",");
-            let sql = format!(
-                "UPDATE emails SET is_read = 0, updated_at = CURRENT_TIMESTAMP \
-                 WHERE account = ?1 AND uid IN ({})",
-                placeholders
-            );
-
-            let mut params_vec: Vec<&dyn ToSql> = Vec::with_capacity(chunk.len() + 1);
-            params_vec.push(&account);
-            for uid in chunk {
-                params_vec.push(uid);
+        let mut updated = 0;
+        {
+            let mut select_stmt = tx
+                .prepare("SELECT body_html FROM emails WHERE account = ?1 AND uid = ?2")
+                .map_err(|e| format!("Failed to prepare body lookup: {}", e))?;
+            let mut update_stmt = tx
+                .prepare(
+                    "UPDATE emails SET body_html_safe = ?3, body_version = ?4 \
+                     WHERE account = ?1 AND uid = ?2",
+                )
+                .map_err(|e| format!("Failed to prepare body update: {}", e))?;
+            for uid in uids {
+                let raw: Option<String> = select_stmt
+                    .query_row(params![account, uid], |row| row.get(0))
+                    .optional()
+                    .map_err(|e| format!("Failed to read body: {}", e))?
+                    .flatten();
+                let Some(raw) = raw else {
+                    continue;
+                };
+                let safe = crate::sanitize::sanitize_html(&raw, false);
+                updated += update_stmt
+                    .execute(params![account, uid, safe, crate::sanitize::SANITIZER_VERSION])
+                    .map_err(|e| format!("Failed to update body: {}", e))?;
             }
-
-            let updated = tx
-                .execute(&sql, params_vec.as_slice())
-                .map_err(|e| format!("Failed to mark unread: {}", e))?;
-            total += updated;
         }
-
         tx.commit()
             .map_err(|e| format!("Failed to commit transaction: {}", e))?;
-        Ok(total)
+        Ok(updated)
     }
 
-    fn get_email_body(&self, account: &str, uid: u32) -> Result<Option<crate::gmail::EmailBody>, String> {
-        let conn = self
-            .conn
-            .lock()
-            .map_err(|_| "Failed to lock DB".to_string())?;
-
-        let row: Option<(Option<String>, Option<String>)> = conn
-            .query_row(
-                "SELECT body_html, body_text FROM emails WHERE account = ?1 AND uid = ?2",
-                params![account, uid],
-                |row| Ok((row.get(0)?, row.get(1)?)),
+    fn list_by_sender_domain(
+        &self,
+        account: &str,
+        domain: &str,
+        unread_only: bool,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<StoredEmail>, String> {
+        let domain = domain
+            .trim()
+            .trim_start_matches("*.")
+            .trim_start_matches(['@', '.'])
+            .trim_end_matches('.')
+            .to_lowercase();
+        if domain.is_empty() {
+            return Err("Domain is empty".to_string());
+        }
+        let subdomains = format!("%.{}", escape_like(&domain));
+
+        let conn = self.reader()?;
+        let sql = format!(
+            "SELECT uid, message_id, subject, sender, date, IFNULL(date_epoch, 0), mailbox, account, is_read, \
+             unsubscribe_url, unsubscribe_mailto, unsubscribe_oneclick, to_recipients, cc_recipients, \
+             EXISTS(SELECT 1 FROM attachments a WHERE a.email_id = emails.id), is_flagged, size_bytes, snippet, is_answered \
+             FROM emails \
+             WHERE account = ?1 AND is_trashed = 0 AND (?2 = 0 OR is_read = 0) \
+               AND (sender_domain = ?3 OR sender_domain LIKE ?4 ESCAPE '\\'){} \
+             ORDER BY date_epoch DESC, uid DESC \
+             LIMIT ?5 OFFSET ?6",
+            NOT_SNOOZED
+        );
+        let mut stmt = conn
+            .prepare(&sql)
+            .map_err(|e| format!("Failed to prepare domain query: {}", e))?;
+        let rows = stmt
+            .query_map(
+                params![account, if unread_only { 1 } else { 0 }, domain, subdomains, limit, offset],
+                stored_email_from_row,
             )
-            .optional()
-            .map_err(|e| format!("Failed to query email body: {}", e))?;
+            .map_err(|e| format!("Failed to query domain emails: {}", e))?;
 
-        Ok(row.and_then(|(html, text)| {
-            if html.is_some() || text.is_some() {
-                Some(crate::gmail::EmailBody { html, text })
-            } else {
-                None
-            }
-        }))
+        let mut results = Vec::new();
+        for row in rows {
+            results.push(row.map_err(|e| format!("Failed to read email: {}", e))?);
+        }
+        Ok(results)
     }
 
-    fn set_email_bodies(
+    fn upsert_emails(
         &self,
         account: &str,
-        bodies: &[crate::gmail::GmailEmailBody],
+        mailbox: &str,
+        emails: &[GmailEmail],
     ) -> Result<(), String> {
-        if bodies.is_empty() {
-            return Ok(());
-        }
-
         let mut conn = self
             .conn
             .lock()
@@ -702,49 +2195,627 @@ impl Storage for SqliteStorage {
         {
             let mut stmt = tx
                 .prepare(
-                    "UPDATE emails SET body_html = ?1, body_text = ?2, updated_at = CURRENT_TIMESTAMP \
-                     WHERE account = ?3 AND uid = ?4",
+                    "INSERT INTO emails \
+                        (uid, message_id, subject, sender, date, date_epoch, mailbox, account, is_read, \
+                         to_recipients, cc_recipients, is_flagged, size_bytes, sender_domain, is_answered) \
+                 VALUES \
+                    (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15) \
+                 ON CONFLICT(account, uid) DO UPDATE SET \
+                    message_id = excluded.message_id,\
+                    subject = excluded.subject,\
+                    sender = excluded.sender,\
+                    sender_domain = excluded.sender_domain,\
+                    date = excluded.date,\
+                    date_epoch = excluded.date_epoch,\
+                    mailbox = excluded.mailbox,\
+                    account = excluded.account,\
+                    is_read = MAX(excluded.is_read, emails.read_locally),\
+                    read_locally = CASE WHEN excluded.is_read = 1 THEN 0 ELSE emails.read_locally END,\
+                    to_recipients = COALESCE(excluded.to_recipients, emails.to_recipients),\
+                    cc_recipients = COALESCE(excluded.cc_recipients, emails.cc_recipients),\
+                    is_flagged = excluded.is_flagged,\
+                    is_answered = excluded.is_answered,\
+                    size_bytes = COALESCE(excluded.size_bytes, emails.size_bytes),\
+                    updated_at = CURRENT_TIMESTAMP",
                 )
-                .map_err(|e| format!("Failed to prepare body update: {}", e))?;
+                .map_err(|e| format!("Failed to prepare upsert: {}", e))?;
 
-            for body in bodies {
+            for email in emails {
                 stmt.execute(params![
-                    body.body.html.as_deref(),
-                    body.body.text.as_deref(),
+                    email.uid,
+                    email.message_id,
+                    email.subject,
+                    email.sender,
+                    email.date,
+                    email.date_epoch,
+                    mailbox,
                     account,
-                    body.uid
+                    if email.is_read { 1 } else { 0 },
+                    email.to.as_deref(),
+                    email.cc.as_deref(),
+                    if email.is_flagged { 1 } else { 0 },
+                    email.size_bytes,
+                    sender_domain(&email.sender).unwrap_or_default(),
+                    if email.is_answered { 1 } else { 0 }
                 ])
-                .map_err(|e| format!("Failed to update body: {}", e))?;
+                .map_err(|e| format!("Failed to upsert email: {}", e))?;
             }
         }
 
         tx.commit()
-            .map_err(|e| format!("Failed to commit body updates: {}", e))?;
+            .map_err(|e| format!("Failed to commit transaction: {}", e))?;
         Ok(())
     }
 
-    fn get_filters(&self) -> Result<Vec<FilterPattern>, String> {
-        let conn = self
+    fn remove_emails(&self, account: &str, uids: &[u32]) -> Result<usize, String> {
+        if uids.is_empty() {
+            return Ok(0);
+        }
+
+        let mut conn = self
             .conn
             .lock()
             .map_err(|_| "Failed to lock DB".to_string())?;
-        let mut stmt = conn
-            .prepare(
-                "SELECT id, name, pattern, field, is_regex, enabled \
-                 FROM filters ORDER BY rowid ASC",
-            )
-            .map_err(|e| format!("Failed to prepare query: {}", e))?;
+        let tx = conn
+            .transaction()
+            .map_err(|e| format!("Failed to start transaction: {}", e))?;
 
-        let rows = stmt
-            .query_map([], |row| {
-                let field: String = row.get(3)?;
-                Ok(FilterPattern {
-                    id: row.get::<_, i64>(0)?,
+        let mut total = 0;
+        for chunk in uids.chunks(500) {
+            let placeholders = vec!["?"; chunk.len()].join(",");
+            let sql = format!(
+                "DELETE FROM emails WHERE account = ?1 AND uid IN ({})",
+                placeholders
+            );
+            let mut params_vec: Vec<&dyn ToSql> = Vec::with_capacity(chunk.len() + 1);
+            params_vec.push(&account);
+            for uid in chunk {
+                params_vec.push(uid);
+            }
+            total += tx
+                .execute(&sql, params_vec.as_slice())
+                .map_err(|e| format!("Failed to remove emails: {}", e))?;
+        }
+
+        tx.commit()
+            .map_err(|e| format!("Failed to commit transaction: {}", e))?;
+        Ok(total)
+    }
+
+    fn trash_emails(&self, account: &str, uids: &[u32]) -> Result<usize, String> {
+        self.execute_for_uids(
+            "UPDATE emails SET is_trashed = 1, updated_at = CURRENT_TIMESTAMP \
+             WHERE account = ?1 AND uid IN ({})",
+            account,
+            uids,
+        )
+    }
+
+    fn untrash_emails(&self, account: &str, uids: &[u32]) -> Result<usize, String> {
+        self.execute_for_uids(
+            "UPDATE emails SET is_trashed = 0, updated_at = CURRENT_TIMESTAMP \
+             WHERE account = ?1 AND uid IN ({})",
+            account,
+            uids,
+        )
+    }
+
+    fn trashed_uids(&self, account: &str) -> Result<Vec<u32>, String> {
+        let conn = self.reader()?;
+        let mut stmt = conn
+            .prepare("SELECT uid FROM emails WHERE account = ?1 AND is_trashed = 1 ORDER BY uid")
+            .map_err(|e| format!("Failed to prepare trash query: {}", e))?;
+        let rows = stmt
+            .query_map(params![account], |row| row.get(0))
+            .map_err(|e| format!("Failed to query trashed emails: {}", e))?;
+        let mut uids = Vec::new();
+        for row in rows {
+            uids.push(row.map_err(|e| format!("Failed to read uid: {}", e))?);
+        }
+        Ok(uids)
+    }
+
+    fn snooze_emails(&self, account: &str, uids: &[u32], until_epoch: i64) -> Result<usize, String> {
+        // `{}` is expanded to the UID placeholders, so the epoch is inlined rather than bound.
+        self.execute_for_uids(
+            &format!(
+                "UPDATE emails SET snoozed_until = {}, updated_at = CURRENT_TIMESTAMP \
+                 WHERE account = ?1 AND uid IN ({{}})",
+                until_epoch
+            ),
+            account,
+            uids,
+        )
+    }
+
+    fn unsnooze_emails(&self, account: &str, uids: &[u32]) -> Result<usize, String> {
+        self.execute_for_uids(
+            "UPDATE emails SET snoozed_until = NULL, updated_at = CURRENT_TIMESTAMP \
+             WHERE account = ?1 AND uid IN ({})",
+            account,
+            uids,
+        )
+    }
+
+    fn list_snoozed(&self, account: &str, limit: u32, offset: u32) -> Result<Vec<SnoozedEmail>, String> {
+        let conn = self.reader()?;
+        let mut stmt = conn
+            .prepare(
+                "SELECT uid, message_id, subject, sender, date, IFNULL(date_epoch, 0), mailbox, account, is_read, \
+                 unsubscribe_url, unsubscribe_mailto, unsubscribe_oneclick, to_recipients, cc_recipients, \
+                 EXISTS(SELECT 1 FROM attachments a WHERE a.email_id = emails.id), is_flagged, size_bytes, snippet, is_answered, \
+                 snoozed_until \
+                 FROM emails \
+                 WHERE account = ?1 AND is_trashed = 0 \
+                   AND snoozed_until > CAST(strftime('%s', 'now') AS INTEGER) \
+                 ORDER BY snoozed_until ASC, uid ASC \
+                 LIMIT ?2 OFFSET ?3",
+            )
+            .map_err(|e| format!("Failed to prepare snoozed query: {}", e))?;
+        let rows = stmt
+            .query_map(params![account, limit, offset], |row| {
+                Ok(SnoozedEmail {
+                    email: stored_email_from_row(row)?,
+                    snoozed_until: row.get(19)?,
+                })
+            })
+            .map_err(|e| format!("Failed to query snoozed emails: {}", e))?;
+
+        let mut results = Vec::new();
+        for row in rows {
+            results.push(row.map_err(|e| format!("Failed to read email: {}", e))?);
+        }
+        Ok(results)
+    }
+
+    fn clear_elapsed_snoozes(&self) -> Result<usize, String> {
+        let conn = self
+            .conn
+            .lock()
+            .map_err(|_| "Failed to lock DB".to_string())?;
+        conn.execute(
+            "UPDATE emails SET snoozed_until = NULL, updated_at = CURRENT_TIMESTAMP \
+             WHERE snoozed_until <= CAST(strftime('%s', 'now') AS INTEGER)",
+            [],
+        )
+        .map_err(|e| format!("Failed to clear elapsed snoozes: {}", e))
+    }
+
+    fn duplicate_message_ids(&self, account: &str) -> Result<Vec<(String, Vec<u32>)>, String> {
+        Ok(self
+            .duplicate_groups(account)?
+            .into_iter()
+            .map(|(message_id, copies)| (message_id, copies.into_iter().map(|(uid, _)| uid).collect()))
+            .collect())
+    }
+
+    fn dedupe_emails(&self, account: &str, keep: KeepStrategy) -> Result<usize, String> {
+        let mut extra = Vec::new();
+        for (_, copies) in self.duplicate_groups(account)? {
+            // Copies are ordered by UID, so the first one is the lowest.
+            let keeper = match keep {
+                KeepStrategy::Inbox => copies
+                    .iter()
+                    .find(|(_, mailbox)| mailbox.eq_ignore_ascii_case("INBOX"))
+                    .unwrap_or(&copies[0])
+                    .0,
+                KeepStrategy::LowestUid => copies[0].0,
+            };
+            extra.extend(copies.iter().map(|(uid, _)| *uid).filter(|uid| *uid != keeper));
+        }
+        self.trash_emails(account, &extra)
+    }
+
+    fn purge_trashed(&self, account: &str, uids: &[u32]) -> Result<usize, String> {
+        self.execute_for_uids(
+            "DELETE FROM emails WHERE account = ?1 AND is_trashed = 1 AND uid IN ({})",
+            account,
+            uids,
+        )
+    }
+
+    fn purge_read_older_than(&self, account: &str, epoch: i64) -> Result<usize, String> {
+        let conn = self
+            .conn
+            .lock()
+            .map_err(|_| "Failed to lock DB".to_string())?;
+        let sql = format!(
+            "DELETE FROM emails \
+             WHERE account = ?1 AND is_read = 1 AND is_flagged = 0 AND is_trashed = 0 \
+               AND date_epoch > 0 AND date_epoch < ?2{}",
+            NOT_SNOOZED
+        );
+        conn.execute(&sql, params![account, epoch])
+            .map_err(|e| format!("Failed to purge old emails: {}", e))
+    }
+
+    fn delete_account_emails(&self, account: &str) -> Result<usize, String> {
+        let mut conn = self
+            .conn
+            .lock()
+            .map_err(|_| "Failed to lock DB".to_string())?;
+        let tx = conn
+            .transaction()
+            .map_err(|e| format!("Failed to start transaction: {}", e))?;
+
+        let deleted = tx
+            .execute("DELETE FROM emails WHERE account = ?1", params![account])
+            .map_err(|e| format!("Failed to delete emails: {}", e))?;
+        tx.execute("DELETE FROM sync_state WHERE account = ?1", params![account])
+            .map_err(|e| format!("Failed to clear sync state: {}", e))?;
+        tx.execute(
+            "DELETE FROM filter_sync_state WHERE account = ?1",
+            params![account],
+        )
+        .map_err(|e| format!("Failed to clear filter sync state: {}", e))?;
+        tx.execute(
+            "DELETE FROM filter_sync_state_v2 WHERE account = ?1",
+            params![account],
+        )
+        .map_err(|e| format!("Failed to clear filter sync state: {}", e))?;
+
+        tx.commit()
+            .map_err(|e| format!("Failed to commit transaction: {}", e))?;
+        Ok(deleted)
+    }
+
+    fn reset_sync_state(&self, account: &str) -> Result<(), String> {
+        let mut conn = self
+            .conn
+            .lock()
+            .map_err(|_| "Failed to lock DB".to_string())?;
+        let tx = conn
+            .transaction()
+            .map_err(|e| format!("Failed to start transaction: {}", e))?;
+
+        tx.execute(
+            "UPDATE sync_state SET last_uid = 0, highest_modseq = NULL, oldest_synced_uid = NULL, \
+             updated_at = CURRENT_TIMESTAMP WHERE account = ?1",
+            params![account],
+        )
+        .map_err(|e| format!("Failed to reset sync state: {}", e))?;
+        tx.execute(
+            "DELETE FROM filter_sync_state WHERE account = ?1",
+            params![account],
+        )
+        .map_err(|e| format!("Failed to clear filter sync state: {}", e))?;
+        tx.execute(
+            "DELETE FROM filter_sync_state_v2 WHERE account = ?1",
+            params![account],
+        )
+        .map_err(|e| format!("Failed to clear filter sync state: {}", e))?;
+
+        tx.commit()
+            .map_err(|e| format!("Failed to commit transaction: {}", e))?;
+        Ok(())
+    }
+
+    fn compact(&self) -> Result<u64, String> {
+        // Held for the whole operation: VACUUM needs exclusive access and can't
+        // run inside a transaction.
+        let conn = self
+            .conn
+            .lock()
+            .map_err(|_| "Failed to lock DB".to_string())?;
+        let before = database_size_bytes(&conn)?;
+        conn.execute_batch("VACUUM;")
+            .map_err(|e| format!("Failed to vacuum DB: {}", e))?;
+        // In WAL mode VACUUM writes the rebuilt pages to the WAL; fold them back in.
+        conn.execute_batch("PRAGMA wal_checkpoint(TRUNCATE);")
+            .map_err(|e| format!("Failed to checkpoint DB: {}", e))?;
+        let after = database_size_bytes(&conn)?;
+        Ok(before.saturating_sub(after))
+    }
+
+    fn checkpoint(&self) -> Result<(), String> {
+        let conn = self
+            .conn
+            .lock()
+            .map_err(|_| "Failed to lock DB".to_string())?;
+        conn.execute_batch("PRAGMA wal_checkpoint(TRUNCATE);")
+            .map_err(|e| format!("Failed to checkpoint DB: {}", e))
+    }
+
+    fn mark_emails_read(&self, account: &str, uids: &[u32]) -> Result<usize, String> {
+        self.set_read_state(account, uids, "is_read = 1, read_locally = 0")
+            .map_err(|e| format!("Failed to mark read: {}", e))
+    }
+
+    fn mark_emails_read_locally(&self, account: &str, uids: &[u32]) -> Result<usize, String> {
+        self.set_read_state(account, uids, "is_read = 1, read_locally = 1")
+            .map_err(|e| format!("Failed to mark read: {}", e))
+    }
+
+    fn mark_emails_unread(&self, account: &str, uids: &[u32]) -> Result<usize, String> {
+        self.set_read_state(account, uids, "is_read = 0, read_locally = 0")
+            .map_err(|e| format!("Failed to mark unread: {}", e))
+    }
+
+    fn set_flagged(&self, account: &str, uids: &[u32], flagged: bool) -> Result<usize, String> {
+        let sql = if flagged {
+            "UPDATE emails SET is_flagged = 1, updated_at = CURRENT_TIMESTAMP \
+             WHERE account = ?1 AND uid IN ({})"
+        } else {
+            "UPDATE emails SET is_flagged = 0, updated_at = CURRENT_TIMESTAMP \
+             WHERE account = ?1 AND uid IN ({})"
+        };
+        self.execute_for_uids(sql, account, uids)
+    }
+
+    fn list_flagged(&self, account: &str, limit: u32, offset: u32) -> Result<Vec<StoredEmail>, String> {
+        let conn = self.reader()?;
+        let mut stmt = conn
+            .prepare(
+                "SELECT uid, message_id, subject, sender, date, IFNULL(date_epoch, 0), mailbox, account, is_read, \
+                 unsubscribe_url, unsubscribe_mailto, unsubscribe_oneclick, to_recipients, cc_recipients, \
+                 EXISTS(SELECT 1 FROM attachments a WHERE a.email_id = emails.id), is_flagged, size_bytes, snippet, is_answered \
+                 FROM emails \
+                 WHERE account = ?1 AND is_flagged = 1 AND is_trashed = 0 \
+                 ORDER BY date_epoch DESC, uid DESC \
+                 LIMIT ?2 OFFSET ?3",
+            )
+            .map_err(|e| format!("Failed to prepare flagged query: {}", e))?;
+        let rows = stmt
+            .query_map(params![account, limit, offset], stored_email_from_row)
+            .map_err(|e| format!("Failed to query flagged emails: {}", e))?;
+
+        let mut results = Vec::new();
+        for row in rows {
+            results.push(row.map_err(|e| format!("Failed to read email: {}", e))?);
+        }
+        Ok(results)
+    }
+
+    fn get_email_body(&self, account: &str, uid: u32) -> Result<Option<crate::gmail::EmailBody>, String> {
+        let conn = self.reader()?;
+
+        let row = conn
+            .query_row(
+                "SELECT body_html, body_html_safe, body_text, unsubscribe_url, unsubscribe_mailto, \
+                 unsubscribe_oneclick, snippet, headers_json \
+                 FROM emails WHERE account = ?1 AND uid = ?2",
+                params![account, uid],
+                |row| {
+                    Ok(crate::gmail::EmailBody {
+                        html_raw: row.get(0)?,
+                        html: row.get(1)?,
+                        text: row.get(2)?,
+                        unsubscribe_url: row.get(3)?,
+                        unsubscribe_mailto: row.get(4)?,
+                        unsubscribe_oneclick: row.get::<_, i64>(5)? != 0,
+                        attachments: Vec::new(),
+                        in_reply_to: None,
+                        references: Vec::new(),
+                        snippet: row.get(6)?,
+                        headers: row
+                            .get::<_, Option<String>>(7)?
+                            .and_then(|json| serde_json::from_str(&json).ok())
+                            .unwrap_or_default(),
+                    })
+                },
+            )
+            .optional()
+            .map_err(|e| format!("Failed to query email body: {}", e))?;
+
+        match row.filter(|body| body.html_raw.is_some() || body.text.is_some()) {
+            Some(mut body) => {
+                // Bodies cached before sanitizing was introduced only have the raw HTML.
+                if body.html.is_none() {
+                    body.html = body
+                        .html_raw
+                        .as_deref()
+                        .map(|raw| crate::sanitize::sanitize_html(raw, false));
+                }
+                body.attachments = query_attachments(&conn, account, uid)?;
+                Ok(Some(body))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn attachments_for(
+        &self,
+        account: &str,
+        uid: u32,
+    ) -> Result<Vec<crate::gmail::AttachmentMeta>, String> {
+        let conn = self.reader()?;
+        query_attachments(&conn, account, uid)
+    }
+
+    fn assign_threads(&self, account: &str) -> Result<(), String> {
+        let mut conn = self.conn.lock().map_err(|_| "Failed to lock DB".to_string())?;
+        let pending: bool = conn
+            .query_row(
+                "SELECT EXISTS(SELECT 1 FROM emails WHERE account = ?1 AND thread_id IS NULL)",
+                params![account],
+                |row| row.get(0),
+            )
+            .map_err(|e| format!("Failed to check for unthreaded emails: {}", e))?;
+        if !pending {
+            return Ok(());
+        }
+        let tx = conn
+            .transaction()
+            .map_err(|e| format!("Failed to start thread transaction: {}", e))?;
+        assign_thread_ids(&tx, account)?;
+        tx.commit()
+            .map_err(|e| format!("Failed to commit threads: {}", e))
+    }
+
+    fn list_threads(&self, account: &str, limit: u32, offset: u32) -> Result<Vec<ThreadSummary>, String> {
+        let conn = self.reader()?;
+        // SQLite fills bare columns from the row that produced MAX(), so `subject`
+        // is the latest email's subject.
+        let mut stmt = conn
+            .prepare(
+                "SELECT IFNULL(thread_id, id) AS thread, subject, MAX(IFNULL(date_epoch, 0)), \
+                        COUNT(*), SUM(CASE WHEN is_read = 0 THEN 1 ELSE 0 END) \
+                 FROM emails \
+                 WHERE account = ?1 AND is_trashed = 0 \
+                 GROUP BY thread \
+                 ORDER BY 3 DESC \
+                 LIMIT ?2 OFFSET ?3",
+            )
+            .map_err(|e| format!("Failed to prepare thread query: {}", e))?;
+        let rows = stmt
+            .query_map(params![account, limit, offset], |row| {
+                Ok(ThreadSummary {
+                    thread_id: row.get(0)?,
+                    subject: row.get(1)?,
+                    latest_epoch: row.get(2)?,
+                    count: row.get(3)?,
+                    unread: row.get(4)?,
+                })
+            })
+            .map_err(|e| format!("Failed to query threads: {}", e))?;
+
+        let mut threads = Vec::new();
+        for row in rows {
+            threads.push(row.map_err(|e| format!("Failed to read thread: {}", e))?);
+        }
+        Ok(threads)
+    }
+
+    fn get_unsubscribe_info(&self, account: &str, uid: u32) -> Result<Option<UnsubscribeInfo>, String> {
+        let conn = self.reader()?;
+        conn.query_row(
+            "SELECT unsubscribe_url, unsubscribe_mailto, unsubscribe_oneclick \
+             FROM emails WHERE account = ?1 AND uid = ?2",
+            params![account, uid],
+            |row| {
+                Ok(UnsubscribeInfo {
+                    unsubscribe_url: row.get(0)?,
+                    unsubscribe_mailto: row.get(1)?,
+                    unsubscribe_oneclick: row.get::<_, i64>(2)? != 0,
+                })
+            },
+        )
+        .optional()
+        .map_err(|e| format!("Failed to query unsubscribe info: {}", e))
+    }
+
+    fn set_email_bodies(
+        &self,
+        account: &str,
+        bodies: &[crate::gmail::GmailEmailBody],
+    ) -> Result<(), String> {
+        if bodies.is_empty() {
+            return Ok(());
+        }
+
+        let mut conn = self
+            .conn
+            .lock()
+            .map_err(|_| "Failed to lock DB".to_string())?;
+        let tx = conn
+            .transaction()
+            .map_err(|e| format!("Failed to start transaction: {}", e))?;
+
+        {
+            let mut stmt = tx
+                .prepare(
+                    "UPDATE emails SET body_html = ?1, body_html_safe = ?2, body_text = ?3, \
+                     unsubscribe_url = ?4, unsubscribe_mailto = ?5, unsubscribe_oneclick = ?6, \
+                     in_reply_to = ?7, references_ids = ?8, snippet = ?11, \
+                     thread_id = CASE WHEN in_reply_to IS ?7 AND references_ids IS ?8 \
+                         THEN thread_id ELSE NULL END, \
+                     headers_json = COALESCE(?12, headers_json), body_version = ?13, \
+                     updated_at = CURRENT_TIMESTAMP \
+                     WHERE account = ?9 AND uid = ?10",
+                )
+                .map_err(|e| format!("Failed to prepare body update: {}", e))?;
+            let mut id_stmt = tx
+                .prepare("SELECT id FROM emails WHERE account = ?1 AND uid = ?2")
+                .map_err(|e| format!("Failed to prepare email lookup: {}", e))?;
+            let mut clear_stmt = tx
+                .prepare("DELETE FROM attachments WHERE email_id = ?1")
+                .map_err(|e| format!("Failed to prepare attachment cleanup: {}", e))?;
+            let mut attachment_stmt = tx
+                .prepare(
+                    "INSERT INTO attachments (email_id, filename, content_type, size) \
+                     VALUES (?1, ?2, ?3, ?4)",
+                )
+                .map_err(|e| format!("Failed to prepare attachment insert: {}", e))?;
+
+            for body in bodies {
+                stmt.execute(params![
+                    body.body.html_raw.as_deref(),
+                    body.body.html.as_deref(),
+                    body.body.text.as_deref(),
+                    body.body.unsubscribe_url.as_deref(),
+                    body.body.unsubscribe_mailto.as_deref(),
+                    body.body.unsubscribe_oneclick,
+                    body.body.in_reply_to.as_deref(),
+                    if body.body.references.is_empty() {
+                        None
+                    } else {
+                        Some(body.body.references.join(" "))
+                    },
+                    account,
+                    body.uid,
+                    body.body.snippet.as_deref(),
+                    if body.body.headers.is_empty() {
+                        None
+                    } else {
+                        serde_json::to_string(&body.body.headers).ok()
+                    },
+                    crate::sanitize::SANITIZER_VERSION
+                ])
+                .map_err(|e| format!("Failed to update body: {}", e))?;
+
+                let email_id: Option<i64> = id_stmt
+                    .query_row(params![account, body.uid], |row| row.get(0))
+                    .optional()
+                    .map_err(|e| format!("Failed to look up email: {}", e))?;
+                let Some(email_id) = email_id else {
+                    continue;
+                };
+                clear_stmt
+                    .execute(params![email_id])
+                    .map_err(|e| format!("Failed to clear attachments: {}", e))?;
+                for attachment in &body.body.attachments {
+                    attachment_stmt
+                        .execute(params![
+                            email_id,
+                            attachment.filename.as_deref(),
+                            attachment.content_type,
+                            attachment.size as i64
+                        ])
+                        .map_err(|e| format!("Failed to store attachment: {}", e))?;
+                }
+            }
+        }
+
+        tx.commit()
+            .map_err(|e| format!("Failed to commit body updates: {}", e))?;
+        Ok(())
+    }
+
+    fn get_filters(&self) -> Result<Vec<FilterPattern>, String> {
+        let conn = self.reader()?;
+        let mut stmt = conn
+            .prepare(
+                "SELECT id, name, pattern, field, is_regex, case_sensitive, enabled, priority, \
+                 action, action_dry_run, match_mode \
+                 FROM filters ORDER BY priority ASC, rowid ASC",
+            )
+            .map_err(|e| format!("Failed to prepare query: {}", e))?;
+
+        let rows = stmt
+            .query_map([], |row| {
+                let field: String = row.get(3)?;
+                Ok(FilterPattern {
+                    id: row.get::<_, i64>(0)?,
                     name: row.get(1)?,
                     pattern: row.get(2)?,
                     field: parse_filter_field(&field)?,
                     is_regex: row.get::<_, i64>(4)? != 0,
-                    enabled: row.get::<_, i64>(5)? != 0,
+                    case_sensitive: row.get::<_, i64>(5)? != 0,
+                    enabled: row.get::<_, i64>(6)? != 0,
+                    priority: row.get(7)?,
+                    action: parse_filter_action(&row.get::<_, String>(8)?),
+                    action_dry_run: row.get::<_, i64>(9)? != 0,
+                    match_mode: parse_match_mode(&row.get::<_, String>(10)?),
                 })
             })
             .map_err(|e| format!("Failed to query filters: {}", e))?;
@@ -776,10 +2847,18 @@ impl Storage for SqliteStorage {
             if let Some(previous) = existing_map.remove(&filter.id) {
                 let needs_refresh = previous.pattern != filter.pattern
                     || previous.is_regex != filter.is_regex
+                    || previous.case_sensitive != filter.case_sensitive
+                    || previous.match_mode != filter.match_mode
                     || filter_field_to_string(&previous.field) != filter_field_to_string(&filter.field);
                 if needs_refresh {
                     to_update.push(filter.clone());
-                } else if previous.name != filter.name || previous.enabled != filter.enabled {
+                } else if previous.name != filter.name
+                    || previous.enabled != filter.enabled
+                    || previous.priority != filter.priority
+                    || previous.action != filter.action
+                    || previous.action_dry_run != filter.action_dry_run
+                {
+                    // Reordering or renaming doesn't change which emails match.
                     to_touch.push(filter.clone());
                 }
             } else {
@@ -815,7 +2894,10 @@ impl Storage for SqliteStorage {
                 .take(update_ids.len())
                 .collect::<Vec<_>>()
                 .join(",");
-            let sql = format!("DELETE FROM filtered_emails WHERE filter_id IN ({})", placeholders);
+            let sql = format!(
+                "DELETE FROM filtered_emails WHERE filter_id IN ({}) AND source = 'auto'",
+                placeholders
+            );
             let mut params: Vec<&dyn ToSql> = Vec::with_capacity(update_ids.len());
             for id in &update_ids {
                 params.push(id);
@@ -829,16 +2911,18 @@ impl Storage for SqliteStorage {
             let mut insert_autoinc_stmt = tx
                 .prepare(
                     "INSERT INTO filters \
-                        (name, pattern, field, is_regex, enabled) \
-                     VALUES (?1, ?2, ?3, ?4, ?5)",
+                        (name, pattern, field, is_regex, case_sensitive, enabled, priority, action, action_dry_run, \
+                         match_mode) \
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
                 )
                 .map_err(|e| format!("Failed to prepare filter insert: {}", e))?;
 
             let mut update_stmt = tx
                 .prepare(
                     "UPDATE filters \
-                     SET name = ?1, pattern = ?2, field = ?3, is_regex = ?4, enabled = ?5 \
-                     WHERE id = ?6",
+                     SET name = ?1, pattern = ?2, field = ?3, is_regex = ?4, case_sensitive = ?5, enabled = ?6, \
+                         priority = ?7, action = ?8, action_dry_run = ?9, match_mode = ?10 \
+                     WHERE id = ?11",
                 )
                 .map_err(|e| format!("Failed to prepare filter update: {}", e))?;
 
@@ -849,7 +2933,12 @@ impl Storage for SqliteStorage {
                         filter.pattern,
                         filter_field_to_string(&filter.field),
                         if filter.is_regex { 1 } else { 0 },
-                        if filter.enabled { 1 } else { 0 }
+                        if filter.case_sensitive { 1 } else { 0 },
+                        if filter.enabled { 1 } else { 0 },
+                        filter.priority,
+                        filter_action_to_string(filter.action),
+                        if filter.action_dry_run { 1 } else { 0 },
+                        match_mode_to_string(filter.match_mode)
                     ])
                     .map_err(|e| format!("Failed to insert filter: {}", e))?;
                 let new_id = tx.last_insert_rowid();
@@ -865,7 +2954,12 @@ impl Storage for SqliteStorage {
                         filter.pattern,
                         filter_field_to_string(&filter.field),
                         if filter.is_regex { 1 } else { 0 },
+                        if filter.case_sensitive { 1 } else { 0 },
                         if filter.enabled { 1 } else { 0 },
+                        filter.priority,
+                        filter_action_to_string(filter.action),
+                        if filter.action_dry_run { 1 } else { 0 },
+                        match_mode_to_string(filter.match_mode),
                         filter.id
                     ])
                     .map_err(|e| format!("Failed to update filter: {}", e))?;
@@ -886,11 +2980,52 @@ impl Storage for SqliteStorage {
         load_filters_from_conn(&conn)
     }
 
+    fn export_filters(&self) -> Result<String, String> {
+        let config = FilterConfig {
+            patterns: self.get_filters()?,
+        };
+        serde_json::to_string_pretty(&config)
+            .map_err(|e| format!("Failed to serialize filters: {}", e))
+    }
+
+    fn import_filters(&self, json: &str, mode: ImportMode) -> Result<Vec<FilterPattern>, String> {
+        let config: FilterConfig = serde_json::from_str(json)
+            .map_err(|e| format!("Failed to parse filters file: {}", e))?;
+        crate::filters::validate_patterns(&config.patterns)?;
+
+        let mut patterns = match mode {
+            ImportMode::Merge => self.get_filters()?,
+            ImportMode::Replace => Vec::new(),
+        };
+        let mut seen: HashSet<(String, String, &'static str)> = patterns
+            .iter()
+            .map(|filter| {
+                (filter.name.clone(), filter.pattern.clone(), filter_field_to_string(&filter.field))
+            })
+            .collect();
+        let mut next_priority = patterns.iter().map(|filter| filter.priority + 1).max().unwrap_or(0);
+        for mut filter in config.patterns {
+            let key = (filter.name.clone(), filter.pattern.clone(), filter_field_to_string(&filter.field));
+            if !seen.insert(key) {
+                continue;
+            }
+            // Ids from another machine mean nothing here; save_filters inserts id 0 as new.
+            filter.id = 0;
+            filter.priority = next_priority;
+            next_priority += 1;
+            patterns.push(filter);
+        }
+
+        // save_filters deletes filters missing from the list and backfills matches for new ones.
+        self.save_filters(&patterns)
+    }
+
     fn set_email_filters(
         &self,
         account: &str,
         uid: u32,
         filter_ids: &[i64],
+        protect: bool,
     ) -> Result<(), String> {
         let mut conn = self
             .conn
@@ -919,17 +3054,23 @@ impl Storage for SqliteStorage {
             params![email_id],
         )
         .map_err(|e| format!("Failed to clear mappings: {}", e))?;
+        tx.execute(
+            "UPDATE emails SET filters_manual = ?2 WHERE id = ?1",
+            params![email_id, if protect { 1 } else { 0 }],
+        )
+        .map_err(|e| format!("Failed to mark manual mappings: {}", e))?;
 
         {
             let mut stmt = tx
                 .prepare(
-                    "INSERT OR IGNORE INTO filtered_emails (email_id, filter_id) \
-                     VALUES (?1, ?2)",
+                    "INSERT OR IGNORE INTO filtered_emails (email_id, filter_id, source) \
+                     VALUES (?1, ?2, ?3)",
                 )
                 .map_err(|e| format!("Failed to prepare mapping insert: {}", e))?;
 
+            let source = if protect { "manual" } else { "auto" };
             for filter_id in filter_ids {
-                stmt.execute(params![email_id, filter_id])
+                stmt.execute(params![email_id, filter_id, source])
                     .map_err(|e| format!("Failed to insert mapping: {}", e))?;
             }
         }
@@ -938,12 +3079,157 @@ impl Storage for SqliteStorage {
             .map_err(|e| format!("Failed to commit transaction: {}", e))?;
         Ok(())
     }
+
+    fn export_emails(
+        &self,
+        account: &str,
+        filter_ids: &[i64],
+        format: ExportFormat,
+    ) -> Result<String, String> {
+        let mut out = Vec::new();
+        self.export_emails_to(account, filter_ids, format, &mut out)?;
+        String::from_utf8(out).map_err(|e| format!("Export is not valid UTF-8: {}", e))
+    }
+
+    fn export_emails_to(
+        &self,
+        account: &str,
+        filter_ids: &[i64],
+        format: ExportFormat,
+        out: &mut dyn std::io::Write,
+    ) -> Result<u64, String> {
+        let filter_clause = if filter_ids.is_empty() {
+            String::new()
+        } else {
+            format!(
+                " AND e.id IN (SELECT email_id FROM filtered_emails WHERE filter_id IN ({}))",
+                vec!["?"; filter_ids.len()].join(",")
+            )
+        };
+        let sql = format!(
+            "SELECT e.uid, e.message_id, e.subject, e.sender, e.date, IFNULL(e.date_epoch, 0), e.mailbox, e.account, e.is_read, \
+             e.unsubscribe_url, e.unsubscribe_mailto, e.unsubscribe_oneclick, e.to_recipients, e.cc_recipients, \
+             EXISTS(SELECT 1 FROM attachments a WHERE a.email_id = e.id), e.is_flagged, e.size_bytes, e.snippet, e.is_answered, e.id \
+             FROM emails e \
+             WHERE e.account = ? AND e.id > ?{} \
+             ORDER BY e.id \
+             LIMIT ?",
+            filter_clause
+        );
+
+        let mut writer = export::ExportWriter::new(out, format)?;
+        let mut last_id: i64 = 0;
+        loop {
+            // Re-acquire the reader per chunk so a long export doesn't pin a pooled connection.
+            let chunk = {
+                let conn = self.reader()?;
+                let mut params: Vec<&dyn ToSql> = Vec::with_capacity(3 + filter_ids.len());
+                params.push(&account);
+                params.push(&last_id);
+                for filter_id in filter_ids {
+                    params.push(filter_id);
+                }
+                params.push(&EXPORT_CHUNK_SIZE);
+
+                let mut stmt = conn
+                    .prepare(&sql)
+                    .map_err(|e| format!("Failed to prepare export query: {}", e))?;
+                let rows = stmt
+                    .query_map(params.as_slice(), |row| {
+                        Ok((stored_email_from_row(row)?, row.get::<_, i64>(19)?))
+                    })
+                    .map_err(|e| format!("Failed to query emails for export: {}", e))?;
+                let mut chunk = Vec::new();
+                for row in rows {
+                    chunk.push(row.map_err(|e| format!("Failed to read email: {}", e))?);
+                }
+                chunk
+            };
+
+            let Some((_, id)) = chunk.last() else {
+                break;
+            };
+            last_id = *id;
+            let emails: Vec<StoredEmail> = chunk.into_iter().map(|(email, _)| email).collect();
+            writer.write_chunk(&emails)?;
+            if emails.len() < EXPORT_CHUNK_SIZE as usize {
+                break;
+            }
+        }
+        writer.finish()
+    }
 }
 
 fn get_db_path() -> Result<PathBuf, String> {
     Ok(get_db_dir()?.join("inboxcleanup.sqlite3"))
 }
 
+/// Run `check` and move the database aside if it reports corruption, returning where
+/// the damaged file went.
+fn recover_if_corrupt(path: &Path, check: &str) -> Result<Option<PathBuf>, String> {
+    let Some(problem) = check_integrity(path, check)? else {
+        return Ok(None);
+    };
+    let moved = quarantine_corrupt_db(path)?;
+    warn!(
+        "Database failed its {} ({}); moved it to {} and starting with an empty cache",
+        check,
+        problem,
+        moved.display()
+    );
+    Ok(Some(moved))
+}
+
+/// Run `PRAGMA <check>` (`quick_check` or `integrity_check`) on an existing database
+/// file. Returns what's wrong if SQLite reports corruption or doesn't recognise the
+/// file; other errors (permissions, a locked file) are passed through rather than
+/// treated as corruption.
+fn check_integrity(path: &Path, check: &str) -> Result<Option<String>, String> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .map_err(|e| format!("Failed to open DB: {}", e))?;
+    let sql = format!("PRAGMA {}(1)", check);
+    match conn.query_row(&sql, [], |row| row.get::<_, String>(0)) {
+        Ok(result) if result == "ok" => Ok(None),
+        Ok(result) => Ok(Some(result)),
+        Err(rusqlite::Error::SqliteFailure(err, message))
+            if matches!(
+                err.code,
+                rusqlite::ErrorCode::DatabaseCorrupt | rusqlite::ErrorCode::NotADatabase
+            ) =>
+        {
+            Ok(Some(message.unwrap_or_else(|| err.to_string())))
+        }
+        Err(e) => Err(format!("Failed to check DB integrity: {}", e)),
+    }
+}
+
+/// Move a corrupt database, and its WAL/shared-memory files so they aren't replayed
+/// into the fresh one, to `<stem>.corrupt-<timestamp>.sqlite3` in the same directory.
+fn quarantine_corrupt_db(path: &Path) -> Result<PathBuf, String> {
+    let stem = path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or("inboxcleanup");
+    let timestamp = chrono::Utc::now().format("%Y%m%d%H%M%S");
+    let target = path.with_file_name(format!("{}.corrupt-{}.sqlite3", stem, timestamp));
+    fs::rename(path, &target).map_err(|e| format!("Failed to move corrupt DB aside: {}", e))?;
+    for suffix in ["-wal", "-shm"] {
+        let mut sidecar = path.as_os_str().to_owned();
+        sidecar.push(suffix);
+        let sidecar = PathBuf::from(sidecar);
+        if sidecar.exists() {
+            let mut moved = target.as_os_str().to_owned();
+            moved.push(suffix);
+            fs::rename(&sidecar, PathBuf::from(moved))
+                .map_err(|e| format!("Failed to move corrupt DB {} file aside: {}", suffix, e))?;
+        }
+    }
+    Ok(target)
+}
+
 pub fn get_db_file_path() -> Result<PathBuf, String> {
     get_db_path()
 }
@@ -957,6 +3243,29 @@ pub fn get_db_dir() -> Result<PathBuf, String> {
     Ok(config_dir)
 }
 
+/// Size of the database file plus its `-wal` file. In-memory databases have no
+/// files, so their page total is used instead.
+fn database_size_bytes(conn: &Connection) -> Result<u64, String> {
+    if let Some(path) = conn.path().filter(|path| !path.is_empty()) {
+        let mut total = 0;
+        for suffix in ["", "-wal"] {
+            match fs::metadata(format!("{}{}", path, suffix)) {
+                Ok(metadata) => total += metadata.len(),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => return Err(format!("Failed to read DB size: {}", e)),
+            }
+        }
+        return Ok(total);
+    }
+    let page_count: u64 = conn
+        .query_row("PRAGMA page_count", [], |row| row.get(0))
+        .map_err(|e| format!("Failed to read page count: {}", e))?;
+    let page_size: u64 = conn
+        .query_row("PRAGMA page_size", [], |row| row.get(0))
+        .map_err(|e| format!("Failed to read page size: {}", e))?;
+    Ok(page_count * page_size)
+}
+
 fn migrate(conn: &mut Connection) -> Result<(), String> {
     conn.execute_batch(
         "BEGIN;
@@ -986,9 +3295,12 @@ fn migrate(conn: &mut Connection) -> Result<(), String> {
            updated_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
          );
          CREATE TABLE IF NOT EXISTS sync_state (
-           account TEXT PRIMARY KEY,
+           account TEXT NOT NULL,
+           mailbox TEXT NOT NULL DEFAULT 'INBOX',
            last_uid INTEGER NOT NULL DEFAULT 0,
-           updated_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+           highest_modseq INTEGER,
+           updated_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+           PRIMARY KEY (account, mailbox)
          );
          CREATE TABLE IF NOT EXISTS filtered_emails (
            email_id INTEGER NOT NULL,
@@ -1010,21 +3322,120 @@ fn migrate(conn: &mut Connection) -> Result<(), String> {
            updated_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
            PRIMARY KEY (account, scope)
          );
+         CREATE TABLE IF NOT EXISTS settings (
+           key TEXT PRIMARY KEY,
+           value TEXT NOT NULL
+         );
+         CREATE TABLE IF NOT EXISTS attachments (
+           id INTEGER PRIMARY KEY AUTOINCREMENT,
+           email_id INTEGER NOT NULL,
+           filename TEXT,
+           content_type TEXT NOT NULL,
+           size INTEGER NOT NULL,
+           FOREIGN KEY (email_id) REFERENCES emails(id) ON DELETE CASCADE
+         );
          CREATE INDEX IF NOT EXISTS idx_emails_uid ON emails(uid);
          CREATE INDEX IF NOT EXISTS idx_emails_message_id ON emails(message_id);
          CREATE INDEX IF NOT EXISTS idx_emails_is_read ON emails(is_read);
          CREATE INDEX IF NOT EXISTS idx_emails_date ON emails(date);
          CREATE INDEX IF NOT EXISTS idx_filtered_emails_filter_id ON filtered_emails(filter_id);
          CREATE INDEX IF NOT EXISTS idx_filtered_emails_email_id ON filtered_emails(email_id);
+         CREATE INDEX IF NOT EXISTS idx_attachments_email_id ON attachments(email_id);
          COMMIT;",
     )
     .map_err(|e| format!("Failed to migrate DB: {}", e))?;
 
     migrate_filters_to_integer_ids(conn)?;
     ensure_column(conn, "emails", "body_html", "TEXT")?;
+    ensure_column(conn, "emails", "is_trashed", "INTEGER NOT NULL DEFAULT 0")?;
+    ensure_column(conn, "emails", "is_flagged", "INTEGER NOT NULL DEFAULT 0")?;
+    ensure_column(conn, "emails", "is_answered", "INTEGER NOT NULL DEFAULT 0")?;
+    // Gmail inbox tab (`promotions`, ...) from the last category sync; NULL if unknown.
+    ensure_column(conn, "emails", "gmail_category", "TEXT")?;
+    // Set when an email was marked read in the app only; a sync won't flip it back to unread.
+    ensure_column(conn, "emails", "read_locally", "INTEGER NOT NULL DEFAULT 0")?;
+    ensure_column(conn, "emails", "size_bytes", "INTEGER")?;
+    ensure_column(conn, "emails", "snippet", "TEXT")?;
+    ensure_column(conn, "emails", "headers_json", "TEXT")?;
+    ensure_column(conn, "emails", "snoozed_until", "INTEGER")?;
+    ensure_column(conn, "emails", "filters_manual", "INTEGER NOT NULL DEFAULT 0")?;
     ensure_column(conn, "emails", "body_text", "TEXT")?;
+    // `body_html` holds the HTML as received; `body_html_safe` the sanitized copy.
+    ensure_column(conn, "emails", "body_html_safe", "TEXT")?;
+    // `sanitize::SANITIZER_VERSION` that produced `body_html_safe`; 0 predates versioning.
+    ensure_column(conn, "emails", "body_version", "INTEGER NOT NULL DEFAULT 0")?;
     ensure_column(conn, "emails", "date_epoch", "INTEGER")?;
+    conn.execute_batch("CREATE INDEX IF NOT EXISTS idx_emails_date_epoch ON emails(date_epoch);")
+        .map_err(|e| format!("Failed to create date_epoch index: {}", e))?;
+    ensure_column(conn, "filters", "case_sensitive", "INTEGER NOT NULL DEFAULT 0")?;
+    ensure_column(conn, "filters", "priority", "INTEGER NOT NULL DEFAULT 0")?;
+    ensure_column(conn, "filters", "action", "TEXT NOT NULL DEFAULT 'none'")?;
+    ensure_column(conn, "filters", "action_dry_run", "INTEGER NOT NULL DEFAULT 0")?;
+    ensure_column(conn, "filters", "match_mode", "TEXT NOT NULL DEFAULT 'substring'")?;
+    // `manual` rows come from `set_email_filters(.., protect)`; refreshes only replace `auto`
+    // rows, and their INSERT OR IGNORE never overwrites a manual one.
+    ensure_column(conn, "filtered_emails", "source", "TEXT NOT NULL DEFAULT 'auto'")?;
+    ensure_column(conn, "sync_state", "highest_modseq", "INTEGER")?;
+    migrate_sync_state_to_mailboxes(conn)?;
+    ensure_column(conn, "sync_state", "oldest_synced_uid", "INTEGER")?;
+    ensure_column(conn, "emails", "unsubscribe_url", "TEXT")?;
+    ensure_column(conn, "emails", "unsubscribe_mailto", "TEXT")?;
+    ensure_column(conn, "emails", "unsubscribe_oneclick", "INTEGER NOT NULL DEFAULT 0")?;
+    ensure_column(conn, "emails", "to_recipients", "TEXT")?;
+    ensure_column(conn, "emails", "cc_recipients", "TEXT")?;
+    ensure_column(conn, "emails", "in_reply_to", "TEXT")?;
+    ensure_column(conn, "emails", "references_ids", "TEXT")?;
+    ensure_column(conn, "emails", "thread_id", "INTEGER")?;
+    conn.execute_batch("CREATE INDEX IF NOT EXISTS idx_emails_thread ON emails(account, thread_id);")
+        .map_err(|e| format!("Failed to create thread index: {}", e))?;
+    // Nearly every query filters by account first, so lead with it.
+    conn.execute_batch(
+        "CREATE INDEX IF NOT EXISTS idx_emails_account_message_id ON emails(account, message_id);
+         CREATE INDEX IF NOT EXISTS idx_emails_account_date_epoch ON emails(account, date_epoch);
+         CREATE INDEX IF NOT EXISTS idx_emails_account_unread ON emails(account, is_read, is_trashed);
+         CREATE INDEX IF NOT EXISTS idx_emails_account_read_date
+           ON emails(account, is_read, date_epoch DESC, uid DESC);",
+    )
+    .map_err(|e| format!("Failed to create account indexes: {}", e))?;
     backfill_date_epoch(conn)?;
+    // NULL for senders without a parseable address, so domain lookups skip them.
+    ensure_column(conn, "emails", "sender_domain", "TEXT")?;
+    conn.execute_batch("CREATE INDEX IF NOT EXISTS idx_emails_sender_domain ON emails(account, sender_domain);")
+        .map_err(|e| format!("Failed to create sender_domain index: {}", e))?;
+    backfill_sender_domain(conn)?;
+    create_accounts_table(conn)?;
+    Ok(())
+}
+
+/// The `accounts` table. Accounts used to exist only implicitly (Keychain entries and
+/// cached mail), so when it is first created it is seeded from the cached accounts.
+fn create_accounts_table(conn: &mut Connection) -> Result<(), String> {
+    let exists: bool = conn
+        .query_row(
+            "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'accounts')",
+            [],
+            |row| row.get(0),
+        )
+        .map_err(|e| format!("Failed to inspect schema: {}", e))?;
+    if exists {
+        return Ok(());
+    }
+
+    let tx = conn
+        .transaction()
+        .map_err(|e| format!("Failed to start accounts migration: {}", e))?;
+    tx.execute_batch(
+        "CREATE TABLE accounts (
+           email TEXT PRIMARY KEY,
+           display_name TEXT,
+           color TEXT,
+           last_synced_epoch INTEGER
+         );
+         INSERT OR IGNORE INTO accounts (email) SELECT DISTINCT account FROM emails;",
+    )
+    .map_err(|e| format!("Failed to create accounts table: {}", e))?;
+    tx.commit()
+        .map_err(|e| format!("Failed to commit accounts migration: {}", e))?;
     Ok(())
 }
 
@@ -1158,6 +3569,36 @@ fn migrate_filters_to_integer_ids(conn: &mut Connection) -> Result<(), String> {
     Ok(())
 }
 
+/// `sync_state` used to be keyed by account alone, with INBOX implied. Rebuild it keyed
+/// by `(account, mailbox)` and file the existing rows under INBOX.
+fn migrate_sync_state_to_mailboxes(conn: &mut Connection) -> Result<(), String> {
+    if get_column_type(conn, "sync_state", "mailbox")?.is_some() {
+        return Ok(());
+    }
+
+    let tx = conn
+        .transaction()
+        .map_err(|e| format!("Failed to start sync state migration: {}", e))?;
+    tx.execute_batch(
+        "CREATE TABLE sync_state_v2 (
+           account TEXT NOT NULL,
+           mailbox TEXT NOT NULL DEFAULT 'INBOX',
+           last_uid INTEGER NOT NULL DEFAULT 0,
+           highest_modseq INTEGER,
+           updated_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+           PRIMARY KEY (account, mailbox)
+         );
+         INSERT INTO sync_state_v2 (account, mailbox, last_uid, highest_modseq, updated_at)
+           SELECT account, 'INBOX', last_uid, highest_modseq, updated_at FROM sync_state;
+         DROP TABLE sync_state;
+         ALTER TABLE sync_state_v2 RENAME TO sync_state;",
+    )
+    .map_err(|e| format!("Failed to migrate sync state: {}", e))?;
+    tx.commit()
+        .map_err(|e| format!("Failed to commit sync state migration: {}", e))?;
+    Ok(())
+}
+
 fn get_column_type(conn: &Connection, table: &str, column: &str) -> Result<Option<String>, String> {
     let sql = format!("PRAGMA table_info({})", table);
     let mut stmt = conn
@@ -1187,8 +3628,8 @@ fn backfill_date_epoch(conn: &mut Connection) -> Result<(), String> {
 
         for row in rows {
             let (id, date_str) = row.map_err(|e| format!("Failed to read row: {}", e))?;
-            if let Ok(dt) = DateTime::parse_from_rfc2822(&date_str) {
-                updates.push((dt.timestamp(), id));
+            if let Some(epoch) = crate::gmail::parse_imap_date_epoch(&date_str) {
+                updates.push((epoch, id));
             }
         }
     }
@@ -1215,6 +3656,47 @@ fn backfill_date_epoch(conn: &mut Connection) -> Result<(), String> {
     Ok(())
 }
 
+/// Fill `sender_domain` for rows synced before the column existed. Senders without
+/// a usable address get `''` so NULL only ever means "not computed yet" and those
+/// rows aren't rescanned on every startup.
+fn backfill_sender_domain(conn: &mut Connection) -> Result<(), String> {
+    let mut updates = Vec::new();
+    {
+        let mut stmt = conn
+            .prepare("SELECT id, sender FROM emails WHERE sender_domain IS NULL")
+            .map_err(|e| format!("Failed to query senders: {}", e))?;
+        let rows = stmt
+            .query_map([], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?)))
+            .map_err(|e| format!("Failed to read senders: {}", e))?;
+
+        for row in rows {
+            let (id, sender) = row.map_err(|e| format!("Failed to read row: {}", e))?;
+            updates.push((sender_domain(&sender).unwrap_or_default(), id));
+        }
+    }
+
+    if updates.is_empty() {
+        return Ok(());
+    }
+
+    let tx = conn
+        .transaction()
+        .map_err(|e| format!("Failed to start backfill transaction: {}", e))?;
+    {
+        let mut update_stmt = tx
+            .prepare("UPDATE emails SET sender_domain = ?1 WHERE id = ?2")
+            .map_err(|e| format!("Failed to prepare backfill: {}", e))?;
+        for (domain, id) in updates {
+            update_stmt
+                .execute(params![domain, id])
+                .map_err(|e| format!("Failed to update sender_domain: {}", e))?;
+        }
+    }
+    tx.commit()
+        .map_err(|e| format!("Failed to commit backfill: {}", e))?;
+    Ok(())
+}
+
 fn ensure_column(conn: &Connection, table: &str, column: &str, column_type: &str) -> Result<(), String> {
     let sql = format!("PRAGMA table_info({})", table);
     let mut stmt = conn
@@ -1241,6 +3723,46 @@ fn ensure_column(conn: &Connection, table: &str, column: &str, column_type: &str
 
 const FILTER_SYNC_SCOPE: &str = "filters_v1";
 
+/// SQL expression extracting the lowercased bare address from `sender`, which may
+/// be either `Name <addr>` or just `addr`.
+const SENDER_ADDRESS_SQL: &str = "lower(trim(CASE \
+    WHEN instr(sender, '<') > 0 AND instr(sender, '>') > instr(sender, '<') \
+    THEN substr(sender, instr(sender, '<') + 1, instr(sender, '>') - instr(sender, '<') - 1) \
+    ELSE sender END))";
+
+/// Rust counterpart of `SENDER_ADDRESS_SQL` for addresses passed in by callers.
+fn bare_address(sender: &str) -> String {
+    let address = match (sender.find('<'), sender.rfind('>')) {
+        (Some(start), Some(end)) if end > start => &sender[start + 1..end],
+        _ => sender,
+    };
+    address.trim().to_lowercase()
+}
+
+/// Lowercased host part of the sender's address, or `None` if there's no usable address.
+/// A stray `>` left over from a half-formed `Name <a@b.com>` is dropped.
+fn sender_domain(sender: &str) -> Option<String> {
+    let address = bare_address(sender);
+    let (local, domain) = address.rsplit_once('@')?;
+    let domain = domain.trim_end_matches(['>', '.']).trim_end();
+    if local.is_empty() || domain.is_empty() || domain.contains(char::is_whitespace) {
+        return None;
+    }
+    Some(domain.to_string())
+}
+
+/// Escape `%`, `_` and `\` for a LIKE pattern using `ESCAPE '\'`.
+fn escape_like(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '%' | '_' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
 fn get_filter_last_email_id(conn: &Connection, account: &str) -> Result<i64, String> {
     let last_id: Option<i64> = conn
         .query_row(
@@ -1269,8 +3791,9 @@ fn set_filter_last_email_id(conn: &Connection, account: &str, last_id: i64) -> R
 fn load_filters_from_conn(conn: &Connection) -> Result<Vec<FilterPattern>, String> {
     let mut stmt = conn
         .prepare(
-            "SELECT id, name, pattern, field, is_regex, enabled \
-             FROM filters ORDER BY rowid ASC",
+            "SELECT id, name, pattern, field, is_regex, case_sensitive, enabled, priority, \
+             action, action_dry_run, match_mode \
+             FROM filters ORDER BY priority ASC, rowid ASC",
         )
         .map_err(|e| format!("Failed to prepare filters query: {}", e))?;
     let rows = stmt
@@ -1282,7 +3805,12 @@ fn load_filters_from_conn(conn: &Connection) -> Result<Vec<FilterPattern>, Strin
                 pattern: row.get(2)?,
                 field: parse_filter_field(&field)?,
                 is_regex: row.get::<_, i64>(4)? != 0,
-                enabled: row.get::<_, i64>(5)? != 0,
+                case_sensitive: row.get::<_, i64>(5)? != 0,
+                enabled: row.get::<_, i64>(6)? != 0,
+                priority: row.get(7)?,
+                action: parse_filter_action(&row.get::<_, String>(8)?),
+                action_dry_run: row.get::<_, i64>(9)? != 0,
+                match_mode: parse_match_mode(&row.get::<_, String>(10)?),
             })
         })
         .map_err(|e| format!("Failed to read filters: {}", e))?;
@@ -1298,23 +3826,33 @@ struct CompiledFilter {
     id: i64,
     field: FilterField,
     regex: Option<regex::Regex>,
-    pattern_lower: Option<String>,
+    /// Substring pattern; already lowercased unless `case_sensitive` is set.
+    pattern: Option<String>,
+    case_sensitive: bool,
 }
 
 fn compile_filters(filters: &[FilterPattern]) -> Vec<CompiledFilter> {
     filters
         .iter()
         .map(|filter| {
+            let whole_word = !filter.is_regex && filter.match_mode == MatchMode::WholeWord;
             let regex = if filter.is_regex {
                 RegexBuilder::new(&filter.pattern)
-                    .case_insensitive(true)
+                    .case_insensitive(!filter.case_sensitive)
+                    .build()
+                    .ok()
+            } else if whole_word {
+                RegexBuilder::new(&format!(r"\b{}\b", regex::escape(&filter.pattern)))
+                    .case_insensitive(!filter.case_sensitive)
                     .build()
                     .ok()
             } else {
                 None
             };
-            let pattern_lower = if filter.is_regex {
+            let pattern = if filter.is_regex || whole_word {
                 None
+            } else if filter.case_sensitive {
+                Some(filter.pattern.clone())
             } else {
                 Some(filter.pattern.to_lowercase())
             };
@@ -1322,15 +3860,34 @@ fn compile_filters(filters: &[FilterPattern]) -> Vec<CompiledFilter> {
                 id: filter.id.clone(),
                 field: filter.field.clone(),
                 regex,
-                pattern_lower,
+                pattern,
+                case_sensitive: filter.case_sensitive,
             }
         })
         .collect()
 }
 
-fn match_filters(subject: &str, sender: &str, filters: &[CompiledFilter]) -> Vec<i64> {
+/// The stored `to_recipients`/`cc_recipients` lists as one entry per recipient.
+fn split_recipients<'a>(to: Option<&'a str>, cc: Option<&'a str>) -> Vec<&'a str> {
+    to.into_iter()
+        .chain(cc)
+        .flat_map(|list| list.split(','))
+        .map(str::trim)
+        .filter(|recipient| !recipient.is_empty())
+        .collect()
+}
+
+/// `recipients` is only consulted by `Recipient` filters, which match when any one
+/// recipient does.
+fn match_filters(
+    subject: &str,
+    sender: &str,
+    recipients: &[&str],
+    filters: &[CompiledFilter],
+) -> Vec<i64> {
     let subject_lower = subject.to_lowercase();
     let sender_lower = sender.to_lowercase();
+    let recipients_lower: Vec<String> = recipients.iter().map(|r| r.to_lowercase()).collect();
     let mut matches = Vec::new();
 
     for filter in filters {
@@ -1338,13 +3895,26 @@ fn match_filters(subject: &str, sender: &str, filters: &[CompiledFilter]) -> Vec
             match filter.field {
                 FilterField::Subject => regex.is_match(subject),
                 FilterField::Sender => regex.is_match(sender),
+                FilterField::Recipient => recipients.iter().any(|r| regex.is_match(r)),
                 FilterField::Any => regex.is_match(subject) || regex.is_match(sender),
             }
-        } else if let Some(pattern) = &filter.pattern_lower {
+        } else if let Some(pattern) = &filter.pattern {
+            let (subject, sender) = if filter.case_sensitive {
+                (subject, sender)
+            } else {
+                (subject_lower.as_str(), sender_lower.as_str())
+            };
             match filter.field {
-                FilterField::Subject => subject_lower.contains(pattern),
-                FilterField::Sender => sender_lower.contains(pattern),
-                FilterField::Any => subject_lower.contains(pattern) || sender_lower.contains(pattern),
+                FilterField::Subject => subject.contains(pattern.as_str()),
+                FilterField::Sender => sender.contains(pattern.as_str()),
+                FilterField::Recipient => {
+                    if filter.case_sensitive {
+                        recipients.iter().any(|r| r.contains(pattern.as_str()))
+                    } else {
+                        recipients_lower.iter().any(|r| r.contains(pattern.as_str()))
+                    }
+                }
+                FilterField::Any => subject.contains(pattern.as_str()) || sender.contains(pattern.as_str()),
             }
         } else {
             false
@@ -1389,16 +3959,22 @@ fn refresh_filter_matches_for_account(
         let batch = {
             let mut stmt = conn
                 .prepare(
-                    "SELECT id, subject, sender \
+                    "SELECT id, subject, sender, to_recipients, cc_recipients \
                      FROM emails \
-                     WHERE account = ?1 AND id > ?2 \
+                     WHERE account = ?1 AND id > ?2 AND filters_manual = 0 \
                      ORDER BY id ASC \
                      LIMIT ?3",
                 )
                 .map_err(|e| format!("Failed to prepare filter refresh query: {}", e))?;
             let rows = stmt
                 .query_map(params![account, last_id, chunk_size], |row| {
-                    Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?, row.get::<_, String>(2)?))
+                    Ok((
+                        row.get::<_, i64>(0)?,
+                        row.get::<_, String>(1)?,
+                        row.get::<_, String>(2)?,
+                        row.get::<_, Option<String>>(3)?,
+                        row.get::<_, Option<String>>(4)?,
+                    ))
                 })
                 .map_err(|e| format!("Failed to query emails for filter refresh: {}", e))?;
 
@@ -1425,8 +4001,9 @@ fn refresh_filter_matches_for_account(
                 )
                 .map_err(|e| format!("Failed to prepare filter insert: {}", e))?;
 
-            for (email_id, subject, sender) in &batch {
-                let matches = match_filters(subject, sender, &compiled_filters);
+            for (email_id, subject, sender, to, cc) in &batch {
+                let recipients = split_recipients(to.as_deref(), cc.as_deref());
+                let matches = match_filters(subject, sender, &recipients, &compiled_filters);
                 for filter_id in matches {
                     insert_stmt
                         .execute(params![email_id, filter_id])
@@ -1462,18 +4039,24 @@ fn maybe_import_filters(conn: &mut Connection) -> Result<(), String> {
         let mut stmt = tx
             .prepare(
                 "INSERT INTO filters \
-                    (name, pattern, field, is_regex, enabled) \
-                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                    (name, pattern, field, is_regex, case_sensitive, enabled, priority, action, action_dry_run, \
+                     match_mode) \
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
             )
             .map_err(|e| format!("Failed to prepare filter import: {}", e))?;
 
-        for filter in config.patterns {
+        for (position, filter) in config.patterns.into_iter().enumerate() {
             stmt.execute(params![
                 filter.name,
                 filter.pattern,
                 filter_field_to_string(&filter.field),
                 if filter.is_regex { 1 } else { 0 },
-                if filter.enabled { 1 } else { 0 }
+                if filter.case_sensitive { 1 } else { 0 },
+                if filter.enabled { 1 } else { 0 },
+                position as i64,
+                filter_action_to_string(filter.action),
+                if filter.action_dry_run { 1 } else { 0 },
+                match_mode_to_string(filter.match_mode)
             ])
             .map_err(|e| format!("Failed to import filter: {}", e))?;
         }
@@ -1484,19 +4067,223 @@ fn maybe_import_filters(conn: &mut Connection) -> Result<(), String> {
     Ok(())
 }
 
+/// Email listing shared by `list_emails` and `list_emails_page`.
+fn query_emails(
+    conn: &Connection,
+    account: &str,
+    unread_only: bool,
+    query: &EmailQuery,
+    sort: SortBy,
+    limit: u32,
+    offset: u32,
+) -> Result<Vec<StoredEmail>, String> {
+    let mut sql = if unread_only {
+        "SELECT uid, message_id, subject, sender, date, IFNULL(date_epoch, 0), mailbox, account, is_read, \
+         unsubscribe_url, unsubscribe_mailto, unsubscribe_oneclick, to_recipients, cc_recipients, \
+         EXISTS(SELECT 1 FROM attachments a WHERE a.email_id = emails.id), is_flagged, size_bytes, snippet, is_answered \
+         FROM emails \
+         WHERE account = ? AND is_trashed = 0 AND is_read = 0"
+            .to_string()
+    } else {
+        "SELECT uid, message_id, subject, sender, date, IFNULL(date_epoch, 0), mailbox, account, is_read, \
+         unsubscribe_url, unsubscribe_mailto, unsubscribe_oneclick, to_recipients, cc_recipients, \
+         EXISTS(SELECT 1 FROM attachments a WHERE a.email_id = emails.id), is_flagged, size_bytes, snippet, is_answered \
+         FROM emails \
+         WHERE account = ? AND is_trashed = 0"
+            .to_string()
+    };
+    let mut params: Vec<&dyn ToSql> = vec![&account];
+    query.append_conditions(&mut sql, &mut params);
+    sql.push_str(query.snooze_condition());
+    sql.push_str(&format!(" ORDER BY {} LIMIT ? OFFSET ?", sort.order_by_clause()));
+    params.push(&limit);
+    params.push(&offset);
+
+    let mut stmt = conn
+        .prepare(&sql)
+        .map_err(|e| format!("Failed to prepare query: {}", e))?;
+
+    let rows = stmt
+        .query_map(params.as_slice(), stored_email_from_row)
+        .map_err(|e| format!("Failed to query emails: {}", e))?;
+
+    let mut results = Vec::new();
+    for row in rows {
+        results.push(row.map_err(|e| format!("Failed to read email: {}", e))?);
+    }
+    Ok(results)
+}
+
+/// Count emails for `query`. Snoozed emails are counted unless `listed_only`
+/// narrows the count to what `query_emails` would list.
+fn query_email_count(
+    conn: &Connection,
+    account: &str,
+    unread_only: bool,
+    query: &EmailQuery,
+    listed_only: bool,
+) -> Result<u64, String> {
+    let mut sql = if unread_only {
+        "SELECT COUNT(*) FROM emails WHERE account = ? AND is_trashed = 0 AND is_read = 0".to_string()
+    } else {
+        "SELECT COUNT(*) FROM emails WHERE account = ? AND is_trashed = 0".to_string()
+    };
+    let mut params: Vec<&dyn ToSql> = vec![&account];
+    query.append_conditions(&mut sql, &mut params);
+    if listed_only {
+        sql.push_str(query.snooze_condition());
+    }
+    let count: u64 = conn
+        .query_row(&sql, params.as_slice(), |row| row.get(0))
+        .map_err(|e| format!("Failed to count emails: {}", e))?;
+    Ok(count)
+}
+
+/// Anti-join for emails without a match: matches of disabled filters are kept in
+/// `filtered_emails` but don't count. An email with no match joins a single all-NULL
+/// row, so filtering on `fe.email_id IS NULL` needs no DISTINCT.
+const UNFILTERED_JOIN: &str = "LEFT JOIN filtered_emails fe ON fe.email_id = e.id \
+     AND fe.filter_id IN (SELECT id FROM filters WHERE enabled = 1)";
+
+/// Map a row selected with the standard email column list into a `StoredEmail`.
+fn stored_email_from_row(row: &rusqlite::Row) -> rusqlite::Result<StoredEmail> {
+    Ok(StoredEmail {
+        uid: row.get(0)?,
+        message_id: row.get(1)?,
+        subject: row.get(2)?,
+        sender: row.get(3)?,
+        date: row.get(4)?,
+        date_epoch: row.get(5)?,
+        mailbox: row.get(6)?,
+        account: row.get(7)?,
+        is_read: row.get::<_, i64>(8)? != 0,
+        unsubscribe_url: row.get(9)?,
+        unsubscribe_mailto: row.get(10)?,
+        unsubscribe_oneclick: row.get::<_, i64>(11)? != 0,
+        to: row.get(12)?,
+        cc: row.get(13)?,
+        has_attachments: row.get::<_, i64>(14)? != 0,
+        is_flagged: row.get::<_, i64>(15)? != 0,
+        size_bytes: row.get(16)?,
+        snippet: row.get(17)?,
+        is_answered: row.get::<_, i64>(18)? != 0,
+    })
+}
+
+/// Recompute `thread_id` for every email in the account. New emails, and emails whose
+/// threading headers changed, have a NULL `thread_id` until this runs.
+fn assign_thread_ids(conn: &Connection, account: &str) -> Result<(), String> {
+    let emails = {
+        let mut stmt = conn
+            .prepare(
+                "SELECT id, message_id, subject, in_reply_to, references_ids \
+                 FROM emails WHERE account = ?1",
+            )
+            .map_err(|e| format!("Failed to prepare thread input query: {}", e))?;
+        let rows = stmt
+            .query_map(params![account], |row| {
+                Ok(threads::ThreadInput {
+                    id: row.get(0)?,
+                    message_id: row.get(1)?,
+                    subject: row.get(2)?,
+                    in_reply_to: row.get(3)?,
+                    references: row.get(4)?,
+                })
+            })
+            .map_err(|e| format!("Failed to query thread input: {}", e))?;
+        let mut emails = Vec::new();
+        for row in rows {
+            emails.push(row.map_err(|e| format!("Failed to read thread input: {}", e))?);
+        }
+        emails
+    };
+
+    let mut stmt = conn
+        .prepare("UPDATE emails SET thread_id = ?1 WHERE id = ?2 AND thread_id IS NOT ?1")
+        .map_err(|e| format!("Failed to prepare thread update: {}", e))?;
+    for (id, thread_id) in threads::compute_thread_ids(&emails) {
+        stmt.execute(params![thread_id, id])
+            .map_err(|e| format!("Failed to update thread: {}", e))?;
+    }
+    Ok(())
+}
+
+fn query_attachments(
+    conn: &Connection,
+    account: &str,
+    uid: u32,
+) -> Result<Vec<crate::gmail::AttachmentMeta>, String> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT a.filename, a.content_type, a.size \
+             FROM attachments a \
+             JOIN emails e ON e.id = a.email_id \
+             WHERE e.account = ?1 AND e.uid = ?2 \
+             ORDER BY a.id",
+        )
+        .map_err(|e| format!("Failed to prepare attachment query: {}", e))?;
+    let rows = stmt
+        .query_map(params![account, uid], |row| {
+            Ok(crate::gmail::AttachmentMeta {
+                filename: row.get(0)?,
+                content_type: row.get(1)?,
+                size: row.get::<_, i64>(2)?.max(0) as u64,
+            })
+        })
+        .map_err(|e| format!("Failed to query attachments: {}", e))?;
+
+    let mut attachments = Vec::new();
+    for row in rows {
+        attachments.push(row.map_err(|e| format!("Failed to read attachment: {}", e))?);
+    }
+    Ok(attachments)
+}
+
 fn parse_filter_field(value: &str) -> Result<FilterField, rusqlite::Error> {
     match value {
         "subject" => Ok(FilterField::Subject),
         "sender" => Ok(FilterField::Sender),
+        "recipient" => Ok(FilterField::Recipient),
         "any" => Ok(FilterField::Any),
         _ => Ok(FilterField::Any),
     }
 }
 
+fn filter_action_to_string(action: FilterAction) -> &'static str {
+    match action {
+        FilterAction::None => "none",
+        FilterAction::MarkRead => "mark_read",
+        FilterAction::Archive => "archive",
+    }
+}
+
+fn parse_filter_action(value: &str) -> FilterAction {
+    match value {
+        "mark_read" => FilterAction::MarkRead,
+        "archive" => FilterAction::Archive,
+        _ => FilterAction::None,
+    }
+}
+
+fn match_mode_to_string(mode: MatchMode) -> &'static str {
+    match mode {
+        MatchMode::Substring => "substring",
+        MatchMode::WholeWord => "whole_word",
+    }
+}
+
+fn parse_match_mode(value: &str) -> MatchMode {
+    match value {
+        "whole_word" => MatchMode::WholeWord,
+        _ => MatchMode::Substring,
+    }
+}
+
 fn filter_field_to_string(field: &FilterField) -> &'static str {
     match field {
         FilterField::Subject => "subject",
         FilterField::Sender => "sender",
+        FilterField::Recipient => "recipient",
         FilterField::Any => "any",
     }
 }
@@ -1524,91 +4311,260 @@ mod tests {
         path
     }
 
+    /// `path` with `suffix` appended, e.g. the `-wal` file next to a database.
+    fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+        let mut name = path.as_os_str().to_owned();
+        name.push(suffix);
+        PathBuf::from(name)
+    }
+
+    /// Remove a test database along with its WAL and shared-memory files.
+    fn remove_test_db(path: &Path) {
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(with_suffix(path, suffix));
+        }
+    }
+
     #[test]
     fn upsert_and_mark_read_roundtrip() {
-        let path = temp_db_path("upsert");
-        {
-            let storage = SqliteStorage::new_with_path(path.clone()).unwrap();
-            let emails = vec![
-                GmailEmail {
-                    uid: 101,
-                    message_id: "msg-101".to_string(),
-                    subject: "Hello".to_string(),
-                    sender: "Alice <alice@example.com>".to_string(),
-                    date: "2024-01-01T10:00:00Z".to_string(),
-                    date_epoch: 1704103200,
-                    is_read: false,
-                },
-                GmailEmail {
-                    uid: 102,
-                    message_id: "msg-102".to_string(),
-                    subject: "Update".to_string(),
-                    sender: "Bob <bob@example.com>".to_string(),
-                    date: "2024-01-02T12:00:00Z".to_string(),
-                    date_epoch: 1704196800,
-                    is_read: true,
-                },
-            ];
-
-            storage
-                .upsert_emails("test@example.com", "INBOX", &emails)
-                .unwrap();
+        let storage = SqliteStorage::new_in_memory().unwrap();
+        let emails = vec![
+            GmailEmail {
+                uid: 101,
+                message_id: "msg-101".to_string(),
+                subject: "Hello".to_string(),
+                sender: "Alice <alice@example.com>".to_string(),
+                date: "2024-01-01T10:00:00Z".to_string(),
+                date_epoch: 1704103200,
+                is_read: false,
+                is_flagged: false,
+                is_answered: false,
+                size_bytes: None,
+                to: None,
+                cc: None,
+            },
+            GmailEmail {
+                uid: 102,
+                message_id: "msg-102".to_string(),
+                subject: "Update".to_string(),
+                sender: "Bob <bob@example.com>".to_string(),
+                date: "2024-01-02T12:00:00Z".to_string(),
+                date_epoch: 1704196800,
+                is_read: true,
+                is_flagged: false,
+                is_answered: false,
+                size_bytes: None,
+                to: None,
+                cc: None,
+            },
+        ];
+
+        storage
+            .upsert_emails("test@example.com", "INBOX", &emails)
+            .unwrap();
+
+        let unread = storage
+            .list_emails("test@example.com", true, &EmailQuery::default(), SortBy::default(), 50, 0)
+            .unwrap();
+        assert_eq!(unread.len(), 1);
+        assert_eq!(unread[0].account, "test@example.com");
+        assert!(!unread[0].is_read);
+
+        let updated = storage
+            .mark_emails_read("test@example.com", &[101])
+            .unwrap();
+        assert_eq!(updated, 1);
+
+        let unread_after = storage
+            .list_emails("test@example.com", true, &EmailQuery::default(), SortBy::default(), 50, 0)
+            .unwrap();
+        assert_eq!(unread_after.len(), 0);
+    }
 
-            let unread = storage
-                .list_emails("test@example.com", true, 50, 0)
-                .unwrap();
-            assert_eq!(unread.len(), 1);
-            assert_eq!(unread[0].account, "test@example.com");
-            assert!(!unread[0].is_read);
+    #[test]
+    fn upsert_stores_recipients() {
+        let storage = SqliteStorage::new_in_memory().unwrap();
+        let mut email = make_email(1, "Hello", "Alice <alice@example.com>");
+        email.to = Some("test@example.com".to_string());
+        email.cc = Some("Carol <carol@example.com>".to_string());
+        storage
+            .upsert_emails("test@example.com", "INBOX", &[email])
+            .unwrap();
+
+        let rows = storage
+            .list_emails("test@example.com", false, &EmailQuery::default(), SortBy::default(), 10, 0)
+            .unwrap();
+        assert_eq!(rows[0].to.as_deref(), Some("test@example.com"));
+        assert_eq!(rows[0].cc.as_deref(), Some("Carol <carol@example.com>"));
+    }
 
-            let updated = storage
-                .mark_emails_read("test@example.com", &[101])
-                .unwrap();
-            assert_eq!(updated, 1);
+    #[test]
+    fn all_unread_uids_lists_only_unread_mail_for_the_account() {
+        let storage = SqliteStorage::new_in_memory().unwrap();
+        let mut read = make_email(2, "Read", "a@example.com");
+        read.is_read = true;
+        storage
+            .upsert_emails(
+                "test@example.com",
+                "INBOX",
+                &[
+                    make_email(3, "Later", "a@example.com"),
+                    read,
+                    make_email(1, "First", "a@example.com"),
+                ],
+            )
+            .unwrap();
+        storage
+            .upsert_emails("other@example.com", "INBOX", &[make_email(4, "Other", "b@example.com")])
+            .unwrap();
 
-            let unread_after = storage
-                .list_emails("test@example.com", true, 50, 0)
-                .unwrap();
-            assert_eq!(unread_after.len(), 0);
-        }
-        let _ = std::fs::remove_file(path);
+        assert_eq!(storage.all_unread_uids("test@example.com").unwrap(), vec![1, 3]);
     }
 
     #[test]
-    fn save_and_load_filters() {
-        let path = temp_db_path("filters");
-        {
-            let storage = SqliteStorage::new_with_path(path.clone()).unwrap();
-            let patterns = vec![
-                FilterPattern {
-                    id: 0,
-                    name: "Subject contains".to_string(),
-                    pattern: "Hello".to_string(),
-                    field: FilterField::Subject,
-                    is_regex: false,
-                    enabled: true,
-                },
-                FilterPattern {
-                    id: 0,
-                    name: "Sender regex".to_string(),
-                    pattern: "example.com$".to_string(),
-                    field: FilterField::Sender,
-                    is_regex: true,
-                    enabled: false,
-                },
-            ];
+    fn count_emails_by_mailbox_orders_by_unread() {
+        let storage = SqliteStorage::new_in_memory().unwrap();
+        let mut read = make_email(2, "Read", "a@example.com");
+        read.is_read = true;
+        storage
+            .upsert_emails("test@example.com", "Archive", &[make_email(1, "Old", "a@example.com"), read])
+            .unwrap();
+        storage
+            .upsert_emails(
+                "test@example.com",
+                "INBOX",
+                &[
+                    make_email(3, "New", "a@example.com"),
+                    make_email(4, "Newer", "a@example.com"),
+                    make_email(6, "Trashed", "a@example.com"),
+                ],
+            )
+            .unwrap();
+        storage.trash_emails("test@example.com", &[6]).unwrap();
+        storage
+            .upsert_emails("other@example.com", "INBOX", &[make_email(5, "Other", "b@example.com")])
+            .unwrap();
+
+        assert_eq!(
+            storage.count_emails_by_mailbox("test@example.com").unwrap(),
+            vec![("INBOX".to_string(), 2, 2), ("Archive".to_string(), 2, 1)]
+        );
+    }
 
-            storage.save_filters(&patterns).unwrap();
-            let loaded = storage.get_filters().unwrap();
-            assert_eq!(loaded.len(), 2);
-            assert!(loaded[0].id > 0);
-            assert!(loaded[1].id > 0);
-        }
-        let _ = std::fs::remove_file(path);
+    #[test]
+    fn list_emails_page_reports_total_and_more() {
+        let storage = SqliteStorage::new_in_memory().unwrap();
+        let mut read = make_email(3, "Read", "a@example.com");
+        read.is_read = true;
+        storage
+            .upsert_emails(
+                "test@example.com",
+                "INBOX",
+                &[
+                    make_email(1, "One", "a@example.com"),
+                    make_email(2, "Two", "a@example.com"),
+                    read,
+                ],
+            )
+            .unwrap();
+
+        let page = storage
+            .list_emails_page("test@example.com", false, &EmailQuery::default(), SortBy::default(), 2, 0)
+            .unwrap();
+        assert_eq!((page.emails.len(), page.total, page.has_more), (2, 3, true));
+        let page = storage
+            .list_emails_page("test@example.com", false, &EmailQuery::default(), SortBy::default(), 2, 2)
+            .unwrap();
+        assert_eq!((page.emails.len(), page.total, page.has_more), (1, 3, false));
+        let page = storage
+            .list_emails_page("test@example.com", true, &EmailQuery::default(), SortBy::default(), 2, 0)
+            .unwrap();
+        assert_eq!((page.emails.len(), page.total, page.has_more), (2, 2, false));
     }
 
-    fn make_email(uid: u32, subject: &str, sender: &str) -> GmailEmail {
-        GmailEmail {
+    #[test]
+    fn list_emails_honours_sort_order() {
+        let storage = SqliteStorage::new_in_memory().unwrap();
+        let email = |uid, subject, sender, date_epoch| {
+            let mut email = make_email(uid, subject, sender);
+            email.date_epoch = date_epoch;
+            email
+        };
+        storage
+            .upsert_emails(
+                "test@example.com",
+                "INBOX",
+                &[
+                    email(1, "banana", "Carol <carol@example.com>", 300),
+                    email(3, "cherry", "Bob <bob@example.com>", 200),
+                    email(2, "Apple", "alice <alice@example.com>", 100),
+                ],
+            )
+            .unwrap();
+
+        let uids = |field, ascending| {
+            storage
+                .list_emails(
+                    "test@example.com",
+                    false,
+                    &EmailQuery::default(),
+                    SortBy { field, ascending },
+                    10,
+                    0,
+                )
+                .unwrap()
+                .iter()
+                .map(|e| e.uid)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(uids(SortField::Sender, true), vec![2, 3, 1]);
+        assert_eq!(uids(SortField::Sender, false), vec![1, 3, 2]);
+        assert_eq!(uids(SortField::Subject, true), vec![2, 1, 3]);
+        assert_eq!(uids(SortField::Date, true), vec![2, 3, 1]);
+        assert_eq!(uids(SortField::Date, false), vec![1, 3, 2]);
+    }
+
+    #[test]
+    fn save_and_load_filters() {
+        let storage = SqliteStorage::new_in_memory().unwrap();
+        let patterns = vec![
+            FilterPattern {
+                id: 0,
+                name: "Subject contains".to_string(),
+                pattern: "Hello".to_string(),
+                field: FilterField::Subject,
+                is_regex: false,
+                case_sensitive: false,
+                enabled: true,
+                priority: 0,
+                action: FilterAction::None,
+                action_dry_run: false,
+                match_mode: MatchMode::Substring,
+            },
+            FilterPattern {
+                id: 0,
+                name: "Sender regex".to_string(),
+                pattern: "example.com$".to_string(),
+                field: FilterField::Sender,
+                is_regex: true,
+                case_sensitive: false,
+                enabled: false,
+                priority: 0,
+                action: FilterAction::None,
+                action_dry_run: false,
+                match_mode: MatchMode::Substring,
+            },
+        ];
+
+        storage.save_filters(&patterns).unwrap();
+        let loaded = storage.get_filters().unwrap();
+        assert_eq!(loaded.len(), 2);
+        assert!(loaded[0].id > 0);
+        assert!(loaded[1].id > 0);
+    }
+
+    fn make_email(uid: u32, subject: &str, sender: &str) -> GmailEmail {
+        GmailEmail {
             uid,
             message_id: format!("msg-{}", uid),
             subject: subject.to_string(),
@@ -1616,110 +4572,1774 @@ mod tests {
             date: "2024-01-02T12:00:00Z".to_string(),
             date_epoch: 1704196800,
             is_read: false,
+            is_flagged: false,
+            is_answered: false,
+            size_bytes: None,
+            to: None,
+            cc: None,
+        }
+    }
+
+    /// An enabled, plain-substring subject filter with no action.
+    fn filter(name: &str, pattern: &str) -> FilterPattern {
+        FilterPattern {
+            id: 0,
+            name: name.to_string(),
+            pattern: pattern.to_string(),
+            field: FilterField::Subject,
+            is_regex: false,
+            case_sensitive: false,
+            enabled: true,
+            priority: 0,
+            action: FilterAction::None,
+            action_dry_run: false,
+            match_mode: MatchMode::Substring,
         }
     }
 
     #[test]
     fn filter_refresh_matches_old_and_new_emails_in_batches() {
-        let path = temp_db_path("filters-batch");
+        let storage = SqliteStorage::new_in_memory().unwrap();
+        let patterns = vec![
+            FilterPattern {
+                id: 0,
+                name: "Subject contains invoice".to_string(),
+                pattern: "invoice".to_string(),
+                field: FilterField::Subject,
+                is_regex: false,
+                case_sensitive: false,
+                enabled: true,
+                priority: 0,
+                action: FilterAction::None,
+                action_dry_run: false,
+                match_mode: MatchMode::Substring,
+            },
+            FilterPattern {
+                id: 0,
+                name: "Sender regex".to_string(),
+                pattern: "@vip\\.example\\.com$".to_string(),
+                field: FilterField::Sender,
+                is_regex: true,
+                case_sensitive: false,
+                enabled: true,
+                priority: 0,
+                action: FilterAction::None,
+                action_dry_run: false,
+                match_mode: MatchMode::Substring,
+            },
+        ];
+        let saved = storage.save_filters(&patterns).unwrap();
+        let subject_id = saved[0].id;
+        let sender_id = saved[1].id;
+
+        let account = "old-new@example.com";
+        let old_emails = vec![
+            make_email(10, "Invoice March", "billing@corp.com"),
+            make_email(11, "Hello", "ceo@vip.example.com"),
+        ];
+        storage.upsert_emails(account, "INBOX", &old_emails).unwrap();
+
+        let processed_first = storage.refresh_filtered_emails(account, 1, true).unwrap();
+        assert_eq!(processed_first, 1);
+        let processed_second = storage.refresh_filtered_emails(account, 1, false).unwrap();
+        assert_eq!(processed_second, 1);
+        let processed_third = storage.refresh_filtered_emails(account, 1, false).unwrap();
+        assert_eq!(processed_third, 0);
+
+        let counts = storage.filter_match_counts(account, false).unwrap();
+        let counts_map: HashMap<i64, u64> = counts.into_iter().collect();
+        assert_eq!(counts_map.get(&subject_id), Some(&1));
+        assert_eq!(counts_map.get(&sender_id), Some(&1));
+
+        let new_emails = vec![make_email(12, "Invoice April", "billing@corp.com")];
+        storage.upsert_emails(account, "INBOX", &new_emails).unwrap();
+
+        let processed_new = storage.refresh_filtered_emails(account, 10, false).unwrap();
+        assert_eq!(processed_new, 1);
+
+        let counts_after = storage.filter_match_counts(account, false).unwrap();
+        let counts_after_map: HashMap<i64, u64> = counts_after.into_iter().collect();
+        assert_eq!(counts_after_map.get(&subject_id), Some(&2));
+        assert_eq!(counts_after_map.get(&sender_id), Some(&1));
+    }
+
+    #[test]
+    fn filter_refresh_rewinds_when_filtered_empty_but_last_id_set() {
+        let storage = SqliteStorage::new_in_memory().unwrap();
+        let patterns = vec![FilterPattern {
+            id: 0,
+            name: "Subject contains".to_string(),
+            pattern: "Hello".to_string(),
+            field: FilterField::Subject,
+            is_regex: false,
+            case_sensitive: false,
+            enabled: true,
+            priority: 0,
+            action: FilterAction::None,
+            action_dry_run: false,
+            match_mode: MatchMode::Substring,
+        }];
+        let saved = storage.save_filters(&patterns).unwrap();
+        let filter_id = saved[0].id;
+
+        let account = "rematch@example.com";
+        let emails = vec![
+            make_email(20, "Hello World", "alice@example.com"),
+            make_email(21, "Hello Again", "bob@example.com"),
+        ];
+        storage.upsert_emails(account, "INBOX", &emails).unwrap();
+
+        {
+            let conn = storage.conn.lock().unwrap();
+            set_filter_last_email_id(&conn, account, 999).unwrap();
+        }
+
+        let processed = storage.refresh_filtered_emails(account, 50, false).unwrap();
+        assert_eq!(processed, 2);
+
+        let counts = storage.filter_match_counts(account, false).unwrap();
+        let counts_map: HashMap<i64, u64> = counts.into_iter().collect();
+        assert_eq!(counts_map.get(&filter_id), Some(&2));
+
+        let last_id = {
+            let conn = storage.conn.lock().unwrap();
+            get_filter_last_email_id(&conn, account).unwrap()
+        };
+        assert_eq!(last_id, 2);
+    }
+
+    #[test]
+    fn case_sensitive_filter_skips_lowercase_matching() {
+        let storage = SqliteStorage::new_in_memory().unwrap();
+        let patterns = vec![
+            FilterPattern {
+                id: 0,
+                name: "Urgent exact".to_string(),
+                pattern: "URGENT".to_string(),
+                field: FilterField::Subject,
+                is_regex: false,
+                case_sensitive: true,
+                enabled: true,
+                priority: 0,
+                action: FilterAction::None,
+                action_dry_run: false,
+                match_mode: MatchMode::Substring,
+            },
+            FilterPattern {
+                id: 0,
+                name: "Urgent regex".to_string(),
+                pattern: "^URGENT".to_string(),
+                field: FilterField::Subject,
+                is_regex: true,
+                case_sensitive: true,
+                enabled: true,
+                priority: 0,
+                action: FilterAction::None,
+                action_dry_run: false,
+                match_mode: MatchMode::Substring,
+            },
+        ];
+        let saved = storage.save_filters(&patterns).unwrap();
+        assert!(saved[0].case_sensitive);
+
+        let account = "case@example.com";
+        let emails = vec![
+            make_email(30, "URGENT: server down", "ops@example.com"),
+            make_email(31, "Please reply urgently", "boss@example.com"),
+        ];
+        storage.upsert_emails(account, "INBOX", &emails).unwrap();
+        storage.refresh_filtered_emails(account, 50, true).unwrap();
+
+        let counts = storage.filter_match_counts(account, false).unwrap();
+        let counts_map: HashMap<i64, u64> = counts.into_iter().collect();
+        assert_eq!(counts_map.get(&saved[0].id), Some(&1));
+        assert_eq!(counts_map.get(&saved[1].id), Some(&1));
+    }
+
+    #[test]
+    fn preview_filter_matches_without_storing() {
+        let storage = SqliteStorage::new_in_memory().unwrap();
+        let account = "preview@example.com";
+        let emails = vec![
+            make_email(40, "Weekly newsletter", "news@example.com"),
+            make_email(41, "Lunch?", "friend@example.com"),
+            make_email(42, "Newsletter #2", "news@example.com"),
+        ];
+        storage.upsert_emails(account, "INBOX", &emails).unwrap();
+
+        let filter = FilterPattern {
+            id: 0,
+            name: String::new(),
+            pattern: "newsletter".to_string(),
+            field: FilterField::Subject,
+            is_regex: false,
+            case_sensitive: false,
+            enabled: true,
+            priority: 0,
+            action: FilterAction::None,
+            action_dry_run: false,
+            match_mode: MatchMode::Substring,
+        };
+        let preview = storage.preview_filter(account, &filter, 10).unwrap();
+        assert_eq!(preview.len(), 2);
+        let limited = storage.preview_filter(account, &filter, 1).unwrap();
+        assert_eq!(limited.len(), 1);
+
+        let filtered_rows: u64 = {
+            let conn = storage.conn.lock().unwrap();
+            conn.query_row("SELECT COUNT(*) FROM filtered_emails", [], |row| row.get(0))
+                .unwrap()
+        };
+        assert_eq!(filtered_rows, 0);
+
+        let invalid = FilterPattern {
+            pattern: "(".to_string(),
+            is_regex: true,
+            ..filter
+        };
+        assert!(storage.preview_filter(account, &invalid, 10).is_err());
+    }
+
+    #[test]
+    fn date_range_excludes_unparsed_dates() {
+        let storage = SqliteStorage::new_in_memory().unwrap();
+        let account = "range@example.com";
+        let mut old = make_email(50, "Old", "a@example.com");
+        old.date_epoch = 1_600_000_000;
+        let mut recent = make_email(51, "Recent", "b@example.com");
+        recent.date_epoch = 1_700_000_000;
+        let mut undated = make_email(52, "Undated", "c@example.com");
+        undated.date = "garbage".to_string();
+        undated.date_epoch = 0;
+        storage
+            .upsert_emails(account, "INBOX", &[old, recent, undated])
+            .unwrap();
+
+        let older_than = EmailQuery {
+            after_epoch: None,
+            before_epoch: Some(1_650_000_000),
+            include_snoozed: false,
+            answered_only: None,
+            gmail_category: None,
+        };
+        let rows = storage
+            .list_emails(account, false, &older_than, SortBy::default(), 50, 0)
+            .unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].uid, 50);
+        assert_eq!(storage.count_emails(account, false, &older_than).unwrap(), 1);
+        assert_eq!(
+            storage
+                .count_emails(account, false, &EmailQuery::default())
+                .unwrap(),
+            3
+        );
+    }
+
+    #[test]
+    fn sender_stats_collapse_display_names() {
+        let storage = SqliteStorage::new_in_memory().unwrap();
+        let account = "stats@example.com";
+        let mut read = make_email(60, "Sale", "a@x.com");
+        read.is_read = true;
+        let emails = vec![
+            make_email(61, "Sale", "Alice <A@X.com>"),
+            read,
+            make_email(62, "Hi", "bob@y.com"),
+            make_email(63, "Old sale", "a@x.com"),
+        ];
+        storage.upsert_emails(account, "INBOX", &emails).unwrap();
+        storage.trash_emails(account, &[63]).unwrap();
+
+        let stats = storage.sender_stats(account, false, 10).unwrap();
+        assert_eq!(stats.len(), 2);
+        assert_eq!(stats[0].sender, "a@x.com");
+        assert_eq!(stats[0].total, 2);
+        assert_eq!(stats[0].unread, 1);
+
+        assert_eq!(storage.uids_for_sender(account, "A@x.com", false).unwrap(), vec![60, 61]);
+        assert_eq!(storage.uids_for_sender(account, "Alice <a@X.COM>", true).unwrap(), vec![61]);
+    }
+
+    #[test]
+    fn reads_do_not_wait_for_writer_lock() {
+        let path = temp_db_path("read-pool");
         {
             let storage = SqliteStorage::new_with_path(path.clone()).unwrap();
-            let patterns = vec![
-                FilterPattern {
-                    id: 0,
-                    name: "Subject contains invoice".to_string(),
-                    pattern: "invoice".to_string(),
-                    field: FilterField::Subject,
-                    is_regex: false,
-                    enabled: true,
-                },
-                FilterPattern {
-                    id: 0,
-                    name: "Sender regex".to_string(),
-                    pattern: "@vip\\.example\\.com$".to_string(),
-                    field: FilterField::Sender,
-                    is_regex: true,
-                    enabled: true,
-                },
-            ];
-            let saved = storage.save_filters(&patterns).unwrap();
-            let subject_id = saved[0].id;
-            let sender_id = saved[1].id;
+            let account = "pool@example.com";
+            storage
+                .upsert_emails(account, "INBOX", &[make_email(70, "Hello", "a@example.com")])
+                .unwrap();
+
+            let _writer = storage.conn.lock().unwrap();
+            let rows = storage
+                .list_emails(account, false, &EmailQuery::default(), SortBy::default(), 10, 0)
+                .unwrap();
+            assert_eq!(rows.len(), 1);
+        }
+        remove_test_db(&path);
+    }
+
+    #[test]
+    fn compact_reports_on_disk_shrinkage_and_empties_the_wal() {
+        let path = temp_db_path("compact");
+        {
+            let storage = SqliteStorage::new_with_path(path.clone()).unwrap();
+            let emails: Vec<GmailEmail> = (1..=2000)
+                .map(|uid| make_email(uid, &"padding ".repeat(40), "a@example.com"))
+                .collect();
+            storage.upsert_emails("a@example.com", "INBOX", &emails).unwrap();
+            storage.checkpoint().unwrap();
+            storage.delete_account_emails("a@example.com").unwrap();
+
+            let before = std::fs::metadata(&path).unwrap().len()
+                + std::fs::metadata(with_suffix(&path, "-wal")).map_or(0, |m| m.len());
+            let reclaimed = storage.compact().unwrap();
+            let after = std::fs::metadata(&path).unwrap().len();
+            assert!(reclaimed > 0);
+            assert_eq!(reclaimed, before - after);
+            assert_eq!(std::fs::metadata(with_suffix(&path, "-wal")).map_or(0, |m| m.len()), 0);
+        }
+        remove_test_db(&path);
+    }
+
+    #[test]
+    fn truncated_database_is_moved_aside_and_recreated() {
+        let path = temp_db_path("corrupt");
+        {
+            let storage = SqliteStorage::new_with_path(path.clone()).unwrap();
+            storage
+                .upsert_emails("a@example.com", "INBOX", &[make_email(1, "Hello", "a@example.com")])
+                .unwrap();
+            assert!(storage.recovered_from().is_none());
+        }
+        let len = std::fs::metadata(&path).unwrap().len();
+        std::fs::OpenOptions::new()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_len(len / 2)
+            .unwrap();
+        std::fs::write(with_suffix(&path, "-wal"), b"stale wal").unwrap();
+
+        let storage = SqliteStorage::new_with_path(path.clone()).unwrap();
+        let moved = storage.recovered_from().unwrap().to_path_buf();
+        assert!(moved.exists());
+        assert!(with_suffix(&moved, "-wal").exists());
+        assert_ne!(
+            std::fs::read(with_suffix(&path, "-wal")).ok().as_deref(),
+            Some(&b"stale wal"[..])
+        );
+        assert!(moved.file_name().unwrap().to_str().unwrap().contains(".corrupt-"));
+        assert_eq!(
+            storage.count_emails("a@example.com", false, &EmailQuery::default()).unwrap(),
+            0
+        );
+        storage
+            .upsert_emails("a@example.com", "INBOX", &[make_email(2, "Again", "a@example.com")])
+            .unwrap();
+        drop(storage);
+
+        assert!(SqliteStorage::new_with_path(path.clone()).unwrap().recovered_from().is_none());
+        remove_test_db(&path);
+        remove_test_db(&moved);
+    }
+
+    #[test]
+    fn stores_unsubscribe_headers_and_attachments_with_body() {
+        let storage = SqliteStorage::new_in_memory().unwrap();
+        let account = "unsub@example.com";
+        storage
+            .upsert_emails(account, "INBOX", &[make_email(80, "Deals", "shop@example.com")])
+            .unwrap();
+        assert_eq!(storage.uids_without_body(account, 10).unwrap(), vec![80]);
+        let body = crate::gmail::EmailBody {
+            html: None,
+            html_raw: None,
+            text: Some("hi".to_string()),
+            unsubscribe_url: Some("https://example.com/u".to_string()),
+            unsubscribe_mailto: Some("mailto:u@example.com".to_string()),
+            unsubscribe_oneclick: true,
+            attachments: vec![crate::gmail::AttachmentMeta {
+                filename: Some("coupon.pdf".to_string()),
+                content_type: "application/pdf".to_string(),
+                size: 2048,
+            }],
+            in_reply_to: None,
+            references: Vec::new(),
+            snippet: Some("hi".to_string()),
+            headers: vec![("From".to_string(), "shop@example.com".to_string())],
+        };
+        storage
+            .set_email_bodies(account, &[crate::gmail::GmailEmailBody { uid: 80, body }])
+            .unwrap();
+        assert!(storage.uids_without_body(account, 10).unwrap().is_empty());
+
+        let info = storage.get_unsubscribe_info(account, 80).unwrap().unwrap();
+        assert_eq!(info.unsubscribe_url.as_deref(), Some("https://example.com/u"));
+        assert!(info.unsubscribe_oneclick);
+        let rows = storage
+            .list_emails(account, false, &EmailQuery::default(), SortBy::default(), 10, 0)
+            .unwrap();
+        assert_eq!(rows[0].unsubscribe_mailto.as_deref(), Some("mailto:u@example.com"));
+        assert!(rows[0].has_attachments);
+        assert_eq!(rows[0].snippet.as_deref(), Some("hi"));
+        let cached = storage.get_email_body(account, 80).unwrap().unwrap();
+        assert_eq!(cached.headers[0].1, "shop@example.com");
+        let attachments = storage.attachments_for(account, 80).unwrap();
+        assert_eq!(attachments.len(), 1);
+        assert_eq!(attachments[0].size, 2048);
+        assert!(storage.get_unsubscribe_info(account, 81).unwrap().is_none());
+    }
+
+    #[test]
+    fn purge_keeps_unread_flagged_and_recent_mail() {
+        let storage = SqliteStorage::new_in_memory().unwrap();
+        let account = "purge@example.com";
+        let mut emails: Vec<GmailEmail> = (1..=5)
+            .map(|uid| {
+                let mut email = make_email(uid, "Old", "a@example.com");
+                email.is_read = true;
+                email.date_epoch = 1_000_000;
+                email
+            })
+            .collect();
+        emails[1].is_read = false;
+        emails[2].is_flagged = true;
+        emails[3].date_epoch = 3_000_000;
+        storage.upsert_emails(account, "INBOX", &emails).unwrap();
+        storage.trash_emails(account, &[5]).unwrap();
+
+        assert_eq!(storage.purge_read_older_than(account, 2_000_000).unwrap(), 1);
+        let mut left: Vec<u32> = storage
+            .list_emails(account, false, &EmailQuery::default(), SortBy::default(), 10, 0)
+            .unwrap()
+            .iter()
+            .map(|e| e.uid)
+            .collect();
+        left.sort_unstable();
+        assert_eq!(left, vec![2, 3, 4]);
+        assert_eq!(storage.trashed_uids(account).unwrap(), vec![5]);
+    }
+
+    #[test]
+    fn stale_bodies_are_resanitized_from_raw_html() {
+        let storage = SqliteStorage::new_in_memory().unwrap();
+        let account = "bodies@example.com";
+        storage
+            .upsert_emails(account, "INBOX", &[make_email(1, "Html", "a@example.com")])
+            .unwrap();
+        let body = crate::gmail::EmailBody {
+            html: Some("<p>hi</p>".to_string()),
+            html_raw: Some("<p>hi</p><script>alert(1)</script>".to_string()),
+            text: None,
+            unsubscribe_url: None,
+            unsubscribe_mailto: None,
+            unsubscribe_oneclick: false,
+            attachments: Vec::new(),
+            in_reply_to: None,
+            references: Vec::new(),
+            snippet: None,
+            headers: Vec::new(),
+        };
+        storage
+            .set_email_bodies(account, &[crate::gmail::GmailEmailBody { uid: 1, body }])
+            .unwrap();
+        let current = crate::sanitize::SANITIZER_VERSION;
+        assert!(storage.stale_body_uids(account, current).unwrap().is_empty());
+        assert_eq!(storage.stale_body_uids(account, current + 1).unwrap(), vec![1]);
+
+        // Simulate a body cached by an older sanitizer.
+        storage
+            .conn
+            .lock()
+            .unwrap()
+            .execute(
+                "UPDATE emails SET body_html_safe = 'stale', body_version = 0 WHERE uid = 1",
+                [],
+            )
+            .unwrap();
+        assert_eq!(storage.stale_body_uids(account, current).unwrap(), vec![1]);
+        assert_eq!(storage.resanitize_bodies(account, &[1, 2]).unwrap(), 1);
+        assert!(storage.stale_body_uids(account, current).unwrap().is_empty());
+        let html = storage.get_email_body(account, 1).unwrap().unwrap().html.unwrap();
+        assert!(html.contains("hi") && !html.contains("script") && html != "stale");
+    }
+
+    #[test]
+    fn list_threads_groups_replies() {
+        let storage = SqliteStorage::new_in_memory().unwrap();
+        let account = "threads@example.com";
+        let mut first = make_email(90, "Plans", "a@example.com");
+        first.message_id = "<plans@x>".to_string();
+        first.date_epoch = 100;
+        let mut reply = make_email(91, "Re: Plans", "b@example.com");
+        reply.date_epoch = 200;
+        let mut other = make_email(92, "Invoice", "c@example.com");
+        other.date_epoch = 150;
+        storage
+            .upsert_emails(account, "INBOX", &[first, reply, other])
+            .unwrap();
+
+        let body = crate::gmail::EmailBody {
+            html: None,
+            html_raw: None,
+            text: Some("sounds good".to_string()),
+            unsubscribe_url: None,
+            unsubscribe_mailto: None,
+            unsubscribe_oneclick: false,
+            attachments: Vec::new(),
+            in_reply_to: Some("<plans@x>".to_string()),
+            references: vec!["<plans@x>".to_string()],
+            snippet: None,
+            headers: Vec::new(),
+        };
+        storage
+            .set_email_bodies(account, &[crate::gmail::GmailEmailBody { uid: 91, body: body.clone() }])
+            .unwrap();
+        // Not grouped until the deferred pass runs.
+        assert_eq!(storage.list_threads(account, 10, 0).unwrap().len(), 3);
+        storage.assign_threads(account).unwrap();
+
+        let threads = storage.list_threads(account, 10, 0).unwrap();
+        assert_eq!(threads.len(), 2);
+        assert_eq!(threads[0].subject, "Re: Plans");
+        assert_eq!(threads[0].count, 2);
+        assert_eq!(threads[0].unread, 2);
+        assert_eq!(threads[0].latest_epoch, 200);
+        assert_eq!(threads[1].count, 1);
+
+        // Refetching a body with the same headers keeps the grouping.
+        storage
+            .set_email_bodies(account, &[crate::gmail::GmailEmailBody { uid: 91, body }])
+            .unwrap();
+        let unthreaded: i64 = storage
+            .reader()
+            .unwrap()
+            .query_row("SELECT COUNT(*) FROM emails WHERE thread_id IS NULL", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(unthreaded, 0);
+    }
+
+    #[test]
+    fn highest_priority_filter_wins_and_reorder_keeps_matches() {
+        let storage = SqliteStorage::new_in_memory().unwrap();
+        let saved = storage
+            .save_filters(&[
+                FilterPattern { field: FilterField::Any, priority: 1, ..filter("Shop", "shop") },
+                FilterPattern { field: FilterField::Any, ..filter("Deals", "deal") },
+            ])
+            .unwrap();
+        assert_eq!(saved[0].name, "Deals");
+        let (deals_id, shop_id) = (saved[0].id, saved[1].id);
+
+        let account = "priority@example.com";
+        storage
+            .upsert_emails(account, "INBOX", &[make_email(95, "Big deal", "shop@example.com")])
+            .unwrap();
+        storage.refresh_filtered_emails(account, 50, true).unwrap();
+        assert_eq!(storage.primary_filter_for_emails(account).unwrap(), vec![(95, deals_id)]);
+
+        let mut reordered = saved.clone();
+        reordered[0].priority = 1;
+        reordered[1].priority = 0;
+        storage.save_filters(&reordered).unwrap();
+        assert_eq!(storage.primary_filter_for_emails(account).unwrap(), vec![(95, shop_id)]);
+        assert_eq!(storage.filter_match_counts(account, false).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn filter_actions_only_for_unread_new_matches() {
+        let storage = SqliteStorage::new_in_memory().unwrap();
+        let newsletter = FilterPattern {
+            id: 0,
+            name: "Newsletters".to_string(),
+            pattern: "newsletter".to_string(),
+            field: FilterField::Subject,
+            is_regex: false,
+            case_sensitive: false,
+            enabled: true,
+            priority: 0,
+            action: FilterAction::MarkRead,
+            action_dry_run: true,
+            match_mode: MatchMode::Substring,
+        };
+        let saved = storage.save_filters(&[newsletter]).unwrap();
+        assert_eq!(saved[0].action, FilterAction::MarkRead);
+        assert!(saved[0].action_dry_run);
+
+        let account = "actions@example.com";
+        let mut already_read = make_email(97, "Newsletter #2", "news@example.com");
+        already_read.is_read = true;
+        storage
+            .upsert_emails(
+                account,
+                "INBOX",
+                &[make_email(96, "Newsletter #1", "news@example.com"), already_read],
+            )
+            .unwrap();
+        storage.refresh_filtered_emails(account, 50, false).unwrap();
+
+        let planned = storage.filter_actions_for_uids(account, &[96, 97]).unwrap();
+        assert_eq!(planned.len(), 1);
+        assert_eq!(planned[0].uid, 96);
+        assert!(planned[0].dry_run);
+
+        assert_eq!(storage.remove_emails(account, &[96]).unwrap(), 1);
+        assert!(storage.filter_actions_for_uids(account, &[96]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn export_emails_as_csv_and_filtered_json() {
+        let storage = SqliteStorage::new_in_memory().unwrap();
+        let saved = storage
+            .save_filters(&[filter("Invoices", "invoice")])
+            .unwrap();
+
+        let account = "export@example.com";
+        storage
+            .upsert_emails(
+                account,
+                "INBOX",
+                &[
+                    make_email(1, "Invoice, March", "billing@example.com"),
+                    make_email(2, "Hello", "friend@example.com"),
+                ],
+            )
+            .unwrap();
+        storage.refresh_filtered_emails(account, 50, false).unwrap();
+
+        let csv = storage.export_emails(account, &[], ExportFormat::Csv).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines[0], "uid,subject,sender,date,is_read");
+        assert_eq!(lines.len(), 3);
+        assert!(lines[1].starts_with("1,\"Invoice, March\",billing@example.com,"));
+
+        let json = storage
+            .export_emails(account, &[saved[0].id], ExportFormat::Json)
+            .unwrap();
+        let exported: Vec<StoredEmail> = serde_json::from_str(&json).unwrap();
+        assert_eq!(exported.len(), 1);
+        assert_eq!(exported[0].uid, 1);
+    }
+
+    #[test]
+    fn import_filters_merges_and_rejects_bad_regex() {
+        let storage = SqliteStorage::new_in_memory().unwrap();
+        storage.save_filters(&[filter("Receipts", "receipt")]).unwrap();
+        let exported = storage.export_filters().unwrap();
+
+        let incoming = serde_json::to_string(&FilterConfig {
+            patterns: vec![
+                filter("Receipts", "receipt"),
+                FilterPattern { is_regex: true, ..filter("Alerts", "^alert") },
+            ],
+        })
+        .unwrap();
+        let merged = storage.import_filters(&incoming, ImportMode::Merge).unwrap();
+        assert_eq!(merged.len(), 2);
+        assert_eq!(merged[1].name, "Alerts");
+        assert_eq!(merged[1].priority, 1);
+
+        let invalid = serde_json::to_string(&FilterConfig {
+            patterns: vec![FilterPattern { is_regex: true, ..filter("Broken", "(unclosed") }],
+        })
+        .unwrap();
+        assert!(storage.import_filters(&invalid, ImportMode::Replace).is_err());
+        assert_eq!(storage.get_filters().unwrap().len(), 2);
+
+        let replaced = storage.import_filters(&exported, ImportMode::Replace).unwrap();
+        assert_eq!(replaced.len(), 1);
+        assert_eq!(replaced[0].name, "Receipts");
+    }
+
+    #[test]
+    fn whole_word_filter_skips_substring_hits() {
+        let storage = SqliteStorage::new_in_memory().unwrap();
+        let account = "words@example.com";
+        storage
+            .upsert_emails(
+                account,
+                "INBOX",
+                &[
+                    make_email(1, "HR update", "people@example.com"),
+                    make_email(2, "Paper shredding day", "office@example.com"),
+                ],
+            )
+            .unwrap();
+
+        let mut filter = filter("HR", "hr");
+        assert_eq!(storage.preview_filter(account, &filter, 10).unwrap().len(), 2);
+
+        filter.match_mode = MatchMode::WholeWord;
+        let preview = storage.preview_filter(account, &filter, 10).unwrap();
+        assert_eq!(preview.len(), 1);
+        assert_eq!(preview[0].uid, 1);
+
+        let saved = storage.save_filters(&[filter]).unwrap();
+        assert_eq!(saved[0].match_mode, MatchMode::WholeWord);
+        storage.refresh_filtered_emails(account, 50, false).unwrap();
+        assert_eq!(storage.count_filtered_emails(account, &[saved[0].id], false).unwrap(), 1);
+    }
+
+    #[test]
+    fn recipient_filter_matches_any_to_or_cc_address() {
+        let storage = SqliteStorage::new_in_memory().unwrap();
+        let account = "me@gmail.com";
+        let mut shopping = make_email(1, "Your order shipped", "orders@shop.example");
+        shopping.to = Some("Me <me@gmail.com>, Partner <partner@example.com>".to_string());
+        shopping.cc = Some("me+shopping@gmail.com".to_string());
+        let mut direct = make_email(2, "Dinner", "friend@example.com");
+        direct.to = Some("me@gmail.com".to_string());
+        let unknown = make_email(3, "No headers yet", "someone@example.com");
+        storage
+            .upsert_emails(account, "INBOX", &[shopping, direct, unknown])
+            .unwrap();
+
+        let mut filter = FilterPattern {
+            id: 0,
+            name: "Shopping alias".to_string(),
+            pattern: "ME+SHOPPING@".to_string(),
+            field: FilterField::Recipient,
+            is_regex: false,
+            case_sensitive: false,
+            enabled: true,
+            priority: 0,
+            action: FilterAction::None,
+            action_dry_run: false,
+            match_mode: MatchMode::Substring,
+        };
+        let preview = storage.preview_filter(account, &filter, 10).unwrap();
+        assert_eq!(preview.iter().map(|e| e.uid).collect::<Vec<_>>(), vec![1]);
+
+        // Anchors apply to each recipient, not the joined list.
+        filter.pattern = "^me\\+shopping@gmail\\.com$".to_string();
+        filter.is_regex = true;
+        let preview = storage.preview_filter(account, &filter, 10).unwrap();
+        assert_eq!(preview.iter().map(|e| e.uid).collect::<Vec<_>>(), vec![1]);
+
+        let saved = storage.save_filters(&[filter]).unwrap();
+        assert!(matches!(saved[0].field, FilterField::Recipient));
+        assert_eq!(storage.count_filtered_emails(account, &[saved[0].id], false).unwrap(), 1);
+        let preview = storage.preview_filter_refresh(account).unwrap();
+        assert_eq!((preview.would_add, preview.would_remove), (0, 0));
+        storage.refresh_filtered_emails(account, 50, true).unwrap();
+        assert_eq!(storage.count_filtered_emails(account, &[saved[0].id], false).unwrap(), 1);
+    }
+
+    #[test]
+    fn bulk_estimate_skips_emails_already_in_state() {
+        let storage = SqliteStorage::new_in_memory().unwrap();
+        let account = "estimate@example.com";
+        let mut read = make_email(2, "Sale ends today", "deals@shop.example");
+        read.is_read = true;
+        storage
+            .upsert_emails(
+                account,
+                "INBOX",
+                &[
+                    make_email(1, "Big sale", "deals@shop.example"),
+                    read,
+                    make_email(3, "Sale preview", "deals@shop.example"),
+                    make_email(4, "Lunch?", "friend@example.com"),
+                ],
+            )
+            .unwrap();
+        storage
+            .upsert_emails(account, "[Gmail]/All Mail", &[make_email(5, "Old sale", "deals@shop.example")])
+            .unwrap();
+
+        let saved = storage
+            .save_filters(&[
+                filter("Sales", "sale"),
+                FilterPattern { field: FilterField::Sender, ..filter("Shop", "shop.example") },
+            ])
+            .unwrap();
+        let ids: Vec<i64> = saved.iter().map(|f| f.id).collect();
+        storage.trash_emails(account, &[3]).unwrap();
+
+        // Both filters match the same emails; each is counted once.
+        let estimate = |action| storage.estimate_bulk_action(account, &ids, action).unwrap();
+        assert_eq!(
+            estimate(BulkAction::MarkRead),
+            BulkEstimate { affected: 2, already_in_state: 1 }
+        );
+        assert_eq!(
+            estimate(BulkAction::Archive),
+            BulkEstimate { affected: 2, already_in_state: 1 }
+        );
+        assert_eq!(
+            estimate(BulkAction::Trash),
+            BulkEstimate { affected: 3, already_in_state: 1 }
+        );
+        assert_eq!(
+            storage.estimate_bulk_action(account, &[], BulkAction::Trash).unwrap(),
+            BulkEstimate { affected: 0, already_in_state: 0 }
+        );
+    }
+
+    #[test]
+    fn unfiltered_lists_emails_no_enabled_filter_matches() {
+        let storage = SqliteStorage::new_in_memory().unwrap();
+        let account = "rest@example.com";
+        let mut hello = make_email(3, "Hello", "friend@example.com");
+        hello.is_read = true;
+        hello.date_epoch += 60;
+        storage
+            .upsert_emails(
+                account,
+                "INBOX",
+                &[
+                    make_email(1, "Invoice and receipt", "billing@corp.com"),
+                    make_email(2, "Lunch?", "friend@example.com"),
+                    hello,
+                    make_email(4, "Old thread", "friend@example.com"),
+                ],
+            )
+            .unwrap();
+        storage
+            .upsert_emails("other@example.com", "INBOX", &[make_email(9, "Hi", "a@b.com")])
+            .unwrap();
+        storage.trash_emails(account, &[4]).unwrap();
+
+        // Email 1 matches two filters; email 2 only a disabled one.
+        storage
+            .save_filters(&[
+                filter("Invoices", "invoice"),
+                filter("Receipts", "receipt"),
+                FilterPattern { enabled: false, ..filter("Lunch", "lunch") },
+            ])
+            .unwrap();
+        storage.refresh_filtered_emails(account, 50, true).unwrap();
+
+        let uids = |unread_only| {
+            storage
+                .list_unfiltered_emails(account, unread_only, 10, 0)
+                .unwrap()
+                .iter()
+                .map(|e| e.uid)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(uids(false), vec![3, 2]);
+        assert_eq!(uids(true), vec![2]);
+        assert_eq!(storage.count_unfiltered_emails(account, false).unwrap(), 2);
+        assert_eq!(storage.count_unfiltered_emails(account, true).unwrap(), 1);
+        assert_eq!(storage.list_unfiltered_emails(account, false, 1, 1).unwrap()[0].uid, 2);
+
+        let conn = storage.reader().unwrap();
+        let mut stmt = conn
+            .prepare(&format!(
+                "EXPLAIN QUERY PLAN SELECT e.uid FROM emails e {} \
+                 WHERE e.account = 'a' AND e.is_trashed = 0 AND fe.email_id IS NULL \
+                 ORDER BY e.date_epoch DESC, e.uid DESC LIMIT 50",
+                UNFILTERED_JOIN
+            ))
+            .unwrap();
+        let plan: Vec<String> = stmt
+            .query_map([], |row| row.get(3))
+            .unwrap()
+            .map(|row| row.unwrap())
+            .collect();
+        let plan = plan.join("; ");
+        assert!(plan.contains("idx_emails_account_date_epoch"), "{}", plan);
+        assert!(!plan.contains("SCAN fe"), "{}", plan);
+    }
 
-            let account = "old-new@example.com";
-            let old_emails = vec![
-                make_email(10, "Invoice March", "billing@corp.com"),
-                make_email(11, "Hello", "ceo@vip.example.com"),
-            ];
-            storage.upsert_emails(account, "INBOX", &old_emails).unwrap();
+    #[test]
+    fn counts_and_stats_skip_trashed_emails() {
+        let storage = SqliteStorage::new_in_memory().unwrap();
+        let account = "trash-counts@example.com";
+        storage
+            .upsert_emails(
+                account,
+                "INBOX",
+                &[
+                    make_email(1, "Sale", "deals@shop.example"),
+                    make_email(2, "Another sale", "deals@shop.example"),
+                ],
+            )
+            .unwrap();
+        let saved = storage
+            .save_filters(&[filter("Sales", "sale")])
+            .unwrap();
+        storage.refresh_filtered_emails(account, 50, true).unwrap();
+        storage.trash_emails(account, &[2]).unwrap();
+
+        let counts: HashMap<i64, u64> =
+            storage.filter_match_counts(account, false).unwrap().into_iter().collect();
+        assert_eq!(counts.get(&saved[0].id), Some(&1));
+        assert_eq!(
+            counts.get(&saved[0].id).copied(),
+            Some(storage.count_filtered_emails(account, &[saved[0].id], false).unwrap())
+        );
+        let stats = storage.sender_stats(account, false, 10).unwrap();
+        assert_eq!((stats[0].total, stats[0].unread), (1, 1));
+        assert_eq!(storage.list_threads(account, 10, 0).unwrap().len(), 1);
+        assert_eq!(storage.all_unread_uids(account).unwrap(), vec![1]);
+    }
 
-            let processed_first = storage.refresh_filtered_emails(account, 1, true).unwrap();
-            assert_eq!(processed_first, 1);
-            let processed_second = storage.refresh_filtered_emails(account, 1, false).unwrap();
-            assert_eq!(processed_second, 1);
-            let processed_third = storage.refresh_filtered_emails(account, 1, false).unwrap();
-            assert_eq!(processed_third, 0);
+    #[test]
+    fn overlap_counts_emails_matched_by_several_selected_filters() {
+        let storage = SqliteStorage::new_in_memory().unwrap();
+        let account = "overlap@example.com";
+        let mut both = make_email(1, "Invoice from shop", "billing@shop.com");
+        both.is_read = true;
+        storage
+            .upsert_emails(
+                account,
+                "INBOX",
+                &[
+                    both,
+                    make_email(2, "Invoice from shop", "billing@shop.com"),
+                    make_email(3, "Newsletter", "news@shop.com"),
+                    make_email(4, "Invoice", "someone@else.com"),
+                ],
+            )
+            .unwrap();
+
+        let saved = storage
+            .save_filters(&[
+                filter("Invoices", "invoice"),
+                FilterPattern { field: FilterField::Sender, ..filter("Shop", "shop.com") },
+            ])
+            .unwrap();
+        storage.refresh_filtered_emails(account, 50, false).unwrap();
+        let ids = [saved[0].id, saved[1].id];
+
+        let per_filter: u64 = storage
+            .filter_match_counts(account, false)
+            .unwrap()
+            .iter()
+            .map(|(_, count)| count)
+            .sum();
+        assert_eq!(per_filter, 6);
+        assert_eq!(storage.count_filtered_emails(account, &ids, false).unwrap(), 4);
+        assert_eq!(storage.count_filtered_emails_overlap(account, &ids, false).unwrap(), 2);
+        assert_eq!(storage.count_filtered_emails_overlap(account, &ids, true).unwrap(), 1);
+        assert_eq!(storage.count_filtered_emails_overlap(account, &ids[..1], false).unwrap(), 0);
+    }
+
+    #[test]
+    fn trashed_emails_are_hidden_until_untrashed_or_purged() {
+        let storage = SqliteStorage::new_in_memory().unwrap();
+        let account = "trash@example.com";
+        storage
+            .upsert_emails(
+                account,
+                "INBOX",
+                &[make_email(1, "Keep", "a@example.com"), make_email(2, "Bin", "b@example.com")],
+            )
+            .unwrap();
+        let query = EmailQuery::default();
+
+        assert_eq!(storage.trash_emails(account, &[1, 2]).unwrap(), 2);
+        assert_eq!(storage.count_emails(account, false, &query).unwrap(), 0);
+        assert_eq!(storage.untrash_emails(account, &[1]).unwrap(), 1);
+        let listed = storage
+            .list_emails(account, false, &query, SortBy::default(), 10, 0)
+            .unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].uid, 1);
+
+        assert_eq!(storage.trashed_uids(account).unwrap(), vec![2]);
+        assert_eq!(storage.purge_trashed(account, &[1, 2]).unwrap(), 1);
+        assert!(storage.trashed_uids(account).unwrap().is_empty());
+        assert_eq!(storage.count_emails(account, false, &query).unwrap(), 1);
+    }
+
+    #[test]
+    fn multi_account_listing_pages_by_date_then_uid() {
+        let storage = SqliteStorage::new_in_memory().unwrap();
+        let mut work = make_email(5, "Standup", "boss@example.com");
+        work.date_epoch = 200;
+        let mut home = make_email(5, "Dinner", "mom@example.com");
+        home.date_epoch = 200;
+        let mut older = make_email(9, "Receipt", "shop@example.com");
+        older.date_epoch = 100;
+        storage.upsert_emails("work@example.com", "INBOX", &[work]).unwrap();
+        storage.upsert_emails("home@example.com", "INBOX", &[home, older]).unwrap();
+        storage.upsert_emails("other@example.com", "INBOX", &[make_email(1, "x", "y")]).unwrap();
+
+        let accounts = vec!["work@example.com".to_string(), "home@example.com".to_string()];
+        assert_eq!(storage.count_emails_multi(&accounts, false).unwrap(), 3);
+
+        let first = storage.list_emails_multi(&accounts, false, 2, 0).unwrap();
+        let second = storage.list_emails_multi(&accounts, false, 2, 2).unwrap();
+        let order: Vec<(&str, u32)> = first
+            .iter()
+            .chain(second.iter())
+            .map(|email| (email.account.as_str(), email.uid))
+            .collect();
+        assert_eq!(
+            order,
+            vec![("home@example.com", 5), ("work@example.com", 5), ("home@example.com", 9)]
+        );
+    }
+
+    #[test]
+    fn multi_account_listing_hides_snoozed_emails() {
+        let storage = SqliteStorage::new_in_memory().unwrap();
+        storage
+            .upsert_emails(
+                "work@example.com",
+                "INBOX",
+                &[make_email(1, "Standup", "boss@example.com"), make_email(2, "Later", "boss@example.com")],
+            )
+            .unwrap();
+        storage
+            .upsert_emails("home@example.com", "INBOX", &[make_email(3, "Dinner", "mom@example.com")])
+            .unwrap();
+        let far_future = chrono::Utc::now().timestamp() + 86_400;
+        storage.snooze_emails("work@example.com", &[2], far_future).unwrap();
+        // An elapsed snooze shows again.
+        storage.snooze_emails("home@example.com", &[3], 1).unwrap();
+
+        let accounts = vec!["work@example.com".to_string(), "home@example.com".to_string()];
+        let mut listed: Vec<u32> = storage
+            .list_emails_multi(&accounts, false, 10, 0)
+            .unwrap()
+            .iter()
+            .map(|email| email.uid)
+            .collect();
+        listed.sort();
+        assert_eq!(listed, vec![1, 3]);
+        assert_eq!(storage.count_emails_multi(&accounts, false).unwrap(), 2);
+        assert_eq!(storage.count_emails_multi(&accounts, true).unwrap(), 2);
+    }
+
+    #[test]
+    fn flagged_state_syncs_and_toggles() {
+        let storage = SqliteStorage::new_in_memory().unwrap();
+        let account = "star@example.com";
+        let mut starred = make_email(1, "Contract", "legal@example.com");
+        starred.is_flagged = true;
+        storage
+            .upsert_emails(account, "INBOX", &[starred, make_email(2, "Lunch", "team@example.com")])
+            .unwrap();
+
+        let flagged = storage.list_flagged(account, 10, 0).unwrap();
+        assert_eq!(flagged.len(), 1);
+        assert!(flagged[0].is_flagged);
+
+        assert_eq!(storage.set_flagged(account, &[2], true).unwrap(), 1);
+        assert_eq!(storage.set_flagged(account, &[1], false).unwrap(), 1);
+        let flagged = storage.list_flagged(account, 10, 0).unwrap();
+        assert_eq!(flagged.len(), 1);
+        assert_eq!(flagged[0].uid, 2);
+    }
+
+    #[test]
+    fn sizes_are_kept_across_upserts_and_summed_per_sender() {
+        let storage = SqliteStorage::new_in_memory().unwrap();
+        let account = "sizes@example.com";
+        let mut big = make_email(1, "Photos", "Ann <ann@example.com>");
+        big.size_bytes = Some(5_000);
+        let mut small = make_email(2, "Note", "ann@example.com");
+        small.size_bytes = Some(300);
+        storage
+            .upsert_emails(account, "INBOX", &[big, small, make_email(3, "Old", "bob@example.com")])
+            .unwrap();
+
+        // A later upsert without a size must not erase the stored one.
+        storage
+            .upsert_emails(account, "INBOX", &[make_email(1, "Photos", "Ann <ann@example.com>")])
+            .unwrap();
+        assert_eq!(storage.uids_without_size(account, 10).unwrap(), vec![3]);
+        storage.set_email_sizes(account, &[(3, 100)]).unwrap();
+        assert!(storage.uids_without_size(account, 10).unwrap().is_empty());
+        let mut trashed = make_email(4, "Video", "ann@example.com");
+        trashed.size_bytes = Some(90_000);
+        storage.upsert_emails(account, "INBOX", &[trashed]).unwrap();
+        storage.trash_emails(account, &[4]).unwrap();
+
+        let stats = storage.total_size_by_sender(account).unwrap();
+        assert_eq!(stats[0].sender, "ann@example.com");
+        assert_eq!((stats[0].count, stats[0].total_bytes), (2, 5_300));
+        assert_eq!(stats[1].total_bytes, 100);
+    }
+
+    #[test]
+    fn duplicates_group_by_message_id_but_not_empty_ids() {
+        let storage = SqliteStorage::new_in_memory().unwrap();
+        let account = "dedupe@example.com";
+        let mut archived = make_email(1, "Hello", "a@example.com");
+        archived.message_id = "<same@example.com>".to_string();
+        let mut inbox = make_email(2, "Hello", "a@example.com");
+        inbox.message_id = "<same@example.com>".to_string();
+        let mut blank_a = make_email(3, "No id", "b@example.com");
+        blank_a.message_id = String::new();
+        let mut blank_b = make_email(4, "No id", "b@example.com");
+        blank_b.message_id = String::new();
+        storage
+            .upsert_emails(account, "[Gmail]/All Mail", &[archived, blank_a, blank_b])
+            .unwrap();
+        storage.upsert_emails(account, "INBOX", &[inbox]).unwrap();
+
+        assert_eq!(
+            storage.duplicate_message_ids(account).unwrap(),
+            vec![("<same@example.com>".to_string(), vec![1, 2])]
+        );
+        assert_eq!(storage.dedupe_emails(account, KeepStrategy::Inbox).unwrap(), 1);
+        assert_eq!(storage.trashed_uids(account).unwrap(), vec![1]);
+        assert!(storage.duplicate_message_ids(account).unwrap().is_empty());
+    }
 
-            let counts = storage.filter_match_counts(account, false).unwrap();
-            let counts_map: HashMap<i64, u64> = counts.into_iter().collect();
-            assert_eq!(counts_map.get(&subject_id), Some(&1));
-            assert_eq!(counts_map.get(&sender_id), Some(&1));
+    #[test]
+    fn account_queries_use_composite_indexes() {
+        let storage = SqliteStorage::new_in_memory().unwrap();
+        let conn = storage.reader().unwrap();
+        let plan = |sql: &str| -> String {
+            let mut stmt = conn.prepare(&format!("EXPLAIN QUERY PLAN {}", sql)).unwrap();
+            let details: Vec<String> = stmt
+                .query_map([], |row| row.get(3))
+                .unwrap()
+                .map(|row| row.unwrap())
+                .collect();
+            details.join("; ")
+        };
 
-            let new_emails = vec![make_email(12, "Invoice April", "billing@corp.com")];
-            storage.upsert_emails(account, "INBOX", &new_emails).unwrap();
+        let listing = plan(
+            "SELECT uid FROM emails WHERE account = 'a' AND is_trashed = 0 \
+             ORDER BY date_epoch DESC, uid DESC LIMIT 50",
+        );
+        assert!(listing.contains("idx_emails_account_date_epoch"), "{}", listing);
+        let by_message_id =
+            plan("SELECT uid FROM emails WHERE account = 'a' AND message_id = '<x@y>'");
+        assert!(by_message_id.contains("idx_emails_account_message_id"), "{}", by_message_id);
+        let unread = plan(
+            "SELECT COUNT(*) FROM emails WHERE account = 'a' AND is_read = 0 AND is_trashed = 0",
+        );
+        assert!(unread.contains("COVERING INDEX idx_emails_account_unread"), "{}", unread);
+        // The unread listing walks the index in order instead of sorting; `uid` is in the
+        // index so the tie-break doesn't need a sort either.
+        let unread_listing = plan(
+            "SELECT uid FROM emails WHERE account = 'a' AND is_trashed = 0 AND is_read = 0 \
+             ORDER BY date_epoch DESC, uid DESC LIMIT 50",
+        );
+        assert!(unread_listing.contains("idx_emails_account_read_date"), "{}", unread_listing);
+        assert!(!unread_listing.contains("TEMP B-TREE"), "{}", unread_listing);
+    }
 
-            let processed_new = storage.refresh_filtered_emails(account, 10, false).unwrap();
-            assert_eq!(processed_new, 1);
+    #[test]
+    fn settings_round_trip_and_overwrite() {
+        let storage = SqliteStorage::new_in_memory().unwrap();
+        assert_eq!(storage.get_setting("sync_batch_size").unwrap(), None);
+        storage.set_setting("sync_batch_size", "250").unwrap();
+        storage.set_setting("sync_batch_size", "300").unwrap();
+        assert_eq!(storage.get_setting("sync_batch_size").unwrap().as_deref(), Some("300"));
+    }
 
-            let counts_after = storage.filter_match_counts(account, false).unwrap();
-            let counts_after_map: HashMap<i64, u64> = counts_after.into_iter().collect();
-            assert_eq!(counts_after_map.get(&subject_id), Some(&2));
-            assert_eq!(counts_after_map.get(&sender_id), Some(&1));
+    #[test]
+    fn reset_sync_state_keeps_cached_emails() {
+        let storage = SqliteStorage::new_in_memory().unwrap();
+        storage.set_last_uid("a@example.com", "INBOX", 42).unwrap();
+        storage.set_last_uid("b@example.com", "INBOX", 7).unwrap();
+        {
+            let conn = storage.conn.lock().unwrap();
+            set_filter_last_email_id(&conn, "a@example.com", 99).unwrap();
         }
-        let _ = std::fs::remove_file(path);
+
+        storage.reset_sync_state("a@example.com").unwrap();
+
+        assert_eq!(storage.get_last_uid("a@example.com", "INBOX").unwrap(), 0);
+        assert_eq!(storage.get_last_uid("b@example.com", "INBOX").unwrap(), 7);
+        let conn = storage.conn.lock().unwrap();
+        assert_eq!(get_filter_last_email_id(&conn, "a@example.com").unwrap(), 0);
     }
 
     #[test]
-    fn filter_refresh_rewinds_when_filtered_empty_but_last_id_set() {
-        let path = temp_db_path("filters-rematch");
+    fn local_reads_survive_syncs_until_the_server_agrees() {
+        use crate::gmail::FlagUpdate;
+        let storage = SqliteStorage::new_in_memory().unwrap();
+        let account = "local@example.com";
+        let emails = [make_email(1, "One", "a@example.com"), make_email(2, "Two", "a@example.com")];
+        storage.upsert_emails(account, "INBOX", &emails).unwrap();
+        assert_eq!(storage.mark_emails_read_locally(account, &[1, 2]).unwrap(), 2);
+
+        // The server still reports both unread.
+        storage.upsert_emails(account, "INBOX", &emails).unwrap();
+        let unread = [FlagUpdate { uid: 2, is_read: false, is_flagged: false, is_answered: false }];
+        assert_eq!(storage.apply_flag_updates(account, &unread).unwrap(), 0);
+        assert_eq!(storage.count_emails(account, true, &EmailQuery::default()).unwrap(), 0);
+
+        // Once the server says read, the override is dropped and later unread state applies.
+        let mut read = make_email(1, "One", "a@example.com");
+        read.is_read = true;
+        storage.upsert_emails(account, "INBOX", &[read]).unwrap();
+        storage.upsert_emails(account, "INBOX", &emails[..1]).unwrap();
+        assert_eq!(storage.all_unread_uids(account).unwrap(), vec![1]);
+
+        // Marking unread in the app clears the override too.
+        storage.mark_emails_unread(account, &[2]).unwrap();
+        storage.apply_flag_updates(account, &unread).unwrap();
+        assert_eq!(storage.all_unread_uids(account).unwrap(), vec![1, 2]);
+    }
+
+    #[test]
+    fn category_sync_replaces_previous_members() {
+        let storage = SqliteStorage::new_in_memory().unwrap();
+        let account = "tabs@example.com";
+        storage
+            .upsert_emails(
+                account,
+                "INBOX",
+                &[
+                    make_email(1, "Sale", "shop@example.com"),
+                    make_email(2, "Deal", "shop@example.com"),
+                    make_email(3, "Hi", "friend@example.com"),
+                ],
+            )
+            .unwrap();
+        let promotions = EmailQuery {
+            gmail_category: Some("promotions".to_string()),
+            ..Default::default()
+        };
+
+        assert_eq!(storage.replace_gmail_category(account, "promotions", &[1, 2, 99]).unwrap(), 2);
+        assert_eq!(storage.count_emails(account, false, &promotions).unwrap(), 2);
+
+        assert_eq!(storage.replace_gmail_category(account, "promotions", &[2]).unwrap(), 1);
+        let rows = storage
+            .list_emails(account, false, &promotions, SortBy::default(), 10, 0)
+            .unwrap();
+        assert_eq!(rows.iter().map(|e| e.uid).collect::<Vec<_>>(), vec![2]);
+    }
+
+    #[test]
+    fn cached_uids_skip_trashed_and_other_mailboxes() {
+        let storage = SqliteStorage::new_in_memory().unwrap();
+        let account = "reconcile@example.com";
+        storage
+            .upsert_emails(
+                account,
+                "INBOX",
+                &[
+                    make_email(30, "Three", "a@example.com"),
+                    make_email(10, "One", "a@example.com"),
+                    make_email(20, "Two", "a@example.com"),
+                ],
+            )
+            .unwrap();
+        storage
+            .upsert_emails(account, "Work", &[make_email(40, "Four", "a@example.com")])
+            .unwrap();
+        storage.trash_emails(account, &[20]).unwrap();
+
+        assert_eq!(storage.cached_uids(account, "INBOX").unwrap(), vec![10, 30]);
+        storage.remove_emails(account, &[30]).unwrap();
+        assert_eq!(storage.cached_uids(account, "INBOX").unwrap(), vec![10]);
+        assert_eq!(storage.cached_uids(account, "Work").unwrap(), vec![40]);
+    }
+
+    #[test]
+    fn last_uid_is_tracked_per_mailbox() {
+        let storage = SqliteStorage::new_in_memory().unwrap();
+        let account = "boxes@example.com";
+        storage.set_last_uid(account, "INBOX", 120).unwrap();
+        storage.set_last_uid(account, "[Gmail]/All Mail", 98000).unwrap();
+        storage.set_last_uid(account, "INBOX", 125).unwrap();
+
+        assert_eq!(storage.get_last_uid(account, "INBOX").unwrap(), 125);
+        assert_eq!(storage.get_last_uid(account, "[Gmail]/All Mail").unwrap(), 98000);
+        assert_eq!(storage.get_last_uid(account, "Work").unwrap(), 0);
+
+        storage
+            .upsert_emails(account, "INBOX", &[make_email(5, "Inbox", "a@example.com")])
+            .unwrap();
+        storage
+            .upsert_emails(account, "Work", &[make_email(900, "Work", "a@example.com")])
+            .unwrap();
+        assert_eq!(storage.get_max_uid(account, "INBOX").unwrap(), Some(5));
+        assert_eq!(storage.get_max_uid(account, "Work").unwrap(), Some(900));
+    }
+
+    #[test]
+    fn low_water_mark_only_moves_down_and_resets_with_sync_state() {
+        let storage = SqliteStorage::new_in_memory().unwrap();
+        let account = "history@example.com";
+        assert_eq!(storage.get_oldest_synced_uid(account, "INBOX").unwrap(), None);
+
+        storage.set_last_uid(account, "INBOX", 900).unwrap();
+        assert_eq!(storage.get_oldest_synced_uid(account, "INBOX").unwrap(), Some(901));
+        storage
+            .upsert_emails(
+                account,
+                "INBOX",
+                &[make_email(700, "Old", "a@example.com"), make_email(900, "New", "a@example.com")],
+            )
+            .unwrap();
+        assert_eq!(storage.get_oldest_synced_uid(account, "INBOX").unwrap(), Some(700));
+
+        storage.set_oldest_synced_uid(account, "INBOX", 400).unwrap();
+        storage.set_oldest_synced_uid(account, "INBOX", 650).unwrap();
+        assert_eq!(storage.get_oldest_synced_uid(account, "INBOX").unwrap(), Some(400));
+        assert_eq!(storage.get_last_uid(account, "INBOX").unwrap(), 900);
+
+        storage.reset_sync_state(account).unwrap();
+        assert_eq!(storage.get_oldest_synced_uid(account, "INBOX").unwrap(), Some(700));
+    }
+
+    #[test]
+    fn accounts_keep_profile_and_last_sync_separately() {
+        let storage = SqliteStorage::new_in_memory().unwrap();
+        storage.upsert_account("b@example.com", Some("Work"), Some("#3b82f6")).unwrap();
+        storage.touch_last_synced("b@example.com", 1_700_000_000).unwrap();
+        storage.touch_last_synced("a@example.com", 1_700_000_500).unwrap();
+        storage.upsert_account("b@example.com", Some("Office"), None).unwrap();
+
+        assert_eq!(
+            storage.list_accounts().unwrap(),
+            vec![
+                AccountInfo {
+                    email: "a@example.com".to_string(),
+                    display_name: None,
+                    color: None,
+                    last_synced_epoch: Some(1_700_000_500),
+                },
+                AccountInfo {
+                    email: "b@example.com".to_string(),
+                    display_name: Some("Office".to_string()),
+                    color: None,
+                    last_synced_epoch: Some(1_700_000_000),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn accounts_are_seeded_from_cached_mail_once() {
+        let path = temp_db_path("accounts-seed");
+        {
+            let conn = Connection::open(&path).unwrap();
+            conn.execute_batch(
+                "CREATE TABLE emails (
+                   id INTEGER PRIMARY KEY,
+                   uid INTEGER NOT NULL,
+                   message_id TEXT NOT NULL,
+                   subject TEXT NOT NULL,
+                   sender TEXT NOT NULL,
+                   date TEXT NOT NULL,
+                   mailbox TEXT NOT NULL,
+                   account TEXT NOT NULL,
+                   is_read INTEGER NOT NULL DEFAULT 0,
+                   UNIQUE(account, uid)
+                 );
+                 INSERT INTO emails (uid, message_id, subject, sender, date, mailbox, account)
+                 VALUES (1, 'm1', 's', 'x@y.com', 'd', 'INBOX', 'old@example.com'),
+                        (2, 'm2', 's', 'x@y.com', 'd', 'INBOX', 'old@example.com');",
+            )
+            .unwrap();
+        }
         {
             let storage = SqliteStorage::new_with_path(path.clone()).unwrap();
-            let patterns = vec![FilterPattern {
-                id: 0,
-                name: "Subject contains".to_string(),
-                pattern: "Hello".to_string(),
-                field: FilterField::Subject,
-                is_regex: false,
-                enabled: true,
-            }];
-            let saved = storage.save_filters(&patterns).unwrap();
-            let filter_id = saved[0].id;
+            let accounts = storage.list_accounts().unwrap();
+            assert_eq!(accounts.len(), 1);
+            assert_eq!(accounts[0].email, "old@example.com");
+        }
+        remove_test_db(&path);
+    }
 
-            let account = "rematch@example.com";
-            let emails = vec![
-                make_email(20, "Hello World", "alice@example.com"),
-                make_email(21, "Hello Again", "bob@example.com"),
-            ];
-            storage.upsert_emails(account, "INBOX", &emails).unwrap();
+    #[test]
+    fn legacy_sync_state_rows_move_to_inbox() {
+        let path = temp_db_path("sync-state-mailbox");
+        {
+            let conn = Connection::open(&path).unwrap();
+            conn.execute_batch(
+                "CREATE TABLE sync_state (
+                   account TEXT PRIMARY KEY,
+                   last_uid INTEGER NOT NULL DEFAULT 0,
+                   updated_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+                 );
+                 INSERT INTO sync_state (account, last_uid) VALUES ('old@example.com', 321);",
+            )
+            .unwrap();
+        }
 
-            {
-                let conn = storage.conn.lock().unwrap();
-                set_filter_last_email_id(&conn, account, 999).unwrap();
+        {
+            let storage = SqliteStorage::new_with_path(path.clone()).unwrap();
+            assert_eq!(storage.get_last_uid("old@example.com", "INBOX").unwrap(), 321);
+            assert_eq!(storage.get_last_uid("old@example.com", "Archive").unwrap(), 0);
+        }
+        remove_test_db(&path);
+    }
+
+    #[test]
+    fn flag_updates_change_only_cached_emails_that_differ() {
+        use crate::gmail::FlagUpdate;
+        let storage = SqliteStorage::new_in_memory().unwrap();
+        let account = "flags@example.com";
+        storage
+            .upsert_emails(
+                account,
+                "INBOX",
+                &[make_email(1, "One", "a@example.com"), make_email(2, "Two", "a@example.com")],
+            )
+            .unwrap();
+        storage.set_last_uid(account, "INBOX", 2).unwrap();
+        assert_eq!(storage.get_highest_modseq(account).unwrap(), None);
+
+        let updates = [
+            FlagUpdate { uid: 1, is_read: true, is_flagged: false, is_answered: true },
+            FlagUpdate { uid: 2, is_read: false, is_flagged: false, is_answered: false },
+            FlagUpdate { uid: 3, is_read: true, is_flagged: true, is_answered: false },
+        ];
+        assert_eq!(storage.apply_flag_updates(account, &updates).unwrap(), 1);
+        assert_eq!(storage.count_emails(account, true, &EmailQuery::default()).unwrap(), 1);
+        assert_eq!(storage.unread_count(account).unwrap(), 1);
+        let unanswered = EmailQuery {
+            answered_only: Some(false),
+            ..Default::default()
+        };
+        let rows = storage
+            .list_emails(account, false, &unanswered, SortBy::default(), 10, 0)
+            .unwrap();
+        assert_eq!(rows.iter().map(|e| e.uid).collect::<Vec<_>>(), vec![2]);
+        let answered = EmailQuery {
+            answered_only: Some(true),
+            ..Default::default()
+        };
+        let rows = storage
+            .list_emails(account, false, &answered, SortBy::default(), 10, 0)
+            .unwrap();
+        assert!(rows.len() == 1 && rows[0].is_answered);
+
+        storage.set_highest_modseq(account, 4242).unwrap();
+        assert_eq!(storage.get_highest_modseq(account).unwrap(), Some(4242));
+        assert_eq!(storage.get_last_uid(account, "INBOX").unwrap(), 2);
+        storage.reset_sync_state(account).unwrap();
+        assert_eq!(storage.get_highest_modseq(account).unwrap(), None);
+    }
+
+    #[test]
+    fn snoozed_emails_are_listed_separately_but_counted() {
+        let storage = SqliteStorage::new_in_memory().unwrap();
+        let account = "snooze@example.com";
+        storage
+            .upsert_emails(
+                account,
+                "INBOX",
+                &[
+                    make_email(1, "Now", "a@example.com"),
+                    make_email(2, "Later", "b@example.com"),
+                    make_email(3, "Elapsed", "c@example.com"),
+                ],
+            )
+            .unwrap();
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64;
+        assert_eq!(storage.snooze_emails(account, &[2], now + 3600).unwrap(), 1);
+        assert_eq!(storage.snooze_emails(account, &[3], now - 60).unwrap(), 1);
+
+        let query = EmailQuery::default();
+        let page = storage
+            .list_emails_page(account, false, &query, SortBy::default(), 10, 0)
+            .unwrap();
+        let mut listed: Vec<u32> = page.emails.iter().map(|e| e.uid).collect();
+        listed.sort();
+        assert_eq!(listed, vec![1, 3]);
+        assert_eq!(page.total, 3);
+        assert!(!page.has_more);
+
+        let everything = EmailQuery {
+            include_snoozed: true,
+            ..EmailQuery::default()
+        };
+        let all = storage
+            .list_emails(account, false, &everything, SortBy::default(), 10, 0)
+            .unwrap();
+        assert_eq!(all.len(), 3);
+
+        let snoozed = storage.list_snoozed(account, 10, 0).unwrap();
+        assert_eq!(snoozed.len(), 1);
+        assert_eq!(snoozed[0].email.uid, 2);
+        assert_eq!(snoozed[0].snoozed_until, now + 3600);
+
+        assert_eq!(storage.clear_elapsed_snoozes().unwrap(), 1);
+        assert_eq!(storage.unsnooze_emails(account, &[2]).unwrap(), 1);
+        assert!(storage.list_snoozed(account, 10, 0).unwrap().is_empty());
+        assert_eq!(storage.count_emails(account, false, &query).unwrap(), 3);
+    }
+
+    #[test]
+    fn uids_for_filter_skip_trashed_and_optionally_read() {
+        let storage = SqliteStorage::new_in_memory().unwrap();
+        let account = "bulk@example.com";
+        let mut read = make_email(2, "Weekly digest", "news@example.com");
+        read.is_read = true;
+        storage
+            .upsert_emails(
+                account,
+                "INBOX",
+                &[
+                    make_email(1, "Daily digest", "news@example.com"),
+                    read,
+                    make_email(3, "Digest again", "news@example.com"),
+                    make_email(4, "Invoice", "billing@example.com"),
+                ],
+            )
+            .unwrap();
+        let saved = storage
+            .save_filters(&[filter("Digests", "digest")])
+            .unwrap();
+        storage.refresh_filtered_emails(account, 50, false).unwrap();
+        storage.trash_emails(account, &[3]).unwrap();
+
+        let filter_id = saved[0].id;
+        assert_eq!(storage.uids_for_filter(account, filter_id, false).unwrap(), vec![1, 2]);
+        assert_eq!(storage.uids_for_filter(account, filter_id, true).unwrap(), vec![1]);
+        assert!(storage.uids_for_filter(account, filter_id + 1, false).unwrap().is_empty());
+    }
+
+    #[test]
+    fn filter_views_hide_snoozed_emails() {
+        let storage = SqliteStorage::new_in_memory().unwrap();
+        let account = "snooze@example.com";
+        storage
+            .upsert_emails(
+                account,
+                "INBOX",
+                &[
+                    make_email(1, "Daily digest", "news@example.com"),
+                    make_email(2, "Weekly digest", "news@example.com"),
+                    make_email(3, "Invoice", "billing@example.com"),
+                    make_email(4, "Receipt", "billing@example.com"),
+                ],
+            )
+            .unwrap();
+        let saved = storage
+            .save_filters(&[filter("Digests", "digest")])
+            .unwrap();
+        let filter_id = saved[0].id;
+        let far_future = chrono::Utc::now().timestamp() + 86_400;
+        storage.snooze_emails(account, &[2, 4], far_future).unwrap();
+
+        let uids = |emails: Vec<StoredEmail>| emails.iter().map(|e| e.uid).collect::<Vec<_>>();
+        let filtered = storage
+            .list_filtered_emails(account, &[filter_id], false, SortBy::default(), 10, 0)
+            .unwrap();
+        assert_eq!(uids(filtered), vec![1]);
+        let filtered_unread = storage
+            .list_filtered_emails(account, &[filter_id], true, SortBy::default(), 10, 0)
+            .unwrap();
+        assert_eq!(uids(filtered_unread), vec![1]);
+        assert_eq!(uids(storage.list_unfiltered_emails(account, false, 10, 0).unwrap()), vec![3]);
+        assert_eq!(storage.uids_for_filter(account, filter_id, false).unwrap(), vec![1]);
+        // Totals keep counting snoozed emails.
+        assert_eq!(storage.count_filtered_emails(account, &[filter_id], false).unwrap(), 2);
+    }
+
+    #[test]
+    fn cleanup_stats_split_inbox_and_archived() {
+        let storage = SqliteStorage::new_in_memory().unwrap();
+        let account = "progress@example.com";
+        assert_eq!(
+            storage.cleanup_stats(account).unwrap(),
+            CleanupStats {
+                total: 0,
+                unread: 0,
+                read_in_inbox: 0,
+                archived: 0,
             }
+        );
 
-            let processed = storage.refresh_filtered_emails(account, 50, false).unwrap();
-            assert_eq!(processed, 2);
+        let mut read = make_email(2, "Read", "a@example.com");
+        read.is_read = true;
+        storage
+            .upsert_emails(
+                account,
+                "INBOX",
+                &[make_email(1, "Unread", "a@example.com"), read, make_email(3, "Binned", "b@example.com")],
+            )
+            .unwrap();
+        storage
+            .upsert_emails(account, "[Gmail]/All Mail", &[make_email(9, "Archived", "c@example.com")])
+            .unwrap();
+        storage.trash_emails(account, &[3]).unwrap();
+
+        assert_eq!(
+            storage.cleanup_stats(account).unwrap(),
+            CleanupStats {
+                total: 4,
+                unread: 1,
+                read_in_inbox: 1,
+                archived: 2,
+            }
+        );
+    }
 
-            let counts = storage.filter_match_counts(account, false).unwrap();
-            let counts_map: HashMap<i64, u64> = counts.into_iter().collect();
-            assert_eq!(counts_map.get(&filter_id), Some(&2));
+    #[test]
+    fn protected_manual_filters_survive_full_refresh() {
+        let storage = SqliteStorage::new_in_memory().unwrap();
+        let account = "manual@example.com";
+        storage
+            .upsert_emails(
+                account,
+                "INBOX",
+                &[make_email(1, "Weekly newsletter", "news@example.com"), make_email(2, "Newsletter", "n@example.com")],
+            )
+            .unwrap();
+        let saved = storage
+            .save_filters(&[
+                filter("News", "newsletter"),
+                FilterPattern {
+                    id: 0,
+                    name: "Receipts".to_string(),
+                    pattern: "receipt".to_string(),
+                    field: FilterField::Subject,
+                    is_regex: false,
+                    case_sensitive: false,
+                    enabled: true,
+                    priority: 1,
+                    action: FilterAction::None,
+                    action_dry_run: false,
+                    match_mode: MatchMode::Substring,
+                },
+            ])
+            .unwrap();
+        let (news, receipts) = (saved[0].id, saved[1].id);
+        storage.refresh_filtered_emails(account, 50, false).unwrap();
 
-            let last_id = {
-                let conn = storage.conn.lock().unwrap();
-                get_filter_last_email_id(&conn, account).unwrap()
-            };
-            assert_eq!(last_id, 2);
+        storage.set_email_filters(account, 1, &[receipts], true).unwrap();
+        storage.set_email_filters(account, 2, &[receipts], false).unwrap();
+        storage.refresh_filtered_emails(account, 50, true).unwrap();
+
+        assert_eq!(storage.uids_for_filter(account, receipts, false).unwrap(), vec![1]);
+        assert_eq!(storage.uids_for_filter(account, news, false).unwrap(), vec![2]);
+    }
+
+    #[test]
+    fn manual_mapping_survives_forced_refresh_and_filter_edit() {
+        let storage = SqliteStorage::new_in_memory().unwrap();
+        let account = "source@example.com";
+        storage
+            .upsert_emails(account, "INBOX", &[make_email(1, "Weekly newsletter", "news@example.com")])
+            .unwrap();
+        let mut filter = filter("News", "newsletter");
+        let saved = storage.save_filters(&[filter.clone()]).unwrap();
+        let filter_id = saved[0].id;
+        storage.refresh_filtered_emails(account, 50, false).unwrap();
+        storage.set_email_filters(account, 1, &[filter_id], true).unwrap();
+
+        let source = |storage: &SqliteStorage| -> Option<String> {
+            let conn = storage.conn.lock().unwrap();
+            conn.query_row(
+                "SELECT source FROM filtered_emails WHERE filter_id = ?1",
+                params![filter_id],
+                |row| row.get(0),
+            )
+            .optional()
+            .unwrap()
+        };
+        assert_eq!(source(&storage).as_deref(), Some("manual"));
+
+        storage.refresh_filtered_emails(account, 50, true).unwrap();
+        assert_eq!(source(&storage).as_deref(), Some("manual"));
+
+        // Editing the filter so it no longer matches drops auto rows, not manual ones.
+        filter.id = filter_id;
+        filter.pattern = "invoice".to_string();
+        storage.save_filters(&[filter]).unwrap();
+        assert_eq!(source(&storage).as_deref(), Some("manual"));
+        assert_eq!(storage.uids_for_filter(account, filter_id, false).unwrap(), vec![1]);
+    }
+
+    #[test]
+    fn list_by_sender_domain_matches_subdomains_only() {
+        let storage = SqliteStorage::new_in_memory().unwrap();
+        let emails = vec![
+            make_email(1, "Weekly", "News <news@mailchimp.com>"),
+            make_email(2, "Promo", "promo@us5.MailChimp.com"),
+            make_email(3, "Lookalike", "x@notmailchimp.com"),
+            make_email(4, "Broken", "Undisclosed recipients"),
+            make_email(5, "Stray bracket", "Alerts alerts@MAILCHIMP.com>"),
+        ];
+        storage.upsert_emails("a@example.com", "INBOX", &emails).unwrap();
+
+        let uids = |domain: &str| -> Vec<u32> {
+            let mut uids: Vec<u32> = storage
+                .list_by_sender_domain("a@example.com", domain, false, 50, 0)
+                .unwrap()
+                .iter()
+                .map(|e| e.uid)
+                .collect();
+            uids.sort_unstable();
+            uids
+        };
+        assert_eq!(uids("mailchimp.com"), vec![1, 2, 5]);
+        assert_eq!(uids("*.MailChimp.com"), vec![1, 2, 5]);
+        assert_eq!(uids("us5.mailchimp.com"), vec![2]);
+        assert_eq!(uids("com"), vec![1, 2, 3, 5]);
+        assert!(storage.list_by_sender_domain("a@example.com", " ", false, 50, 0).is_err());
+    }
+
+    #[test]
+    fn sender_domain_backfill_marks_senders_without_a_domain() {
+        let storage = SqliteStorage::new_in_memory().unwrap();
+        let emails = vec![
+            make_email(1, "Weekly", "News <news@mailchimp.com>"),
+            make_email(2, "Broken", "Undisclosed recipients"),
+        ];
+        storage.upsert_emails("a@example.com", "INBOX", &emails).unwrap();
+
+        let mut conn = storage.conn.lock().unwrap();
+        let unset: u32 = conn
+            .query_row("SELECT COUNT(*) FROM emails WHERE sender_domain IS NULL", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(unset, 0);
+        conn.execute("UPDATE emails SET sender_domain = NULL", []).unwrap();
+        backfill_sender_domain(&mut conn).unwrap();
+        let domains: Vec<Option<String>> = conn
+            .prepare("SELECT sender_domain FROM emails ORDER BY uid")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(domains, vec![Some("mailchimp.com".to_string()), Some(String::new())]);
+    }
+
+    #[test]
+    fn filter_refresh_preview_matches_forced_refresh() {
+        let storage = SqliteStorage::new_in_memory().unwrap();
+        let account = "preview@example.com";
+        storage
+            .upsert_emails(
+                account,
+                "INBOX",
+                &[
+                    make_email(1, "Weekly newsletter", "news@example.com"),
+                    make_email(2, "Your receipt", "shop@example.com"),
+                    make_email(3, "Hello", "friend@example.com"),
+                ],
+            )
+            .unwrap();
+        let filter = filter("News", "newsletter");
+        let saved = storage.save_filters(&[filter]).unwrap();
+        let filter_id = saved[0].id;
+        storage.refresh_filtered_emails(account, 50, false).unwrap();
+        storage.set_email_filters(account, 3, &[filter_id], true).unwrap();
+
+        let unchanged = storage.preview_filter_refresh(account).unwrap();
+        assert_eq!((unchanged.would_add, unchanged.would_remove), (0, 0));
+        assert!(unchanged.per_filter.is_empty());
+
+        // Change the pattern behind the matcher's back so the stored matches go stale.
+        {
+            let conn = storage.conn.lock().unwrap();
+            conn.execute("UPDATE filters SET pattern = 'receipt' WHERE id = ?1", params![filter_id])
+                .unwrap();
         }
-        let _ = std::fs::remove_file(path);
+        storage
+            .upsert_emails(account, "INBOX", &[make_email(4, "Another receipt", "shop@example.com")])
+            .unwrap();
+        let last_id_before = {
+            let conn = storage.conn.lock().unwrap();
+            get_filter_last_email_id(&conn, account).unwrap()
+        };
+        let preview = storage.preview_filter_refresh(account).unwrap();
+        assert_eq!((preview.would_add, preview.would_remove), (2, 1));
+        assert_eq!(preview.per_filter, vec![(filter_id, 1)]);
+        assert_eq!(storage.uids_for_filter(account, filter_id, false).unwrap(), vec![1, 3]);
+        let conn = storage.conn.lock().unwrap();
+        assert_eq!(get_filter_last_email_id(&conn, account).unwrap(), last_id_before);
     }
 }
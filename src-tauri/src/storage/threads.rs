@@ -0,0 +1,196 @@
+//! Conversation grouping for cached emails.
+//!
+//! Emails are joined into a thread when one lists the other's `Message-ID` in
+//! `References`/`In-Reply-To`. Emails without those headers fall back to the
+//! normalized subject, but only when some email in the account replied to or
+//! forwarded that subject, so unrelated newsletters with a shared subject stay apart.
+
+use std::collections::{HashMap, HashSet};
+
+pub(crate) struct ThreadInput {
+    pub id: i64,
+    pub message_id: String,
+    pub subject: String,
+    pub in_reply_to: Option<String>,
+    /// Space-separated message ids from `References`.
+    pub references: Option<String>,
+}
+
+/// Strip `Re:`/`Fwd:`/`Fw:` prefixes (including `Re[2]:` and repeats) and lowercase.
+/// Returns the normalized subject and whether any prefix was removed.
+pub(crate) fn normalize_subject(subject: &str) -> (String, bool) {
+    let mut rest = subject.trim();
+    let mut stripped = false;
+    loop {
+        let lower = rest.to_ascii_lowercase();
+        let prefix_len = ["re", "fwd", "fw"].iter().find_map(|prefix| {
+            let after = lower.strip_prefix(prefix)?;
+            let counter = if after.starts_with('[') {
+                after.find(']').map_or(0, |end| end + 1)
+            } else {
+                0
+            };
+            after[counter..]
+                .starts_with(':')
+                .then_some(prefix.len() + counter + 1)
+        });
+        match prefix_len {
+            Some(len) => {
+                rest = rest[len..].trim_start();
+                stripped = true;
+            }
+            None => break,
+        }
+    }
+    (rest.to_lowercase(), stripped)
+}
+
+struct UnionFind {
+    parent: Vec<usize>,
+}
+
+impl UnionFind {
+    fn new() -> Self {
+        Self { parent: Vec::new() }
+    }
+
+    fn add(&mut self) -> usize {
+        self.parent.push(self.parent.len());
+        self.parent.len() - 1
+    }
+
+    fn find(&mut self, mut node: usize) -> usize {
+        while self.parent[node] != node {
+            self.parent[node] = self.parent[self.parent[node]];
+            node = self.parent[node];
+        }
+        node
+    }
+
+    fn union(&mut self, a: usize, b: usize) {
+        let (a, b) = (self.find(a), self.find(b));
+        if a != b {
+            self.parent[b] = a;
+        }
+    }
+}
+
+/// Assign each email a thread id: the smallest email id in its conversation.
+pub(crate) fn compute_thread_ids(emails: &[ThreadInput]) -> Vec<(i64, i64)> {
+    let mut uf = UnionFind::new();
+    let mut keys: HashMap<String, usize> = HashMap::new();
+    let email_nodes: Vec<usize> = emails.iter().map(|_| uf.add()).collect();
+
+    for (email, &node) in emails.iter().zip(&email_nodes) {
+        if email.message_id.is_empty() {
+            continue;
+        }
+        match keys.get(&email.message_id) {
+            // Duplicate Message-ID (e.g. the same mail in two mailboxes).
+            Some(&existing) => uf.union(existing, node),
+            None => {
+                keys.insert(email.message_id.clone(), node);
+            }
+        }
+    }
+
+    let normalized: Vec<(String, bool)> = emails
+        .iter()
+        .map(|email| normalize_subject(&email.subject))
+        .collect();
+    let reply_subjects: HashSet<&str> = normalized
+        .iter()
+        .filter(|(subject, stripped)| *stripped && !subject.is_empty())
+        .map(|(subject, _)| subject.as_str())
+        .collect();
+
+    for (index, email) in emails.iter().enumerate() {
+        let node = email_nodes[index];
+        let references = email
+            .references
+            .iter()
+            .flat_map(|refs| refs.split_whitespace())
+            .chain(email.in_reply_to.as_deref())
+            .filter(|id| !id.is_empty())
+            .collect::<Vec<_>>();
+
+        if references.is_empty() {
+            let subject = normalized[index].0.as_str();
+            if reply_subjects.contains(subject) {
+                let key = format!("subject:{}", subject);
+                let subject_node = *keys.entry(key).or_insert_with(|| uf.add());
+                uf.union(subject_node, node);
+            }
+            continue;
+        }
+
+        for reference in references {
+            let ref_node = match keys.get(reference) {
+                Some(&existing) => existing,
+                None => {
+                    let added = uf.add();
+                    keys.insert(reference.to_string(), added);
+                    added
+                }
+            };
+            uf.union(ref_node, node);
+        }
+    }
+
+    let mut min_id_by_root: HashMap<usize, i64> = HashMap::new();
+    for (email, &node) in emails.iter().zip(&email_nodes) {
+        let root = uf.find(node);
+        min_id_by_root
+            .entry(root)
+            .and_modify(|min| *min = (*min).min(email.id))
+            .or_insert(email.id);
+    }
+
+    emails
+        .iter()
+        .zip(&email_nodes)
+        .map(|(email, &node)| (email.id, min_id_by_root[&uf.find(node)]))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn input(id: i64, message_id: &str, subject: &str, in_reply_to: Option<&str>, refs: Option<&str>) -> ThreadInput {
+        ThreadInput {
+            id,
+            message_id: message_id.to_string(),
+            subject: subject.to_string(),
+            in_reply_to: in_reply_to.map(str::to_string),
+            references: refs.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn normalizes_reply_prefixes() {
+        assert_eq!(normalize_subject("Re: RE[2]: Fwd: Lunch"), ("lunch".to_string(), true));
+        assert_eq!(normalize_subject("Weekly digest"), ("weekly digest".to_string(), false));
+        assert_eq!(normalize_subject("Reminder: rent"), ("reminder: rent".to_string(), false));
+    }
+
+    #[test]
+    fn groups_by_references_and_subject_fallback() {
+        let emails = vec![
+            input(1, "<a@x>", "Plans", None, None),
+            input(2, "<b@x>", "Re: Plans", Some("<a@x>"), None),
+            // References a message we never cached, through which it reaches <b@x>.
+            input(3, "<c@x>", "Re: Plans", None, Some("<missing@x> <b@x>")),
+            // No headers: joins via the replied-to subject.
+            input(4, "<d@x>", "RE: plans", None, None),
+            input(5, "<e@x>", "Newsletter", None, None),
+            input(6, "<f@x>", "Newsletter", None, None),
+        ];
+        let ids: HashMap<i64, i64> = compute_thread_ids(&emails).into_iter().collect();
+        assert_eq!(ids[&2], 1);
+        assert_eq!(ids[&3], 1);
+        assert_eq!(ids[&4], 1);
+        assert_eq!(ids[&5], 5);
+        assert_eq!(ids[&6], 6);
+    }
+}
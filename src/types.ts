@@ -8,13 +8,22 @@ export interface Email {
   mailbox: string;
   account: string;
   is_read: boolean;
+  snippet?: string | null;
+  to?: string | null;
+  cc?: string | null;
 }
 
 export interface EmailWithMatches extends Email {
   matchingFilters: string[]; // Filter names that matched
 }
 
-export type FilterField = "subject" | "sender" | "any";
+export type FilterField = "subject" | "sender" | "recipient" | "any";
+
+export type FilterAction = "none" | "mark_read" | "archive";
+
+export type MatchMode = "substring" | "whole_word";
+
+export type ImportMode = "merge" | "replace";
 
 export interface FilterPattern {
   id: number;
@@ -22,7 +31,19 @@ export interface FilterPattern {
   pattern: string;
   field: FilterField;
   is_regex: boolean;
+  case_sensitive?: boolean;
   enabled: boolean;
+  priority?: number;
+  action?: FilterAction;
+  action_dry_run?: boolean;
+  match_mode?: MatchMode;
+}
+
+export interface PlannedFilterAction {
+  uid: number;
+  filter_id: number;
+  action: FilterAction;
+  dry_run: boolean;
 }
 
 export interface TestPatternResult {
@@ -39,6 +60,10 @@ export interface GmailEmail {
   date: string;
   date_epoch: number;
   is_read: boolean;
+  is_flagged?: boolean;
+  size_bytes?: number | null;
+  to?: string | null;
+  cc?: string | null;
 }
 
 export interface StoredEmail {
@@ -51,6 +76,93 @@ export interface StoredEmail {
   mailbox: string;
   account: string;
   is_read: boolean;
+  unsubscribe_url: string | null;
+  unsubscribe_mailto: string | null;
+  unsubscribe_oneclick: boolean;
+  to: string | null;
+  cc: string | null;
+  has_attachments: boolean;
+  is_flagged: boolean;
+  size_bytes: number | null;
+  snippet: string | null;
+  is_answered: boolean;
+}
+
+export interface SnoozedEmail extends StoredEmail {
+  snoozed_until: number;
+}
+
+export interface EmailPage {
+  emails: StoredEmail[];
+  total: number;
+  has_more: boolean;
+}
+
+export interface AttachmentMeta {
+  filename: string | null;
+  content_type: string;
+  size: number;
+}
+
+export interface UnsubscribeInfo {
+  unsubscribe_url: string | null;
+  unsubscribe_mailto: string | null;
+  unsubscribe_oneclick: boolean;
+}
+
+export type UnsubscribeResult =
+  | { action: "posted"; status: number }
+  | { action: "compose_email"; mailto: string }
+  | { action: "open_url"; url: string };
+
+export interface SearchCriteria {
+  from?: string | null;
+  subject?: string | null;
+  since?: number | null; // unix seconds
+  before?: number | null; // unix seconds
+  unseen?: boolean;
+}
+
+export interface ThreadSummary {
+  thread_id: number;
+  subject: string;
+  latest_epoch: number;
+  count: number;
+  unread: number;
+}
+
+export interface MailboxCount {
+  mailbox: string;
+  total: number;
+  unread: number;
+}
+
+export interface SenderSize {
+  sender: string;
+  count: number;
+  total_bytes: number;
+}
+
+export type SyncErrorKind =
+  | "auth_failed"
+  | "credentials_missing"
+  | "network"
+  | "rate_limited"
+  | "unknown";
+
+export interface SyncError {
+  kind: SyncErrorKind;
+  message: string;
+}
+
+export interface ConnectionStatus {
+  online: boolean;
+  message: string | null;
+}
+
+export interface ConfiguredAccount {
+  email: string;
+  has_cache: boolean;
 }
 
 export interface AppSettings {
@@ -61,4 +173,67 @@ export interface AppSettings {
 export interface EmailBody {
   html: string | null;
   text: string | null;
+  unsubscribe_url?: string | null;
+  unsubscribe_mailto?: string | null;
+  unsubscribe_oneclick?: boolean;
+  attachments?: AttachmentMeta[];
+  snippet?: string | null;
+}
+
+export interface GmailEmailBody {
+  uid: number;
+  body: EmailBody;
+}
+
+export interface AccountInfo {
+  email: string;
+  display_name: string | null;
+  color: string | null;
+  last_synced_epoch: number | null;
+}
+
+export interface EmailDetails {
+  body: EmailBody;
+  headers: [string, string][];
+  to: string | null;
+  cc: string | null;
+  reply_to: string | null;
+}
+
+export type ExportFormat = "csv" | "json";
+
+export type KeepStrategy = "inbox" | "lowest_uid";
+
+export type SortField = "date" | "sender" | "subject";
+
+export interface SortBy {
+  field: SortField;
+  ascending: boolean;
+}
+
+export interface BulkResult {
+  matched: number;
+  applied: number;
+  failed: number;
+}
+
+/** Server and cache outcomes of `gmail_mark_as_read`; `error` is set if either failed. */
+export interface MarkReadResult {
+  server_marked: number;
+  cache_marked: number;
+  error: string | null;
+}
+
+export interface CleanupStats {
+  total: number;
+  unread: number;
+  read_in_inbox: number;
+  archived: number;
+}
+
+export interface FilterRefreshPreview {
+  would_add: number;
+  would_remove: number;
+  /** `[filter_id, net change]` for filters whose match count would change. */
+  per_filter: Array<[number, number]>;
 }